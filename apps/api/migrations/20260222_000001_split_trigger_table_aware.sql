@@ -0,0 +1,18 @@
+-- plpgsql resolves record fields at run time, so reading new.transaction_id
+-- on a transactions row raised "record new has no field transaction_id".
+create or replace function enforce_transaction_split_cardinality()
+returns trigger as $$
+declare
+  tx_id uuid;
+begin
+  if tg_table_name = 'transactions' then
+    tx_id := coalesce(new.id, old.id);
+  elsif tg_op = 'DELETE' then
+    tx_id := old.transaction_id;
+  else
+    tx_id := new.transaction_id;
+  end if;
+  perform ensure_transaction_has_split(tx_id);
+  return null;
+end;
+$$ language plpgsql;
@@ -0,0 +1,27 @@
+-- Keys are scoped to the signed-in caller; anonymous requests are never
+-- replayed, and neither are auth responses that carry session tokens. While
+-- the handler runs, `heartbeat_at` is refreshed so a slow request keeps its
+-- reservation; one that stops beating was abandoned and can be reclaimed.
+create table if not exists idempotency_keys (
+  id uuid primary key default gen_random_uuid(),
+  pillid text unique not null default gen_pillid(),
+  user_id uuid not null references users(id) on delete cascade,
+  user_pillid text,
+  idempotency_key text not null,
+  request_method text not null,
+  request_path text not null,
+  request_hash text not null,
+  response_status smallint,
+  response_content_type text,
+  response_body bytea,
+  created_at timestamptz not null default now(),
+  heartbeat_at timestamptz not null default now(),
+  completed_at timestamptz,
+  expires_at timestamptz not null
+);
+
+create unique index if not exists idempotency_keys_scope_key
+  on idempotency_keys(user_id, idempotency_key);
+
+create index if not exists idempotency_keys_expires_at_idx
+  on idempotency_keys(expires_at);
@@ -0,0 +1,92 @@
+-- Server knowledge: every insert/update on a synced table stamps the row with
+-- the next value of a global sequence, so clients can ask for "everything
+-- after N" without relying on wall-clock timestamps. Soft deletes are updates
+-- and therefore bump knowledge too.
+create sequence if not exists sync_knowledge_seq;
+
+-- `knowledge` is taken when a row is written, not when its transaction
+-- commits, so a slow writer can commit a lower value after a sync already
+-- handed out a higher cursor. Each writing transaction therefore holds a
+-- shared advisory lock keyed just below the first value it takes, for as long
+-- as it runs, and sync never hands out a cursor at or above the lowest key
+-- still held.
+--
+-- The locks use the two-int form, so they never meet single-bigint keys such
+-- as sqlx's migration lock, and live in a reserved range of class ids starting
+-- at 0x455A0000 ("EZ"): the class id carries the key's high bits above 32 and
+-- the object id its low 32 bits, which covers knowledge up to 2^48.
+create or replace function hold_knowledge_floor()
+returns void as $$
+declare
+  floor_key bigint;
+begin
+  if coalesce(current_setting('envelopezero.knowledge_floor', true), '') = '' then
+    select last_value - 1 into floor_key from sync_knowledge_seq;
+    perform pg_advisory_xact_lock_shared(
+      (1163526144 + (floor_key >> 32))::integer,
+      floor_key::bit(32)::integer
+    );
+    perform set_config('envelopezero.knowledge_floor', 'held', true);
+  end if;
+end;
+$$ language plpgsql;
+
+create or replace function bump_sync_knowledge()
+returns trigger as $$
+begin
+  perform hold_knowledge_floor();
+  new.knowledge := nextval('sync_knowledge_seq');
+  return new;
+end;
+$$ language plpgsql;
+
+-- The highest knowledge below which every value has been committed or rolled
+-- back. Call it before taking the snapshot the rows are read in: a writer that
+-- commits in between is then visible, and one that takes its lock later only
+-- takes values above `handed_out`. Only locks in the reserved range count.
+create or replace function committed_knowledge()
+returns bigint as $$
+declare
+  handed_out bigint;
+  in_flight bigint;
+begin
+  select last_value into handed_out from sync_knowledge_seq;
+  select min(((l.classid::bigint - 1163526144) << 32) | l.objid::bigint) into in_flight
+  from pg_locks l
+  where l.locktype = 'advisory' and l.objsubid = 2
+    and l.classid::bigint between 1163526144 and 1163526144 + 65535
+    and l.database = (select oid from pg_database where datname = current_database());
+  return least(handed_out, in_flight);
+end;
+$$ language plpgsql volatile;
+
+alter table budgets add column if not exists knowledge bigint not null default nextval('sync_knowledge_seq');
+alter table accounts add column if not exists knowledge bigint not null default nextval('sync_knowledge_seq');
+alter table supercategories add column if not exists knowledge bigint not null default nextval('sync_knowledge_seq');
+alter table categories add column if not exists knowledge bigint not null default nextval('sync_knowledge_seq');
+alter table transactions add column if not exists knowledge bigint not null default nextval('sync_knowledge_seq');
+alter table transaction_splits add column if not exists knowledge bigint not null default nextval('sync_knowledge_seq');
+alter table category_assignments add column if not exists knowledge bigint not null default nextval('sync_knowledge_seq');
+
+create trigger budgets_sync_knowledge before insert or update on budgets
+for each row execute function bump_sync_knowledge();
+create trigger accounts_sync_knowledge before insert or update on accounts
+for each row execute function bump_sync_knowledge();
+create trigger supercategories_sync_knowledge before insert or update on supercategories
+for each row execute function bump_sync_knowledge();
+create trigger categories_sync_knowledge before insert or update on categories
+for each row execute function bump_sync_knowledge();
+create trigger transactions_sync_knowledge before insert or update on transactions
+for each row execute function bump_sync_knowledge();
+create trigger transaction_splits_sync_knowledge before insert or update on transaction_splits
+for each row execute function bump_sync_knowledge();
+create trigger category_assignments_sync_knowledge before insert or update on category_assignments
+for each row execute function bump_sync_knowledge();
+
+create index if not exists budgets_user_knowledge_idx on budgets(user_id, knowledge);
+create index if not exists accounts_user_knowledge_idx on accounts(user_id, knowledge);
+create index if not exists supercategories_user_knowledge_idx on supercategories(user_id, knowledge);
+create index if not exists categories_user_knowledge_idx on categories(user_id, knowledge);
+create index if not exists transactions_user_knowledge_idx on transactions(user_id, knowledge);
+create index if not exists transaction_splits_knowledge_idx on transaction_splits(knowledge);
+create index if not exists category_assignments_user_knowledge_idx on category_assignments(user_id, knowledge);
@@ -0,0 +1,3 @@
+-- Reconciled transactions are locked against offline replays; see
+-- docs/offline-sync.md.
+alter table transactions add column if not exists reconciled_at timestamptz;
@@ -0,0 +1,53 @@
+-- Per-budget change feed. Handlers append a row and pg_notify in the same
+-- transaction; the id doubles as the SSE event id for Last-Event-ID resume.
+-- Ids are handed out like sync knowledge, so a stream can hold back events
+-- above a write still in flight (see `committed_knowledge()`) instead of
+-- skipping them once it has sent a higher id.
+create table if not exists change_events (
+  id bigint primary key,
+  budget_id uuid not null references budgets(id) on delete cascade,
+  budget_pillid text not null,
+  entity text not null,
+  entity_pillid text not null,
+  op text not null check (op in ('create', 'update', 'delete')),
+  created_at timestamptz not null default now()
+);
+
+create index if not exists change_events_budget_id_idx on change_events(budget_id, id);
+create index if not exists change_events_created_at_idx on change_events(created_at);
+
+create or replace function stamp_change_event()
+returns trigger as $$
+begin
+  perform hold_knowledge_floor();
+  new.id := nextval('sync_knowledge_seq');
+  return new;
+end;
+$$ language plpgsql;
+
+create trigger change_events_stamp
+before insert on change_events
+for each row execute function stamp_change_event();
+
+-- Events are purged after a retention period; a resume from before the
+-- highest purged id cannot be served.
+create table if not exists change_event_horizon (
+  purged_through bigint not null
+);
+insert into change_event_horizon (purged_through) values (0);
+
+-- `EventSource` cannot send an Authorization header, so a signed-in client
+-- trades its session for a short-lived ticket and opens the stream with
+-- `?ticket=`. A ticket dies with the session that minted it.
+create table if not exists event_tickets (
+  id uuid primary key default gen_random_uuid(),
+  pillid text unique not null default gen_pillid(),
+  session_id uuid not null references sessions(id) on delete cascade,
+  user_id uuid not null references users(id) on delete cascade,
+  user_pillid text not null,
+  ticket_hash text not null unique,
+  created_at timestamptz not null default now(),
+  expires_at timestamptz not null
+);
+
+create index if not exists event_tickets_user_id_idx on event_tickets(user_id);
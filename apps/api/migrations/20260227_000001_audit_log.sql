@@ -0,0 +1,66 @@
+-- Append-only history of every mutation made through the API, written in the
+-- same transaction as the change itself. Entries outlive what they describe:
+-- deleting a user or budget detaches its entries (the id becomes null, the
+-- pillid stays) instead of cascading, and the entries themselves can be
+-- neither changed nor deleted.
+--
+-- Undo/redo works on groups of entries written by one database transaction
+-- (`txid`). Undo and redo journal their own writes with `kind`/`reverts_txid`,
+-- which they set as transaction-local settings before calling record_change.
+create table if not exists audit_log (
+  id bigserial primary key,
+  pillid text unique not null default gen_pillid(),
+  user_id uuid references users(id) on delete set null,
+  user_pillid text not null,
+  budget_id uuid references budgets(id) on delete set null,
+  budget_pillid text not null,
+  entity text not null,
+  entity_pillid text not null,
+  op text not null check (op in ('create', 'update', 'delete')),
+  before jsonb,
+  after jsonb,
+  txid bigint not null default txid_current(),
+  kind text not null default coalesce(nullif(current_setting('envelopezero.audit_kind', true), ''), 'do'),
+  reverts_txid bigint default nullif(current_setting('envelopezero.reverts_txid', true), '')::bigint,
+  created_at timestamptz not null default now(),
+  constraint audit_log_kind_check check (kind in ('do', 'undo', 'redo'))
+);
+
+create index if not exists audit_log_budget_idx on audit_log(budget_id, id desc);
+create index if not exists audit_log_entity_idx on audit_log(entity_pillid, id desc);
+create index if not exists audit_log_budget_txid_idx on audit_log(budget_id, txid);
+create index if not exists audit_log_reverts_txid_idx on audit_log(reverts_txid) where reverts_txid is not null;
+
+create or replace function reject_audit_log_update()
+returns trigger as $$
+begin
+  -- The only change allowed is the one `on delete set null` makes.
+  if (new.user_id is not distinct from old.user_id
+      or (new.user_id is null and not exists (select 1 from users where id = old.user_id)))
+     and (new.budget_id is not distinct from old.budget_id
+      or (new.budget_id is null and not exists (select 1 from budgets where id = old.budget_id)))
+     and to_jsonb(new) - 'user_id' - 'budget_id' = to_jsonb(old) - 'user_id' - 'budget_id' then
+    return new;
+  end if;
+  raise exception 'audit_log is append-only';
+end;
+$$ language plpgsql;
+
+create or replace function reject_audit_log_delete()
+returns trigger as $$
+begin
+  raise exception 'audit_log is append-only';
+end;
+$$ language plpgsql;
+
+create trigger audit_log_append_only
+before update on audit_log
+for each row execute function reject_audit_log_update();
+
+create trigger audit_log_no_delete
+before delete on audit_log
+for each row execute function reject_audit_log_delete();
+
+create trigger audit_log_no_truncate
+before truncate on audit_log
+for each statement execute function reject_audit_log_delete();
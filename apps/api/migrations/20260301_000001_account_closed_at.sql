@@ -0,0 +1,3 @@
+-- Accounts with history are closed instead of deleted: they keep their
+-- transactions but take no new ones.
+alter table accounts add column if not exists closed_at timestamptz;
@@ -0,0 +1,137 @@
+-- Every row below a budget must agree with its parent on budget_id and user_id.
+-- Existing violations are repaired first (each step is reported with a
+-- notice), then enforced: composite foreign keys where the child carries both
+-- columns, constraint triggers for splits, which do not.
+
+do $$
+declare
+  n bigint;
+  repaired bigint := 0;
+  r record;
+  target uuid;
+  parent uuid;
+begin
+  -- The budget decides the owner.
+  update accounts a set user_id = b.user_id, user_pillid = b.user_pillid
+  from budgets b where b.id = a.budget_id and a.user_id <> b.user_id;
+  get diagnostics n = row_count;
+  if n > 0 then raise notice 'budget consistency: gave % account(s) their budget''s owner', n; end if;
+
+  update supercategories s set user_id = b.user_id, user_pillid = b.user_pillid
+  from budgets b where b.id = s.budget_id and s.user_id <> b.user_id;
+  get diagnostics n = row_count;
+  if n > 0 then raise notice 'budget consistency: gave % supercategory(ies) their budget''s owner', n; end if;
+
+  -- Categories follow their supercategory, transactions their account.
+  update categories c
+  set budget_id = s.budget_id, budget_pillid = s.budget_pillid, user_id = s.user_id, user_pillid = s.user_pillid
+  from supercategories s
+  where s.id = c.supercategory_id and (c.budget_id <> s.budget_id or c.user_id <> s.user_id);
+  get diagnostics n = row_count;
+  if n > 0 then raise notice 'budget consistency: moved % category(ies) into their supercategory''s budget', n; end if;
+
+  update transactions t
+  set budget_id = a.budget_id, budget_pillid = a.budget_pillid, user_id = a.user_id, user_pillid = a.user_pillid
+  from accounts a
+  where a.id = t.account_id and (t.budget_id <> a.budget_id or t.user_id <> a.user_id);
+  get diagnostics n = row_count;
+  if n > 0 then raise notice 'budget consistency: moved % transaction(s) into their account''s budget', n; end if;
+
+  -- A split pointing into another budget is moved to the same-named category
+  -- of its transaction's budget, created under "Repaired" if there is none.
+  for r in
+    select ts.id as split_id, t.budget_id, t.budget_pillid, t.user_id, t.user_pillid, c.name
+    from transaction_splits ts
+    join transactions t on t.id = ts.transaction_id
+    join categories c on c.id = ts.category_id
+    where ts.deleted_at is null and (c.budget_id <> t.budget_id or c.user_id <> t.user_id)
+  loop
+    select id into target from categories
+    where budget_id = r.budget_id and user_id = r.user_id and name = r.name and deleted_at is null
+    order by created_at limit 1;
+    if target is null then
+      select id into parent from supercategories
+      where budget_id = r.budget_id and user_id = r.user_id and name = 'Repaired' and deleted_at is null
+      limit 1;
+      if parent is null then
+        insert into supercategories (user_id, user_pillid, budget_id, budget_pillid, name)
+        values (r.user_id, r.user_pillid, r.budget_id, r.budget_pillid, 'Repaired')
+        returning id into parent;
+      end if;
+      insert into categories (user_id, user_pillid, budget_id, budget_pillid, supercategory_id, supercategory_pillid, name)
+      select r.user_id, r.user_pillid, r.budget_id, r.budget_pillid, s.id, s.pillid, r.name
+      from supercategories s where s.id = parent
+      returning id into target;
+    end if;
+    update transaction_splits ts set category_id = c.id, category_pillid = c.pillid, updated_at = now()
+    from categories c where c.id = target and ts.id = r.split_id;
+    repaired := repaired + 1;
+  end loop;
+  if repaired > 0 then raise notice 'budget consistency: recategorized % split(s) into their transaction''s budget', repaired; end if;
+
+  -- Assignments follow their category.
+  update category_assignments ca
+  set budget_id = c.budget_id, budget_pillid = c.budget_pillid, user_id = c.user_id, user_pillid = c.user_pillid
+  from categories c
+  where c.id = ca.category_id and (ca.budget_id <> c.budget_id or ca.user_id <> c.user_id);
+  get diagnostics n = row_count;
+  if n > 0 then raise notice 'budget consistency: moved % assignment(s) into their category''s budget', n; end if;
+end;
+$$;
+
+alter table budgets add constraint budgets_id_user_key unique (id, user_id);
+alter table accounts add constraint accounts_id_budget_user_key unique (id, budget_id, user_id);
+alter table supercategories add constraint supercategories_id_budget_user_key unique (id, budget_id, user_id);
+alter table categories add constraint categories_id_budget_user_key unique (id, budget_id, user_id);
+
+alter table accounts add constraint accounts_budget_user_fkey
+  foreign key (budget_id, user_id) references budgets(id, user_id) on delete cascade;
+alter table supercategories add constraint supercategories_budget_user_fkey
+  foreign key (budget_id, user_id) references budgets(id, user_id) on delete cascade;
+alter table categories add constraint categories_supercategory_budget_user_fkey
+  foreign key (supercategory_id, budget_id, user_id) references supercategories(id, budget_id, user_id) on delete cascade;
+alter table transactions add constraint transactions_account_budget_user_fkey
+  foreign key (account_id, budget_id, user_id) references accounts(id, budget_id, user_id) on delete cascade;
+alter table category_assignments add constraint category_assignments_category_budget_user_fkey
+  foreign key (category_id, budget_id, user_id) references categories(id, budget_id, user_id) on delete cascade;
+
+-- Splits only carry transaction_id and category_id, and superseded splits are
+-- kept around after a transaction moves budgets, so only active splits are
+-- checked.
+create or replace function enforce_split_budget_consistency()
+returns trigger as $$
+begin
+  if exists (
+    select 1
+    from transaction_splits ts
+    join transactions t on t.id = ts.transaction_id
+    join categories c on c.id = ts.category_id
+    where ts.deleted_at is null
+      and (c.budget_id <> t.budget_id or c.user_id <> t.user_id)
+      and case tg_table_name
+            when 'transaction_splits' then ts.id = new.id
+            when 'transactions' then t.id = new.id
+            else c.id = new.id
+          end
+  ) then
+    raise exception 'split category must be in the same budget as its transaction'
+      using errcode = 'foreign_key_violation';
+  end if;
+  return null;
+end;
+$$ language plpgsql;
+
+create constraint trigger split_budget_consistency_on_splits
+after insert or update of transaction_id, category_id, deleted_at on transaction_splits
+for each row execute function enforce_split_budget_consistency();
+
+create constraint trigger split_budget_consistency_on_categories
+after update of budget_id, user_id on categories
+for each row execute function enforce_split_budget_consistency();
+
+-- Deferred: moving a transaction to another budget replaces its splits after
+-- the transaction row itself is updated.
+create constraint trigger split_budget_consistency_on_transactions
+after update of budget_id, user_id on transactions
+deferrable initially deferred
+for each row execute function enforce_split_budget_consistency();
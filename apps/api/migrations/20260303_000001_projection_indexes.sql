@@ -0,0 +1,9 @@
+-- Month projections, the dashboard and reports aggregate a budget's live
+-- transactions by date and join their splits; none of these columns were
+-- indexed.
+create index if not exists transactions_budget_date_idx
+  on transactions(budget_id, tx_date) where deleted_at is null;
+create index if not exists transaction_splits_transaction_idx
+  on transaction_splits(transaction_id) where deleted_at is null;
+create index if not exists category_assignments_budget_month_idx
+  on category_assignments(budget_id, month) where deleted_at is null;
@@ -0,0 +1,86 @@
+-- A closed month keeps the numbers it had when it was closed: per-category
+-- assigned, activity and available, and Ready to Assign.
+create table if not exists month_closings (
+  id uuid primary key default gen_random_uuid(),
+  budget_id uuid not null references budgets(id) on delete cascade,
+  budget_pillid text not null,
+  month date not null check (month = date_trunc('month', month)::date),
+  ready_to_assign bigint not null,
+  closed_at timestamptz not null default now(),
+  unique (budget_id, month)
+);
+
+create table if not exists month_closing_categories (
+  closing_id uuid not null references month_closings(id) on delete cascade,
+  category_id uuid not null references categories(id) on delete cascade,
+  category_pillid text not null,
+  assigned bigint not null,
+  activity bigint not null,
+  available bigint not null,
+  primary key (closing_id, category_id)
+);
+
+-- Closing a month freezes it and every month before it. Writes take a key
+-- share lock on the budget row so they wait for a close in progress, which
+-- holds the row for update while it takes the snapshot.
+-- `envelopezero.closed_months = 'writable'` lets repairs (fsck) through.
+create or replace function ensure_month_open(budget uuid, day date)
+returns void as $$
+begin
+  if coalesce(current_setting('envelopezero.closed_months', true), '') = 'writable' then
+    return;
+  end if;
+  perform 1 from budgets where id = budget for key share;
+  if exists (
+    select 1 from month_closings
+    where budget_id = budget and month >= date_trunc('month', day)::date
+  ) then
+    raise exception 'month % is closed', to_char(day, 'YYYY-MM')
+      using errcode = 'EZ001';
+  end if;
+end;
+$$ language plpgsql;
+
+-- Deletes are not checked: rows only disappear for good when their budget,
+-- category or transaction is purged, which takes the snapshot with it.
+create or replace function enforce_open_month()
+returns trigger as $$
+declare
+  target record;
+begin
+  if tg_table_name = 'transactions' then
+    if tg_op = 'UPDATE' then
+      perform ensure_month_open(old.budget_id, old.tx_date);
+    end if;
+    perform ensure_month_open(new.budget_id, new.tx_date);
+  elsif tg_table_name = 'transaction_splits' then
+    if tg_op = 'UPDATE' then
+      select budget_id, tx_date into target from transactions where id = old.transaction_id;
+      perform ensure_month_open(target.budget_id, target.tx_date);
+    end if;
+    select budget_id, tx_date into target from transactions where id = new.transaction_id;
+    perform ensure_month_open(target.budget_id, target.tx_date);
+  else
+    if tg_op = 'UPDATE' then
+      perform ensure_month_open(old.budget_id, old.month);
+    end if;
+    perform ensure_month_open(new.budget_id, new.month);
+  end if;
+  return new;
+end;
+$$ language plpgsql;
+
+drop trigger if exists transactions_open_month on transactions;
+create trigger transactions_open_month
+before insert or update of budget_id, account_id, tx_date, payee, memo, deleted_at on transactions
+for each row execute function enforce_open_month();
+
+drop trigger if exists transaction_splits_open_month on transaction_splits;
+create trigger transaction_splits_open_month
+before insert or update of transaction_id, category_id, memo, inflow, outflow, deleted_at on transaction_splits
+for each row execute function enforce_open_month();
+
+drop trigger if exists category_assignments_open_month on category_assignments;
+create trigger category_assignments_open_month
+before insert or update of budget_id, category_id, month, amount, deleted_at on category_assignments
+for each row execute function enforce_open_month();
@@ -0,0 +1,45 @@
+-- An account may hold another currency than its budget. Accounts created
+-- without one (including every existing account) take the budget's.
+alter table accounts add column if not exists currency_code text;
+update accounts a set currency_code = b.currency_code
+from budgets b
+where b.id = a.budget_id and a.currency_code is null;
+alter table accounts alter column currency_code set not null;
+
+create or replace function default_account_currency()
+returns trigger as $$
+begin
+  if new.currency_code is null then
+    select currency_code into new.currency_code from budgets where id = new.budget_id;
+  end if;
+  return new;
+end;
+$$ language plpgsql;
+
+drop trigger if exists accounts_default_currency on accounts;
+create trigger accounts_default_currency
+before insert on accounts
+for each row execute function default_account_currency();
+
+-- `inflow` and `outflow` stay in the budget's currency, so everything budgeted
+-- adds up; in a foreign-currency account these hold what was actually booked.
+alter table transaction_splits
+  add column if not exists account_inflow bigint,
+  add column if not exists account_outflow bigint;
+alter table transaction_splits drop constraint if exists transaction_splits_account_amounts;
+-- Both or neither: a null comparison would pass a check on its own.
+alter table transaction_splits add constraint transaction_splits_account_amounts check (
+  (account_inflow is null) = (account_outflow is null)
+  and coalesce(account_inflow, 0) >= 0
+  and coalesce(account_outflow, 0) >= 0
+);
+
+-- One `base` is worth `rate` of `quote` on `rate_date`. Loaded from CSV by
+-- the `load-rates` admin command; the latest rate on or before a day applies.
+create table if not exists exchange_rates (
+  base text not null,
+  quote text not null,
+  rate_date date not null,
+  rate numeric not null check (rate > 0),
+  primary key (base, quote, rate_date)
+);
@@ -0,0 +1,3 @@
+-- Transfers between accounts are booked to a category whose legs cancel out.
+-- Reports, the forecast and the dashboard leave such categories out.
+alter table categories add column if not exists is_transfer boolean not null default false;
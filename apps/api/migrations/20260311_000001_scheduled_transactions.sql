@@ -0,0 +1,63 @@
+-- Upcoming money movements the forecast projects: a scheduled transaction
+-- lands on `next_date` and then every `frequency` until `end_date`. Amounts are
+-- in the budget's currency, like split `inflow`/`outflow`. Deletes are soft,
+-- like every other synced row, so they reach sync clients and can be undone.
+create table if not exists scheduled_transactions (
+  id uuid primary key default gen_random_uuid(),
+  pillid text unique not null default gen_pillid(),
+  user_id uuid not null references users(id) on delete cascade,
+  user_pillid text not null,
+  budget_id uuid not null references budgets(id) on delete cascade,
+  budget_pillid text not null,
+  account_id uuid not null,
+  account_pillid text not null,
+  payee text,
+  memo text,
+  inflow bigint not null default 0,
+  outflow bigint not null default 0,
+  frequency text not null check (frequency in ('once', 'weekly', 'every_other_week', 'monthly', 'yearly')),
+  next_date date not null,
+  end_date date,
+  created_at timestamptz not null default now(),
+  updated_at timestamptz not null default now(),
+  deleted_at timestamptz,
+  knowledge bigint not null default nextval('sync_knowledge_seq'),
+  foreign key (account_id, budget_id, user_id) references accounts(id, budget_id, user_id) on delete cascade,
+  check ((inflow = 0 and outflow > 0) or (outflow = 0 and inflow > 0)),
+  check (end_date is null or end_date >= next_date)
+);
+
+create index if not exists scheduled_transactions_budget_idx
+  on scheduled_transactions(budget_id, next_date);
+create index if not exists scheduled_transactions_user_knowledge_idx
+  on scheduled_transactions(user_id, knowledge);
+
+create trigger scheduled_transactions_sync_knowledge before insert or update on scheduled_transactions
+for each row execute function bump_sync_knowledge();
+
+-- A category's goal: how much it is expected to spend each month. The
+-- forecast charges it instead of the category's average spending. A category
+-- has at most one target row; clearing the target soft-deletes it and setting
+-- it again brings the same row back.
+create table if not exists category_targets (
+  id uuid primary key default gen_random_uuid(),
+  pillid text unique not null default gen_pillid(),
+  user_id uuid not null references users(id) on delete cascade,
+  user_pillid text not null,
+  budget_id uuid not null references budgets(id) on delete cascade,
+  budget_pillid text not null,
+  category_id uuid not null unique,
+  category_pillid text not null,
+  monthly_amount bigint not null check (monthly_amount > 0),
+  created_at timestamptz not null default now(),
+  updated_at timestamptz not null default now(),
+  deleted_at timestamptz,
+  knowledge bigint not null default nextval('sync_knowledge_seq'),
+  foreign key (category_id, budget_id, user_id) references categories(id, budget_id, user_id) on delete cascade
+);
+
+create index if not exists category_targets_user_knowledge_idx
+  on category_targets(user_id, knowledge);
+
+create trigger category_targets_sync_knowledge before insert or update on category_targets
+for each row execute function bump_sync_knowledge();
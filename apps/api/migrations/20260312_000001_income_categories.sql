@@ -0,0 +1,18 @@
+-- Income categories hold what there is to assign: the dashboard's Ready to
+-- Assign, the income vs expense report and the journal export's `Income:`
+-- accounts read this flag.
+alter table categories add column if not exists is_income boolean not null default false;
+
+-- Until now a category counted as income when it had taken in more than it
+-- paid out; mark those so existing budgets keep their numbers.
+update categories c set is_income = true
+where not c.is_transfer
+  and (
+    select coalesce(sum(ts.inflow - ts.outflow), 0)
+    from transaction_splits ts
+    join transactions t on t.id = ts.transaction_id and t.deleted_at is null
+    where ts.category_id = c.id and ts.deleted_at is null
+  ) > 0;
+
+alter table categories add constraint categories_income_or_transfer
+  check (not (is_income and is_transfer));
@@ -0,0 +1,97 @@
+//! Operations behind the admin subcommands of the binary.
+
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::archive::export_budget;
+use crate::archive::import_budget;
+use crate::archive::BudgetArchive;
+use crate::find_or_create_user;
+use crate::insert_magic_link_token;
+use crate::magic_link_url;
+
+fn normalize_email(email: &str) -> anyhow::Result<String> {
+    let email = email.trim().to_lowercase();
+    anyhow::ensure!(email.contains('@'), "not an email address: {email:?}");
+    Ok(email)
+}
+
+/// Creates the user for `email` (as a first magic-link sign-in would) and
+/// returns their pillid.
+pub async fn create_user(db: &PgPool, email: &str) -> anyhow::Result<String> {
+    let email = normalize_email(email)?;
+    let mut tx = db.begin().await?;
+    let user_id = find_or_create_user(&mut tx, &email).await?;
+    let (pillid,): (String,) = sqlx::query_as("select pillid from users where id = $1")
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(pillid)
+}
+
+/// A sign-in link for `email`, without sending any mail.
+pub async fn issue_login_link(
+    db: &PgPool,
+    app_origin: &str,
+    email: &str,
+) -> anyhow::Result<String> {
+    let email = normalize_email(email)?;
+    let token = insert_magic_link_token(db, &email).await?;
+    Ok(magic_link_url(app_origin, &token))
+}
+
+/// Revokes every active session of `email`'s user; returns how many.
+pub async fn revoke_sessions(db: &PgPool, email: &str) -> anyhow::Result<u64> {
+    let email = normalize_email(email)?;
+    let user_id = user_by_email(db, &email).await?;
+    let revoked = sqlx::query(
+        "update sessions set revoked_at = now() where user_id = $1 and revoked_at is null and expires_at > now()",
+    )
+    .bind(user_id)
+    .execute(db)
+    .await?
+    .rows_affected();
+    Ok(revoked)
+}
+
+pub async fn export_budget_archive(
+    db: &PgPool,
+    budget_pillid: &str,
+) -> anyhow::Result<BudgetArchive> {
+    let mut tx = db.begin().await?;
+    // One consistent snapshot, like the HTTP export.
+    sqlx::query("set transaction isolation level repeatable read, read only")
+        .execute(&mut *tx)
+        .await?;
+    let archive = export_budget(&mut tx, budget_pillid)
+        .await?
+        .with_context(|| format!("no budget {budget_pillid}"))?;
+    tx.commit().await?;
+    Ok(archive)
+}
+
+/// Imports an archive as a new budget of `email`'s user, creating the user if
+/// needed; returns the new budget's pillid.
+pub async fn import_budget_archive(
+    db: &PgPool,
+    email: &str,
+    archive: &BudgetArchive,
+) -> anyhow::Result<String> {
+    let email = normalize_email(email)?;
+    let mut tx = db.begin().await?;
+    let user_id = find_or_create_user(&mut tx, &email).await?;
+    let budget_pillid = import_budget(&mut tx, user_id, archive, true).await?;
+    tx.commit().await?;
+    Ok(budget_pillid)
+}
+
+async fn user_by_email(db: &PgPool, email: &str) -> anyhow::Result<Uuid> {
+    let row: Option<(Uuid,)> = sqlx::query_as("select user_id from user_emails where email = $1")
+        .bind(email)
+        .fetch_optional(db)
+        .await?;
+    row.map(|r| r.0)
+        .with_context(|| format!("no user with email {email}"))
+}
@@ -0,0 +1,852 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::DateTime;
+use chrono::NaiveDate;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::FromRow;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::changes::record_change;
+use crate::changes::ChangeEntity;
+use crate::changes::ChangeOp;
+use crate::ensure_budget_slot;
+use crate::models::new_pillid;
+use crate::money::Currency;
+use crate::user_from_headers;
+use crate::AppState;
+
+pub const ARCHIVE_FORMAT: &str = "envelopezero-budget";
+pub const ARCHIVE_VERSION: u32 = 2;
+/// Version 1 archives predate schedules and targets; they still import.
+const OLDEST_READABLE_VERSION: u32 = 1;
+
+/// A whole budget as a portable JSON document. Rows are keyed by pillid and
+/// refer to each other by pillid; internal ids never leave the database.
+/// Only live rows are included, not the trash.
+#[derive(Serialize, Deserialize)]
+pub struct BudgetArchive {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub budget: ArchivedBudget,
+    pub accounts: Vec<ArchivedAccount>,
+    pub supercategories: Vec<ArchivedSupercategory>,
+    pub categories: Vec<ArchivedCategory>,
+    pub transactions: Vec<ArchivedTransaction>,
+    pub category_assignments: Vec<ArchivedAssignment>,
+    #[serde(default)]
+    pub scheduled_transactions: Vec<ArchivedScheduledTransaction>,
+    #[serde(default)]
+    pub category_targets: Vec<ArchivedTarget>,
+}
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct ArchivedBudget {
+    pub id: String,
+    pub name: String,
+    pub currency_code: String,
+}
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct ArchivedAccount {
+    pub id: String,
+    pub name: String,
+    /// Only when it is not the budget's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency_code: Option<String>,
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct ArchivedSupercategory {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct ArchivedCategory {
+    pub id: String,
+    pub supercategory_id: String,
+    pub name: String,
+    /// Only when set.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_transfer: bool,
+    /// Only when set.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_income: bool,
+}
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct ArchivedTransaction {
+    pub id: String,
+    pub account_id: String,
+    pub date: NaiveDate,
+    pub payee: Option<String>,
+    pub memo: Option<String>,
+    pub reconciled_at: Option<DateTime<Utc>>,
+    #[sqlx(skip)]
+    pub splits: Vec<ArchivedSplit>,
+}
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct ArchivedSplit {
+    pub id: String,
+    #[serde(skip)]
+    pub(crate) transaction_id: String,
+    pub category_id: String,
+    pub memo: Option<String>,
+    pub inflow: i64,
+    pub outflow: i64,
+    /// What was booked, in a foreign-currency account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_inflow: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_outflow: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct ArchivedAssignment {
+    pub id: String,
+    pub category_id: String,
+    /// `YYYY-MM`, as in the assignments API.
+    pub month: String,
+    pub amount: i64,
+}
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct ArchivedScheduledTransaction {
+    pub id: String,
+    pub account_id: String,
+    pub payee: Option<String>,
+    pub memo: Option<String>,
+    pub inflow: i64,
+    pub outflow: i64,
+    /// As in the scheduled transactions API (`monthly`, ...).
+    pub frequency: String,
+    pub next_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+}
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct ArchivedTarget {
+    pub id: String,
+    pub category_id: String,
+    pub monthly_amount: i64,
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    /// Not an archive this version can read.
+    Unsupported(String),
+    /// A row refers to a pillid the archive does not contain.
+    DanglingReference(String),
+    Database(sqlx::Error),
+    /// The import could not be written to the audit log.
+    Journal,
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Unsupported(what) => write!(f, "unsupported archive: {what}"),
+            ArchiveError::DanglingReference(id) => write!(f, "archive refers to missing id {id}"),
+            ArchiveError::Database(err) => write!(f, "{err}"),
+            ArchiveError::Journal => write!(f, "failed to journal the import"),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<sqlx::Error> for ArchiveError {
+    fn from(err: sqlx::Error) -> Self {
+        ArchiveError::Database(err)
+    }
+}
+
+/// Reads a live budget into an archive, or `None` if there is no such budget.
+pub async fn export_budget(
+    conn: &mut PgConnection,
+    budget_pillid: &str,
+) -> Result<Option<BudgetArchive>, sqlx::Error> {
+    let Some((budget_id,)): Option<(Uuid,)> =
+        sqlx::query_as("select id from budgets where pillid = $1 and deleted_at is null")
+            .bind(budget_pillid)
+            .fetch_optional(&mut *conn)
+            .await?
+    else {
+        return Ok(None);
+    };
+
+    let budget = sqlx::query_as::<_, ArchivedBudget>(
+        "select pillid as id, name, currency_code from budgets where id = $1",
+    )
+    .bind(budget_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    let accounts = sqlx::query_as::<_, ArchivedAccount>(
+        "select a.pillid as id, a.name, nullif(a.currency_code, b.currency_code) as currency_code, a.closed_at
+         from accounts a join budgets b on b.id = a.budget_id
+         where a.budget_id = $1 and a.deleted_at is null order by a.created_at",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    let supercategories = sqlx::query_as::<_, ArchivedSupercategory>(
+        "select pillid as id, name from supercategories
+         where budget_id = $1 and deleted_at is null order by created_at",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    let categories = sqlx::query_as::<_, ArchivedCategory>(
+        "select pillid as id, supercategory_pillid as supercategory_id, name, is_transfer, is_income from categories
+         where budget_id = $1 and deleted_at is null order by created_at",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut transactions = sqlx::query_as::<_, ArchivedTransaction>(
+        "select pillid as id, account_pillid as account_id, tx_date as date, payee, memo, reconciled_at
+         from transactions
+         where budget_id = $1 and deleted_at is null order by tx_date, created_at",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    let splits = sqlx::query_as::<_, ArchivedSplit>(
+        "select ts.pillid as id, ts.transaction_pillid as transaction_id, ts.category_pillid as category_id, ts.memo, ts.inflow, ts.outflow,
+                ts.account_inflow, ts.account_outflow
+         from transaction_splits ts
+         join transactions t on t.id = ts.transaction_id
+         where t.budget_id = $1 and t.deleted_at is null and ts.deleted_at is null
+         order by ts.created_at",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    let category_assignments = sqlx::query_as::<_, ArchivedAssignment>(
+        "select pillid as id, category_pillid as category_id, to_char(month, 'YYYY-MM') as month, amount
+         from category_assignments
+         where budget_id = $1 and deleted_at is null order by month, created_at",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    let scheduled_transactions = sqlx::query_as::<_, ArchivedScheduledTransaction>(
+        "select st.pillid as id, st.account_pillid as account_id, st.payee, st.memo, st.inflow, st.outflow,
+                st.frequency, st.next_date, st.end_date
+         from scheduled_transactions st
+         join accounts a on a.id = st.account_id and a.deleted_at is null
+         where st.budget_id = $1 and st.deleted_at is null order by st.next_date, st.created_at",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    let category_targets = sqlx::query_as::<_, ArchivedTarget>(
+        "select ct.pillid as id, ct.category_pillid as category_id, ct.monthly_amount
+         from category_targets ct
+         join categories c on c.id = ct.category_id and c.deleted_at is null
+         where ct.budget_id = $1 and ct.deleted_at is null order by c.created_at",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut by_transaction: HashMap<String, Vec<ArchivedSplit>> = HashMap::new();
+    for split in splits {
+        by_transaction
+            .entry(split.transaction_id.clone())
+            .or_default()
+            .push(split);
+    }
+    for transaction in &mut transactions {
+        transaction.splits = by_transaction.remove(&transaction.id).unwrap_or_default();
+    }
+
+    Ok(Some(BudgetArchive {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now(),
+        budget,
+        accounts,
+        supercategories,
+        categories,
+        transactions,
+        category_assignments,
+        scheduled_transactions,
+        category_targets,
+    }))
+}
+
+impl BudgetArchive {
+    /// Strips what identifies the owner, for sharing a budget as a test
+    /// fixture: names become `Account 1`, `Category 1`, ..., each distinct
+    /// payee becomes `Payee N`, memos are dropped and every pillid is replaced
+    /// (consistently, so references still resolve). Dates and amounts stay.
+    pub fn anonymize(&mut self) {
+        let mut pillids: HashMap<String, String> = HashMap::new();
+        let mut fresh = |pillid: &mut String| {
+            *pillid = pillids
+                .entry(pillid.clone())
+                .or_insert_with(new_pillid)
+                .clone();
+        };
+
+        fresh(&mut self.budget.id);
+        self.budget.name = "Budget".to_string();
+        for (n, account) in self.accounts.iter_mut().enumerate() {
+            fresh(&mut account.id);
+            account.name = format!("Account {}", n + 1);
+        }
+        for (n, supercategory) in self.supercategories.iter_mut().enumerate() {
+            fresh(&mut supercategory.id);
+            supercategory.name = format!("Group {}", n + 1);
+        }
+        for (n, category) in self.categories.iter_mut().enumerate() {
+            fresh(&mut category.id);
+            fresh(&mut category.supercategory_id);
+            category.name = format!("Category {}", n + 1);
+        }
+        let mut payees: HashMap<String, String> = HashMap::new();
+        let mut rename = |payee: &mut Option<String>| {
+            if let Some(payee) = payee {
+                let next = payees.len() + 1;
+                *payee = payees
+                    .entry(payee.clone())
+                    .or_insert_with(|| format!("Payee {next}"))
+                    .clone();
+            }
+        };
+        for transaction in &mut self.transactions {
+            fresh(&mut transaction.id);
+            fresh(&mut transaction.account_id);
+            rename(&mut transaction.payee);
+            transaction.memo = None;
+            for split in &mut transaction.splits {
+                fresh(&mut split.id);
+                fresh(&mut split.category_id);
+                split.memo = None;
+            }
+        }
+        for assignment in &mut self.category_assignments {
+            fresh(&mut assignment.id);
+            fresh(&mut assignment.category_id);
+        }
+        for schedule in &mut self.scheduled_transactions {
+            fresh(&mut schedule.id);
+            fresh(&mut schedule.account_id);
+            rename(&mut schedule.payee);
+            schedule.memo = None;
+        }
+        for target in &mut self.category_targets {
+            fresh(&mut target.id);
+            fresh(&mut target.category_id);
+        }
+    }
+}
+
+/// Internal id and pillid each archived pillid was imported as.
+type IdMap = HashMap<String, (Uuid, String)>;
+
+fn resolve<'a>(ids: &'a IdMap, pillid: &str) -> Result<&'a (Uuid, String), ArchiveError> {
+    ids.get(pillid)
+        .ok_or_else(|| ArchiveError::DanglingReference(pillid.to_string()))
+}
+
+/// Recreates an archived budget as a new budget of `user_id` and returns its
+/// pillid.
+///
+/// With `reuse_ids`, pillids are kept when none of them exist in this database
+/// yet (moving between instances); otherwise every row gets a fresh one
+/// (importing a copy next to the original). Run inside a transaction: the
+/// split invariant is only checked at commit.
+pub async fn import_budget(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    archive: &BudgetArchive,
+    reuse_ids: bool,
+) -> Result<String, ArchiveError> {
+    if archive.format != ARCHIVE_FORMAT {
+        return Err(ArchiveError::Unsupported(format!(
+            "format {:?}",
+            archive.format
+        )));
+    }
+    if !(OLDEST_READABLE_VERSION..=ARCHIVE_VERSION).contains(&archive.version) {
+        return Err(ArchiveError::Unsupported(format!(
+            "version {}",
+            archive.version
+        )));
+    }
+    let currencies = archive
+        .accounts
+        .iter()
+        .filter_map(|account| account.currency_code.as_ref());
+    for code in std::iter::once(&archive.budget.currency_code).chain(currencies) {
+        if Currency::parse(code).is_none() {
+            return Err(ArchiveError::Unsupported(format!("currency {code:?}")));
+        }
+    }
+
+    let keep_ids = reuse_ids && !pillids_taken(conn, archive).await?;
+    let keep = |pillid: &str| keep_ids.then(|| pillid.to_string());
+
+    let (budget_id, budget_pillid): (Uuid, String) = sqlx::query_as(
+        "insert into budgets (pillid, user_id, user_pillid, name, currency_code)
+         select coalesce($2, gen_pillid()), u.id, u.pillid, $3, $4 from users u where u.id = $1
+         returning id, pillid",
+    )
+    .bind(user_id)
+    .bind(keep(&archive.budget.id))
+    .bind(&archive.budget.name)
+    .bind(&archive.budget.currency_code)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let mut supercategories = IdMap::new();
+    for supercategory in &archive.supercategories {
+        let row: (Uuid, String) = sqlx::query_as(
+            "insert into supercategories (pillid, user_id, user_pillid, budget_id, budget_pillid, name)
+             select coalesce($2, gen_pillid()), b.user_id, b.user_pillid, b.id, b.pillid, $3 from budgets b where b.id = $1
+             returning id, pillid",
+        )
+        .bind(budget_id)
+        .bind(keep(&supercategory.id))
+        .bind(&supercategory.name)
+        .fetch_one(&mut *conn)
+        .await?;
+        supercategories.insert(supercategory.id.clone(), row);
+    }
+
+    let mut categories = IdMap::new();
+    for category in &archive.categories {
+        let (supercategory_id, supercategory_pillid) =
+            resolve(&supercategories, &category.supercategory_id)?;
+        let row: (Uuid, String) = sqlx::query_as(
+            "insert into categories (pillid, user_id, user_pillid, budget_id, budget_pillid, supercategory_id, supercategory_pillid, name, is_transfer, is_income)
+             select coalesce($2, gen_pillid()), b.user_id, b.user_pillid, b.id, b.pillid, $3, $4, $5, $6, $7 from budgets b where b.id = $1
+             returning id, pillid",
+        )
+        .bind(budget_id)
+        .bind(keep(&category.id))
+        .bind(supercategory_id)
+        .bind(supercategory_pillid)
+        .bind(&category.name)
+        .bind(category.is_transfer)
+        .bind(category.is_income)
+        .fetch_one(&mut *conn)
+        .await?;
+        categories.insert(category.id.clone(), row);
+    }
+
+    let mut accounts = IdMap::new();
+    for account in &archive.accounts {
+        let row: (Uuid, String) = sqlx::query_as(
+            "insert into accounts (pillid, user_id, user_pillid, budget_id, budget_pillid, name, closed_at, currency_code)
+             select coalesce($2, gen_pillid()), b.user_id, b.user_pillid, b.id, b.pillid, $3, $4, $5 from budgets b where b.id = $1
+             returning id, pillid",
+        )
+        .bind(budget_id)
+        .bind(keep(&account.id))
+        .bind(&account.name)
+        .bind(account.closed_at)
+        .bind(&account.currency_code)
+        .fetch_one(&mut *conn)
+        .await?;
+        accounts.insert(account.id.clone(), row);
+    }
+
+    for transaction in &archive.transactions {
+        let (account_id, account_pillid) = resolve(&accounts, &transaction.account_id)?;
+        let (transaction_id, transaction_pillid): (Uuid, String) = sqlx::query_as(
+            "insert into transactions (pillid, user_id, user_pillid, budget_id, budget_pillid, account_id, account_pillid, tx_date, payee, memo, reconciled_at)
+             select coalesce($2, gen_pillid()), b.user_id, b.user_pillid, b.id, b.pillid, $3, $4, $5, $6, $7, $8 from budgets b where b.id = $1
+             returning id, pillid",
+        )
+        .bind(budget_id)
+        .bind(keep(&transaction.id))
+        .bind(account_id)
+        .bind(account_pillid)
+        .bind(transaction.date)
+        .bind(&transaction.payee)
+        .bind(&transaction.memo)
+        .bind(transaction.reconciled_at)
+        .fetch_one(&mut *conn)
+        .await?;
+        for split in &transaction.splits {
+            let (category_id, category_pillid) = resolve(&categories, &split.category_id)?;
+            sqlx::query(
+                "insert into transaction_splits (pillid, transaction_id, transaction_pillid, category_id, category_pillid, memo, inflow, outflow, account_inflow, account_outflow)
+                 values (coalesce($1, gen_pillid()), $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            )
+            .bind(keep(&split.id))
+            .bind(transaction_id)
+            .bind(&transaction_pillid)
+            .bind(category_id)
+            .bind(category_pillid)
+            .bind(&split.memo)
+            .bind(split.inflow)
+            .bind(split.outflow)
+            .bind(split.account_inflow)
+            .bind(split.account_outflow)
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    for assignment in &archive.category_assignments {
+        let (category_id, category_pillid) = resolve(&categories, &assignment.category_id)?;
+        let month = NaiveDate::parse_from_str(&format!("{}-01", assignment.month), "%Y-%m-%d")
+            .map_err(|_| ArchiveError::Unsupported(format!("month {:?}", assignment.month)))?;
+        sqlx::query(
+            "insert into category_assignments (pillid, user_id, user_pillid, budget_id, budget_pillid, category_id, category_pillid, month, amount)
+             select coalesce($2, gen_pillid()), b.user_id, b.user_pillid, b.id, b.pillid, $3, $4, $5, $6 from budgets b where b.id = $1",
+        )
+        .bind(budget_id)
+        .bind(keep(&assignment.id))
+        .bind(category_id)
+        .bind(category_pillid)
+        .bind(month)
+        .bind(assignment.amount)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    for schedule in &archive.scheduled_transactions {
+        let (account_id, account_pillid) = resolve(&accounts, &schedule.account_id)?;
+        sqlx::query(
+            "insert into scheduled_transactions (pillid, user_id, user_pillid, budget_id, budget_pillid, account_id, account_pillid, payee, memo, inflow, outflow, frequency, next_date, end_date)
+             select coalesce($2, gen_pillid()), b.user_id, b.user_pillid, b.id, b.pillid, $3, $4, $5, $6, $7, $8, $9, $10, $11 from budgets b where b.id = $1",
+        )
+        .bind(budget_id)
+        .bind(keep(&schedule.id))
+        .bind(account_id)
+        .bind(account_pillid)
+        .bind(&schedule.payee)
+        .bind(&schedule.memo)
+        .bind(schedule.inflow)
+        .bind(schedule.outflow)
+        .bind(&schedule.frequency)
+        .bind(schedule.next_date)
+        .bind(schedule.end_date)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    for target in &archive.category_targets {
+        let (category_id, category_pillid) = resolve(&categories, &target.category_id)?;
+        sqlx::query(
+            "insert into category_targets (pillid, user_id, user_pillid, budget_id, budget_pillid, category_id, category_pillid, monthly_amount)
+             select coalesce($2, gen_pillid()), b.user_id, b.user_pillid, b.id, b.pillid, $3, $4, $5 from budgets b where b.id = $1",
+        )
+        .bind(budget_id)
+        .bind(keep(&target.id))
+        .bind(category_id)
+        .bind(category_pillid)
+        .bind(target.monthly_amount)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    // One journal entry for the new budget: undoing the import deletes it.
+    record_change(
+        conn,
+        user_id,
+        &budget_pillid,
+        ChangeEntity::Budget,
+        &budget_pillid,
+        ChangeOp::Create,
+        None,
+    )
+    .await
+    .map_err(|_| ArchiveError::Journal)?;
+
+    Ok(budget_pillid)
+}
+
+/// Whether any pillid in the archive is already used in this database.
+async fn pillids_taken(
+    conn: &mut PgConnection,
+    archive: &BudgetArchive,
+) -> Result<bool, sqlx::Error> {
+    let splits = archive
+        .transactions
+        .iter()
+        .flat_map(|t| t.splits.iter().map(|s| s.id.clone()))
+        .collect::<Vec<_>>();
+    let groups: [(&str, Vec<String>); 9] = [
+        ("budgets", vec![archive.budget.id.clone()]),
+        (
+            "accounts",
+            archive.accounts.iter().map(|a| a.id.clone()).collect(),
+        ),
+        (
+            "supercategories",
+            archive
+                .supercategories
+                .iter()
+                .map(|s| s.id.clone())
+                .collect(),
+        ),
+        (
+            "categories",
+            archive.categories.iter().map(|c| c.id.clone()).collect(),
+        ),
+        (
+            "transactions",
+            archive.transactions.iter().map(|t| t.id.clone()).collect(),
+        ),
+        ("transaction_splits", splits),
+        (
+            "category_assignments",
+            archive
+                .category_assignments
+                .iter()
+                .map(|a| a.id.clone())
+                .collect(),
+        ),
+        (
+            "scheduled_transactions",
+            archive
+                .scheduled_transactions
+                .iter()
+                .map(|s| s.id.clone())
+                .collect(),
+        ),
+        (
+            "category_targets",
+            archive
+                .category_targets
+                .iter()
+                .map(|t| t.id.clone())
+                .collect(),
+        ),
+    ];
+    for (table, pillids) in groups {
+        let sql = format!("select exists (select 1 from {table} where pillid = any($1))");
+        let (taken,): (bool,) = sqlx::query_as(&sql)
+            .bind(&pillids)
+            .fetch_one(&mut *conn)
+            .await?;
+        if taken {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ExportQuery {
+    anonymize: Option<bool>,
+}
+
+/// The whole budget as a [`BudgetArchive`]; `?anonymize=true` for a shareable
+/// fixture. Only the budget's owner can export it.
+pub(crate) async fn export(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Json<BudgetArchive>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Repeatable read so the archive is one consistent snapshot.
+    sqlx::query("set transaction isolation level repeatable read, read only")
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let owned: Option<(Uuid,)> = sqlx::query_as(
+        "select id from budgets where pillid = $1 and user_id = $2 and deleted_at is null",
+    )
+    .bind(&budget_pillid)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if owned.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let mut archive = export_budget(&mut tx, &budget_pillid)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if query.anonymize.unwrap_or(false) {
+        archive.anonymize();
+    }
+    Ok(Json(archive))
+}
+
+#[derive(Serialize)]
+pub(crate) struct ImportResponse {
+    budget_id: String,
+}
+
+/// Recreates an archive as a new budget of the caller, always with fresh
+/// pillids: whether an archived id is taken would tell callers about other
+/// users' rows. Without multi-budget it takes the one slot next to the default
+/// budget, like a created budget.
+pub(crate) async fn import(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(archive): Json<BudgetArchive>,
+) -> Result<Json<ImportResponse>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    ensure_budget_slot(&mut tx, &state, user_id).await?;
+    let budget_id = import_budget(&mut tx, user_id, &archive, false)
+        .await
+        .map_err(import_status)?;
+    tx.commit()
+        .await
+        .map_err(|err| import_status(ArchiveError::Database(err)))?;
+    Ok(Json(ImportResponse { budget_id }))
+}
+
+/// Archives that do not fit the schema (bad references, negative amounts,
+/// duplicate ids) are the client's problem.
+fn import_status(err: ArchiveError) -> StatusCode {
+    match err {
+        ArchiveError::Unsupported(_)
+        | ArchiveError::DanglingReference(_)
+        | ArchiveError::Database(sqlx::Error::Database(_)) => StatusCode::BAD_REQUEST,
+        ArchiveError::Database(_) | ArchiveError::Journal => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn sample() -> BudgetArchive {
+        let split = |id: &str, category: &str| ArchivedSplit {
+            id: id.into(),
+            transaction_id: String::new(),
+            category_id: category.into(),
+            memo: Some("secret".into()),
+            inflow: 0,
+            outflow: 500,
+            account_inflow: None,
+            account_outflow: None,
+        };
+        BudgetArchive {
+            format: ARCHIVE_FORMAT.into(),
+            version: ARCHIVE_VERSION,
+            exported_at: Utc::now(),
+            budget: ArchivedBudget {
+                id: "b".into(),
+                name: "Family".into(),
+                currency_code: "EUR".into(),
+            },
+            accounts: vec![ArchivedAccount {
+                id: "a".into(),
+                name: "Checking at Big Bank".into(),
+                currency_code: None,
+                closed_at: None,
+            }],
+            supercategories: vec![ArchivedSupercategory {
+                id: "s".into(),
+                name: "Bills".into(),
+            }],
+            categories: vec![ArchivedCategory {
+                id: "c".into(),
+                supercategory_id: "s".into(),
+                name: "Rent".into(),
+                is_transfer: false,
+                is_income: false,
+            }],
+            transactions: ["t1", "t2", "t3"]
+                .iter()
+                .zip(["Landlord", "Grocer", "Landlord"])
+                .map(|(id, payee)| ArchivedTransaction {
+                    id: id.to_string(),
+                    account_id: "a".into(),
+                    date: NaiveDate::from_ymd_opt(2026, 1, 1).unwrap(),
+                    payee: Some(payee.into()),
+                    memo: Some("secret".into()),
+                    reconciled_at: None,
+                    splits: vec![split(&format!("{id}-split"), "c")],
+                })
+                .collect(),
+            category_assignments: vec![ArchivedAssignment {
+                id: "ca".into(),
+                category_id: "c".into(),
+                month: "2026-01".into(),
+                amount: 1500,
+            }],
+            scheduled_transactions: vec![ArchivedScheduledTransaction {
+                id: "st".into(),
+                account_id: "a".into(),
+                payee: Some("Grocer".into()),
+                memo: Some("secret".into()),
+                inflow: 0,
+                outflow: 900,
+                frequency: "weekly".into(),
+                next_date: NaiveDate::from_ymd_opt(2026, 2, 1).unwrap(),
+                end_date: None,
+            }],
+            category_targets: vec![ArchivedTarget {
+                id: "ct".into(),
+                category_id: "c".into(),
+                monthly_amount: 2000,
+            }],
+        }
+    }
+
+    #[test]
+    fn anonymize_replaces_names_and_ids_consistently() {
+        let mut archive = sample();
+        archive.anonymize();
+
+        assert_eq!(archive.budget.currency_code, "EUR");
+        assert_eq!(archive.accounts[0].name, "Account 1");
+        assert_eq!(archive.supercategories[0].name, "Group 1");
+        assert_eq!(archive.categories[0].name, "Category 1");
+        let payees: Vec<_> = archive
+            .transactions
+            .iter()
+            .map(|t| t.payee.as_deref().unwrap())
+            .collect();
+        assert_eq!(payees, ["Payee 1", "Payee 2", "Payee 1"]);
+
+        let account_id = &archive.accounts[0].id;
+        let category_id = &archive.categories[0].id;
+        assert_ne!(account_id, "a");
+        assert_eq!(
+            &archive.categories[0].supercategory_id,
+            &archive.supercategories[0].id
+        );
+        assert_eq!(&archive.category_assignments[0].category_id, category_id);
+        assert_eq!(&archive.category_targets[0].category_id, category_id);
+        let schedule = &archive.scheduled_transactions[0];
+        assert_eq!(&schedule.account_id, account_id);
+        assert_eq!(schedule.payee.as_deref(), Some("Payee 2"));
+        assert!(schedule.memo.is_none());
+        for transaction in &archive.transactions {
+            assert_eq!(&transaction.account_id, account_id);
+            assert!(transaction.memo.is_none());
+            let split = &transaction.splits[0];
+            assert_eq!(&split.category_id, category_id);
+            assert_eq!(split.outflow, 500);
+            assert!(split.memo.is_none());
+        }
+    }
+}
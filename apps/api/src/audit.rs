@@ -0,0 +1,115 @@
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::FromRow;
+use sqlx::PgConnection;
+
+use crate::changes::ChangeEntity;
+use crate::user_from_headers;
+use crate::AppState;
+
+/// Internal keys stripped from snapshots; the API only speaks pillids.
+const INTERNAL_COLUMNS: &[&str] = &[
+    "id",
+    "user_id",
+    "budget_id",
+    "account_id",
+    "supercategory_id",
+    "category_id",
+    "transaction_id",
+    "knowledge",
+];
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// JSON image of one entity row as it is right now, or `None` if it does not
+/// exist. Transactions include their active splits.
+pub(crate) async fn snapshot(
+    conn: &mut PgConnection,
+    entity: ChangeEntity,
+    pillid: &str,
+) -> Result<Option<Value>, StatusCode> {
+    let sql = match entity {
+        ChangeEntity::Transaction => "select (to_jsonb(t) - $2::text[]) || jsonb_build_object('splits', coalesce((select jsonb_agg(to_jsonb(ts) - $2::text[] order by ts.created_at) from transaction_splits ts where ts.transaction_id = t.id and ts.deleted_at is null), '[]'::jsonb)) from transactions t where t.pillid = $1".to_string(),
+        _ => format!(
+            "select to_jsonb(r) - $2::text[] from {} r where r.pillid = $1",
+            entity.table()
+        ),
+    };
+    let row: Option<(Value,)> = sqlx::query_as(&sql)
+        .bind(pillid)
+        .bind(INTERNAL_COLUMNS)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(row.map(|r| r.0))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AuditQuery {
+    entity: Option<String>,
+    entity_id: Option<String>,
+    /// Only entries older than this `seq`, for paging backwards.
+    before_seq: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, FromRow)]
+pub(crate) struct AuditEntryDto {
+    id: String,
+    seq: i64,
+    user_id: String,
+    entity: String,
+    entity_id: String,
+    op: String,
+    before: Option<Value>,
+    after: Option<Value>,
+    created_at: DateTime<Utc>,
+}
+
+/// Newest-first audit entries for one budget, optionally narrowed to an entity
+/// type and/or a single entity.
+pub(crate) async fn list_audit_log(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_id): Path<String>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntryDto>>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let rows = sqlx::query_as::<_, AuditEntryDto>(
+        "select a.pillid as id, a.id as seq, a.user_pillid as user_id, a.entity, a.entity_pillid as entity_id, a.op, a.before, a.after, a.created_at
+         from audit_log a
+         join budgets b on b.id = a.budget_id and b.user_id = $1
+         where a.budget_pillid = $2
+           and ($3::text is null or a.entity = $3)
+           and ($4::text is null or a.entity_pillid = $4)
+           and ($5::bigint is null or a.id < $5)
+         order by a.id desc
+         limit $6",
+    )
+    .bind(user_id)
+    .bind(budget_id)
+    .bind(query.entity)
+    .bind(query.entity_id)
+    .bind(query.before_seq)
+    .bind(limit)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(rows))
+}
@@ -0,0 +1,516 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::response::sse::Event;
+use axum::response::sse::KeepAlive;
+use axum::response::sse::Sse;
+use axum::Json;
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::postgres::PgListener;
+use sqlx::FromRow;
+use sqlx::PgConnection;
+use sqlx::PgPool;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::sync::OnceCell;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use uuid::Uuid;
+
+use crate::audit::snapshot;
+use crate::extract_bearer_token;
+use crate::random_token;
+use crate::sha256_hex;
+use crate::user_from_headers;
+use crate::AppState;
+
+const CHANGE_CHANNEL: &str = "budget_changes";
+const LIVE_BUFFER: usize = 1024;
+/// Events waiting for an SSE client to read them.
+const STREAM_BUFFER: usize = 64;
+/// How often a stream looks again while it holds back events behind a write
+/// that has not committed; that write may not notify when it does.
+const HELD_BACK_RECHECK: Duration = Duration::from_secs(1);
+/// How long change events are kept for `Last-Event-ID` resumes.
+const CHANGE_EVENT_RETENTION_DAYS: i32 = 30;
+/// Long enough for the browser's own reconnects after a dropped stream; a
+/// client whose ticket has run out mints another.
+const EVENT_TICKET_TTL_SECONDS: i32 = 120;
+
+#[derive(Clone, Copy)]
+pub(crate) enum ChangeEntity {
+    Budget,
+    Account,
+    Supercategory,
+    Category,
+    Transaction,
+    CategoryAssignment,
+    ScheduledTransaction,
+    CategoryTarget,
+}
+
+impl ChangeEntity {
+    pub(crate) fn table(self) -> &'static str {
+        match self {
+            ChangeEntity::Budget => "budgets",
+            ChangeEntity::Account => "accounts",
+            ChangeEntity::Supercategory => "supercategories",
+            ChangeEntity::Category => "categories",
+            ChangeEntity::Transaction => "transactions",
+            ChangeEntity::CategoryAssignment => "category_assignments",
+            ChangeEntity::ScheduledTransaction => "scheduled_transactions",
+            ChangeEntity::CategoryTarget => "category_targets",
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            ChangeEntity::Budget => "budget",
+            ChangeEntity::Account => "account",
+            ChangeEntity::Supercategory => "supercategory",
+            ChangeEntity::Category => "category",
+            ChangeEntity::Transaction => "transaction",
+            ChangeEntity::CategoryAssignment => "category_assignment",
+            ChangeEntity::ScheduledTransaction => "scheduled_transaction",
+            ChangeEntity::CategoryTarget => "category_target",
+        }
+    }
+
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        [
+            ChangeEntity::Budget,
+            ChangeEntity::Account,
+            ChangeEntity::Supercategory,
+            ChangeEntity::Category,
+            ChangeEntity::Transaction,
+            ChangeEntity::CategoryAssignment,
+            ChangeEntity::ScheduledTransaction,
+            ChangeEntity::CategoryTarget,
+        ]
+        .into_iter()
+        .find(|entity| entity.as_str() == value)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum ChangeOp {
+    Create,
+    Update,
+    Delete,
+}
+
+impl ChangeOp {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            ChangeOp::Create => "create",
+            ChangeOp::Update => "update",
+            ChangeOp::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, FromRow)]
+pub(crate) struct ChangeEvent {
+    id: i64,
+    budget_id: String,
+    entity: String,
+    entity_id: String,
+    op: String,
+}
+
+/// What the shared listener tells open streams.
+#[derive(Clone, Debug)]
+enum FeedSignal {
+    /// A change in the budget with this pillid committed.
+    Changed(String),
+    /// The listener failed and notifications may have been missed.
+    Lost,
+}
+
+/// Fan-out of `budget_changes` notifications to open SSE connections.
+///
+/// A single `LISTEN` connection is opened on first subscribe and shared by
+/// every stream in the process. Notifications only wake streams up; each one
+/// reads its events back from `change_events`.
+#[derive(Clone)]
+pub struct ChangeFeed {
+    sender: broadcast::Sender<FeedSignal>,
+    listener: Arc<OnceCell<ListenerTask>>,
+}
+
+/// Stops the shared listener once the last `ChangeFeed` clone is dropped.
+struct ListenerTask(JoinHandle<()>);
+
+impl Drop for ListenerTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(LIVE_BUFFER);
+        Self {
+            sender,
+            listener: Arc::new(OnceCell::new()),
+        }
+    }
+}
+
+impl ChangeFeed {
+    async fn subscribe(&self, db: &PgPool) -> Result<broadcast::Receiver<FeedSignal>, StatusCode> {
+        self.listener
+            .get_or_try_init(|| async {
+                let mut listener = PgListener::connect_with(db).await?;
+                listener.listen(CHANGE_CHANNEL).await?;
+                let task = tokio::spawn(forward_notifications(listener, self.sender.clone()));
+                Ok::<_, sqlx::Error>(ListenerTask(task))
+            })
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(self.sender.subscribe())
+    }
+}
+
+async fn forward_notifications(mut listener: PgListener, sender: broadcast::Sender<FeedSignal>) {
+    loop {
+        match listener.recv().await {
+            Ok(notification) => {
+                if let Ok(event) = serde_json::from_str::<ChangeEvent>(notification.payload()) {
+                    // No receivers just means nobody is watching right now.
+                    let _ = sender.send(FeedSignal::Changed(event.budget_id));
+                }
+            }
+            Err(err) => {
+                // Whatever is notified until the listener reconnects is lost, so
+                // open streams end and their clients resume with Last-Event-ID.
+                tracing::warn!(%err, "change feed listener failed, closing streams");
+                let _ = sender.send(FeedSignal::Lost);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+/// Journals a mutation: appends an audit entry with before/after snapshots and
+/// a change event for `budget_pillid`, and notifies listeners.
+///
+/// Call this inside the handler's transaction, after the write, passing the
+/// snapshot taken before it (`None` for creates). Nothing becomes visible
+/// unless the mutation commits.
+pub(crate) async fn record_change(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    budget_pillid: &str,
+    entity: ChangeEntity,
+    entity_pillid: &str,
+    op: ChangeOp,
+    before: Option<Value>,
+) -> Result<(), StatusCode> {
+    let after = snapshot(conn, entity, entity_pillid).await?;
+    sqlx::query(
+        "insert into audit_log (user_id, user_pillid, budget_id, budget_pillid, entity, entity_pillid, op, before, after)
+         select u.id, u.pillid, b.id, b.pillid, $3, $4, $5, $6, $7
+         from users u join budgets b on b.pillid = $2
+         where u.id = $1",
+    )
+    .bind(user_id)
+    .bind(budget_pillid)
+    .bind(entity.as_str())
+    .bind(entity_pillid)
+    .bind(op.as_str())
+    .bind(before)
+    .bind(after)
+    .execute(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    sqlx::query(
+        "with e as (
+           insert into change_events (budget_id, budget_pillid, entity, entity_pillid, op)
+           select b.id, b.pillid, $2, $3, $4 from budgets b where b.pillid = $1
+           returning id, budget_pillid, entity, entity_pillid, op
+         )
+         select pg_notify($5, json_build_object('id', id, 'budget_id', budget_pillid, 'entity', entity, 'entity_id', entity_pillid, 'op', op)::text)
+         from e",
+    )
+    .bind(budget_pillid)
+    .bind(entity.as_str())
+    .bind(entity_pillid)
+    .bind(op.as_str())
+    .bind(CHANGE_CHANNEL)
+    .execute(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub(crate) struct EventTicket {
+    ticket: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Mints a ticket that opens `/api/budgets/:id/events?ticket=...` for the
+/// caller, standing in for the bearer token `EventSource` cannot send. It can
+/// be used any number of times until it expires, so the browser's automatic
+/// reconnects keep working; after that, mint another and resume with
+/// `last_event_id`. The caller's expired tickets are cleared on the way.
+pub(crate) async fn create_event_ticket(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<EventTicket>, StatusCode> {
+    let token_hash = sha256_hex(extract_bearer_token(&headers)?);
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (session_id, user_id) = sqlx::query_as::<_, (Uuid, Uuid)>(
+        "select id, user_id from sessions where token_hash = $1 and revoked_at is null and expires_at > now()",
+    )
+    .bind(token_hash)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    sqlx::query("delete from event_tickets where user_id = $1 and expires_at <= now()")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let ticket = random_token(32);
+    let (expires_at,): (DateTime<Utc>,) = sqlx::query_as(
+        "insert into event_tickets (session_id, user_id, user_pillid, ticket_hash, expires_at)
+         select $1, u.id, u.pillid, $3, now() + make_interval(secs => $4) from users u where u.id = $2
+         returning expires_at",
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .bind(sha256_hex(&ticket))
+    .bind(EVENT_TICKET_TTL_SECONDS)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(EventTicket { ticket, expires_at }))
+}
+
+async fn user_from_event_ticket(db: &PgPool, ticket: &str) -> Result<Uuid, StatusCode> {
+    sqlx::query_as::<_, (Uuid,)>(
+        "select t.user_id from event_tickets t
+         join sessions s on s.id = t.session_id
+         where t.ticket_hash = $1 and t.expires_at > now()
+           and s.revoked_at is null and s.expires_at > now()",
+    )
+    .bind(sha256_hex(ticket))
+    .fetch_optional(db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map(|(user_id,)| user_id)
+    .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct EventsQuery {
+    /// From `POST /api/auth/event-ticket`, in place of the bearer token.
+    ticket: Option<String>,
+    /// Used when the `Last-Event-ID` header is absent, i.e. on a stream the
+    /// client opened itself rather than one the browser reconnected.
+    last_event_id: Option<i64>,
+}
+
+/// Server-Sent Events stream of changes to one budget.
+///
+/// Each event has `id` set to the change id; a reconnect carrying
+/// `Last-Event-ID` first replays everything after that id, or sends a single
+/// `reset` event if some of it has been purged. The `reset` carries the purge
+/// horizon as its id, so the browser's automatic reconnect resumes from there
+/// instead of getting `reset` again. Events are sent in id order
+/// and never ahead of a write that has not committed, so resuming after the
+/// last id seen misses nothing.
+pub(crate) async fn budget_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_id): Path<String>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, StatusCode> {
+    let user_id = match &query.ticket {
+        Some(ticket) => user_from_event_ticket(&state.db, ticket).await?,
+        None => user_from_headers(&state, &headers).await?,
+    };
+    let (budget,) = sqlx::query_as::<_, (Uuid,)>(
+        "select id from budgets where pillid = $1 and user_id = $2 and deleted_at is null",
+    )
+    .bind(&budget_id)
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let last_event_id = match headers.get("last-event-id") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .ok_or(StatusCode::BAD_REQUEST)?,
+        ),
+        None => query.last_event_id,
+    };
+
+    // Subscribe before reading anything so no wake-up falls in between.
+    let live = state.changes.subscribe(&state.db).await?;
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+    match last_event_id {
+        Some(after) => {
+            let (purged_through,): (i64,) =
+                sqlx::query_as("select purged_through from change_event_horizon")
+                    .fetch_one(&state.db)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            if after < purged_through {
+                let reset = Event::default()
+                    .id(purged_through.to_string())
+                    .event("reset")
+                    .data("resync");
+                let _ = sender.try_send(Ok(reset));
+            } else {
+                tokio::spawn(stream_changes(
+                    state.db.clone(),
+                    budget,
+                    budget_id,
+                    after,
+                    live,
+                    sender,
+                ));
+            }
+        }
+        None => {
+            let after = committed_knowledge(&state.db)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            tokio::spawn(stream_changes(
+                state.db.clone(),
+                budget,
+                budget_id,
+                after,
+                live,
+                sender,
+            ));
+        }
+    }
+
+    Ok(Sse::new(ReceiverStream::new(receiver)).keep_alive(KeepAlive::default()))
+}
+
+/// Sends the budget's committed changes after `after` until the client goes
+/// away or the listener fails.
+async fn stream_changes(
+    db: PgPool,
+    budget: Uuid,
+    budget_id: String,
+    mut after: i64,
+    mut live: broadcast::Receiver<FeedSignal>,
+    out: mpsc::Sender<Result<Event, axum::Error>>,
+) {
+    loop {
+        let Ok((events, held_back)) = committed_changes(&db, budget, after).await else {
+            return;
+        };
+        for event in events {
+            after = event.id;
+            let event = Event::default()
+                .id(event.id.to_string())
+                .event("change")
+                .json_data(&event);
+            if out.send(event).await.is_err() {
+                return;
+            }
+        }
+        loop {
+            let signal = tokio::select! {
+                _ = out.closed() => return,
+                _ = tokio::time::sleep(HELD_BACK_RECHECK), if held_back => break,
+                signal = live.recv() => signal,
+            };
+            match signal {
+                Ok(FeedSignal::Changed(budget)) if held_back || budget == budget_id => break,
+                Ok(FeedSignal::Changed(_)) => {}
+                // Missed wake-ups are harmless: the next read covers them.
+                Err(RecvError::Lagged(_)) => break,
+                Ok(FeedSignal::Lost) | Err(RecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+/// The budget's changes after `after` that are safe to send, and whether more
+/// are held back behind a write that has not committed.
+async fn committed_changes(
+    db: &PgPool,
+    budget: Uuid,
+    after: i64,
+) -> Result<(Vec<ChangeEvent>, bool), sqlx::Error> {
+    // Before the read, like sync; see the migration that defines it.
+    let committed = committed_knowledge(db).await?;
+    let mut events = sqlx::query_as::<_, ChangeEvent>(
+        "select id, budget_pillid as budget_id, entity, entity_pillid as entity_id, op
+         from change_events where budget_id = $1 and id > $2 order by id",
+    )
+    .bind(budget)
+    .bind(after)
+    .fetch_all(db)
+    .await?;
+    let held_back = events.iter().position(|event| event.id > committed);
+    if let Some(index) = held_back {
+        events.truncate(index);
+    }
+    Ok((events, held_back.is_some()))
+}
+
+/// Knowledge below which every write has committed or rolled back; read it
+/// before the rows it guards.
+pub(crate) async fn committed_knowledge(db: &PgPool) -> Result<i64, sqlx::Error> {
+    let (committed,): (i64,) = sqlx::query_as("select committed_knowledge()")
+        .fetch_one(db)
+        .await?;
+    Ok(committed)
+}
+
+/// Deletes change events older than the retention period and returns how many
+/// went. Run periodically next to the trash purge.
+pub async fn purge_change_events(db: &PgPool) -> Result<u64, sqlx::Error> {
+    let (purged,): (i64,) = sqlx::query_as(
+        "with purged as (
+           delete from change_events
+           where created_at < now() - make_interval(days => $1)
+           returning id
+         ), horizon as (
+           update change_event_horizon
+           set purged_through = greatest(purged_through, (select max(id) from purged))
+           where exists (select 1 from purged)
+         )
+         select count(*) from purged",
+    )
+    .bind(CHANGE_EVENT_RETENTION_DAYS)
+    .fetch_one(db)
+    .await?;
+    Ok(purged as u64)
+}
@@ -0,0 +1,286 @@
+//! Month closing: a closed month keeps the numbers it had when it was closed.
+//!
+//! Closing stores each category's assigned, activity and available and the
+//! budget's Ready to Assign for the month. From then on the database refuses
+//! changes to transactions, splits and assignments dated in that month or any
+//! month before it (see the `month_closings` migration) until the month is
+//! reopened. Only the latest closed month can be reopened.
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::DateTime;
+use chrono::Months;
+use chrono::NaiveDate;
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::FromRow;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::category_month;
+use crate::compute_dashboard_projection;
+use crate::parse_projection_month;
+use crate::trash::owned_budget;
+use crate::user_from_headers;
+use crate::AppState;
+use crate::CategoryProjectionDto;
+
+/// SQLSTATE raised by the triggers for a write into a closed month.
+pub(crate) const MONTH_CLOSED: &str = "EZ001";
+
+/// The status for a failed write: `409 Conflict` if it touched a closed
+/// month, `fallback` if the row was missing or the data was refused (bad
+/// values, constraints, trigger checks), and a server error otherwise, `503`
+/// where a retry may succeed (serialization failures, deadlocks).
+pub(crate) fn write_status(err: sqlx::Error, fallback: StatusCode) -> StatusCode {
+    let code = match &err {
+        sqlx::Error::RowNotFound => return fallback,
+        sqlx::Error::Database(err) => err.code(),
+        _ => return StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    match code.as_deref() {
+        Some(MONTH_CLOSED) => StatusCode::CONFLICT,
+        Some(code)
+            if ["22", "23", "P0"]
+                .iter()
+                .any(|class| code.starts_with(class)) =>
+        {
+            fallback
+        }
+        Some(code) if code.starts_with("40") => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[derive(Serialize, FromRow)]
+pub(crate) struct ClosedMonthDto {
+    /// `YYYY-MM`
+    month: String,
+    ready_to_assign: i64,
+    closed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct MonthSnapshotDto {
+    #[serde(flatten)]
+    closing: ClosedMonthDto,
+    categories: Vec<CategoryProjectionDto>,
+}
+
+/// `GET /api/budgets/:id/months/closed`, latest first.
+pub(crate) async fn list_closed_months(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+) -> Result<Json<Vec<ClosedMonthDto>>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut conn, &budget_pillid, user_id).await?;
+    let rows = sqlx::query_as::<_, ClosedMonthDto>(
+        "select to_char(month, 'YYYY-MM') as month, ready_to_assign, closed_at
+         from month_closings
+         where budget_id = $1
+         order by month desc",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(rows))
+}
+
+/// `POST /api/budgets/:id/months/:month/close`. Only a month that has ended
+/// can be closed, and only once.
+pub(crate) async fn close_month(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((budget_pillid, month)): Path<(String, String)>,
+) -> Result<Json<MonthSnapshotDto>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let period = parse_projection_month(&month)?;
+    let end = period
+        .checked_add_months(Months::new(1))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    if end > Utc::now().date_naive() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut tx, &budget_pillid, user_id).await?;
+    // Waits for writes in flight and holds off new ones until the snapshot is
+    // taken (they take a key share lock on the budget).
+    sqlx::query("select 1 from budgets where id = $1 for update")
+        .bind(budget_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let ready_to_assign = compute_dashboard_projection(&mut tx, budget_id, period, 1)
+        .await?
+        .ready_to_assign;
+    let closing: Option<(Uuid, DateTime<Utc>)> = sqlx::query_as(
+        "insert into month_closings (budget_id, budget_pillid, month, ready_to_assign)
+         values ($1, $2, $3, $4)
+         on conflict (budget_id, month) do nothing
+         returning id, closed_at",
+    )
+    .bind(budget_id)
+    .bind(&budget_pillid)
+    .bind(period)
+    .bind(ready_to_assign)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some((closing_id, closed_at)) = closing else {
+        return Err(StatusCode::CONFLICT);
+    };
+
+    let categories = category_month(&mut tx, budget_id, period).await?;
+    sqlx::query(
+        "insert into month_closing_categories (closing_id, category_id, category_pillid, assigned, activity, available)
+         select $1, c.id, c.pillid, s.assigned, s.activity, s.available
+         from unnest($2::text[], $3::bigint[], $4::bigint[], $5::bigint[])
+              as s(category_pillid, assigned, activity, available)
+         join categories c on c.pillid = s.category_pillid and c.budget_id = $6",
+    )
+    .bind(closing_id)
+    .bind(categories.iter().map(|c| c.category_id.clone()).collect::<Vec<_>>())
+    .bind(categories.iter().map(|c| c.assigned).collect::<Vec<_>>())
+    .bind(categories.iter().map(|c| c.activity).collect::<Vec<_>>())
+    .bind(categories.iter().map(|c| c.available).collect::<Vec<_>>())
+    .bind(budget_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(MonthSnapshotDto {
+        closing: ClosedMonthDto {
+            month: period.format("%Y-%m").to_string(),
+            ready_to_assign,
+            closed_at,
+        },
+        categories,
+    }))
+}
+
+/// `POST /api/budgets/:id/months/:month/reopen` drops the snapshot. Months
+/// before a later closed month stay frozen, so only the latest one reopens.
+pub(crate) async fn reopen_month(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((budget_pillid, month)): Path<(String, String)>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let period = parse_projection_month(&month)?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut tx, &budget_pillid, user_id).await?;
+    sqlx::query("select 1 from budgets where id = $1 for update")
+        .bind(budget_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let latest: Option<(NaiveDate,)> = sqlx::query_as(
+        "select month from month_closings where budget_id = $1 order by month desc limit 1",
+    )
+    .bind(budget_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match latest {
+        Some((latest,)) if latest == period => {}
+        Some((latest,)) if latest > period => return Err(StatusCode::CONFLICT),
+        _ => return Err(StatusCode::NOT_FOUND),
+    }
+    sqlx::query("delete from month_closings where budget_id = $1 and month = $2")
+        .bind(budget_id)
+        .bind(period)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /api/budgets/:id/months/:month/snapshot`
+pub(crate) async fn month_snapshot(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((budget_pillid, month)): Path<(String, String)>,
+) -> Result<Json<MonthSnapshotDto>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let period = parse_projection_month(&month)?;
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut conn, &budget_pillid, user_id).await?;
+    let closing: ClosedMonthDto = sqlx::query_as(
+        "select to_char(month, 'YYYY-MM') as month, ready_to_assign, closed_at
+         from month_closings
+         where budget_id = $1 and month = $2",
+    )
+    .bind(budget_id)
+    .bind(period)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    let categories = snapshot_categories(&mut conn, budget_id, period)
+        .await?
+        .unwrap_or_default();
+    Ok(Json(MonthSnapshotDto {
+        closing,
+        categories,
+    }))
+}
+
+/// The categories as the month was closed, or `None` if it is open.
+pub(crate) async fn snapshot_categories(
+    conn: &mut PgConnection,
+    budget_id: Uuid,
+    period: NaiveDate,
+) -> Result<Option<Vec<CategoryProjectionDto>>, StatusCode> {
+    let closed: Option<(Uuid,)> =
+        sqlx::query_as("select id from month_closings where budget_id = $1 and month = $2")
+            .bind(budget_id)
+            .bind(period)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some((closing_id,)) = closed else {
+        return Ok(None);
+    };
+    let rows = sqlx::query_as::<_, CategoryProjectionDto>(
+        "select m.category_pillid as category_id, m.assigned, m.activity, m.available
+         from month_closing_categories m
+         join categories c on c.id = m.category_id
+         where m.closing_id = $1
+         order by c.created_at, c.pillid",
+    )
+    .bind(closing_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Some(rows))
+}
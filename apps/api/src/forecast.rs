@@ -0,0 +1,451 @@
+//! Cash flow forecast: each account's balance day by day over the coming
+//! months, from today's balances.
+//!
+//! Scheduled transactions (see [`crate::schedule`]) land on each of their
+//! occurrences, and so do transactions already entered with a later date.
+//! Categories with a target pay it out every month; with `average_months`, the
+//! others pay their average spending of that many full months. Both are spread
+//! evenly over the days and charged to the accounts in the shares they paid the
+//! category from.
+
+use std::collections::HashMap;
+
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::Datelike;
+use chrono::Months;
+use chrono::NaiveDate;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::FromRow;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::money::Currency;
+use crate::reports::open_budget;
+use crate::schedule::ScheduledTransactionDto;
+use crate::AppState;
+
+#[derive(Deserialize)]
+pub(crate) struct ForecastQuery {
+    /// Months to project, 1 to 24; defaults to 3.
+    months: Option<u32>,
+    /// Full months before this one to average spending over, 1 to 24; only
+    /// targets are charged without it.
+    average_months: Option<u32>,
+}
+
+#[derive(FromRow, Serialize)]
+pub(crate) struct ForecastAccount {
+    id: String,
+    name: String,
+    /// The balance at the end of today.
+    balance: i64,
+    /// Average spending per month applied to this account.
+    #[sqlx(skip)]
+    monthly_spending: i64,
+    /// Its share of the monthly category targets.
+    #[sqlx(skip)]
+    monthly_targets: i64,
+    /// The balance at the end of each of [`Forecast::dates`].
+    #[sqlx(skip)]
+    balances: Vec<i64>,
+}
+
+/// A day on which an account's balance drops below zero.
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct NegativeBalance {
+    account_id: String,
+    date: NaiveDate,
+    balance: i64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Forecast {
+    #[serde(flatten)]
+    currency: Currency,
+    /// Tomorrow through the last projected day.
+    dates: Vec<NaiveDate>,
+    accounts: Vec<ForecastAccount>,
+    /// The first day of every run of days an account spends below zero,
+    /// earliest first.
+    alerts: Vec<NegativeBalance>,
+}
+
+#[derive(FromRow)]
+struct Scheduled {
+    account_id: String,
+    date: NaiveDate,
+    amount: i64,
+}
+
+/// `GET /api/budgets/:id/forecast?months=3&average_months=3`
+pub(crate) async fn forecast(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+    Query(query): Query<ForecastQuery>,
+) -> Result<Json<Forecast>, StatusCode> {
+    let months = query.months.unwrap_or(3);
+    if !(1..=24).contains(&months) || query.average_months.is_some_and(|m| !(1..=24).contains(&m)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let (mut conn, budget_id, currency) = open_budget(&state, &headers, &budget_pillid).await?;
+    let today = Utc::now().date_naive();
+    Ok(Json(
+        compute_forecast(
+            &mut conn,
+            budget_id,
+            currency,
+            today,
+            months,
+            query.average_months,
+        )
+        .await?,
+    ))
+}
+
+async fn compute_forecast(
+    conn: &mut PgConnection,
+    budget_id: Uuid,
+    currency: Currency,
+    today: NaiveDate,
+    months: u32,
+    average_months: Option<u32>,
+) -> Result<Forecast, StatusCode> {
+    let last = today
+        .checked_add_months(Months::new(months))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let mut accounts: Vec<ForecastAccount> = sqlx::query_as(
+        "select a.pillid as id, a.name,
+                coalesce(sum(ts.inflow - ts.outflow) filter (where t.tx_date <= $2), 0)::bigint as balance
+         from accounts a
+         left join transactions t on t.account_id = a.id and t.deleted_at is null
+         left join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+         where a.budget_id = $1 and a.deleted_at is null and a.closed_at is null
+         group by a.id
+         order by a.created_at, a.pillid",
+    )
+    .bind(budget_id)
+    .bind(today)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut scheduled: Vec<Scheduled> = sqlx::query_as(
+        "select t.account_pillid as account_id, t.tx_date as date,
+                sum(ts.inflow - ts.outflow)::bigint as amount
+         from transactions t
+         join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+         where t.budget_id = $1 and t.deleted_at is null
+           and t.tx_date > $2 and t.tx_date <= $3
+         group by 1, 2",
+    )
+    .bind(budget_id)
+    .bind(today)
+    .bind(last)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let schedules: Vec<ScheduledTransactionDto> = sqlx::query_as(
+        "select st.pillid as id, st.account_pillid as account_id, st.payee, st.memo, st.inflow, st.outflow,
+                st.frequency, st.next_date, st.end_date
+         from scheduled_transactions st
+         join accounts a on a.id = st.account_id and a.deleted_at is null and a.closed_at is null
+         where st.budget_id = $1 and st.deleted_at is null and st.next_date <= $2",
+    )
+    .bind(budget_id)
+    .bind(last)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for schedule in &schedules {
+        for date in schedule.occurrences(today, last) {
+            scheduled.push(Scheduled {
+                account_id: schedule.account_id.clone(),
+                date,
+                amount: schedule.inflow - schedule.outflow,
+            });
+        }
+    }
+
+    // Who paid for what over the averaging window; targets without
+    // `average_months` are shared out over the last three full months.
+    let this_month = today.with_day(1).unwrap_or(today);
+    let since = this_month
+        .checked_sub_months(Months::new(average_months.unwrap_or(3)))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let spending: Vec<Spending> = sqlx::query_as(
+        "select t.account_pillid as account_id, c.pillid as category_id,
+                sum(ts.outflow - ts.inflow)::bigint as amount
+         from transactions t
+         join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+         join categories c on c.id = ts.category_id and not c.is_transfer
+         where t.budget_id = $1 and t.deleted_at is null
+           and t.tx_date >= $2 and t.tx_date < $3
+         group by 1, 2",
+    )
+    .bind(budget_id)
+    .bind(since)
+    .bind(this_month)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let targets: HashMap<String, i64> = sqlx::query_as(
+        "select ct.category_pillid, ct.monthly_amount
+         from category_targets ct
+         join categories c on c.id = ct.category_id and c.deleted_at is null and not c.is_transfer
+         where ct.budget_id = $1 and ct.deleted_at is null",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .into_iter()
+    .collect();
+    monthly_charges(&mut accounts, &spending, &targets, average_months);
+
+    let dates: Vec<NaiveDate> = today
+        .iter_days()
+        .skip(1)
+        .take_while(|date| *date <= last)
+        .collect();
+    let alerts = project(&dates, &mut accounts, scheduled);
+    Ok(Forecast {
+        currency,
+        dates,
+        accounts,
+        alerts,
+    })
+}
+
+#[derive(FromRow)]
+struct Spending {
+    account_id: String,
+    category_id: String,
+    /// Outflow minus inflow.
+    amount: i64,
+}
+
+/// Sets each account's monthly spending and targets. A category with a target
+/// charges it in full; with `average_months`, every other category that paid
+/// out more than it took in charges its average. Each is split between the
+/// accounts in the shares they paid it from; a target nothing has been paid
+/// for yet goes to the first account.
+fn monthly_charges(
+    accounts: &mut [ForecastAccount],
+    spending: &[Spending],
+    targets: &HashMap<String, i64>,
+    average_months: Option<u32>,
+) {
+    let mut by_category: HashMap<&str, Vec<&Spending>> = HashMap::new();
+    for row in spending {
+        by_category
+            .entry(row.category_id.as_str())
+            .or_default()
+            .push(row);
+    }
+
+    let mut averaged: HashMap<&str, i64> = HashMap::new();
+    if average_months.is_some() {
+        for (category, rows) in &by_category {
+            if targets.contains_key(*category)
+                || rows.iter().map(|row| row.amount).sum::<i64>() <= 0
+            {
+                continue;
+            }
+            for row in rows {
+                *averaged.entry(row.account_id.as_str()).or_default() += row.amount;
+            }
+        }
+    }
+
+    let mut targeted: HashMap<String, i64> = HashMap::new();
+    for (category, &monthly_amount) in targets {
+        let shares: Vec<(&str, i64)> = by_category
+            .get(category.as_str())
+            .into_iter()
+            .flatten()
+            .filter(|row| row.amount > 0 && accounts.iter().any(|a| a.id == row.account_id))
+            .map(|row| (row.account_id.as_str(), row.amount))
+            .collect();
+        let total: i64 = shares.iter().map(|(_, amount)| amount).sum();
+        if total == 0 {
+            if let Some(first) = accounts.first() {
+                *targeted.entry(first.id.clone()).or_default() += monthly_amount;
+            }
+            continue;
+        }
+        // Rounding leftovers go to the account that paid the most.
+        let mut charged = 0;
+        for (account, amount) in &shares {
+            let share = monthly_amount * amount / total;
+            *targeted.entry((*account).to_string()).or_default() += share;
+            charged += share;
+        }
+        if let Some((largest, _)) = shares.iter().max_by_key(|(_, amount)| *amount) {
+            *targeted.entry((*largest).to_string()).or_default() += monthly_amount - charged;
+        }
+    }
+
+    for account in accounts {
+        account.monthly_spending = average_months.map_or(0, |months| {
+            averaged
+                .get(account.id.as_str())
+                .copied()
+                .unwrap_or(0)
+                .max(0)
+                / i64::from(months)
+        });
+        account.monthly_targets = targeted.get(&account.id).copied().unwrap_or(0);
+    }
+}
+
+/// Fills in each account's balance over `dates` and returns where one drops
+/// below zero. Average spending and targets go out a little every day,
+/// rounded so that a 30-day stretch pays exactly one month's worth.
+fn project(
+    dates: &[NaiveDate],
+    accounts: &mut [ForecastAccount],
+    scheduled: Vec<Scheduled>,
+) -> Vec<NegativeBalance> {
+    let Some(&first) = dates.first() else {
+        return Vec::new();
+    };
+    let mut on: HashMap<(&str, NaiveDate), i64> = HashMap::new();
+    for item in &scheduled {
+        *on.entry((item.account_id.as_str(), item.date)).or_default() += item.amount;
+    }
+    let mut alerts = Vec::new();
+    for account in accounts.iter_mut() {
+        let mut balance = account.balance;
+        let mut spent = 0;
+        account.balances = Vec::with_capacity(dates.len());
+        for (day, date) in dates.iter().enumerate() {
+            let before = balance;
+            balance += on.get(&(account.id.as_str(), *date)).copied().unwrap_or(0);
+            let due = (account.monthly_spending + account.monthly_targets) * (day as i64 + 1) / 30;
+            balance -= due - spent;
+            spent = due;
+            if balance < 0 && (before >= 0 || *date == first) {
+                alerts.push(NegativeBalance {
+                    account_id: account.id.clone(),
+                    date: *date,
+                    balance,
+                });
+            }
+            account.balances.push(balance);
+        }
+    }
+    alerts.sort_by_key(|alert| alert.date);
+    alerts
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn account(id: &str, balance: i64, monthly_spending: i64) -> ForecastAccount {
+        ForecastAccount {
+            id: id.into(),
+            name: id.into(),
+            balance,
+            monthly_spending,
+            monthly_targets: 0,
+            balances: Vec::new(),
+        }
+    }
+
+    fn spent(account_id: &str, category_id: &str, amount: i64) -> Spending {
+        Spending {
+            account_id: account_id.into(),
+            category_id: category_id.into(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn monthly_charges_split_targets_and_averages_by_share() {
+        let mut accounts = [account("checking", 0, 0), account("card", 0, 0)];
+        let spending = [
+            spent("checking", "groceries", 1000),
+            spent("card", "groceries", 2000),
+            spent("checking", "rent", 6000),
+            spent("card", "fuel", 900),
+            spent("checking", "salary", -9000),
+        ];
+        let targets = HashMap::from([("groceries".to_string(), 3001), ("gifts".to_string(), 500)]);
+
+        monthly_charges(&mut accounts, &spending, &targets, Some(3));
+        // Groceries has a target, so only rent and fuel are averaged.
+        assert_eq!(accounts[0].monthly_spending, 2000);
+        assert_eq!(accounts[1].monthly_spending, 300);
+        // A third of the groceries target, plus gifts nobody has paid for yet;
+        // the card paid the most and takes the rounding leftover.
+        assert_eq!(accounts[0].monthly_targets, 1000 + 500);
+        assert_eq!(accounts[1].monthly_targets, 2001);
+
+        monthly_charges(&mut accounts, &spending, &targets, None);
+        assert_eq!(accounts[0].monthly_spending, 0);
+        assert_eq!(accounts[1].monthly_targets, 2001);
+    }
+
+    #[test]
+    fn project_applies_scheduled_and_average_spending() {
+        let dates: Vec<NaiveDate> = date(2026, 4, 1).iter_days().take(30).collect();
+        let mut accounts = [account("checking", 1000, 3000), account("card", -50, 0)];
+        let scheduled = vec![
+            Scheduled {
+                account_id: "checking".into(),
+                date: date(2026, 4, 15),
+                amount: 2000,
+            },
+            Scheduled {
+                account_id: "card".into(),
+                date: date(2026, 4, 2),
+                amount: 100,
+            },
+            Scheduled {
+                account_id: "card".into(),
+                date: date(2026, 4, 20),
+                amount: -80,
+            },
+        ];
+
+        let alerts = project(&dates, &mut accounts, scheduled);
+        // 100 a day: below zero on day 11, back above with the 15th's inflow.
+        assert_eq!(accounts[0].balances[9], 0);
+        assert_eq!(accounts[0].balances[10], -100);
+        assert_eq!(accounts[0].balances[14], 1500);
+        assert_eq!(accounts[0].balances[29], 0);
+        assert_eq!(accounts[1].balances[..2], [-50, 50]);
+        assert_eq!(
+            alerts,
+            [
+                NegativeBalance {
+                    account_id: "card".into(),
+                    date: date(2026, 4, 1),
+                    balance: -50,
+                },
+                NegativeBalance {
+                    account_id: "checking".into(),
+                    date: date(2026, 4, 11),
+                    balance: -100,
+                },
+                NegativeBalance {
+                    account_id: "card".into(),
+                    date: date(2026, 4, 20),
+                    balance: -30,
+                },
+            ]
+        );
+    }
+}
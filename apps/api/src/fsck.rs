@@ -0,0 +1,228 @@
+use std::fmt;
+
+use sqlx::PgPool;
+
+const SAMPLE_SIZE: usize = 5;
+
+/// One integrity check: `find` selects an identifier per offending row, `fix`
+/// (if any) repairs them in place.
+struct Check {
+    name: String,
+    description: String,
+    find: String,
+    fix: Option<String>,
+}
+
+/// `*_pillid` columns denormalized next to a foreign key, as
+/// (table, fk column, pillid column, referenced table).
+const PILLID_COLUMNS: &[(&str, &str, &str, &str)] = &[
+    ("budgets", "user_id", "user_pillid", "users"),
+    ("sessions", "user_id", "user_pillid", "users"),
+    ("accounts", "user_id", "user_pillid", "users"),
+    ("accounts", "budget_id", "budget_pillid", "budgets"),
+    ("supercategories", "user_id", "user_pillid", "users"),
+    ("supercategories", "budget_id", "budget_pillid", "budgets"),
+    ("categories", "user_id", "user_pillid", "users"),
+    ("categories", "budget_id", "budget_pillid", "budgets"),
+    (
+        "categories",
+        "supercategory_id",
+        "supercategory_pillid",
+        "supercategories",
+    ),
+    ("transactions", "user_id", "user_pillid", "users"),
+    ("transactions", "budget_id", "budget_pillid", "budgets"),
+    ("transactions", "account_id", "account_pillid", "accounts"),
+    (
+        "transaction_splits",
+        "transaction_id",
+        "transaction_pillid",
+        "transactions",
+    ),
+    (
+        "transaction_splits",
+        "category_id",
+        "category_pillid",
+        "categories",
+    ),
+    ("category_assignments", "user_id", "user_pillid", "users"),
+    (
+        "category_assignments",
+        "budget_id",
+        "budget_pillid",
+        "budgets",
+    ),
+    (
+        "category_assignments",
+        "category_id",
+        "category_pillid",
+        "categories",
+    ),
+];
+
+/// Checks in fix order: pillids first so later fixes see clean references,
+/// reviving categories before dropping assignments that point at them.
+fn checks() -> Vec<Check> {
+    let mut checks: Vec<Check> = PILLID_COLUMNS
+        .iter()
+        .map(|(table, fk, column, referenced)| Check {
+            name: format!("stale_pillid:{table}.{column}"),
+            description: format!("{table}.{column} does not match {referenced}.pillid"),
+            find: format!(
+                "select c.pillid::text from {table} c join {referenced} r on r.id = c.{fk} where c.{column} is distinct from r.pillid"
+            ),
+            fix: Some(format!(
+                "update {table} c set {column} = r.pillid from {referenced} r where r.id = c.{fk} and c.{column} is distinct from r.pillid"
+            )),
+        })
+        .collect();
+
+    checks.extend([
+        Check {
+            name: "splits_in_other_budget".into(),
+            description: "active splits whose category is in another budget than their transaction (fixed only where that budget has a live category of the same name)".into(),
+            find: "select ts.pillid from transaction_splits ts
+                   join transactions t on t.id = ts.transaction_id
+                   join categories c on c.id = ts.category_id
+                   where ts.deleted_at is null and (c.budget_id <> t.budget_id or c.user_id <> t.user_id)".into(),
+            fix: Some("update transaction_splits ts set category_id = same.id, category_pillid = same.pillid, updated_at = now()
+                       from transactions t, categories c, lateral (
+                         select id, pillid from categories s
+                         where s.budget_id = t.budget_id and s.user_id = t.user_id and s.name = c.name and s.deleted_at is null
+                         order by s.created_at limit 1
+                       ) same
+                       where t.id = ts.transaction_id and c.id = ts.category_id and ts.deleted_at is null
+                         and (c.budget_id <> t.budget_id or c.user_id <> t.user_id)".into()),
+        },
+        Check {
+            name: "splits_on_deleted_categories".into(),
+            description: "live transactions with active splits in a deleted category (fix restores the category and its supercategory)".into(),
+            find: "select distinct t.pillid from transaction_splits ts
+                   join transactions t on t.id = ts.transaction_id and t.deleted_at is null
+                   join categories c on c.id = ts.category_id
+                   where ts.deleted_at is null and c.deleted_at is not null".into(),
+            fix: Some("with revived as (
+                         update categories c set deleted_at = null, updated_at = now()
+                         where c.deleted_at is not null and exists (
+                           select 1 from transaction_splits ts
+                           join transactions t on t.id = ts.transaction_id and t.deleted_at is null
+                           where ts.category_id = c.id and ts.deleted_at is null
+                         )
+                         returning supercategory_id
+                       )
+                       update supercategories s set deleted_at = null, updated_at = now()
+                       where s.deleted_at is not null and s.id in (select supercategory_id from revived)".into()),
+        },
+        Check {
+            name: "assignments_on_deleted_categories".into(),
+            description: "live category assignments to deleted categories (fix deletes the assignment)".into(),
+            find: "select ca.pillid from category_assignments ca
+                   join categories c on c.id = ca.category_id
+                   where ca.deleted_at is null and c.deleted_at is not null".into(),
+            fix: Some("update category_assignments ca set deleted_at = now(), updated_at = now()
+                       from categories c
+                       where c.id = ca.category_id and ca.deleted_at is null and c.deleted_at is not null".into()),
+        },
+        Check {
+            name: "transactions_without_active_splits".into(),
+            description: "live transactions with no active split (fix moves them to the trash)".into(),
+            find: "select t.pillid from transactions t
+                   where t.deleted_at is null
+                     and not exists (select 1 from transaction_splits ts where ts.transaction_id = t.id and ts.deleted_at is null)".into(),
+            fix: Some("update transactions t set deleted_at = now(), updated_at = now()
+                       where t.deleted_at is null
+                         and not exists (select 1 from transaction_splits ts where ts.transaction_id = t.id and ts.deleted_at is null)".into()),
+        },
+        // Foreign keys make this impossible unless data was loaded with
+        // triggers disabled, e.g. a data-only pg_restore.
+        Check {
+            name: "sessions_for_missing_users".into(),
+            description: "sessions whose user no longer exists (fix deletes them)".into(),
+            find: "select coalesce(s.pillid, s.id::text) from sessions s left join users u on u.id = s.user_id where u.id is null".into(),
+            fix: Some("delete from sessions s where not exists (select 1 from users u where u.id = s.user_id)".into()),
+        },
+    ]);
+    checks
+}
+
+pub struct Finding {
+    pub name: String,
+    pub description: String,
+    pub count: usize,
+    pub sample: Vec<String>,
+    /// Rows changed by the fix, if one was run.
+    pub fixed: Option<u64>,
+    /// Offending rows left after the fix.
+    pub remaining: usize,
+}
+
+pub struct FsckReport {
+    pub findings: Vec<Finding>,
+}
+
+impl FsckReport {
+    /// Problems found and not fixed.
+    pub fn outstanding(&self) -> usize {
+        self.findings.iter().map(|f| f.remaining).sum()
+    }
+}
+
+impl fmt::Display for FsckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in &self.findings {
+            if finding.count == 0 {
+                writeln!(f, "ok    {}", finding.name)?;
+                continue;
+            }
+            writeln!(
+                f,
+                "FOUND {}: {} ({})",
+                finding.name, finding.count, finding.description
+            )?;
+            writeln!(f, "      e.g. {}", finding.sample.join(", "))?;
+            if let Some(fixed) = finding.fixed {
+                writeln!(
+                    f,
+                    "      fixed {fixed} row(s), {} remaining",
+                    finding.remaining
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Scans for inconsistencies the schema cannot rule out (or that predate the
+/// constraints), and with `fix` repairs them in a single transaction.
+pub async fn run_fsck(db: &PgPool, fix: bool) -> Result<FsckReport, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    if fix {
+        // Repairs may touch closed months.
+        sqlx::query("select set_config('envelopezero.closed_months', 'writable', true)")
+            .execute(&mut *tx)
+            .await?;
+    }
+    let mut findings = Vec::new();
+    for check in checks() {
+        let rows: Vec<(String,)> = sqlx::query_as(&check.find).fetch_all(&mut *tx).await?;
+        let mut remaining = rows.len();
+        let mut fixed = None;
+        if let (Some(sql), true) = (&check.fix, fix && !rows.is_empty()) {
+            fixed = Some(sqlx::query(sql).execute(&mut *tx).await?.rows_affected());
+            remaining = sqlx::query_as::<_, (String,)>(&check.find)
+                .fetch_all(&mut *tx)
+                .await?
+                .len();
+        }
+        findings.push(Finding {
+            name: check.name,
+            description: check.description,
+            count: rows.len(),
+            sample: rows.into_iter().take(SAMPLE_SIZE).map(|r| r.0).collect(),
+            fixed,
+            remaining,
+        });
+    }
+    tx.commit().await?;
+    Ok(FsckReport { findings })
+}
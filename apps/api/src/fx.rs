@@ -0,0 +1,250 @@
+//! Accounts in another currency than their budget, and the exchange rates
+//! between them.
+//!
+//! A split in such an account keeps what was booked in the account's currency
+//! (`account_inflow`, `account_outflow`) and its value in the budget's
+//! currency at the rate of the transaction's day (`inflow`, `outflow`), which
+//! is what the budget counts. Rates come from CSV files loaded with the
+//! `load-rates` admin command; there is no live feed.
+
+use anyhow::Context;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::NaiveDate;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::FromRow;
+use sqlx::PgConnection;
+use sqlx::PgPool;
+
+use crate::money::Currency;
+use crate::reports::open_budget;
+use crate::AppState;
+
+/// A line of a rates CSV (`date,base,quote,rate`): one `base` is worth `rate`
+/// of `quote` from `date` on.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct ExchangeRate {
+    pub date: NaiveDate,
+    pub base: String,
+    pub quote: String,
+    /// A positive decimal, kept as text so it is stored exactly.
+    pub rate: String,
+}
+
+/// Reads a rates CSV with a `date,base,quote,rate` header, e.g.
+/// `2026-03-31,EUR,USD,1.0832`.
+pub fn parse_rates(text: &str) -> anyhow::Result<Vec<ExchangeRate>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let mut rates = Vec::new();
+    for (line, row) in reader.deserialize::<ExchangeRate>().enumerate() {
+        let line = line + 2;
+        let row = row.with_context(|| format!("line {line}"))?;
+        for code in [&row.base, &row.quote] {
+            if Currency::parse(code).is_none() {
+                anyhow::bail!("line {line}: {code:?} is not an ISO 4217 currency");
+            }
+        }
+        if row.base == row.quote {
+            anyhow::bail!("line {line}: rate of {} to itself", row.base);
+        }
+        let positive = row.rate.chars().all(|c| c.is_ascii_digit() || c == '.')
+            && row.rate.parse::<f64>().is_ok_and(|rate| rate > 0.0);
+        if !positive {
+            anyhow::bail!("line {line}: rate {:?} is not a positive decimal", row.rate);
+        }
+        rates.push(row);
+    }
+    Ok(rates)
+}
+
+/// Saves rates, replacing any for the same pair and day.
+pub async fn store_rates(db: &PgPool, rates: &[ExchangeRate]) -> Result<u64, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let mut stored = 0;
+    for rate in rates {
+        stored += sqlx::query(
+            "insert into exchange_rates (base, quote, rate_date, rate)
+             values ($1, $2, $3, $4::numeric)
+             on conflict (base, quote, rate_date) do update set rate = excluded.rate",
+        )
+        .bind(&rate.base)
+        .bind(&rate.quote)
+        .bind(rate.date)
+        .bind(&rate.rate)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+    tx.commit().await?;
+    Ok(stored)
+}
+
+/// `amount` minor units of `from` in minor units of `to`, rounded, at the
+/// latest rate on or before `date` (either way round); `None` without one.
+pub(crate) async fn convert(
+    conn: &mut PgConnection,
+    from: &Currency,
+    to: &Currency,
+    date: NaiveDate,
+    amount: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    if from == to {
+        return Ok(Some(amount));
+    }
+    let converted: Option<(i64,)> = sqlx::query_as(
+        "select round($4::numeric * r.rate * power(10::numeric, $5))::bigint
+         from (
+             select rate, rate_date from exchange_rates
+             where base = $1 and quote = $2 and rate_date <= $3
+             union all
+             select 1 / rate, rate_date from exchange_rates
+             where base = $2 and quote = $1 and rate_date <= $3
+         ) r
+         order by r.rate_date desc
+         limit 1",
+    )
+    .bind(from.code())
+    .bind(to.code())
+    .bind(date)
+    .bind(amount)
+    .bind(to.minor_unit() as i32 - from.minor_unit() as i32)
+    .fetch_optional(&mut *conn)
+    .await?;
+    Ok(converted.map(|r| r.0))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct FxGainsQuery {
+    /// Day to value the accounts on; defaults to today.
+    date: Option<NaiveDate>,
+}
+
+#[derive(FromRow)]
+struct ForeignAccount {
+    id: String,
+    name: String,
+    currency_code: String,
+    balance: i64,
+    book_value: i64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct FxGainAccount {
+    id: String,
+    name: String,
+    /// The account's `currency_code` and `minor_unit`.
+    #[serde(flatten)]
+    currency: Currency,
+    /// In the account's currency.
+    balance: i64,
+    /// What the balance was booked at, in the budget's currency.
+    book_value: i64,
+    /// The balance at the day's rate; `None` without a rate.
+    market_value: Option<i64>,
+    /// `market_value - book_value`.
+    unrealized_gain: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct FxGains {
+    /// The budget's currency, which every value but `balance` is in.
+    #[serde(flatten)]
+    currency: Currency,
+    date: NaiveDate,
+    accounts: Vec<FxGainAccount>,
+    /// Over the accounts with a rate.
+    unrealized_gain: i64,
+}
+
+/// `GET /api/budgets/:id/reports/fx-gains?date=YYYY-MM-DD`: for each open
+/// account in a foreign currency, what its balance would fetch at the day's
+/// rate against what it was booked at.
+pub(crate) async fn fx_gains(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+    Query(query): Query<FxGainsQuery>,
+) -> Result<Json<FxGains>, StatusCode> {
+    let (mut conn, budget_id, currency) = open_budget(&state, &headers, &budget_pillid).await?;
+    let date = query.date.unwrap_or_else(|| Utc::now().date_naive());
+    let rows: Vec<ForeignAccount> = sqlx::query_as(
+        "select a.pillid as id, a.name, a.currency_code,
+                coalesce(sum(coalesce(ts.account_inflow, ts.inflow) - coalesce(ts.account_outflow, ts.outflow)), 0)::bigint as balance,
+                coalesce(sum(ts.inflow - ts.outflow), 0)::bigint as book_value
+         from accounts a
+         left join transactions t on t.account_id = a.id and t.deleted_at is null and t.tx_date <= $3
+         left join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+         where a.budget_id = $1 and a.deleted_at is null and a.closed_at is null
+           and a.currency_code <> $2
+         group by a.id
+         order by a.created_at, a.pillid",
+    )
+    .bind(budget_id)
+    .bind(currency.code())
+    .bind(date)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut accounts = Vec::with_capacity(rows.len());
+    for row in rows {
+        let account_currency = Currency::stored(&row.currency_code);
+        let market_value = convert(&mut conn, &account_currency, &currency, date, row.balance)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        accounts.push(FxGainAccount {
+            id: row.id,
+            name: row.name,
+            currency: account_currency,
+            balance: row.balance,
+            book_value: row.book_value,
+            market_value,
+            unrealized_gain: market_value.map(|value| value - row.book_value),
+        });
+    }
+    let unrealized_gain = accounts.iter().filter_map(|a| a.unrealized_gain).sum();
+    Ok(Json(FxGains {
+        currency,
+        date,
+        accounts,
+        unrealized_gain,
+    }))
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn parse_rates_checks_codes_and_rates() {
+        let rates = parse_rates("date,base,quote,rate\n2026-03-31, EUR ,USD,1.0832\n").unwrap();
+        assert_eq!(
+            rates,
+            [ExchangeRate {
+                date: NaiveDate::from_ymd_opt(2026, 3, 31).unwrap(),
+                base: "EUR".into(),
+                quote: "USD".into(),
+                rate: "1.0832".into(),
+            }]
+        );
+        for bad in [
+            "2026-03-31,EUR,XYZ,1.1",
+            "2026-03-31,EUR,EUR,1",
+            "2026-03-31,EUR,USD,0",
+            "2026-03-31,EUR,USD,-1.1",
+            "2026-03-31,EUR,USD,1e3",
+            "31/03/2026,EUR,USD,1.1",
+        ] {
+            let text = format!("date,base,quote,rate\n{bad}\n");
+            assert!(parse_rates(&text).is_err(), "{bad}");
+        }
+    }
+}
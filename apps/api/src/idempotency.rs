@@ -0,0 +1,271 @@
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::Request;
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::Method;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::Response;
+use sha2::Digest;
+use sha2::Sha256;
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::user_from_headers;
+use crate::AppState;
+use crate::ARCHIVE_IMPORT_PATH;
+use crate::MAX_ARCHIVE_BYTES;
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const IDEMPOTENT_REPLAY_HEADER: &str = "idempotent-replayed";
+
+/// How long a stored response stays replayable for the same key.
+const IDEMPOTENCY_WINDOW_HOURS: i32 = 24;
+/// A reservation whose heartbeat is older than this belongs to a request that
+/// is no longer running (e.g. the client hung up mid-handler or the server
+/// restarted), and the next retry runs the handler again.
+const IN_FLIGHT_TIMEOUT_SECONDS: i32 = 60;
+/// How often a running request refreshes its reservation's heartbeat; well
+/// inside the timeout so one slow write never lets a live request be reclaimed.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Responses here carry session tokens, which must not be stored in the clear.
+const NOT_REPLAYED_PREFIX: &str = "/api/auth/";
+const MAX_KEY_LEN: usize = 255;
+/// axum's default body limit, which every route but the archive import keeps.
+const MAX_BUFFERED_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+type StoredResponse = (String, Option<i16>, Option<String>, Option<Vec<u8>>);
+
+/// Middleware for POST requests carrying an `Idempotency-Key` header.
+///
+/// The first request for a key (scoped to the calling user) reserves the key
+/// and stores the handler's response. Retries with the same key and the same
+/// method/path/body get the stored response back instead of re-running the
+/// handler. Reusing a key for a different request is rejected with 422, and a
+/// retry that races the original gets 409. Anonymous requests and the auth
+/// endpoints run as if the header were absent.
+pub(crate) async fn replay_idempotent_post(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    if request.method() != Method::POST || request.uri().path().starts_with(NOT_REPLAYED_PREFIX) {
+        return Ok(next.run(request).await);
+    }
+    let Some(raw_key) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(next.run(request).await);
+    };
+    let key = raw_key
+        .to_str()
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+        .trim()
+        .to_string();
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let Ok(user_id) = user_from_headers(&state, request.headers()).await else {
+        return Ok(next.run(request).await);
+    };
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, max_body_bytes(parts.uri.path()))
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or_else(|| parts.uri.path())
+        .to_string();
+    let request_hash = request_fingerprint(&parts.method, &path, &bytes);
+
+    // An expired key, or one whose request was abandoned mid-flight, is
+    // reserved afresh.
+    let reserved: Option<(Uuid,)> = sqlx::query_as(
+        "insert into idempotency_keys (user_id, user_pillid, idempotency_key, request_method, request_path, request_hash, expires_at)
+         select $1, (select pillid from users where id = $1), $2, $3, $4, $5, now() + make_interval(hours => $6)
+         on conflict (user_id, idempotency_key) do update set
+           request_method = excluded.request_method, request_path = excluded.request_path,
+           request_hash = excluded.request_hash, response_status = null,
+           response_content_type = null, response_body = null, created_at = now(),
+           heartbeat_at = now(), completed_at = null, expires_at = excluded.expires_at
+         where idempotency_keys.expires_at <= now()
+            or (idempotency_keys.response_status is null
+                and idempotency_keys.heartbeat_at < now() - make_interval(secs => $7))
+         returning id",
+    )
+    .bind(user_id)
+    .bind(&key)
+    .bind(parts.method.as_str())
+    .bind(&path)
+    .bind(&request_hash)
+    .bind(IDEMPOTENCY_WINDOW_HOURS)
+    .bind(IN_FLIGHT_TIMEOUT_SECONDS)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let Some((reservation_id,)) = reserved else {
+        let stored: StoredResponse = sqlx::query_as(
+            "select request_hash, response_status, response_content_type, response_body from idempotency_keys where user_id = $1 and idempotency_key = $2",
+        )
+        .bind(user_id)
+        .bind(&key)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::CONFLICT)?;
+        return replay_stored_response(stored, &request_hash);
+    };
+
+    let heartbeat = Heartbeat::start(state.db.clone(), reservation_id);
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+    drop(heartbeat);
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if parts.status.is_server_error() {
+        // Let the client retry failures instead of pinning them to the key.
+        sqlx::query("delete from idempotency_keys where id = $1")
+            .bind(reservation_id)
+            .execute(&state.db)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    } else {
+        let content_type = parts
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        sqlx::query("update idempotency_keys set response_status = $2, response_content_type = $3, response_body = $4, completed_at = now() where id = $1")
+            .bind(reservation_id)
+            .bind(parts.status.as_u16() as i16)
+            .bind(content_type)
+            .bind(bytes.to_vec())
+            .execute(&state.db)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    Ok(Response::from_parts(parts, Body::from(bytes)))
+}
+
+/// Keeps a reservation's heartbeat fresh for as long as it is alive. Dropping
+/// it stops the beat, including when the middleware future itself is dropped
+/// because the client went away, which leaves the key reclaimable.
+struct Heartbeat(JoinHandle<()>);
+
+impl Heartbeat {
+    fn start(db: PgPool, reservation_id: Uuid) -> Self {
+        Self(tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(HEARTBEAT_INTERVAL);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                // A missed beat is retried on the next tick; only a run of
+                // them as long as the timeout gives the key away.
+                let _ = sqlx::query(
+                    "update idempotency_keys set heartbeat_at = now() where id = $1 and response_status is null",
+                )
+                .bind(reservation_id)
+                .execute(&db)
+                .await;
+            }
+        }))
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Deletes expired keys and returns how many went. Run periodically next to
+/// the trash purge; an expired key is also reclaimed when it is reused.
+pub async fn purge_idempotency_keys(db: &PgPool) -> Result<u64, sqlx::Error> {
+    Ok(
+        sqlx::query("delete from idempotency_keys where expires_at <= now()")
+            .execute(db)
+            .await?
+            .rows_affected(),
+    )
+}
+
+/// The body limit the router applies to `path`, so buffering here never turns
+/// away a request the handler itself would accept.
+fn max_body_bytes(path: &str) -> usize {
+    if path == ARCHIVE_IMPORT_PATH {
+        MAX_ARCHIVE_BYTES
+    } else {
+        MAX_BUFFERED_BODY_BYTES
+    }
+}
+
+fn replay_stored_response(
+    (stored_hash, status, content_type, body): StoredResponse,
+    request_hash: &str,
+) -> Result<Response, StatusCode> {
+    if stored_hash != request_hash {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    // No stored status means the original request is still in flight.
+    let status = status.ok_or(StatusCode::CONFLICT)?;
+    let status =
+        StatusCode::from_u16(status as u16).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut builder = Response::builder()
+        .status(status)
+        .header(IDEMPOTENT_REPLAY_HEADER, "true");
+    if let Some(content_type) = content_type {
+        builder = builder.header(CONTENT_TYPE, content_type);
+    }
+    builder
+        .body(Body::from(body.unwrap_or_default()))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn request_fingerprint(method: &Method, path: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(path.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_depends_on_path_and_body() {
+        let a = request_fingerprint(&Method::POST, "/api/transactions", b"{}");
+        assert_eq!(
+            a,
+            request_fingerprint(&Method::POST, "/api/transactions", b"{}")
+        );
+        assert_ne!(
+            a,
+            request_fingerprint(&Method::POST, "/api/accounts", b"{}")
+        );
+        assert_ne!(
+            a,
+            request_fingerprint(&Method::POST, "/api/transactions", b"{\"a\":1}")
+        );
+    }
+
+    #[test]
+    fn archive_import_buffers_up_to_its_own_limit() {
+        assert_eq!(max_body_bytes(ARCHIVE_IMPORT_PATH), MAX_ARCHIVE_BYTES);
+        assert_eq!(max_body_bytes("/api/transactions"), MAX_BUFFERED_BODY_BYTES);
+    }
+}
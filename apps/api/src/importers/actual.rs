@@ -0,0 +1,602 @@
+//! Actual Budget exports: the zip from "Export data" (a `db.sqlite` plus
+//! `metadata.json`), or the `db.sqlite` on its own.
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+
+use chrono::Days;
+use chrono::Months;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+use sqlx::SqliteConnection;
+use uuid::Uuid;
+
+use super::open_sqlite;
+use super::rescale;
+use super::to_minor;
+use super::ArchiveBuilder;
+use super::ConvertError;
+use super::Imported;
+use super::ScheduleLine;
+use super::SplitLine;
+use super::TRANSFERS;
+use super::UNCATEGORIZED;
+use crate::money::minor_digits;
+
+const DEFAULT_NAME: &str = "Actual import";
+
+/// Actual stores every amount in hundredths.
+const ACTUAL_DIGITS: u32 = 2;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metadata {
+    budget_name: Option<String>,
+}
+
+/// The database unpacked from an export zip, deleted when dropped.
+struct Unpacked(PathBuf);
+
+impl Drop for Unpacked {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Converts an export zip or a bare `db.sqlite`. Actual does not record a
+/// currency, so it has to be given.
+pub async fn from_file(
+    path: &Path,
+    name: Option<&str>,
+    currency_code: &str,
+) -> Result<Imported, ConvertError> {
+    let mut magic = [0u8; 4];
+    File::open(path)?.read_exact(&mut magic)?;
+    if magic != *b"PK\x03\x04" {
+        let mut conn = open_sqlite(path).await?;
+        return convert(&mut conn, name.unwrap_or(DEFAULT_NAME), currency_code).await;
+    }
+
+    let mut zip = zip::ZipArchive::new(File::open(path)?)
+        .map_err(|err| ConvertError(format!("invalid zip: {err}")))?;
+    let metadata: Option<Metadata> = match zip.by_name("metadata.json") {
+        Ok(mut entry) => {
+            let mut json = String::new();
+            entry.read_to_string(&mut json)?;
+            serde_json::from_str(&json).ok()
+        }
+        Err(_) => None,
+    };
+    let unpacked = Unpacked(
+        std::env::temp_dir().join(format!("envelopezero-actual-{}.sqlite", Uuid::now_v7())),
+    );
+    {
+        let mut entry = zip
+            .by_name("db.sqlite")
+            .map_err(|_| ConvertError("zip has no db.sqlite".into()))?;
+        io::copy(&mut entry, &mut File::create(&unpacked.0)?)?;
+    }
+    let name = name
+        .map(str::to_string)
+        .or_else(|| metadata.and_then(|m| m.budget_name))
+        .unwrap_or_else(|| DEFAULT_NAME.to_string());
+    let mut conn = open_sqlite(&unpacked.0).await?;
+    convert(&mut conn, &name, currency_code).await
+}
+
+/// One condition of a rule; a schedule keeps its account, payee, amount and
+/// date in the conditions of its rule.
+#[derive(Deserialize)]
+struct Condition {
+    field: String,
+    op: String,
+    value: Value,
+}
+
+/// A recurring schedule date. Weekday and month-day `patterns` have no
+/// equivalent here.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Recurrence {
+    start: NaiveDate,
+    frequency: String,
+    interval: Option<u32>,
+    #[serde(default)]
+    patterns: Vec<Value>,
+    end_mode: Option<String>,
+    end_occurrences: Option<u32>,
+    end_date: Option<NaiveDate>,
+}
+
+impl Recurrence {
+    /// The frequency this schema calls it, and the last date it lands on.
+    fn mapped(&self) -> Result<(&'static str, Option<NaiveDate>), &'static str> {
+        if !self.patterns.is_empty() {
+            return Err("schedule on a weekday or day pattern skipped (no equivalent)");
+        }
+        let (frequency, step) = match (self.frequency.as_str(), self.interval.unwrap_or(1)) {
+            ("weekly", 1) => ("weekly", Step::Days(7)),
+            ("weekly", 2) => ("every_other_week", Step::Days(14)),
+            ("monthly", 1) => ("monthly", Step::Months(1)),
+            ("yearly", 1) => ("yearly", Step::Months(12)),
+            _ => return Err("schedule with an interval that has no equivalent skipped"),
+        };
+        let end_date = match self.end_mode.as_deref() {
+            Some("on_date") => self.end_date,
+            Some("after_n_occurrences") => {
+                let n = self.end_occurrences.unwrap_or(1).saturating_sub(1);
+                match step {
+                    Step::Days(days) => self.start.checked_add_days(Days::new(days * u64::from(n))),
+                    Step::Months(months) => self.start.checked_add_months(Months::new(months * n)),
+                }
+            }
+            _ => None,
+        };
+        Ok((frequency, end_date))
+    }
+}
+
+enum Step {
+    Days(u64),
+    Months(u32),
+}
+
+/// The monthly amount of a category's goal templates when they are all plain
+/// `#template <amount>` lines, which Actual adds up. The other kinds depend
+/// on schedules, balances or due dates, so there is no fixed monthly amount
+/// to target.
+fn monthly_template(goal_def: &str, digits: u32) -> Option<i64> {
+    let templates: Vec<Value> = serde_json::from_str(goal_def).ok()?;
+    let mut total = 0;
+    for template in &templates {
+        if template["type"] != "simple" || template["directive"] == "goal" {
+            return None;
+        }
+        total += to_minor(template["monthly"].as_f64()?, digits);
+    }
+    (!templates.is_empty()).then_some(total)
+}
+
+/// A column that not every Actual version has reads as `None`.
+fn column<'r, T>(row: &'r SqliteRow, name: &str) -> Option<T>
+where
+    T: sqlx::Decode<'r, sqlx::Sqlite> + sqlx::Type<sqlx::Sqlite>,
+{
+    row.try_get::<Option<T>, _>(name).ok().flatten()
+}
+
+fn flag(row: &SqliteRow, name: &str) -> bool {
+    column::<i64>(row, name).unwrap_or(0) != 0
+}
+
+/// `20240105` → 2024-01-05.
+fn parse_date(date: i64) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(
+        (date / 10000) as i32,
+        (date / 100 % 100) as u32,
+        (date % 100) as u32,
+    )
+}
+
+async fn live_rows(
+    conn: &mut SqliteConnection,
+    table: &str,
+) -> Result<Vec<SqliteRow>, sqlx::Error> {
+    let rows = sqlx::query(&format!("select * from {table}"))
+        .fetch_all(&mut *conn)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .filter(|row| !flag(row, "tombstone"))
+        .collect())
+}
+
+/// Live rows of a table that older versions may not have; none if missing.
+async fn optional_rows(conn: &mut SqliteConnection, table: &str) -> Vec<SqliteRow> {
+    live_rows(conn, table).await.unwrap_or_default()
+}
+
+pub(crate) async fn convert(
+    conn: &mut SqliteConnection,
+    name: &str,
+    currency_code: &str,
+) -> Result<Imported, ConvertError> {
+    let digits = minor_digits(currency_code);
+    let amount =
+        |row: &SqliteRow| rescale(column(row, "amount").unwrap_or(0), ACTUAL_DIGITS, digits);
+    let mut builder = ArchiveBuilder::new(name, currency_code);
+
+    let groups: HashMap<String, String> = live_rows(conn, "category_groups")
+        .await?
+        .iter()
+        .filter_map(|row| Some((column(row, "id")?, column(row, "name")?)))
+        .collect();
+    let mut categories: HashMap<String, String> = HashMap::new();
+    for row in live_rows(conn, "categories").await? {
+        let (Some(id), Some(category_name)) =
+            (column::<String>(&row, "id"), column::<String>(&row, "name"))
+        else {
+            continue;
+        };
+        let Some(group) = column::<String>(&row, "cat_group").and_then(|g| groups.get(&g)) else {
+            builder.note("category without a group skipped");
+            continue;
+        };
+        let category_id = builder.category(group, &category_name);
+        if flag(&row, "is_income") {
+            builder.mark_income(&category_id);
+        }
+        if let Some(goal) = column::<String>(&row, "goal_def").filter(|goal| !goal.is_empty()) {
+            match monthly_template(&goal, digits) {
+                Some(monthly_amount) => builder.target(&category_id, monthly_amount),
+                None => builder.note(
+                    "category goal template not imported (only fixed monthly amounts map to a target)",
+                ),
+            }
+        }
+        categories.insert(id, category_id);
+    }
+    // Merged categories leave a mapping from the old id to the surviving one.
+    for row in optional_rows(conn, "category_mapping").await {
+        if let (Some(from), Some(to)) = (
+            column::<String>(&row, "id"),
+            column::<String>(&row, "transferId"),
+        ) {
+            if let Some(category_id) = categories.get(&to).cloned() {
+                categories.entry(from).or_insert(category_id);
+            }
+        }
+    }
+
+    let mut accounts: HashMap<String, String> = HashMap::new();
+    for row in live_rows(conn, "accounts").await? {
+        let (Some(id), Some(account_name)) =
+            (column::<String>(&row, "id"), column::<String>(&row, "name"))
+        else {
+            continue;
+        };
+        if flag(&row, "offbudget") {
+            builder.note("off-budget account imported as a regular account");
+        }
+        accounts.insert(id, builder.account(&account_name, flag(&row, "closed")));
+    }
+
+    let payees: HashMap<String, String> = live_rows(conn, "payees")
+        .await?
+        .iter()
+        .filter_map(|row| Some((column(row, "id")?, column(row, "name")?)))
+        .collect();
+    let mut payee_targets: HashMap<String, String> = HashMap::new();
+    for row in optional_rows(conn, "payee_mapping").await {
+        if let (Some(from), Some(to)) = (column(&row, "id"), column(&row, "targetId")) {
+            payee_targets.insert(from, to);
+        }
+    }
+    let payee_name = |id: Option<String>| {
+        let id = id?;
+        let id = payee_targets.get(&id).unwrap_or(&id);
+        payees.get(id).cloned()
+    };
+
+    let transactions = live_rows(conn, "transactions").await?;
+    let mut children: HashMap<String, Vec<&SqliteRow>> = HashMap::new();
+    for row in transactions.iter().filter(|row| flag(row, "isChild")) {
+        if let Some(parent) = column::<String>(row, "parent_id") {
+            children.entry(parent).or_default().push(row);
+        }
+    }
+    let category_for = |builder: &mut ArchiveBuilder, row: &SqliteRow| {
+        if let Some(category_id) =
+            column::<String>(row, "category").and_then(|c| categories.get(&c))
+        {
+            return category_id.clone();
+        }
+        let (group, category_name) = if column::<String>(row, "transferred_id").is_some() {
+            builder.note("transfer between accounts booked to Imported/Transfers");
+            TRANSFERS
+        } else {
+            builder.note("uncategorized transaction booked to Imported/Uncategorized");
+            UNCATEGORIZED
+        };
+        builder.category(group, category_name)
+    };
+
+    for row in transactions.iter().filter(|row| !flag(row, "isChild")) {
+        let Some(account_id) = column::<String>(row, "acct").and_then(|a| accounts.get(&a)) else {
+            builder.note("transaction in a deleted account skipped");
+            continue;
+        };
+        let Some(date) = column::<i64>(row, "date").and_then(parse_date) else {
+            builder.note("transaction with an unreadable date skipped");
+            continue;
+        };
+        let id = column::<String>(row, "id").unwrap_or_default();
+        let lines = match children.get(&id) {
+            Some(lines) if flag(row, "isParent") => lines
+                .iter()
+                .map(|child| SplitLine {
+                    category_id: category_for(&mut builder, child),
+                    memo: column(child, "notes"),
+                    amount: amount(child),
+                })
+                .collect(),
+            _ => vec![SplitLine {
+                category_id: category_for(&mut builder, row),
+                memo: None,
+                amount: amount(row),
+            }],
+        };
+        builder.transaction(
+            &account_id.clone(),
+            date,
+            payee_name(column(row, "description")),
+            column(row, "notes"),
+            flag(row, "reconciled"),
+            lines,
+        );
+    }
+
+    // Envelope budgets keep their amounts in zero_budgets, tracking budgets
+    // in reflect_budgets; a file has whichever it was using.
+    let mut budgeted = optional_rows(conn, "zero_budgets").await;
+    if budgeted.is_empty() {
+        budgeted = optional_rows(conn, "reflect_budgets").await;
+        if !budgeted.is_empty() {
+            builder.note("tracking budget amounts imported as category assignments");
+        }
+    }
+    for row in &budgeted {
+        let Some(category_id) = column::<String>(row, "category").and_then(|c| categories.get(&c))
+        else {
+            builder.note("budgeted amount for an unknown category skipped");
+            continue;
+        };
+        let Some(month) = column::<i64>(row, "month").and_then(|m| parse_date(m * 100 + 1)) else {
+            builder.note("budgeted amount with an unreadable month skipped");
+            continue;
+        };
+        builder.assign(&category_id.clone(), month, amount(row));
+    }
+
+    let mut rules: HashMap<String, Option<String>> = optional_rows(conn, "rules")
+        .await
+        .iter()
+        .filter_map(|row| Some((column(row, "id")?, column(row, "conditions"))))
+        .collect();
+    let next_dates: HashMap<String, NaiveDate> = optional_rows(conn, "schedules_next_date")
+        .await
+        .iter()
+        .filter_map(|row| {
+            let date = column::<i64>(row, "local_next_date").and_then(parse_date)?;
+            Some((column(row, "schedule_id")?, date))
+        })
+        .collect();
+    for row in optional_rows(conn, "schedules").await {
+        let conditions = column::<String>(&row, "rule")
+            .and_then(|rule| rules.remove(&rule))
+            .flatten()
+            .and_then(|conditions| serde_json::from_str::<Vec<Condition>>(&conditions).ok());
+        if flag(&row, "completed") {
+            builder.note("completed schedule skipped");
+            continue;
+        }
+        let Some(conditions) = conditions else {
+            builder.note("schedule without readable conditions skipped");
+            continue;
+        };
+        let next_date = column::<String>(&row, "id").and_then(|id| next_dates.get(&id).copied());
+        let (mut account_id, mut payee, mut amount, mut date) = (None, None, None, None);
+        for condition in &conditions {
+            match (condition.field.as_str(), condition.op.as_str()) {
+                ("account", "is") => {
+                    account_id = condition.value.as_str().and_then(|a| accounts.get(a));
+                }
+                ("payee", "is") => payee = payee_name(condition.value.as_str().map(str::to_string)),
+                ("amount", "is" | "isapprox") => amount = condition.value.as_i64(),
+                ("amount", "isbetween") => {
+                    builder.note("schedule amount range imported as its midpoint");
+                    let bound = |key: &str| condition.value[key].as_i64();
+                    amount = bound("num1")
+                        .zip(bound("num2"))
+                        .map(|(low, high)| (low + high) / 2);
+                }
+                ("date", "is" | "isapprox") => date = Some(&condition.value),
+                _ => {}
+            }
+        }
+        let (Some(account_id), Some(amount), Some(date)) = (account_id, amount, date) else {
+            builder.note("schedule without an account, amount or date skipped");
+            continue;
+        };
+        let mapped = match date {
+            Value::String(day) => NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                .map(|day| (day, "once", None))
+                .map_err(|_| "schedule with an unreadable date skipped"),
+            recurrence => serde_json::from_value::<Recurrence>(recurrence.clone())
+                .map_err(|_| "schedule with an unreadable date skipped")
+                .and_then(|recurrence| {
+                    let (frequency, end_date) = recurrence.mapped()?;
+                    Ok((recurrence.start, frequency, end_date))
+                }),
+        };
+        let (start, frequency, end_date) = match mapped {
+            Ok(mapped) => mapped,
+            Err(why) => {
+                builder.note(why);
+                continue;
+            }
+        };
+        builder.schedule(ScheduleLine {
+            account_id: account_id.clone(),
+            payee,
+            amount: rescale(amount, ACTUAL_DIGITS, digits),
+            frequency,
+            next_date: next_date.unwrap_or(start),
+            end_date,
+        });
+    }
+    for _ in rules.into_values() {
+        builder.note("rule not imported (no equivalent)");
+    }
+    Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use sqlx::Connection;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn actual_maps_groups_splits_and_budgets() {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        for sql in [
+            "create table accounts (id text, name text, offbudget integer, closed integer, tombstone integer)",
+            "create table category_groups (id text, name text, is_income integer, tombstone integer)",
+            "create table categories (id text, name text, cat_group text, is_income integer, tombstone integer)",
+            "create table category_mapping (id text, transferId text)",
+            "create table payees (id text, name text, transfer_acct text, tombstone integer)",
+            "create table transactions (id text, isParent integer, isChild integer, acct text, category text, amount integer, description text, notes text, date integer, parent_id text, tombstone integer, transferred_id text)",
+            "create table zero_budgets (id text, month integer, category text, amount integer)",
+            "insert into accounts values ('a', 'Checking', 0, 0, 0), ('b', 'Old', 1, 1, 0), ('x', 'Gone', 0, 0, 1)",
+            "insert into category_groups values ('g', 'Bills', 0, 0), ('i', 'Income', 1, 0)",
+            "insert into categories values ('c', 'Rent', 'g', 0, 0), ('s', 'Salary', 'i', 1, 0)",
+            "insert into category_mapping values ('old', 'c')",
+            "insert into payees values ('p', 'Landlord', null, 0)",
+            "insert into transactions values
+               ('t1', 0, 0, 'a', 's', 250000, null, 'pay', 20240101, null, 0, null),
+               ('t2', 1, 0, 'a', null, -120050, 'p', null, 20240102, null, 0, null),
+               ('t2a', 0, 1, 'a', 'c', -120000, 'p', 'rent', 20240102, 't2', 0, null),
+               ('t2b', 0, 1, 'a', 'old', -50, 'p', null, 20240102, 't2', 0, null),
+               ('t3', 0, 0, 'a', null, -10000, null, null, 20240103, null, 0, 't4'),
+               ('t5', 0, 0, 'x', 'c', -100, null, null, 20240103, null, 0, null)",
+            "insert into zero_budgets values ('202401-c', 202401, 'c', 120000)",
+        ] {
+            sqlx::query(sql).execute(&mut conn).await.unwrap();
+        }
+
+        let imported = convert(&mut conn, "Home", "USD").await.unwrap();
+        let archive = &imported.archive;
+        let accounts: Vec<_> = archive
+            .accounts
+            .iter()
+            .map(|a| (a.name.as_str(), a.closed_at.is_some()))
+            .collect();
+        assert_eq!(accounts, [("Checking", false), ("Old", true)]);
+        assert_eq!(archive.transactions.len(), 3);
+        let split = &archive.transactions[1];
+        assert_eq!(split.payee.as_deref(), Some("Landlord"));
+        let lines: Vec<_> = split
+            .splits
+            .iter()
+            .map(|s| (s.outflow, s.memo.as_deref()))
+            .collect();
+        assert_eq!(lines, [(120000, Some("rent")), (50, None)]);
+        assert_eq!(split.splits[0].category_id, split.splits[1].category_id);
+        assert_eq!(archive.category_assignments[0].amount, 120000);
+        let income: Vec<_> = archive
+            .categories
+            .iter()
+            .filter(|c| c.is_income)
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(income, ["Salary"]);
+        assert_eq!(
+            imported.unmapped["transaction in a deleted account skipped"],
+            1
+        );
+        assert_eq!(
+            imported.unmapped["transfer between accounts booked to Imported/Transfers"],
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn actual_maps_schedules_and_goal_templates() {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        let monthly = r#"[{"field":"account","op":"is","value":"a"},{"field":"payee","op":"is","value":"p"},{"field":"amount","op":"isapprox","value":-120000},{"field":"date","op":"isapprox","value":{"start":"2024-01-01","frequency":"monthly","interval":1,"endMode":"after_n_occurrences","endOccurrences":12}}]"#;
+        let once = r#"[{"field":"account","op":"is","value":"a"},{"field":"amount","op":"isbetween","value":{"num1":1000,"num2":3000}},{"field":"date","op":"is","value":"2024-03-15"}]"#;
+        let daily = r#"[{"field":"account","op":"is","value":"a"},{"field":"amount","op":"is","value":-500},{"field":"date","op":"is","value":{"start":"2024-01-01","frequency":"daily"}}]"#;
+        for sql in [
+            "create table accounts (id text, name text, offbudget integer, closed integer, tombstone integer)",
+            "create table category_groups (id text, name text, tombstone integer)",
+            "create table categories (id text, name text, cat_group text, goal_def text, tombstone integer)",
+            "create table payees (id text, name text, tombstone integer)",
+            "create table transactions (id text, isParent integer, isChild integer, acct text, category text, amount integer, description text, notes text, date integer, parent_id text, tombstone integer)",
+            "create table rules (id text, conditions text, tombstone integer)",
+            "create table schedules (id text, rule text, completed integer, tombstone integer)",
+            "create table schedules_next_date (id text, schedule_id text, local_next_date integer, tombstone integer)",
+            "insert into accounts values ('a', 'Checking', 0, 0, 0)",
+            "insert into category_groups values ('g', 'Bills', 0)",
+            r#"insert into categories values
+               ('c', 'Rent', 'g', '[{"type":"simple","monthly":1200,"directive":"template"}]', 0),
+               ('w', 'Water', 'g', '[{"type":"schedule","name":"Water","directive":"template"}]', 0)"#,
+            "insert into payees values ('p', 'Landlord', 0)",
+            "insert into schedules values ('s1', 'r1', 0, 0), ('s2', 'r2', 0, 0), ('s3', 'r3', 0, 0)",
+            "insert into schedules_next_date values ('n1', 's1', 20240201, 0)",
+            "insert into rules values ('r4', '[]', 0)",
+        ] {
+            sqlx::query(sql).execute(&mut conn).await.unwrap();
+        }
+        for (id, conditions) in [("r1", monthly), ("r2", once), ("r3", daily)] {
+            sqlx::query("insert into rules values (?, ?, 0)")
+                .bind(id)
+                .bind(conditions)
+                .execute(&mut conn)
+                .await
+                .unwrap();
+        }
+
+        let imported = convert(&mut conn, "Home", "USD").await.unwrap();
+        let archive = &imported.archive;
+        let schedules: Vec<_> = archive
+            .scheduled_transactions
+            .iter()
+            .map(|s| {
+                (
+                    s.payee.as_deref(),
+                    s.inflow,
+                    s.outflow,
+                    s.frequency.as_str(),
+                    s.next_date.to_string(),
+                    s.end_date.map(|d| d.to_string()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            schedules,
+            [
+                (
+                    Some("Landlord"),
+                    0,
+                    120000,
+                    "monthly",
+                    "2024-02-01".to_string(),
+                    Some("2024-12-01".to_string())
+                ),
+                (None, 2000, 0, "once", "2024-03-15".to_string(), None),
+            ]
+        );
+        assert_eq!(archive.category_targets.len(), 1);
+        assert_eq!(archive.category_targets[0].monthly_amount, 120000);
+        assert_eq!(
+            imported.unmapped["schedule with an interval that has no equivalent skipped"],
+            1
+        );
+        assert_eq!(
+            imported.unmapped[
+                "category goal template not imported (only fixed monthly amounts map to a target)"
+            ],
+            1
+        );
+        assert_eq!(imported.unmapped["rule not imported (no equivalent)"], 1);
+    }
+}
@@ -0,0 +1,729 @@
+//! GnuCash books, in the XML format (usually gzip-compressed) or SQLite.
+//!
+//! GnuCash is double-entry: asset and liability accounts become accounts,
+//! income, expense and equity accounts become categories, and each
+//! transaction becomes one transaction per account it touches.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use chrono::Months;
+use chrono::NaiveDate;
+use flate2::read::GzDecoder;
+use roxmltree::Document;
+use roxmltree::Node;
+use sqlx::FromRow;
+use sqlx::SqliteConnection;
+
+use super::open_sqlite;
+use super::ArchiveBuilder;
+use super::ConvertError;
+use super::Imported;
+use super::SplitLine;
+use super::TRANSFERS;
+use crate::money::minor_digits;
+
+const DEFAULT_NAME: &str = "GnuCash import";
+
+/// The parts of a book the conversion uses, whichever format it came from.
+#[derive(Default)]
+struct Book {
+    root: String,
+    accounts: Vec<Account>,
+    transactions: Vec<Transaction>,
+    budgets: Vec<Budget>,
+    scheduled: usize,
+}
+
+struct Account {
+    id: String,
+    name: String,
+    kind: String,
+    parent: Option<String>,
+    commodity: Option<String>,
+    placeholder: bool,
+    hidden: bool,
+}
+
+struct Transaction {
+    date: NaiveDate,
+    currency: Option<String>,
+    description: Option<String>,
+    notes: Option<String>,
+    splits: Vec<Split>,
+}
+
+struct Split {
+    account: String,
+    memo: Option<String>,
+    /// Value in the transaction's currency, as a fraction.
+    value: (i64, i64),
+    reconciled: bool,
+}
+
+struct Budget {
+    start: NaiveDate,
+    /// Length of a period, or `None` if it is not counted in months.
+    months: Option<u32>,
+    amounts: Vec<BudgetAmount>,
+}
+
+/// (account, period, amount).
+type BudgetAmount = (String, u32, (i64, i64));
+
+/// Reads an XML (plain or gzipped) or SQLite book.
+pub async fn from_file(path: &Path, name: Option<&str>) -> Result<Imported, ConvertError> {
+    let bytes = fs::read(path)?;
+    let book = if bytes.starts_with(b"SQLite format 3\0") {
+        read_sqlite(&mut open_sqlite(path).await?).await?
+    } else if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut xml = String::new();
+        GzDecoder::new(bytes.as_slice()).read_to_string(&mut xml)?;
+        read_xml(&xml)?
+    } else {
+        read_xml(&String::from_utf8_lossy(&bytes))?
+    };
+    convert(book, name.unwrap_or(DEFAULT_NAME))
+}
+
+/// `num/denom` as written in the XML.
+fn parse_fraction(text: &str) -> Option<(i64, i64)> {
+    let (num, denom) = text.trim().split_once('/')?;
+    Some((num.parse().ok()?, denom.parse().ok()?))
+}
+
+/// Dates are `2024-01-05 10:59:00 +0000`, or `20240105105900` in SQLite books
+/// written by GnuCash 2.
+fn parse_date(text: &str) -> Option<NaiveDate> {
+    let text = text.trim();
+    match text.as_bytes().get(4) {
+        Some(b'-') => NaiveDate::parse_from_str(text.get(..10)?, "%Y-%m-%d").ok(),
+        _ => NaiveDate::parse_from_str(text.get(..8)?, "%Y%m%d").ok(),
+    }
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|c| c.tag_name().name() == name)
+}
+
+fn children<'a, 'i: 'a>(node: Node<'a, 'i>, name: &'a str) -> impl Iterator<Item = Node<'a, 'i>> {
+    node.children().filter(move |c| c.tag_name().name() == name)
+}
+
+fn text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|c| c.text())
+}
+
+/// `<slot>`s of a `*:slots` element as (key, value node).
+fn slots<'a, 'i: 'a>(node: Node<'a, 'i>) -> impl Iterator<Item = (&'a str, Node<'a, 'i>)> {
+    children(node, "slot").filter_map(|slot| Some((text(slot, "key")?, child(slot, "value")?)))
+}
+
+fn slot_text<'a>(node: Node<'a, '_>, slots_name: &str, key: &str) -> Option<&'a str> {
+    slots(child(node, slots_name)?)
+        .find(|(k, _)| *k == key)
+        .and_then(|(_, value)| value.text())
+}
+
+fn read_xml(xml: &str) -> Result<Book, ConvertError> {
+    let document =
+        Document::parse(xml).map_err(|err| ConvertError(format!("invalid GnuCash XML: {err}")))?;
+    let book_node = document
+        .descendants()
+        .find(|n| n.tag_name().name() == "book")
+        .ok_or_else(|| ConvertError("no book in GnuCash XML".into()))?;
+
+    // Only direct children: scheduled transactions keep their templates
+    // nested under template-transactions.
+    let mut book = Book::default();
+    for node in book_node.children() {
+        match node.tag_name().name() {
+            "account" => {
+                let account = Account {
+                    id: text(node, "id").unwrap_or_default().to_string(),
+                    name: text(node, "name").unwrap_or_default().to_string(),
+                    kind: text(node, "type").unwrap_or_default().to_string(),
+                    parent: text(node, "parent").map(str::to_string),
+                    commodity: child(node, "commodity")
+                        .and_then(|c| text(c, "id"))
+                        .map(str::to_string),
+                    placeholder: slot_text(node, "slots", "placeholder") == Some("true"),
+                    hidden: slot_text(node, "slots", "hidden") == Some("true"),
+                };
+                if account.kind == "ROOT" {
+                    book.root = account.id.clone();
+                }
+                book.accounts.push(account);
+            }
+            "transaction" => {
+                let Some(date) = child(node, "date-posted")
+                    .and_then(|d| text(d, "date"))
+                    .and_then(parse_date)
+                else {
+                    continue;
+                };
+                let splits = child(node, "splits")
+                    .into_iter()
+                    .flat_map(|s| children(s, "split"))
+                    .filter_map(|split| {
+                        Some(Split {
+                            account: text(split, "account")?.to_string(),
+                            memo: text(split, "memo").map(str::to_string),
+                            value: parse_fraction(text(split, "value")?)?,
+                            reconciled: text(split, "reconciled-state") == Some("y"),
+                        })
+                    })
+                    .collect();
+                book.transactions.push(Transaction {
+                    date,
+                    currency: child(node, "currency")
+                        .and_then(|c| text(c, "id"))
+                        .map(str::to_string),
+                    description: text(node, "description").map(str::to_string),
+                    notes: slot_text(node, "slots", "notes").map(str::to_string),
+                    splits,
+                });
+            }
+            "budget" => {
+                let recurrence = child(node, "recurrence");
+                let start = recurrence
+                    .and_then(|r| child(r, "start"))
+                    .and_then(|s| text(s, "gdate"))
+                    .and_then(parse_date);
+                let Some(start) = start else {
+                    continue;
+                };
+                let months = recurrence.and_then(|r| {
+                    period_months(text(r, "period_type")?, text(r, "mult")?.parse().ok()?)
+                });
+                let mut amounts = Vec::new();
+                for (account, periods) in child(node, "slots").into_iter().flat_map(slots) {
+                    for (period, value) in slots(periods) {
+                        if let (Ok(period), Some(amount)) =
+                            (period.parse(), value.text().and_then(parse_fraction))
+                        {
+                            amounts.push((account.to_string(), period, amount));
+                        }
+                    }
+                }
+                book.budgets.push(Budget {
+                    start,
+                    months,
+                    amounts,
+                });
+            }
+            "schedxaction" => book.scheduled += 1,
+            _ => {}
+        }
+    }
+    Ok(book)
+}
+
+#[derive(FromRow)]
+struct AccountRow {
+    guid: String,
+    name: String,
+    account_type: String,
+    parent_guid: Option<String>,
+    mnemonic: Option<String>,
+    placeholder: Option<i64>,
+    hidden: Option<i64>,
+}
+
+#[derive(FromRow)]
+struct TransactionRow {
+    guid: String,
+    post_date: String,
+    description: Option<String>,
+    mnemonic: Option<String>,
+    notes: Option<String>,
+}
+
+#[derive(FromRow)]
+struct SplitRow {
+    tx_guid: String,
+    account_guid: String,
+    memo: Option<String>,
+    value_num: i64,
+    value_denom: i64,
+    reconcile_state: Option<String>,
+}
+
+#[derive(FromRow)]
+struct BudgetRow {
+    guid: String,
+    recurrence_mult: Option<i64>,
+    recurrence_period_type: Option<String>,
+    recurrence_period_start: Option<String>,
+}
+
+#[derive(FromRow)]
+struct BudgetAmountRow {
+    budget_guid: String,
+    account_guid: String,
+    period_num: i64,
+    amount_num: i64,
+    amount_denom: i64,
+}
+
+/// Months in a budget period of `mult` `period_type`s, if whole months.
+fn period_months(period_type: &str, mult: u32) -> Option<u32> {
+    match period_type {
+        "month" | "end of month" => Some(mult),
+        "year" => Some(mult * 12),
+        _ => None,
+    }
+}
+
+async fn read_sqlite(conn: &mut SqliteConnection) -> Result<Book, ConvertError> {
+    let (root,): (String,) = sqlx::query_as("select root_account_guid from books limit 1")
+        .fetch_one(&mut *conn)
+        .await?;
+    let accounts = sqlx::query_as::<_, AccountRow>(
+        "select a.guid, a.name, a.account_type, a.parent_guid, c.mnemonic, a.placeholder, a.hidden
+         from accounts a left join commodities c on c.guid = a.commodity_guid",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let transactions = sqlx::query_as::<_, TransactionRow>(
+        "select t.guid, t.post_date, t.description, c.mnemonic,
+                (select s.string_val from slots s where s.obj_guid = t.guid and s.name = 'notes') as notes
+         from transactions t left join commodities c on c.guid = t.currency_guid
+         order by t.post_date",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let splits = sqlx::query_as::<_, SplitRow>(
+        "select tx_guid, account_guid, memo, value_num, value_denom, reconcile_state from splits",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let (scheduled,): (i64,) = sqlx::query_as("select count(*) from schedxactions")
+        .fetch_one(&mut *conn)
+        .await?;
+    let budgets = sqlx::query_as::<_, BudgetRow>(
+        "select b.guid, r.recurrence_mult, r.recurrence_period_type, r.recurrence_period_start
+         from budgets b left join recurrences r on r.obj_guid = b.guid",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let amounts = sqlx::query_as::<_, BudgetAmountRow>(
+        "select budget_guid, account_guid, period_num, amount_num, amount_denom from budget_amounts",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut by_transaction: HashMap<String, Vec<Split>> = HashMap::new();
+    for row in splits {
+        by_transaction.entry(row.tx_guid).or_default().push(Split {
+            account: row.account_guid,
+            memo: row.memo,
+            value: (row.value_num, row.value_denom),
+            reconciled: row.reconcile_state.as_deref() == Some("y"),
+        });
+    }
+    let mut by_budget: HashMap<String, Vec<BudgetAmount>> = HashMap::new();
+    for row in amounts {
+        by_budget.entry(row.budget_guid).or_default().push((
+            row.account_guid,
+            row.period_num as u32,
+            (row.amount_num, row.amount_denom),
+        ));
+    }
+
+    Ok(Book {
+        root,
+        accounts: accounts
+            .into_iter()
+            .map(|row| Account {
+                id: row.guid,
+                name: row.name,
+                kind: row.account_type,
+                parent: row.parent_guid,
+                commodity: row.mnemonic,
+                placeholder: row.placeholder.unwrap_or(0) != 0,
+                hidden: row.hidden.unwrap_or(0) != 0,
+            })
+            .collect(),
+        transactions: transactions
+            .into_iter()
+            .filter_map(|row| {
+                Some(Transaction {
+                    date: parse_date(&row.post_date)?,
+                    currency: row.mnemonic,
+                    description: row.description,
+                    notes: row.notes,
+                    splits: by_transaction.remove(&row.guid).unwrap_or_default(),
+                })
+            })
+            .collect(),
+        budgets: budgets
+            .into_iter()
+            .filter_map(|row| {
+                Some(Budget {
+                    start: parse_date(row.recurrence_period_start.as_deref()?)?,
+                    months: row
+                        .recurrence_period_type
+                        .as_deref()
+                        .zip(row.recurrence_mult)
+                        .and_then(|(kind, mult)| period_months(kind, mult as u32)),
+                    amounts: by_budget.remove(&row.guid).unwrap_or_default(),
+                })
+            })
+            .collect(),
+        scheduled: scheduled as usize,
+    })
+}
+
+enum Role {
+    /// Where money is held: becomes an account.
+    Account(String),
+    /// Where money goes or comes from: becomes a category.
+    Category(String),
+    Skip,
+}
+
+/// `value` (a fraction of a unit) in minor units.
+fn to_minor((num, denom): (i64, i64), digits: u32) -> i64 {
+    if denom == 0 {
+        return 0;
+    }
+    let scaled = num as i128 * 10i128.pow(digits);
+    ((scaled as f64) / denom as f64).round() as i64
+}
+
+/// Path below the root, e.g. `["Expenses", "Auto", "Fuel"]`; `None` for the
+/// template root of scheduled transactions and anything under it.
+fn path<'b>(
+    account: &'b Account,
+    by_id: &HashMap<&str, &'b Account>,
+    root: &str,
+) -> Option<Vec<&'b str>> {
+    let mut segments = vec![account.name.as_str()];
+    let mut parent = account.parent.as_deref();
+    while let Some(id) = parent {
+        if id == root {
+            segments.reverse();
+            return Some(segments);
+        }
+        let next = by_id.get(id)?;
+        segments.push(&next.name);
+        parent = next.parent.as_deref();
+    }
+    None
+}
+
+fn convert(book: Book, name: &str) -> Result<Imported, ConvertError> {
+    let by_id: HashMap<&str, &Account> = book.accounts.iter().map(|a| (a.id.as_str(), a)).collect();
+    let currency = by_id
+        .get(book.root.as_str())
+        .and_then(|root| root.commodity.clone())
+        .or_else(|| book.transactions.iter().find_map(|t| t.currency.clone()))
+        .unwrap_or_else(|| "USD".to_string());
+    let digits = minor_digits(&currency);
+    let mut builder = ArchiveBuilder::new(name, &currency);
+
+    let mut leaf_names: HashMap<&str, usize> = HashMap::new();
+    for account in &book.accounts {
+        *leaf_names.entry(account.name.as_str()).or_default() += 1;
+    }
+
+    let mut roles: HashMap<&str, Role> = HashMap::new();
+    for account in &book.accounts {
+        let Some(segments) = path(account, &by_id, &book.root) else {
+            continue;
+        };
+        let role = match account.kind.as_str() {
+            "BANK" | "CASH" | "ASSET" | "CREDIT" | "LIABILITY" | "RECEIVABLE" | "PAYABLE"
+            | "STOCK" | "MUTUAL" => {
+                if matches!(account.kind.as_str(), "STOCK" | "MUTUAL") {
+                    builder.note("investment account imported by value, not share quantity");
+                }
+                // Leaf names read best; the full path only where they clash.
+                let account_name = match leaf_names[account.name.as_str()] {
+                    1 => account.name.clone(),
+                    _ => segments.join(":"),
+                };
+                if account.placeholder {
+                    Role::Skip
+                } else {
+                    Role::Account(builder.account(&account_name, account.hidden))
+                }
+            }
+            "INCOME" | "EXPENSE" | "EQUITY" => {
+                let (group, category_name) = match segments.split_last() {
+                    Some((leaf, [])) => (leaf.to_string(), leaf.to_string()),
+                    Some((leaf, parents)) => (parents.join(":"), leaf.to_string()),
+                    None => continue,
+                };
+                if account.placeholder {
+                    Role::Skip
+                } else {
+                    let category_id = builder.category(&group, &category_name);
+                    // Opening balances sit in equity and are there to assign
+                    // too.
+                    if matches!(account.kind.as_str(), "INCOME" | "EQUITY") {
+                        builder.mark_income(&category_id);
+                    }
+                    Role::Category(category_id)
+                }
+            }
+            "TRADING" => Role::Skip,
+            _ => continue,
+        };
+        roles.insert(account.id.as_str(), role);
+    }
+
+    for transaction in &book.transactions {
+        if transaction
+            .currency
+            .as_deref()
+            .is_some_and(|c| c != currency)
+        {
+            builder.note(format!(
+                "transaction in another currency imported at face value as {currency}"
+            ));
+        }
+        let mut held: Vec<(&str, &Split)> = Vec::new();
+        let mut lines: Vec<SplitLine> = Vec::new();
+        for split in &transaction.splits {
+            match roles.get(split.account.as_str()) {
+                Some(Role::Account(id)) => held.push((id, split)),
+                Some(Role::Category(id)) => lines.push(SplitLine {
+                    category_id: id.clone(),
+                    memo: split.memo.clone().filter(|m| !m.is_empty()),
+                    amount: -to_minor(split.value, digits),
+                }),
+                Some(Role::Skip) => {
+                    builder.note("split in a trading or placeholder account skipped")
+                }
+                None => builder.note("split in an unknown account skipped"),
+            }
+        }
+        let Some((&(first_account, first_split), others)) = held.split_first() else {
+            builder.note("transaction touching no asset or liability account skipped");
+            continue;
+        };
+
+        // Income and expense splits go with the first account; whatever that
+        // leaves unbalanced, and every other account, is a transfer.
+        let payee = transaction.description.clone().filter(|d| !d.is_empty());
+        let memo = transaction.notes.clone().filter(|n| !n.is_empty());
+        let first_value = to_minor(first_split.value, digits);
+        let remainder = first_value - lines.iter().map(|l| l.amount).sum::<i64>();
+        if remainder != 0 {
+            builder.note("transfer between accounts booked to Imported/Transfers");
+            lines.push(SplitLine {
+                category_id: builder.category(TRANSFERS.0, TRANSFERS.1),
+                memo: None,
+                amount: remainder,
+            });
+        }
+        builder.transaction(
+            first_account,
+            transaction.date,
+            payee.clone(),
+            memo.clone(),
+            first_split.reconciled,
+            lines,
+        );
+        for &(account, split) in others {
+            let category_id = builder.category(TRANSFERS.0, TRANSFERS.1);
+            builder.transaction(
+                account,
+                transaction.date,
+                payee.clone(),
+                memo.clone(),
+                split.reconciled,
+                vec![SplitLine {
+                    category_id,
+                    memo: split.memo.clone().filter(|m| !m.is_empty()),
+                    amount: to_minor(split.value, digits),
+                }],
+            );
+        }
+    }
+
+    let mut budgets = book.budgets.iter();
+    if let Some(budget) = budgets.next() {
+        let expenses: HashSet<&str> = book
+            .accounts
+            .iter()
+            .filter(|a| a.kind == "EXPENSE")
+            .map(|a| a.id.as_str())
+            .collect();
+        match budget.months {
+            Some(months) => {
+                for (account, period, amount) in &budget.amounts {
+                    let category_id = match roles.get(account.as_str()) {
+                        Some(Role::Category(id)) if expenses.contains(account.as_str()) => {
+                            id.clone()
+                        }
+                        _ => {
+                            builder.note("budget amount for a non-expense account skipped");
+                            continue;
+                        }
+                    };
+                    let Some(month) = budget
+                        .start
+                        .checked_add_months(Months::new(period * months))
+                    else {
+                        continue;
+                    };
+                    if months > 1 {
+                        builder
+                            .note("budget period longer than a month assigned to its first month");
+                    }
+                    builder.assign(&category_id, month, to_minor(*amount, digits));
+                }
+            }
+            None => builder.note("budget with periods shorter than a month skipped"),
+        }
+    }
+    for _ in budgets {
+        builder.note("additional budget skipped (only the first is imported)");
+    }
+    for _ in 0..book.scheduled {
+        builder.note(
+            "scheduled transaction not imported (its amounts are formulas on template accounts)",
+        );
+    }
+    Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use sqlx::Connection;
+
+    use super::*;
+
+    const BOOK: &str = r#"<?xml version="1.0" encoding="utf-8" ?>
+<gnc-v2 xmlns:gnc="http://www.gnucash.org/XML/gnc" xmlns:act="http://www.gnucash.org/XML/act"
+  xmlns:trn="http://www.gnucash.org/XML/trn" xmlns:split="http://www.gnucash.org/XML/split"
+  xmlns:ts="http://www.gnucash.org/XML/ts" xmlns:cmdty="http://www.gnucash.org/XML/cmdty"
+  xmlns:slot="http://www.gnucash.org/XML/slot" xmlns:bgt="http://www.gnucash.org/XML/bgt"
+  xmlns:recurrence="http://www.gnucash.org/XML/recurrence">
+<gnc:book version="2.0.0">
+<gnc:account version="2.0.0"><act:name>Root Account</act:name><act:id type="guid">root</act:id><act:type>ROOT</act:type>
+  <act:commodity><cmdty:space>CURRENCY</cmdty:space><cmdty:id>EUR</cmdty:id></act:commodity></gnc:account>
+<gnc:account version="2.0.0"><act:name>Assets</act:name><act:id type="guid">assets</act:id><act:type>ASSET</act:type><act:parent type="guid">root</act:parent>
+  <act:slots><slot><slot:key>placeholder</slot:key><slot:value type="string">true</slot:value></slot></act:slots></gnc:account>
+<gnc:account version="2.0.0"><act:name>Checking</act:name><act:id type="guid">checking</act:id><act:type>BANK</act:type><act:parent type="guid">assets</act:parent></gnc:account>
+<gnc:account version="2.0.0"><act:name>Savings</act:name><act:id type="guid">savings</act:id><act:type>BANK</act:type><act:parent type="guid">assets</act:parent></gnc:account>
+<gnc:account version="2.0.0"><act:name>Expenses</act:name><act:id type="guid">expenses</act:id><act:type>EXPENSE</act:type><act:parent type="guid">root</act:parent>
+  <act:slots><slot><slot:key>placeholder</slot:key><slot:value type="string">true</slot:value></slot></act:slots></gnc:account>
+<gnc:account version="2.0.0"><act:name>Groceries</act:name><act:id type="guid">groceries</act:id><act:type>EXPENSE</act:type><act:parent type="guid">expenses</act:parent></gnc:account>
+<gnc:account version="2.0.0"><act:name>Household</act:name><act:id type="guid">household</act:id><act:type>EXPENSE</act:type><act:parent type="guid">expenses</act:parent></gnc:account>
+<gnc:transaction version="2.0.0"><trn:id type="guid">t1</trn:id>
+  <trn:currency><cmdty:space>CURRENCY</cmdty:space><cmdty:id>EUR</cmdty:id></trn:currency>
+  <trn:date-posted><ts:date>2024-01-05 10:59:00 +0000</ts:date></trn:date-posted>
+  <trn:description>Market</trn:description>
+  <trn:splits>
+    <trn:split><split:id type="guid">s1</split:id><split:reconciled-state>y</split:reconciled-state><split:value>-4550/100</split:value><split:quantity>-4550/100</split:quantity><split:account type="guid">checking</split:account></trn:split>
+    <trn:split><split:id type="guid">s2</split:id><split:memo>food</split:memo><split:reconciled-state>n</split:reconciled-state><split:value>4000/100</split:value><split:quantity>4000/100</split:quantity><split:account type="guid">groceries</split:account></trn:split>
+    <trn:split><split:id type="guid">s3</split:id><split:reconciled-state>n</split:reconciled-state><split:value>550/100</split:value><split:quantity>550/100</split:quantity><split:account type="guid">household</split:account></trn:split>
+  </trn:splits></gnc:transaction>
+<gnc:transaction version="2.0.0"><trn:id type="guid">t2</trn:id>
+  <trn:date-posted><ts:date>2024-01-06 10:59:00 +0000</ts:date></trn:date-posted>
+  <trn:description>Saving</trn:description>
+  <trn:splits>
+    <trn:split><split:id type="guid">s4</split:id><split:reconciled-state>n</split:reconciled-state><split:value>-10000/100</split:value><split:quantity>-10000/100</split:quantity><split:account type="guid">checking</split:account></trn:split>
+    <trn:split><split:id type="guid">s5</split:id><split:reconciled-state>n</split:reconciled-state><split:value>10000/100</split:value><split:quantity>10000/100</split:quantity><split:account type="guid">savings</split:account></trn:split>
+  </trn:splits></gnc:transaction>
+<gnc:budget version="2.0.0"><bgt:id type="guid">b</bgt:id><bgt:name>2024</bgt:name><bgt:num-periods>12</bgt:num-periods>
+  <bgt:recurrence version="1.0.0"><recurrence:mult>1</recurrence:mult><recurrence:period_type>month</recurrence:period_type><recurrence:start><gdate>2024-01-01</gdate></recurrence:start></bgt:recurrence>
+  <bgt:slots><slot><slot:key>groceries</slot:key><slot:value type="frame">
+    <slot><slot:key>0</slot:key><slot:value type="numeric">30000/100</slot:value></slot>
+    <slot><slot:key>1</slot:key><slot:value type="numeric">32000/100</slot:value></slot>
+  </slot:value></slot></bgt:slots></gnc:budget>
+<gnc:schedxaction version="2.0.0"><sx:id xmlns:sx="http://www.gnucash.org/XML/sx" type="guid">sx</sx:id></gnc:schedxaction>
+<gnc:template-transactions>
+  <gnc:transaction version="2.0.0"><trn:id type="guid">tt</trn:id><trn:date-posted><ts:date>2024-01-01 10:59:00 +0000</ts:date></trn:date-posted></gnc:transaction>
+</gnc:template-transactions>
+</gnc:book>
+</gnc-v2>"#;
+
+    #[test]
+    fn xml_book_maps_accounts_categories_and_budget() {
+        let imported = convert(read_xml(BOOK).unwrap(), "Books").unwrap();
+        let archive = &imported.archive;
+        assert_eq!(archive.budget.currency_code, "EUR");
+        let accounts: Vec<_> = archive.accounts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(accounts, ["Checking", "Savings"]);
+        let categories: Vec<_> = archive.categories.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(categories[..2], ["Groceries", "Household"]);
+        assert_eq!(archive.supercategories[0].name, "Expenses");
+
+        let shopping = &archive.transactions[0];
+        assert_eq!(shopping.payee.as_deref(), Some("Market"));
+        assert!(shopping.reconciled_at.is_some());
+        let lines: Vec<_> = shopping
+            .splits
+            .iter()
+            .map(|s| (s.outflow, s.memo.as_deref()))
+            .collect();
+        assert_eq!(lines, [(4000, Some("food")), (550, None)]);
+
+        // The transfer becomes one transaction per account.
+        assert_eq!(archive.transactions.len(), 3);
+        assert_eq!(archive.transactions[1].splits[0].outflow, 10000);
+        assert_eq!(archive.transactions[2].splits[0].inflow, 10000);
+
+        let budgeted: Vec<_> = archive
+            .category_assignments
+            .iter()
+            .map(|a| (a.month.as_str(), a.amount))
+            .collect();
+        assert_eq!(budgeted, [("2024-01", 30000), ("2024-02", 32000)]);
+        assert_eq!(
+            imported.unmapped["scheduled transaction not imported (its amounts are formulas on template accounts)"],
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn sqlite_book_reads_like_xml() {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        for sql in [
+            "create table books (guid text, root_account_guid text, root_template_guid text)",
+            "create table commodities (guid text, namespace text, mnemonic text)",
+            "create table accounts (guid text, name text, account_type text, commodity_guid text, parent_guid text, placeholder integer, hidden integer)",
+            "create table transactions (guid text, currency_guid text, post_date text, description text)",
+            "create table splits (guid text, tx_guid text, account_guid text, memo text, reconcile_state text, value_num integer, value_denom integer)",
+            "create table slots (obj_guid text, name text, string_val text)",
+            "create table schedxactions (guid text)",
+            "create table budgets (guid text, name text, num_periods integer)",
+            "create table recurrences (obj_guid text, recurrence_mult integer, recurrence_period_type text, recurrence_period_start text)",
+            "create table budget_amounts (budget_guid text, account_guid text, period_num integer, amount_num integer, amount_denom integer)",
+            "insert into books values ('book', 'root', 'template')",
+            "insert into commodities values ('usd', 'CURRENCY', 'USD')",
+            "insert into accounts values
+               ('root', 'Root Account', 'ROOT', 'usd', null, 0, 0),
+               ('cash', 'Wallet', 'CASH', 'usd', 'root', 0, 1),
+               ('salary', 'Salary', 'INCOME', 'usd', 'root', 0, 0)",
+            "insert into transactions values ('t', 'usd', '20240131105900', 'Employer')",
+            "insert into slots values ('t', 'notes', 'january')",
+            "insert into splits values
+               ('s1', 't', 'cash', '', 'n', 150000, 100),
+               ('s2', 't', 'salary', '', 'n', -150000, 100)",
+        ] {
+            sqlx::query(sql).execute(&mut conn).await.unwrap();
+        }
+
+        let imported = convert(read_sqlite(&mut conn).await.unwrap(), "Books").unwrap();
+        let archive = &imported.archive;
+        assert!(archive.accounts[0].closed_at.is_some());
+        let transaction = &archive.transactions[0];
+        assert_eq!(
+            transaction.date,
+            NaiveDate::from_ymd_opt(2024, 1, 31).unwrap()
+        );
+        assert_eq!(transaction.memo.as_deref(), Some("january"));
+        assert_eq!(transaction.splits[0].inflow, 150000);
+        assert_eq!(archive.supercategories[0].name, "Salary");
+        assert!(archive.categories[0].is_income);
+    }
+}
@@ -0,0 +1,437 @@
+//! Converters from other budgeting apps' exports into a [`BudgetArchive`],
+//! which is then loaded with [`crate::archive::import_budget`] like any other
+//! archive.
+
+pub mod actual;
+pub mod gnucash;
+pub mod ynab;
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use chrono::DateTime;
+use chrono::Datelike;
+use chrono::NaiveDate;
+use chrono::Utc;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::ConnectOptions;
+use sqlx::SqliteConnection;
+
+use crate::archive::ArchivedAccount;
+use crate::archive::ArchivedAssignment;
+use crate::archive::ArchivedBudget;
+use crate::archive::ArchivedCategory;
+use crate::archive::ArchivedScheduledTransaction;
+use crate::archive::ArchivedSplit;
+use crate::archive::ArchivedSupercategory;
+use crate::archive::ArchivedTarget;
+use crate::archive::ArchivedTransaction;
+use crate::archive::BudgetArchive;
+use crate::archive::ARCHIVE_FORMAT;
+use crate::archive::ARCHIVE_VERSION;
+use crate::models::new_pillid;
+
+/// Where transfers between accounts go: this schema has no transfer type, so
+/// both legs are booked to one category, marked `is_transfer`, and cancel out
+/// there.
+pub const TRANSFERS: (&str, &str) = ("Imported", "Transfers");
+/// Where income goes when the other app has no categories of its own for it;
+/// marked `is_income`.
+pub const INCOME: (&str, &str) = ("Income", "Income");
+/// Where transactions without a category go.
+pub const UNCATEGORIZED: (&str, &str) = ("Imported", "Uncategorized");
+
+/// A converted budget plus what did not survive the conversion.
+pub struct Imported {
+    pub archive: BudgetArchive,
+    /// What could not be mapped (or was mapped approximately), with how many
+    /// rows it affected.
+    pub unmapped: BTreeMap<String, usize>,
+}
+
+impl fmt::Display for Imported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (what, count) in &self.unmapped {
+            writeln!(f, "{count:>6}  {what}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct ConvertError(pub String);
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+impl From<serde_json::Error> for ConvertError {
+    fn from(err: serde_json::Error) -> Self {
+        ConvertError(format!("invalid JSON: {err}"))
+    }
+}
+
+impl From<csv::Error> for ConvertError {
+    fn from(err: csv::Error) -> Self {
+        ConvertError(format!("invalid CSV: {err}"))
+    }
+}
+
+impl From<sqlx::Error> for ConvertError {
+    fn from(err: sqlx::Error) -> Self {
+        ConvertError(format!("cannot read SQLite file: {err}"))
+    }
+}
+
+impl From<std::io::Error> for ConvertError {
+    fn from(err: std::io::Error) -> Self {
+        ConvertError(err.to_string())
+    }
+}
+
+/// Opens an SQLite export without any chance of writing to it.
+pub(crate) async fn open_sqlite(path: &Path) -> Result<SqliteConnection, ConvertError> {
+    Ok(SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .connect()
+        .await?)
+}
+
+/// One split in signed minor units: positive is inflow.
+pub(crate) struct SplitLine {
+    pub category_id: String,
+    pub memo: Option<String>,
+    pub amount: i64,
+}
+
+/// One scheduled transaction in signed minor units: positive is inflow.
+/// `frequency` is one of the scheduled transactions API's (`monthly`, ...).
+pub(crate) struct ScheduleLine {
+    pub account_id: String,
+    pub payee: Option<String>,
+    pub amount: i64,
+    pub frequency: &'static str,
+    pub next_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+}
+
+/// Builds an archive by name: accounts and categories are created the first
+/// time they are mentioned, with fresh pillids.
+pub(crate) struct ArchiveBuilder {
+    archive: BudgetArchive,
+    accounts: HashMap<String, String>,
+    supercategories: HashMap<String, String>,
+    categories: HashMap<(String, String), String>,
+    assignments: HashMap<(String, NaiveDate), usize>,
+    unmapped: BTreeMap<String, usize>,
+}
+
+impl ArchiveBuilder {
+    pub fn new(name: &str, currency_code: &str) -> Self {
+        ArchiveBuilder {
+            archive: BudgetArchive {
+                format: ARCHIVE_FORMAT.to_string(),
+                version: ARCHIVE_VERSION,
+                exported_at: Utc::now(),
+                budget: ArchivedBudget {
+                    id: new_pillid(),
+                    name: name.to_string(),
+                    currency_code: currency_code.to_string(),
+                },
+                accounts: Vec::new(),
+                supercategories: Vec::new(),
+                categories: Vec::new(),
+                transactions: Vec::new(),
+                category_assignments: Vec::new(),
+                scheduled_transactions: Vec::new(),
+                category_targets: Vec::new(),
+            },
+            accounts: HashMap::new(),
+            supercategories: HashMap::new(),
+            categories: HashMap::new(),
+            assignments: HashMap::new(),
+            unmapped: BTreeMap::new(),
+        }
+    }
+
+    /// The account named `name`; `closed` only applies when it is new.
+    pub fn account(&mut self, name: &str, closed: bool) -> String {
+        if let Some(id) = self.accounts.get(name) {
+            return id.clone();
+        }
+        let id = new_pillid();
+        self.archive.accounts.push(ArchivedAccount {
+            id: id.clone(),
+            name: name.to_string(),
+            currency_code: None,
+            closed_at: closed.then(Utc::now),
+        });
+        self.accounts.insert(name.to_string(), id.clone());
+        id
+    }
+
+    /// The category `name` in supercategory `group`.
+    pub fn category(&mut self, group: &str, name: &str) -> String {
+        let key = (group.to_string(), name.to_string());
+        if let Some(id) = self.categories.get(&key) {
+            return id.clone();
+        }
+        let supercategory_id = match self.supercategories.get(group) {
+            Some(id) => id.clone(),
+            None => {
+                let id = new_pillid();
+                self.archive.supercategories.push(ArchivedSupercategory {
+                    id: id.clone(),
+                    name: group.to_string(),
+                });
+                self.supercategories.insert(group.to_string(), id.clone());
+                id
+            }
+        };
+        let id = new_pillid();
+        self.archive.categories.push(ArchivedCategory {
+            id: id.clone(),
+            supercategory_id,
+            name: name.to_string(),
+            is_transfer: (group, name) == TRANSFERS,
+            is_income: (group, name) == INCOME,
+        });
+        self.categories.insert(key, id.clone());
+        id
+    }
+
+    /// Marks a category as income.
+    pub fn mark_income(&mut self, category_id: &str) {
+        if let Some(category) = self
+            .archive
+            .categories
+            .iter_mut()
+            .find(|category| category.id == category_id && !category.is_transfer)
+        {
+            category.is_income = true;
+        }
+    }
+
+    /// Adds a transaction. Zero splits are dropped (the schema has no room
+    /// for them), and so is a transaction left without any.
+    pub fn transaction(
+        &mut self,
+        account_id: &str,
+        date: NaiveDate,
+        payee: Option<String>,
+        memo: Option<String>,
+        reconciled: bool,
+        lines: Vec<SplitLine>,
+    ) {
+        let transaction_id = new_pillid();
+        let splits: Vec<ArchivedSplit> = lines
+            .into_iter()
+            .filter(|line| line.amount != 0)
+            .map(|line| ArchivedSplit {
+                id: new_pillid(),
+                transaction_id: transaction_id.clone(),
+                category_id: line.category_id,
+                memo: line.memo,
+                inflow: line.amount.max(0),
+                outflow: (-line.amount).max(0),
+                account_inflow: None,
+                account_outflow: None,
+            })
+            .collect();
+        if splits.is_empty() {
+            self.note("zero-amount transaction skipped");
+            return;
+        }
+        self.archive.transactions.push(ArchivedTransaction {
+            id: transaction_id,
+            account_id: account_id.to_string(),
+            date,
+            payee,
+            memo,
+            reconciled_at: reconciled.then(|| start_of_day(date)),
+            splits,
+        });
+    }
+
+    /// Adds `amount` to the category's assignment for `month` (any day in it).
+    pub fn assign(&mut self, category_id: &str, month: NaiveDate, amount: i64) {
+        if amount == 0 {
+            return;
+        }
+        let month = month.with_day0(0).unwrap_or(month);
+        match self.assignments.get(&(category_id.to_string(), month)) {
+            Some(&index) => self.archive.category_assignments[index].amount += amount,
+            None => {
+                self.assignments.insert(
+                    (category_id.to_string(), month),
+                    self.archive.category_assignments.len(),
+                );
+                self.archive.category_assignments.push(ArchivedAssignment {
+                    id: new_pillid(),
+                    category_id: category_id.to_string(),
+                    month: month.format("%Y-%m").to_string(),
+                    amount,
+                });
+            }
+        }
+    }
+
+    /// Adds a scheduled transaction; one that moves nothing is dropped.
+    pub fn schedule(&mut self, line: ScheduleLine) {
+        if line.amount == 0 {
+            self.note("zero-amount schedule skipped");
+            return;
+        }
+        if line.end_date.is_some_and(|end| end < line.next_date) {
+            self.note("finished schedule skipped");
+            return;
+        }
+        self.archive
+            .scheduled_transactions
+            .push(ArchivedScheduledTransaction {
+                id: new_pillid(),
+                account_id: line.account_id,
+                payee: line.payee,
+                memo: None,
+                inflow: line.amount.max(0),
+                outflow: (-line.amount).max(0),
+                frequency: line.frequency.to_string(),
+                next_date: line.next_date,
+                end_date: line.end_date,
+            });
+    }
+
+    /// Sets the category's monthly target; the last one given wins.
+    pub fn target(&mut self, category_id: &str, monthly_amount: i64) {
+        if monthly_amount <= 0 {
+            self.note("target without a positive monthly amount skipped");
+            return;
+        }
+        self.archive
+            .category_targets
+            .retain(|target| target.category_id != category_id);
+        self.archive.category_targets.push(ArchivedTarget {
+            id: new_pillid(),
+            category_id: category_id.to_string(),
+            monthly_amount,
+        });
+    }
+
+    /// Records something that could not be carried over as is.
+    pub fn note(&mut self, what: impl Into<String>) {
+        *self.unmapped.entry(what.into()).or_default() += 1;
+    }
+
+    pub fn finish(mut self) -> Imported {
+        self.archive
+            .category_assignments
+            .retain(|assignment| assignment.amount != 0);
+        Imported {
+            archive: self.archive,
+            unmapped: self.unmapped,
+        }
+    }
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+}
+
+/// Converts an integer amount with `from` implied decimals to `to` decimals.
+pub(crate) fn rescale(amount: i64, from: u32, to: u32) -> i64 {
+    if to >= from {
+        amount * 10i64.pow(to - from)
+    } else {
+        (amount as f64 / 10f64.powi((from - to) as i32)).round() as i64
+    }
+}
+
+/// Converts a decimal amount as exported (`12.34`) to minor units.
+pub(crate) fn to_minor(amount: f64, digits: u32) -> i64 {
+    (amount * 10f64.powi(digits as i32)).round() as i64
+}
+
+/// Parses an amount as written in a CSV export: currency symbols, spaces and
+/// thousands separators are ignored, and whichever of `.` or `,` comes last is
+/// the decimal point if at most `digits` digits follow it.
+pub(crate) fn parse_amount(text: &str, digits: u32) -> Option<i64> {
+    let negative = text.contains('-') || (text.contains('(') && text.contains(')'));
+    let kept: String = text
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+        .collect();
+    if !kept.chars().any(|c| c.is_ascii_digit()) {
+        return if text.trim().is_empty() {
+            Some(0)
+        } else {
+            None
+        };
+    }
+    let (whole, fraction) = match kept.rfind(['.', ',']) {
+        Some(at) if kept.len() - at - 1 <= digits as usize => (&kept[..at], &kept[at + 1..]),
+        _ => (kept.as_str(), ""),
+    };
+    let whole: String = whole.chars().filter(char::is_ascii_digit).collect();
+    let mut fraction = fraction.to_string();
+    while fraction.len() < digits as usize {
+        fraction.push('0');
+    }
+    let value: i64 = format!("{whole}{fraction}").parse().ok()?;
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn parse_amount_handles_export_formats() {
+        assert_eq!(parse_amount("$1,234.56", 2), Some(123456));
+        assert_eq!(parse_amount("1.234,56 €", 2), Some(123456));
+        assert_eq!(parse_amount("-12.5", 2), Some(-1250));
+        assert_eq!(parse_amount("(7.00)", 2), Some(-700));
+        assert_eq!(parse_amount("1,000", 0), Some(1000));
+        assert_eq!(parse_amount("", 2), Some(0));
+        assert_eq!(parse_amount("n/a", 2), None);
+    }
+
+    #[test]
+    fn builder_merges_assignments_and_drops_zero_splits() {
+        let mut builder = ArchiveBuilder::new("Imported", "USD");
+        let account = builder.account("Checking", false);
+        let rent = builder.category("Bills", "Rent");
+        assert_eq!(builder.category("Bills", "Rent"), rent);
+        let january = NaiveDate::from_ymd_opt(2026, 1, 15).unwrap();
+        builder.assign(&rent, january, 1000);
+        builder.assign(&rent, january, 500);
+        builder.transaction(
+            &account,
+            january,
+            None,
+            None,
+            false,
+            vec![SplitLine {
+                category_id: rent.clone(),
+                memo: None,
+                amount: 0,
+            }],
+        );
+
+        let imported = builder.finish();
+        let assignments = &imported.archive.category_assignments;
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(
+            (assignments[0].month.as_str(), assignments[0].amount),
+            ("2026-01", 1500)
+        );
+        assert!(imported.archive.transactions.is_empty());
+        assert_eq!(imported.unmapped["zero-amount transaction skipped"], 1);
+    }
+}
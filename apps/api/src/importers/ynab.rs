@@ -0,0 +1,926 @@
+//! YNAB exports: the YNAB4 `Budget.yfull` file, the budget JSON of the YNAB
+//! API (`GET /v1/budgets/{id}`), and the register and budget CSVs that both
+//! YNAB versions can export.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use super::parse_amount;
+use super::rescale;
+use super::to_minor;
+use super::ArchiveBuilder;
+use super::ConvertError;
+use super::Imported;
+use super::SplitLine;
+use super::INCOME;
+use super::TRANSFERS;
+use super::UNCATEGORIZED;
+use crate::money::minor_digits;
+
+const DEFAULT_NAME: &str = "YNAB import";
+
+/// Converts either JSON export, telling them apart by their keys.
+pub fn from_json(
+    json: &str,
+    name: Option<&str>,
+    currency_code: Option<&str>,
+) -> Result<Imported, ConvertError> {
+    let value: serde_json::Value = serde_json::from_str(json)?;
+    if value.get("masterCategories").is_some() {
+        from_ynab4(serde_json::from_value(value)?, name, currency_code)
+    } else {
+        let budget = match serde_json::from_value::<ApiExport>(value)? {
+            ApiExport::Response { data } => data.budget,
+            ApiExport::Budget(budget) => budget,
+        };
+        Ok(from_api(budget, name))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ynab4Budget {
+    #[serde(default)]
+    accounts: Vec<Ynab4Account>,
+    #[serde(default)]
+    master_categories: Vec<Ynab4MasterCategory>,
+    #[serde(default)]
+    payees: Vec<Ynab4Payee>,
+    #[serde(default)]
+    transactions: Vec<Ynab4Transaction>,
+    #[serde(default)]
+    monthly_budgets: Vec<Ynab4MonthlyBudget>,
+    #[serde(default)]
+    scheduled_transactions: Vec<serde_json::Value>,
+    budget_meta_data: Option<Ynab4MetaData>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ynab4Account {
+    entity_id: String,
+    account_name: String,
+    #[serde(default = "yes")]
+    on_budget: bool,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    is_tombstone: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ynab4MasterCategory {
+    entity_id: String,
+    name: String,
+    #[serde(default)]
+    sub_categories: Option<Vec<Ynab4SubCategory>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ynab4SubCategory {
+    entity_id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ynab4Payee {
+    entity_id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ynab4Transaction {
+    account_id: String,
+    date: String,
+    amount: f64,
+    payee_id: Option<String>,
+    category_id: Option<String>,
+    memo: Option<String>,
+    cleared: Option<String>,
+    #[serde(default)]
+    is_tombstone: bool,
+    #[serde(default)]
+    sub_transactions: Option<Vec<Ynab4SubTransaction>>,
+    transfer_transaction_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ynab4SubTransaction {
+    amount: f64,
+    category_id: Option<String>,
+    memo: Option<String>,
+    #[serde(default)]
+    is_tombstone: bool,
+    transfer_transaction_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ynab4MonthlyBudget {
+    month: String,
+    #[serde(default)]
+    monthly_sub_category_budgets: Vec<Ynab4CategoryBudget>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ynab4CategoryBudget {
+    category_id: String,
+    budgeted: f64,
+    #[serde(default)]
+    is_tombstone: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ynab4MetaData {
+    currency_locale: Option<String>,
+}
+
+fn yes() -> bool {
+    true
+}
+
+/// YNAB4 stores a locale rather than a currency; these are the common ones.
+fn currency_for_locale(locale: &str) -> Option<&'static str> {
+    Some(match locale {
+        "en_US" | "es_US" => "USD",
+        "en_GB" => "GBP",
+        "en_CA" | "fr_CA" => "CAD",
+        "en_AU" => "AUD",
+        "en_NZ" => "NZD",
+        "en_IE" | "de_DE" | "de_AT" | "fr_FR" | "fr_BE" | "nl_NL" | "nl_BE" | "es_ES" | "it_IT"
+        | "pt_PT" | "fi_FI" => "EUR",
+        "de_CH" | "fr_CH" => "CHF",
+        "sv_SE" => "SEK",
+        "nb_NO" => "NOK",
+        "da_DK" => "DKK",
+        "ja_JP" => "JPY",
+        "pt_BR" => "BRL",
+        "en_IN" => "INR",
+        "en_ZA" => "ZAR",
+        _ => return None,
+    })
+}
+
+fn parse_iso_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok()
+}
+
+fn from_ynab4(
+    budget: Ynab4Budget,
+    name: Option<&str>,
+    currency_code: Option<&str>,
+) -> Result<Imported, ConvertError> {
+    let locale = budget
+        .budget_meta_data
+        .and_then(|meta| meta.currency_locale)
+        .unwrap_or_default();
+    let currency = currency_code
+        .or_else(|| currency_for_locale(&locale))
+        .ok_or_else(|| {
+            ConvertError(format!(
+                "cannot tell the currency of locale {locale:?}; pass it explicitly"
+            ))
+        })?;
+    let digits = minor_digits(currency);
+    let mut builder = ArchiveBuilder::new(name.unwrap_or(DEFAULT_NAME), currency);
+
+    // Hidden subcategories live under one master category with their original
+    // group in the name: "Group ` Name ` n".
+    let mut categories: HashMap<String, (String, String)> = HashMap::new();
+    for master in &budget.master_categories {
+        for sub in master.sub_categories.iter().flatten() {
+            let mut parts = sub.name.split(" ` ");
+            let named = match (master.entity_id.as_str(), parts.next(), parts.next()) {
+                ("MasterCategory/__Hidden__", Some(group), Some(name)) => {
+                    (group.to_string(), name.to_string())
+                }
+                _ => (master.name.clone(), sub.name.clone()),
+            };
+            categories.insert(sub.entity_id.clone(), named);
+        }
+    }
+
+    let mut accounts = HashMap::new();
+    for account in budget.accounts.iter().filter(|a| !a.is_tombstone) {
+        if !account.on_budget {
+            builder.note("off-budget (tracking) account imported as a regular account");
+        }
+        let id = builder.account(&account.account_name, account.hidden);
+        accounts.insert(account.entity_id.clone(), id);
+    }
+    let payees: HashMap<&str, &str> = budget
+        .payees
+        .iter()
+        .map(|p| (p.entity_id.as_str(), p.name.as_str()))
+        .collect();
+
+    let category_for = |builder: &mut ArchiveBuilder, id: Option<&str>, transfer: bool| {
+        let (group, name) = match id {
+            Some("Category/__ImmediateIncome__") => INCOME,
+            Some("Category/__DeferredIncome__") => {
+                builder.note("income for next month booked as income when received");
+                INCOME
+            }
+            Some(id) if categories.contains_key(id) => {
+                let (group, name) = &categories[id];
+                return builder.category(group, name);
+            }
+            _ if transfer => {
+                builder.note("transfer between accounts booked to Imported/Transfers");
+                TRANSFERS
+            }
+            _ => {
+                builder.note("uncategorized transaction booked to Imported/Uncategorized");
+                UNCATEGORIZED
+            }
+        };
+        builder.category(group, name)
+    };
+
+    for transaction in budget.transactions.iter().filter(|t| !t.is_tombstone) {
+        let Some(account_id) = accounts.get(&transaction.account_id) else {
+            builder.note("transaction in a deleted account skipped");
+            continue;
+        };
+        let Some(date) = parse_iso_date(&transaction.date) else {
+            builder.note("transaction with an unreadable date skipped");
+            continue;
+        };
+        let subs: Vec<_> = transaction
+            .sub_transactions
+            .iter()
+            .flatten()
+            .filter(|s| !s.is_tombstone)
+            .collect();
+        let lines = if subs.is_empty() {
+            vec![SplitLine {
+                category_id: category_for(
+                    &mut builder,
+                    transaction.category_id.as_deref(),
+                    transaction.transfer_transaction_id.is_some(),
+                ),
+                memo: None,
+                amount: to_minor(transaction.amount, digits),
+            }]
+        } else {
+            subs.iter()
+                .map(|sub| SplitLine {
+                    category_id: category_for(
+                        &mut builder,
+                        sub.category_id.as_deref(),
+                        sub.transfer_transaction_id.is_some(),
+                    ),
+                    memo: sub.memo.clone().filter(|m| !m.is_empty()),
+                    amount: to_minor(sub.amount, digits),
+                })
+                .collect()
+        };
+        let payee = transaction
+            .payee_id
+            .as_deref()
+            .and_then(|id| payees.get(id))
+            .map(|name| name.to_string());
+        builder.transaction(
+            account_id,
+            date,
+            payee,
+            transaction.memo.clone().filter(|m| !m.is_empty()),
+            transaction.cleared.as_deref() == Some("Reconciled"),
+            lines,
+        );
+    }
+
+    for month in &budget.monthly_budgets {
+        let Some(date) = parse_iso_date(&month.month) else {
+            builder.note("monthly budget with an unreadable month skipped");
+            continue;
+        };
+        for entry in month
+            .monthly_sub_category_budgets
+            .iter()
+            .filter(|e| !e.is_tombstone)
+        {
+            let Some((group, name)) = categories.get(&entry.category_id) else {
+                builder.note("budgeted amount for an unknown category skipped");
+                continue;
+            };
+            let category_id = builder.category(group, name);
+            builder.assign(&category_id, date, to_minor(entry.budgeted, digits));
+        }
+    }
+
+    for _ in &budget.scheduled_transactions {
+        builder.note("scheduled transaction not imported (no equivalent)");
+    }
+    Ok(builder.finish())
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ApiExport {
+    Response { data: ApiData },
+    Budget(ApiBudget),
+}
+
+#[derive(Deserialize)]
+struct ApiData {
+    budget: ApiBudget,
+}
+
+#[derive(Deserialize)]
+struct ApiBudget {
+    name: String,
+    currency_format: Option<ApiCurrencyFormat>,
+    #[serde(default)]
+    accounts: Vec<ApiAccount>,
+    #[serde(default)]
+    category_groups: Vec<ApiCategoryGroup>,
+    #[serde(default)]
+    categories: Vec<ApiCategory>,
+    #[serde(default)]
+    payees: Vec<ApiPayee>,
+    #[serde(default)]
+    months: Vec<ApiMonth>,
+    #[serde(default)]
+    transactions: Vec<ApiTransaction>,
+    #[serde(default)]
+    subtransactions: Vec<ApiSubtransaction>,
+    #[serde(default)]
+    scheduled_transactions: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ApiCurrencyFormat {
+    iso_code: String,
+    decimal_digits: u32,
+}
+
+#[derive(Deserialize)]
+struct ApiAccount {
+    id: String,
+    name: String,
+    #[serde(default = "yes")]
+    on_budget: bool,
+    #[serde(default)]
+    closed: bool,
+    #[serde(default)]
+    deleted: bool,
+}
+
+#[derive(Deserialize)]
+struct ApiCategoryGroup {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ApiCategory {
+    id: String,
+    category_group_id: String,
+    name: String,
+    goal_type: Option<String>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+#[derive(Deserialize)]
+struct ApiPayee {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ApiMonth {
+    month: String,
+    #[serde(default)]
+    categories: Vec<ApiMonthCategory>,
+}
+
+#[derive(Deserialize)]
+struct ApiMonthCategory {
+    id: String,
+    budgeted: i64,
+    #[serde(default)]
+    deleted: bool,
+}
+
+#[derive(Deserialize)]
+struct ApiTransaction {
+    id: String,
+    date: String,
+    amount: i64,
+    memo: Option<String>,
+    cleared: Option<String>,
+    account_id: String,
+    payee_id: Option<String>,
+    category_id: Option<String>,
+    transfer_account_id: Option<String>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+#[derive(Deserialize)]
+struct ApiSubtransaction {
+    transaction_id: String,
+    amount: i64,
+    memo: Option<String>,
+    category_id: Option<String>,
+    transfer_account_id: Option<String>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+/// The API counts in thousandths of the currency unit.
+fn from_milliunits(amount: i64, digits: u32) -> i64 {
+    rescale(amount, 3, digits)
+}
+
+fn from_api(budget: ApiBudget, name: Option<&str>) -> Imported {
+    let (currency, digits) = match &budget.currency_format {
+        Some(format) => (format.iso_code.as_str(), format.decimal_digits),
+        None => ("USD", 2),
+    };
+    let mut builder = ArchiveBuilder::new(name.unwrap_or(&budget.name), currency);
+
+    let groups: HashMap<&str, &str> = budget
+        .category_groups
+        .iter()
+        .map(|g| (g.id.as_str(), g.name.as_str()))
+        .collect();
+    let mut categories: HashMap<&str, (&str, &str)> = HashMap::new();
+    for category in &budget.categories {
+        let group = groups
+            .get(category.category_group_id.as_str())
+            .copied()
+            .unwrap_or(UNCATEGORIZED.0);
+        let named = match (group, category.name.as_str()) {
+            ("Internal Master Category", "Inflow: Ready to Assign")
+            | ("Internal Master Category", "Inflow: To be Budgeted")
+            | ("Internal Master Category", "To be Budgeted")
+            | ("Internal Master Category", "Deferred Income SubCategory") => INCOME,
+            ("Internal Master Category", _) => UNCATEGORIZED,
+            named => named,
+        };
+        if category.goal_type.is_some() && !category.deleted {
+            builder.note("category goal not imported (no equivalent)");
+        }
+        categories.insert(category.id.as_str(), named);
+    }
+
+    let mut accounts = HashMap::new();
+    for account in budget.accounts.iter().filter(|a| !a.deleted) {
+        if !account.on_budget {
+            builder.note("off-budget (tracking) account imported as a regular account");
+        }
+        let id = builder.account(&account.name, account.closed);
+        accounts.insert(account.id.as_str(), id);
+    }
+    let payees: HashMap<&str, &str> = budget
+        .payees
+        .iter()
+        .map(|p| (p.id.as_str(), p.name.as_str()))
+        .collect();
+    let mut subtransactions: HashMap<&str, Vec<&ApiSubtransaction>> = HashMap::new();
+    for sub in budget.subtransactions.iter().filter(|s| !s.deleted) {
+        subtransactions
+            .entry(sub.transaction_id.as_str())
+            .or_default()
+            .push(sub);
+    }
+
+    let category_for = |builder: &mut ArchiveBuilder, id: Option<&str>, transfer: bool| {
+        let (group, name) = match id.and_then(|id| categories.get(id)) {
+            Some(&named) => named,
+            None if transfer => {
+                builder.note("transfer between accounts booked to Imported/Transfers");
+                TRANSFERS
+            }
+            None => {
+                builder.note("uncategorized transaction booked to Imported/Uncategorized");
+                UNCATEGORIZED
+            }
+        };
+        builder.category(group, name)
+    };
+
+    for transaction in budget.transactions.iter().filter(|t| !t.deleted) {
+        let Some(account_id) = accounts.get(transaction.account_id.as_str()) else {
+            builder.note("transaction in a deleted account skipped");
+            continue;
+        };
+        let Some(date) = parse_iso_date(&transaction.date) else {
+            builder.note("transaction with an unreadable date skipped");
+            continue;
+        };
+        let lines = match subtransactions.get(transaction.id.as_str()) {
+            Some(subs) => subs
+                .iter()
+                .map(|sub| SplitLine {
+                    category_id: category_for(
+                        &mut builder,
+                        sub.category_id.as_deref(),
+                        sub.transfer_account_id.is_some(),
+                    ),
+                    memo: sub.memo.clone().filter(|m| !m.is_empty()),
+                    amount: from_milliunits(sub.amount, digits),
+                })
+                .collect(),
+            None => vec![SplitLine {
+                category_id: category_for(
+                    &mut builder,
+                    transaction.category_id.as_deref(),
+                    transaction.transfer_account_id.is_some(),
+                ),
+                memo: None,
+                amount: from_milliunits(transaction.amount, digits),
+            }],
+        };
+        builder.transaction(
+            account_id,
+            date,
+            transaction
+                .payee_id
+                .as_deref()
+                .and_then(|id| payees.get(id))
+                .map(|name| name.to_string()),
+            transaction.memo.clone().filter(|m| !m.is_empty()),
+            transaction.cleared.as_deref() == Some("reconciled"),
+            lines,
+        );
+    }
+
+    for month in &budget.months {
+        let Some(date) = parse_iso_date(&month.month) else {
+            builder.note("monthly budget with an unreadable month skipped");
+            continue;
+        };
+        for entry in month.categories.iter().filter(|c| !c.deleted) {
+            let Some(&(group, name)) = categories.get(entry.id.as_str()) else {
+                builder.note("budgeted amount for an unknown category skipped");
+                continue;
+            };
+            let category_id = builder.category(group, name);
+            builder.assign(&category_id, date, from_milliunits(entry.budgeted, digits));
+        }
+    }
+
+    for _ in &budget.scheduled_transactions {
+        builder.note("scheduled transaction not imported (no equivalent)");
+    }
+    builder.finish()
+}
+
+/// Column indexes of a CSV export, looked up by any of the header names the
+/// YNAB versions have used.
+struct Columns(csv::StringRecord);
+
+impl Columns {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|header| names.iter().any(|n| header.trim().eq_ignore_ascii_case(n)))
+    }
+
+    fn require(&self, names: &[&str]) -> Result<usize, ConvertError> {
+        self.find(names)
+            .ok_or_else(|| ConvertError(format!("CSV has no {:?} column", names[0])))
+    }
+}
+
+fn csv_reader(text: &str) -> csv::Reader<&[u8]> {
+    csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.trim_start_matches('\u{feff}').as_bytes())
+}
+
+/// Dates as YNAB writes them depending on the export locale. Day-first dates
+/// need `date_format`, since they are ambiguous with the US default.
+fn parse_csv_date(text: &str, date_format: Option<&str>) -> Option<NaiveDate> {
+    match date_format {
+        Some(format) => NaiveDate::parse_from_str(text, format).ok(),
+        None => ["%m/%d/%Y", "%Y-%m-%d", "%d.%m.%Y"]
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(text, format).ok()),
+    }
+}
+
+/// `Split (2/3) memo` → `(2, 3, "memo")`.
+fn split_marker(memo: &str) -> Option<(usize, usize, &str)> {
+    let rest = memo.strip_prefix("Split (")?;
+    let (position, rest) = rest.split_once(')')?;
+    let (index, count) = position.split_once('/')?;
+    Some((index.parse().ok()?, count.parse().ok()?, rest.trim()))
+}
+
+fn csv_category(group: &str, name: &str, payee: &str) -> Option<(&'static str, &'static str)> {
+    if group == "Inflow" || group == "Income" {
+        return Some(INCOME);
+    }
+    if !name.is_empty() {
+        return None;
+    }
+    Some(if payee.starts_with("Transfer : ") {
+        TRANSFERS
+    } else {
+        UNCATEGORIZED
+    })
+}
+
+/// A split transaction whose `Split (i/n)` rows are still being read.
+struct PendingSplit {
+    account_id: String,
+    date: NaiveDate,
+    payee: Option<String>,
+    reconciled: bool,
+    lines: Vec<SplitLine>,
+    remaining: usize,
+}
+
+impl PendingSplit {
+    fn finish(self, builder: &mut ArchiveBuilder) {
+        if self.remaining > 0 {
+            builder.note("incomplete split transaction imported with the lines present");
+        }
+        builder.transaction(
+            &self.account_id,
+            self.date,
+            self.payee,
+            None,
+            self.reconciled,
+            self.lines,
+        );
+    }
+}
+
+/// Converts a register CSV, plus the budget CSV for monthly amounts if given.
+/// Neither carries a budget name or currency.
+pub fn from_csv(
+    register: &str,
+    budget: Option<&str>,
+    name: Option<&str>,
+    currency_code: &str,
+    date_format: Option<&str>,
+) -> Result<Imported, ConvertError> {
+    let digits = minor_digits(currency_code);
+    let mut builder = ArchiveBuilder::new(name.unwrap_or(DEFAULT_NAME), currency_code);
+
+    let mut reader = csv_reader(register);
+    let columns = Columns(reader.headers()?.clone());
+    let account = columns.require(&["Account"])?;
+    let date = columns.require(&["Date"])?;
+    let payee = columns.require(&["Payee"])?;
+    let group = columns.require(&["Category Group", "Master Category"])?;
+    let category = columns.require(&["Category", "Sub Category"])?;
+    let memo = columns.require(&["Memo"])?;
+    let outflow = columns.require(&["Outflow"])?;
+    let inflow = columns.require(&["Inflow"])?;
+    let cleared = columns.find(&["Cleared"]);
+
+    let mut pending: Option<PendingSplit> = None;
+    for record in reader.records() {
+        let record = record?;
+        let field = |i: usize| record.get(i).unwrap_or("").trim();
+        let Some(tx_date) = parse_csv_date(field(date), date_format) else {
+            builder.note("register row with an unreadable date skipped");
+            continue;
+        };
+        let (Some(out), Some(inn)) = (
+            parse_amount(field(outflow), digits),
+            parse_amount(field(inflow), digits),
+        ) else {
+            builder.note("register row with an unreadable amount skipped");
+            continue;
+        };
+        let category_id = match csv_category(field(group), field(category), field(payee)) {
+            Some((g, c)) => {
+                if (g, c) != INCOME {
+                    builder.note(format!("transaction without a category booked to {g}/{c}"));
+                }
+                builder.category(g, c)
+            }
+            None => builder.category(field(group), field(category)),
+        };
+        let account_id = builder.account(field(account), false);
+        let payee = Some(field(payee).to_string()).filter(|s| !s.is_empty());
+        let reconciled = cleared.is_some_and(|i| field(i) == "Reconciled");
+        let amount = inn.abs() - out.abs();
+
+        let Some((_, count, line_memo)) = split_marker(field(memo)) else {
+            builder.transaction(
+                &account_id,
+                tx_date,
+                payee,
+                Some(field(memo).to_string()).filter(|s| !s.is_empty()),
+                reconciled,
+                vec![SplitLine {
+                    category_id,
+                    memo: None,
+                    amount,
+                }],
+            );
+            continue;
+        };
+        let continues = pending
+            .as_ref()
+            .is_some_and(|p| p.account_id == account_id && p.date == tx_date && p.payee == payee);
+        if !continues {
+            if let Some(incomplete) = pending.take() {
+                incomplete.finish(&mut builder);
+            }
+            pending = Some(PendingSplit {
+                account_id,
+                date: tx_date,
+                payee,
+                reconciled,
+                lines: Vec::new(),
+                remaining: count,
+            });
+        }
+        let Some(split) = pending.as_mut() else {
+            continue;
+        };
+        split.lines.push(SplitLine {
+            category_id,
+            memo: Some(line_memo.to_string()).filter(|m| !m.is_empty()),
+            amount,
+        });
+        split.remaining = split.remaining.saturating_sub(1);
+        if split.remaining == 0 {
+            if let Some(complete) = pending.take() {
+                complete.finish(&mut builder);
+            }
+        }
+    }
+    if let Some(incomplete) = pending {
+        incomplete.finish(&mut builder);
+    }
+
+    if let Some(budget) = budget {
+        let mut reader = csv_reader(budget);
+        let columns = Columns(reader.headers()?.clone());
+        let month = columns.require(&["Month"])?;
+        let group = columns.require(&["Category Group", "Master Category"])?;
+        let category = columns.require(&["Category", "Sub Category"])?;
+        let budgeted = columns.require(&["Budgeted", "Assigned"])?;
+        for record in reader.records() {
+            let record = record?;
+            let field = |i: usize| record.get(i).unwrap_or("").trim();
+            if csv_category(field(group), field(category), "").is_some() {
+                continue;
+            }
+            let first_of_month = format!("1 {}", field(month));
+            let Some(date) = ["%d %b %Y", "%d %B %Y"]
+                .iter()
+                .find_map(|format| NaiveDate::parse_from_str(&first_of_month, format).ok())
+            else {
+                builder.note("budget row with an unreadable month skipped");
+                continue;
+            };
+            let Some(amount) = parse_amount(field(budgeted), digits) else {
+                builder.note("budget row with an unreadable amount skipped");
+                continue;
+            };
+            let category_id = builder.category(field(group), field(category));
+            builder.assign(&category_id, date, amount);
+        }
+    }
+    Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn ynab4_maps_categories_splits_and_budgets() {
+        let yfull = r#"{
+            "budgetMetaData": {"currencyLocale": "en_GB"},
+            "accounts": [{"entityId": "A1", "accountName": "Current", "onBudget": true}],
+            "masterCategories": [
+                {"entityId": "M1", "name": "Bills", "subCategories": [{"entityId": "C1", "name": "Rent"}]},
+                {"entityId": "MasterCategory/__Hidden__", "name": "Hidden", "subCategories": [{"entityId": "C2", "name": "Bills ` Phone ` 3"}]}
+            ],
+            "payees": [{"entityId": "P1", "name": "Landlord"}],
+            "transactions": [
+                {"entityId": "T1", "accountId": "A1", "date": "2016-01-02", "amount": -512.5,
+                 "payeeId": "P1", "categoryId": "Category/__Split__", "cleared": "Reconciled",
+                 "subTransactions": [
+                   {"entityId": "S1", "amount": -500.0, "categoryId": "C1", "memo": "jan"},
+                   {"entityId": "S2", "amount": -12.5, "categoryId": "C2"}
+                 ]},
+                {"entityId": "T2", "accountId": "A1", "date": "2016-01-01", "amount": 1000,
+                 "categoryId": "Category/__ImmediateIncome__"},
+                {"entityId": "T3", "accountId": "A1", "date": "2016-01-01", "amount": 5, "isTombstone": true}
+            ],
+            "monthlyBudgets": [{"month": "2016-01-01", "monthlySubCategoryBudgets": [
+                {"categoryId": "C1", "budgeted": 500}
+            ]}],
+            "scheduledTransactions": [{}]
+        }"#;
+        let imported = from_json(yfull, None, None).unwrap();
+        let archive = &imported.archive;
+        assert_eq!(archive.budget.currency_code, "GBP");
+        let names: Vec<_> = archive.categories.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Rent", "Phone", "Income"]);
+        assert_eq!(archive.supercategories.len(), 2);
+        assert!(archive.categories[2].is_income);
+
+        let split = &archive.transactions[0];
+        assert_eq!(split.payee.as_deref(), Some("Landlord"));
+        assert!(split.reconciled_at.is_some());
+        let outflows: Vec<_> = split.splits.iter().map(|s| s.outflow).collect();
+        assert_eq!(outflows, [50000, 1250]);
+        assert_eq!(archive.transactions[1].splits[0].inflow, 100000);
+        assert_eq!(archive.transactions.len(), 2);
+
+        assert_eq!(archive.category_assignments[0].month, "2016-01");
+        assert_eq!(archive.category_assignments[0].amount, 50000);
+        assert_eq!(
+            imported.unmapped["scheduled transaction not imported (no equivalent)"],
+            1
+        );
+    }
+
+    #[test]
+    fn api_export_uses_milliunits_and_subtransactions() {
+        let json = r#"{"data": {"budget": {
+            "name": "Home",
+            "currency_format": {"iso_code": "EUR", "decimal_digits": 2},
+            "accounts": [{"id": "a", "name": "Giro", "on_budget": true, "closed": true, "deleted": false}],
+            "category_groups": [{"id": "g", "name": "Everyday"}, {"id": "i", "name": "Internal Master Category"}],
+            "categories": [
+                {"id": "c", "category_group_id": "g", "name": "Food", "goal_type": "NEED", "deleted": false},
+                {"id": "r", "category_group_id": "i", "name": "Inflow: Ready to Assign", "deleted": false}
+            ],
+            "payees": [],
+            "months": [{"month": "2024-03-01", "categories": [{"id": "c", "budgeted": 120000, "deleted": false}]}],
+            "transactions": [
+                {"id": "t", "date": "2024-03-02", "amount": -25500, "cleared": "cleared",
+                 "account_id": "a", "category_id": null, "deleted": false},
+                {"id": "u", "date": "2024-03-01", "amount": -10000, "account_id": "a",
+                 "transfer_account_id": "b", "deleted": false}
+            ],
+            "subtransactions": [
+                {"transaction_id": "t", "amount": -20000, "category_id": "c", "deleted": false},
+                {"transaction_id": "t", "amount": -5500, "category_id": "r", "deleted": false}
+            ]
+        }}}"#;
+        let imported = from_json(json, None, None).unwrap();
+        let archive = &imported.archive;
+        assert_eq!(archive.budget.name, "Home");
+        assert!(archive.accounts[0].closed_at.is_some());
+        let outflows: Vec<_> = archive.transactions[0]
+            .splits
+            .iter()
+            .map(|s| s.outflow)
+            .collect();
+        assert_eq!(outflows, [2000, 550]);
+        assert_eq!(archive.category_assignments[0].amount, 12000);
+        assert_eq!(
+            imported.unmapped["transfer between accounts booked to Imported/Transfers"],
+            1
+        );
+        assert_eq!(
+            imported.unmapped["category goal not imported (no equivalent)"],
+            1
+        );
+    }
+
+    #[test]
+    fn csv_register_groups_split_rows() {
+        let register = "\u{feff}\"Account\",\"Flag\",\"Date\",\"Payee\",\"Category Group/Category\",\"Category Group\",\"Category\",\"Memo\",\"Outflow\",\"Inflow\",\"Cleared\"\n\
+            \"Checking\",\"\",\"03/02/2024\",\"Market\",\"Bills: Phone\",\"Bills\",\"Phone\",\"Split (1/2) \",$30.00,$0.00,\"Reconciled\"\n\
+            \"Checking\",\"\",\"03/02/2024\",\"Market\",\"Food: Groceries\",\"Food\",\"Groceries\",\"Split (2/2) bread\",$4.50,$0.00,\"Reconciled\"\n\
+            \"Checking\",\"\",\"03/01/2024\",\"Transfer : Savings\",\"\",\"\",\"\",\"\",$100.00,$0.00,\"Cleared\"\n\
+            \"Checking\",\"\",\"03/01/2024\",\"Employer\",\"Inflow: Ready to Assign\",\"Inflow\",\"Ready to Assign\",\"March\",$0.00,\"$2,000.00\",\"Cleared\"\n";
+        let budget = "\"Month\",\"Category Group/Category\",\"Category Group\",\"Category\",\"Budgeted\",\"Activity\",\"Available\"\n\
+            \"Mar 2024\",\"Food: Groceries\",\"Food\",\"Groceries\",$250.00,-$4.50,$245.50\n";
+        let imported = from_csv(register, Some(budget), None, "USD", None).unwrap();
+        let archive = &imported.archive;
+        assert_eq!(archive.transactions.len(), 3);
+        let split = &archive.transactions[0];
+        assert!(split.reconciled_at.is_some());
+        let lines: Vec<_> = split
+            .splits
+            .iter()
+            .map(|s| (s.outflow, s.memo.as_deref()))
+            .collect();
+        assert_eq!(lines, [(3000, None), (450, Some("bread"))]);
+        assert_eq!(archive.transactions[2].splits[0].inflow, 200000);
+        assert_eq!(archive.category_assignments[0].amount, 25000);
+        assert_eq!(archive.category_assignments[0].month, "2024-03");
+        assert_eq!(
+            imported.unmapped["transaction without a category booked to Imported/Transfers"],
+            1
+        );
+    }
+}
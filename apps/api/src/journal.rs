@@ -0,0 +1,435 @@
+//! Plain-text accounting export: a budget's transactions as a ledger,
+//! hledger or beancount journal.
+//!
+//! Accounts become `Assets:<name>`. Categories become
+//! `Expenses:<supercategory>:<category>`, or `Income:...` for categories that
+//! take in more than they pay out over the budget's whole history. Each split
+//! is one posting against its category in the budget's currency, balanced by
+//! one posting against the account in the account's currency; when the two
+//! differ, the account posting carries its budget value as a `@@` total price.
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::header;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use sqlx::FromRow;
+
+use crate::money::Currency;
+use crate::money::Money;
+use crate::trash::owned_budget;
+use crate::user_from_headers;
+use crate::AppState;
+
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum JournalFormat {
+    Ledger,
+    Hledger,
+    Beancount,
+}
+
+impl JournalFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            JournalFormat::Ledger => "ledger",
+            JournalFormat::Hledger => "journal",
+            JournalFormat::Beancount => "beancount",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct JournalQuery {
+    format: Option<JournalFormat>,
+    /// First day to include.
+    from: Option<NaiveDate>,
+    /// Last day to include.
+    to: Option<NaiveDate>,
+}
+
+/// One split with what its transaction, account and category are called.
+#[derive(FromRow)]
+struct JournalRow {
+    transaction_id: String,
+    date: NaiveDate,
+    payee: Option<String>,
+    memo: Option<String>,
+    reconciled: bool,
+    account: String,
+    account_currency: String,
+    supercategory: String,
+    category: String,
+    category_id: String,
+    split_memo: Option<String>,
+    /// In the budget's currency.
+    inflow: i64,
+    outflow: i64,
+    /// In the account's currency.
+    account_inflow: i64,
+    account_outflow: i64,
+}
+
+/// `GET /api/budgets/:id/export/journal?format=ledger|hledger|beancount&from=&to=`
+pub(crate) async fn export_journal(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+    Query(query): Query<JournalQuery>,
+) -> Result<Response, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let format = query.format.unwrap_or(JournalFormat::Ledger);
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut conn, &budget_pillid, user_id).await?;
+    let (name, currency_code): (String, String) =
+        sqlx::query_as("select name, currency_code from budgets where id = $1")
+            .bind(budget_id)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let rows = sqlx::query_as::<_, JournalRow>(
+        "select t.pillid as transaction_id, t.tx_date as date, t.payee, t.memo,
+                t.reconciled_at is not null as reconciled, a.name as account,
+                a.currency_code as account_currency,
+                s.name as supercategory, c.name as category, c.pillid as category_id,
+                ts.memo as split_memo, ts.inflow, ts.outflow,
+                coalesce(ts.account_inflow, ts.inflow) as account_inflow,
+                coalesce(ts.account_outflow, ts.outflow) as account_outflow
+         from transactions t
+         join accounts a on a.id = t.account_id
+         join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+         join categories c on c.id = ts.category_id
+         join supercategories s on s.id = c.supercategory_id
+         where t.budget_id = $1 and t.deleted_at is null
+           and ($2::date is null or t.tx_date >= $2)
+           and ($3::date is null or t.tx_date <= $3)
+         order by t.tx_date, t.created_at, t.id, ts.created_at, ts.id",
+    )
+    .bind(budget_id)
+    .bind(query.from)
+    .bind(query.to)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let income: Vec<(String,)> =
+        sqlx::query_as("select pillid from categories where budget_id = $1 and is_income")
+            .bind(budget_id)
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let income: HashSet<String> = income.into_iter().map(|r| r.0).collect();
+
+    let currency = Currency::stored(&currency_code);
+    let body = render(format, &name, &currency, &rows, &income);
+    let disposition = format!(
+        "attachment; filename=\"{budget_pillid}.{}\"",
+        format.extension()
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; charset=utf-8".to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// An account name component: ledger and hledger only forbid `:` and runs of
+/// spaces; beancount wants an uppercase letter or digit, then letters, digits
+/// and dashes.
+fn component(name: &str, format: JournalFormat) -> String {
+    if format != JournalFormat::Beancount {
+        let name = name.replace(':', "-");
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        return if name.is_empty() { "-".into() } else { name };
+    }
+    let mut out = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            out.push(c);
+        } else if !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    let out = out.trim_matches('-');
+    let mut chars = out.chars();
+    match chars.next() {
+        Some(first) if first.is_alphabetic() => {
+            format!("{}{}", first.to_uppercase(), chars.as_str())
+        }
+        Some(first) if first.is_numeric() => out.to_string(),
+        _ => format!("X{out}"),
+    }
+}
+
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// One line, for memos that go into comments.
+pub(crate) fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn render(
+    format: JournalFormat,
+    budget_name: &str,
+    currency: &Currency,
+    rows: &[JournalRow],
+    income: &HashSet<String>,
+) -> String {
+    let currency_code = currency.code();
+    let amount = |minor: i64, currency: &Currency| {
+        format!(
+            "{} {}",
+            Money::new(minor, currency).decimal(),
+            currency.code()
+        )
+    };
+    let account_name = |row: &JournalRow| format!("Assets:{}", component(&row.account, format));
+    let category_name = |row: &JournalRow| {
+        let root = if income.contains(&row.category_id) {
+            "Income"
+        } else {
+            "Expenses"
+        };
+        format!(
+            "{root}:{}:{}",
+            component(&row.supercategory, format),
+            component(&row.category, format)
+        )
+    };
+
+    let mut out = String::new();
+    // Every account with the currency it is kept in.
+    let mut accounts: BTreeMap<String, &str> = BTreeMap::new();
+    let mut currencies = BTreeMap::from([(currency_code, currency.clone())]);
+    for row in rows {
+        accounts.insert(account_name(row), &row.account_currency);
+        accounts.insert(category_name(row), currency_code);
+        currencies
+            .entry(&row.account_currency)
+            .or_insert_with(|| Currency::stored(&row.account_currency));
+    }
+    match format {
+        JournalFormat::Beancount => {
+            let _ = writeln!(out, "option \"title\" {}", quoted(budget_name));
+            let _ = writeln!(out, "option \"operating_currency\" \"{currency_code}\"\n");
+            if let Some(first) = rows.first() {
+                for (account, code) in &accounts {
+                    let _ = writeln!(out, "{} open {account} {code}", first.date);
+                }
+                out.push('\n');
+            }
+        }
+        JournalFormat::Ledger | JournalFormat::Hledger => {
+            let _ = writeln!(out, "; {}", single_line(budget_name));
+            for (code, currency) in &currencies {
+                let example = amount(10i64.pow(currency.minor_unit()) * 1000, currency);
+                match format {
+                    JournalFormat::Hledger => {
+                        let _ = writeln!(out, "commodity {example}");
+                    }
+                    _ => {
+                        let _ = writeln!(out, "commodity {code}\n    format {example}");
+                    }
+                }
+            }
+            out.push('\n');
+            for account in accounts.keys() {
+                let _ = writeln!(out, "account {account}");
+            }
+            out.push('\n');
+        }
+    }
+
+    for transaction in rows.chunk_by(|a, b| a.transaction_id == b.transaction_id) {
+        let head = &transaction[0];
+        let payee = head.payee.as_deref().unwrap_or("");
+        let memo = head.memo.as_deref().filter(|m| !m.trim().is_empty());
+        match format {
+            JournalFormat::Beancount => {
+                let _ = write!(out, "{} * {}", head.date, quoted(payee));
+                if let Some(memo) = memo {
+                    let _ = write!(out, " {}", quoted(&single_line(memo)));
+                }
+                out.push('\n');
+            }
+            JournalFormat::Ledger | JournalFormat::Hledger => {
+                let status = if head.reconciled { " *" } else { "" };
+                let _ = writeln!(out, "{}{status} {}", head.date, single_line(payee));
+                if let Some(memo) = memo {
+                    let _ = writeln!(out, "    ; {}", single_line(memo));
+                }
+            }
+        }
+        let mut balance = 0;
+        let mut account_balance = 0;
+        for split in transaction {
+            let posted = split.outflow - split.inflow;
+            balance += posted;
+            account_balance += split.account_outflow - split.account_inflow;
+            let _ = write!(
+                out,
+                "    {}  {}",
+                category_name(split),
+                amount(posted, currency)
+            );
+            match (
+                format,
+                split.split_memo.as_deref().filter(|m| !m.trim().is_empty()),
+            ) {
+                (_, None) => out.push('\n'),
+                (JournalFormat::Beancount, Some(memo)) => {
+                    let _ = writeln!(out, "\n      memo: {}", quoted(&single_line(memo)));
+                }
+                (_, Some(memo)) => {
+                    let _ = writeln!(out, "  ; {}", single_line(memo));
+                }
+            }
+        }
+        let account_currency = &currencies[head.account_currency.as_str()];
+        let _ = write!(
+            out,
+            "    {}  {}",
+            account_name(head),
+            amount(-account_balance, account_currency)
+        );
+        if account_currency != currency && balance != 0 {
+            let _ = write!(out, " @@ {}", amount(balance.abs(), currency));
+        }
+        out.push_str("\n\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn row(transaction: &str, category: &str, inflow: i64, outflow: i64) -> JournalRow {
+        JournalRow {
+            transaction_id: transaction.into(),
+            date: NaiveDate::from_ymd_opt(2026, 3, 1).unwrap(),
+            payee: Some("Corner \"Shop\"".into()),
+            memo: None,
+            reconciled: transaction == "t1",
+            account: "Main: Checking".into(),
+            account_currency: "USD".into(),
+            supercategory: "Everyday".into(),
+            category: category.into(),
+            category_id: category.into(),
+            split_memo: (category == "Food").then(|| "bread".into()),
+            inflow,
+            outflow,
+            account_inflow: inflow,
+            account_outflow: outflow,
+        }
+    }
+
+    #[test]
+    fn renders_balanced_ledger_and_beancount() {
+        let rows = [
+            row("t1", "Food", 0, 1250),
+            row("t1", "Home goods", 0, 300),
+            row("t2", "Salary", 200000, 0),
+        ];
+        let income = HashSet::from(["Salary".to_string()]);
+
+        let ledger = render(
+            JournalFormat::Ledger,
+            "Home",
+            &Currency::stored("USD"),
+            &rows,
+            &income,
+        );
+        assert!(ledger.contains("commodity USD\n    format 1000.00 USD\n"));
+        assert!(ledger.contains(
+            "2026-03-01 * Corner \"Shop\"\n    Expenses:Everyday:Food  12.50 USD  ; bread\n    Expenses:Everyday:Home goods  3.00 USD\n    Assets:Main- Checking  -15.50 USD\n"
+        ));
+        assert!(ledger.contains(
+            "2026-03-01 Corner \"Shop\"\n    Income:Everyday:Salary  -2000.00 USD\n    Assets:Main- Checking  2000.00 USD\n"
+        ));
+
+        let rows = rows.map(|row| JournalRow {
+            account_currency: "JPY".into(),
+            ..row
+        });
+        let beancount = render(
+            JournalFormat::Beancount,
+            "Home",
+            &Currency::stored("JPY"),
+            &rows,
+            &income,
+        );
+        assert!(beancount.contains("option \"operating_currency\" \"JPY\""));
+        assert!(beancount.contains("2026-03-01 open Assets:Main-Checking JPY"));
+        assert!(beancount.contains(
+            "2026-03-01 * \"Corner \\\"Shop\\\"\"\n    Expenses:Everyday:Food  1250 JPY\n      memo: \"bread\"\n    Expenses:Everyday:Home-goods  300 JPY\n    Assets:Main-Checking  -1550 JPY\n"
+        ));
+    }
+
+    #[test]
+    fn foreign_accounts_post_in_their_currency_at_cost() {
+        let rows = [JournalRow {
+            account: "Travel card".into(),
+            account_currency: "EUR".into(),
+            account_outflow: 1000,
+            ..row("t1", "Food", 0, 1100)
+        }];
+        let income = HashSet::new();
+
+        let ledger = render(
+            JournalFormat::Ledger,
+            "Home",
+            &Currency::stored("USD"),
+            &rows,
+            &income,
+        );
+        assert!(ledger.contains("commodity EUR\n    format 1000.00 EUR\ncommodity USD\n"));
+        assert!(ledger.contains(
+            "    Expenses:Everyday:Food  11.00 USD  ; bread\n    Assets:Travel card  -10.00 EUR @@ 11.00 USD\n"
+        ));
+
+        let beancount = render(
+            JournalFormat::Beancount,
+            "Home",
+            &Currency::stored("USD"),
+            &rows,
+            &income,
+        );
+        assert!(beancount.contains("2026-03-01 open Assets:Travel-card EUR"));
+        assert!(beancount.contains("2026-03-01 open Expenses:Everyday:Food USD"));
+        assert!(beancount.contains("    Assets:Travel-card  -10.00 EUR @@ 11.00 USD\n"));
+    }
+
+    #[test]
+    fn beancount_components_are_valid() {
+        assert_eq!(
+            component("credit card", JournalFormat::Beancount),
+            "Credit-card"
+        );
+        assert_eq!(component("401(k)", JournalFormat::Beancount), "401-k");
+        assert_eq!(component("ärzte", JournalFormat::Beancount), "Ärzte");
+        assert_eq!(component("(misc)", JournalFormat::Beancount), "Misc");
+        assert_eq!(component("…", JournalFormat::Beancount), "X");
+        assert_eq!(component("a:b  c", JournalFormat::Hledger), "a-b c");
+    }
+}
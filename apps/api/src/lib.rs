@@ -1,10 +1,33 @@
+pub mod admin;
+pub mod archive;
+mod audit;
+mod changes;
+mod closing;
+mod forecast;
+pub mod fsck;
+pub mod fx;
+mod idempotency;
+pub mod importers;
+mod journal;
 pub mod models;
-
+pub mod money;
+mod reassign;
+mod register;
+mod reports;
+mod schedule;
+mod sync;
+mod trash;
+mod undo;
+
+use audit::snapshot;
+use axum::extract::DefaultBodyLimit;
 use axum::extract::Path;
+use axum::extract::Query;
 use axum::extract::State;
 use axum::http::header::AUTHORIZATION;
 use axum::http::HeaderMap;
 use axum::http::StatusCode;
+use axum::middleware;
 use axum::routing::get;
 use axum::routing::post;
 use axum::routing::put;
@@ -12,22 +35,42 @@ use axum::Json;
 use axum::Router;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use base64::Engine;
+pub use changes::purge_change_events;
+use changes::record_change;
+use changes::ChangeEntity;
+pub use changes::ChangeFeed;
+use changes::ChangeOp;
+use chrono::DateTime;
+use chrono::Datelike;
 use chrono::Duration;
+use chrono::Months;
 use chrono::NaiveDate;
 use chrono::Utc;
+use closing::write_status;
+pub use idempotency::purge_idempotency_keys;
 use lettre::message::Mailbox;
 use lettre::AsyncSmtpTransport;
 use lettre::AsyncTransport;
 use lettre::Message;
 use lettre::Tokio1Executor;
+use money::Currency;
 use rand::rngs::OsRng;
 use rand::RngCore;
+use reassign::live_target;
+use reassign::release_account;
+use reassign::release_category;
+use reassign::release_supercategory;
+use reassign::DeleteError;
+use reassign::DeleteQuery;
 use serde::Deserialize;
 use serde::Serialize;
 use sha2::Digest;
 use sha2::Sha256;
 use sqlx::FromRow;
+use sqlx::PgConnection;
 use sqlx::PgPool;
+use trash::owned_budget;
+pub use trash::purge_trash;
 use uuid::Uuid;
 
 #[async_trait::async_trait]
@@ -70,22 +113,97 @@ pub struct AppState {
     pub smtp_host: String,
     pub smtp_port: u16,
     pub smtp_from: String,
+    pub changes: ChangeFeed,
 }
 
+/// Request body limit for budget imports; other routes keep axum's 2MB.
+pub(crate) const MAX_ARCHIVE_BYTES: usize = 64 * 1024 * 1024;
+pub(crate) const ARCHIVE_IMPORT_PATH: &str = "/api/budgets/import";
+
 pub fn router(state: AppState) -> Router {
     Router::new()
         .route("/api/health", get(health))
         .route("/api/auth/magic-link/request", post(request_magic_link))
         .route("/api/auth/magic-link/verify", post(verify_magic_link))
         .route("/api/auth/me", get(me))
+        .route("/api/auth/event-ticket", post(changes::create_event_ticket))
         .route("/api/auth/passkey/register/start", post(passkey_disabled))
         .route("/api/auth/passkey/register/finish", post(passkey_disabled))
         .route("/api/budgets", get(list_budgets).post(create_budget))
+        .route(
+            ARCHIVE_IMPORT_PATH,
+            post(archive::import).layer(DefaultBodyLimit::max(MAX_ARCHIVE_BYTES)),
+        )
+        .route("/api/budgets/:id/export", get(archive::export))
+        .route(
+            "/api/budgets/:id/export/journal",
+            get(journal::export_journal),
+        )
+        .route("/api/budgets/:id/reports/spending", get(reports::spending))
+        .route(
+            "/api/budgets/:id/reports/income-expense",
+            get(reports::income_expense),
+        )
+        .route(
+            "/api/budgets/:id/reports/net-worth",
+            get(reports::net_worth),
+        )
+        .route(
+            "/api/budgets/:id/reports/age-of-money",
+            get(reports::age_of_money),
+        )
+        .route("/api/budgets/:id/reports/fx-gains", get(fx::fx_gains))
+        .route("/api/budgets/:id/forecast", get(forecast::forecast))
+        .route(
+            "/api/budgets/:id/scheduled",
+            get(schedule::list_scheduled).post(schedule::create_scheduled),
+        )
+        .route(
+            "/api/budgets/:id/scheduled/:scheduled_id",
+            put(schedule::update_scheduled).delete(schedule::delete_scheduled),
+        )
+        .route("/api/budgets/:id/targets", get(schedule::list_targets))
+        .route(
+            "/api/budgets/:id/targets/:category_id",
+            put(schedule::set_target).delete(schedule::delete_target),
+        )
+        .route("/api/budgets/:id/dashboard", get(dashboard))
+        .route(
+            "/api/budgets/:id/projections/month/:month",
+            get(month_projection),
+        )
+        .route(
+            "/api/budgets/:id/months/closed",
+            get(closing::list_closed_months),
+        )
+        .route(
+            "/api/budgets/:id/months/:month/close",
+            post(closing::close_month),
+        )
+        .route(
+            "/api/budgets/:id/months/:month/reopen",
+            post(closing::reopen_month),
+        )
+        .route(
+            "/api/budgets/:id/months/:month/snapshot",
+            get(closing::month_snapshot),
+        )
+        .route("/api/budgets/:id/events", get(changes::budget_events))
+        .route("/api/budgets/:id/audit", get(audit::list_audit_log))
+        .route("/api/budgets/:id/undo", post(undo::undo))
+        .route("/api/budgets/:id/redo", post(undo::redo))
+        .route("/api/budgets/:id/trash", get(trash::list_trash))
+        .route(
+            "/api/budgets/:id/trash/:entity/:entity_id/restore",
+            post(trash::restore_from_trash),
+        )
         .route("/api/accounts", get(list_accounts).post(create_account))
         .route(
             "/api/accounts/:id",
             put(update_account).delete(delete_account),
         )
+        .route("/api/accounts/:id/close", post(close_account))
+        .route("/api/accounts/:id/reopen", post(reopen_account))
         .route(
             "/api/supercategories",
             get(list_supercategories).post(create_supercategory),
@@ -106,16 +224,24 @@ pub fn router(state: AppState) -> Router {
             "/api/transactions",
             get(list_transactions).post(create_transaction),
         )
+        .route(
+            "/api/transactions/export",
+            get(register::export_transactions),
+        )
         .route(
             "/api/transactions/:id",
             put(update_transaction).delete(delete_transaction),
         )
-        .route("/api/dashboard", get(dashboard))
-        .route("/api/projections/month/:month", get(month_projection))
+        .route("/api/sync", get(sync::sync))
+        .route("/api/sync/mutations", post(sync::apply_mutations))
         .route(
             "/api/category-assignments",
             get(list_category_assignments).post(create_category_assignment),
         )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            idempotency::replay_idempotent_post,
+        ))
         .with_state(state)
 }
 
@@ -158,16 +284,10 @@ async fn request_magic_link(
     if email.is_empty() || !email.contains('@') {
         return Err(StatusCode::BAD_REQUEST);
     }
-    let token = random_token(32);
-    let token_hash = sha256_hex(&token);
-    let magic_url = format!("{}/?token={token}", state.app_origin.trim_end_matches('/'));
-
-    sqlx::query("insert into magic_link_tokens (email, token_hash, expires_at) values ($1, $2, now() + interval '15 minutes')")
-        .bind(&email)
-        .bind(&token_hash)
-        .execute(&state.db)
+    let token = insert_magic_link_token(&state.db, &email)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let magic_url = magic_link_url(&state.app_origin, &token);
 
     let body = format!("Click to sign in: {magic_url}");
     sqlx::query("insert into email_outbox (to_email, subject, body) values ($1, $2, $3)")
@@ -186,6 +306,54 @@ async fn request_magic_link(
     }))
 }
 
+/// Stores a 15-minute sign-in token for `email` and returns the raw token.
+async fn insert_magic_link_token(db: &PgPool, email: &str) -> Result<String, sqlx::Error> {
+    let token = random_token(32);
+    sqlx::query("insert into magic_link_tokens (email, token_hash, expires_at) values ($1, $2, now() + interval '15 minutes')")
+        .bind(email)
+        .bind(sha256_hex(&token))
+        .execute(db)
+        .await?;
+    Ok(token)
+}
+
+fn magic_link_url(app_origin: &str, token: &str) -> String {
+    format!("{}/?token={token}", app_origin.trim_end_matches('/'))
+}
+
+/// The user owning `email`, created with a verified email, a magic-link auth
+/// method and a default budget if there is none yet.
+async fn find_or_create_user(conn: &mut PgConnection, email: &str) -> Result<Uuid, sqlx::Error> {
+    if let Some((uid,)) =
+        sqlx::query_as::<_, (Uuid,)>("select user_id from user_emails where email = $1 limit 1")
+            .bind(email)
+            .fetch_optional(&mut *conn)
+            .await?
+    {
+        return Ok(uid);
+    }
+    let uid = Uuid::now_v7();
+    sqlx::query("insert into users (id) values ($1)")
+        .bind(uid)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("insert into user_emails (user_id, user_pillid, email, verified_at) select u.id, u.pillid, $2, now() from users u where u.id = $1")
+        .bind(uid)
+        .bind(email)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("insert into auth_methods (user_id, user_pillid, method_type, label) select u.id, u.pillid, 'magic_link_email', $2 from users u where u.id = $1")
+        .bind(uid)
+        .bind(email)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("insert into budgets (user_id, user_pillid, name, currency_code, is_default) select u.id, u.pillid, 'My Budget', 'USD', true from users u where u.id = $1")
+        .bind(uid)
+        .execute(&mut *conn)
+        .await?;
+    Ok(uid)
+}
+
 async fn verify_magic_link(
     State(state): State<AppState>,
     Json(payload): Json<MagicLinkVerifyRequest>,
@@ -205,41 +373,9 @@ async fn verify_magic_link(
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let user_id: Uuid = if let Some((uid,)) =
-        sqlx::query_as::<_, (Uuid,)>("select user_id from user_emails where email = $1 limit 1")
-            .bind(&email)
-            .fetch_optional(&mut *tx)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    {
-        uid
-    } else {
-        let uid = Uuid::now_v7();
-        sqlx::query("insert into users (id) values ($1)")
-            .bind(uid)
-            .execute(&mut *tx)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        sqlx::query("insert into user_emails (user_id, user_pillid, email, verified_at) select u.id, u.pillid, $2, now() from users u where u.id = $1")
-            .bind(uid)
-            .bind(&email)
-            .execute(&mut *tx)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        sqlx::query("insert into auth_methods (user_id, user_pillid, method_type, label) select u.id, u.pillid, 'magic_link_email', $2 from users u where u.id = $1")
-            .bind(uid)
-            .bind(&email)
-            .execute(&mut *tx)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-        sqlx::query("insert into budgets (user_id, user_pillid, name, currency_code, is_default) select u.id, u.pillid, 'My Budget', 'USD', true from users u where u.id = $1")
-            .bind(uid)
-            .execute(&mut *tx)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        uid
-    };
+    let user_id = find_or_create_user(&mut tx, &email)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     sqlx::query("update magic_link_tokens set consumed_at = now() where id = $1")
         .bind(token_id)
@@ -334,16 +470,25 @@ struct UserDto {
 struct BudgetDto {
     id: String,
     name: String,
-    currency_code: String,
+    /// `currency_code` and the `minor_unit` every amount in the budget is in.
+    #[serde(flatten)]
+    #[sqlx(rename = "currency_code", try_from = "String")]
+    currency: Currency,
     is_default: bool,
 }
 
 #[derive(Deserialize)]
 struct CreateBudget {
     name: String,
+    /// ISO 4217, e.g. `USD`; defaults to `USD`.
     currency_code: Option<String>,
 }
 
+/// An ISO 4217 code from a request, in either case.
+fn parse_currency(code: &str) -> Result<Currency, StatusCode> {
+    Currency::parse(&code.trim().to_ascii_uppercase()).ok_or(StatusCode::BAD_REQUEST)
+}
+
 async fn list_budgets(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -357,28 +502,60 @@ async fn list_budgets(
     Ok(Json(rows))
 }
 
+/// Without multi-budget a user has their default budget and at most one other
+/// live budget, whether created or imported; `409` once that one exists.
+pub(crate) async fn ensure_budget_slot(
+    conn: &mut PgConnection,
+    state: &AppState,
+    user_id: Uuid,
+) -> Result<(), StatusCode> {
+    if state.feature_multi_budget {
+        return Ok(());
+    }
+    let (taken,): (bool,) = sqlx::query_as(
+        "select exists (select 1 from budgets where user_id = $1 and deleted_at is null and not is_default)",
+    )
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if taken {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(())
+}
+
 async fn create_budget(
     State(state): State<AppState>,
     headers: HeaderMap,
     Json(payload): Json<CreateBudget>,
 ) -> Result<Json<BudgetDto>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
-    if !state.feature_multi_budget {
-        let (count,): (i64,) = sqlx::query_as("select count(*) from budgets where user_id = $1")
-            .bind(user_id)
-            .fetch_one(&state.db)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        if count > 0 {
-            return Err(StatusCode::CONFLICT);
-        }
-    }
-    let currency = payload.currency_code.unwrap_or_else(|| "USD".into());
+    let currency = parse_currency(payload.currency_code.as_deref().unwrap_or("USD"))?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    ensure_budget_slot(&mut tx, &state, user_id).await?;
     let row = sqlx::query_as::<_, BudgetDto>("insert into budgets (user_id, user_pillid, name, currency_code, is_default) select u.id, u.pillid, $2, $3, false from users u where u.id = $1 returning pillid as id, name, currency_code, is_default")
         .bind(user_id)
         .bind(payload.name)
-        .bind(currency)
-        .fetch_one(&state.db)
+        .bind(currency.code())
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &row.id,
+        ChangeEntity::Budget,
+        &row.id,
+        ChangeOp::Create,
+        None,
+    )
+    .await?;
+    tx.commit()
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(row))
@@ -389,12 +566,20 @@ struct AccountDto {
     id: String,
     budget_id: String,
     name: String,
+    /// `currency_code` and `minor_unit` of the account's own amounts.
+    #[serde(flatten)]
+    #[sqlx(rename = "currency_code", try_from = "String")]
+    currency: Currency,
+    closed_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Deserialize)]
 struct SaveAccount {
     budget_id: String,
     name: String,
+    /// ISO 4217; defaults to the budget's. Set when the account is created,
+    /// an update may only repeat it.
+    currency_code: Option<String>,
 }
 
 async fn list_accounts(
@@ -402,7 +587,7 @@ async fn list_accounts(
     headers: HeaderMap,
 ) -> Result<Json<Vec<AccountDto>>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
-    let rows = sqlx::query_as::<_, AccountDto>("select pillid as id, budget_pillid as budget_id, name from accounts where user_id = $1 and deleted_at is null order by created_at")
+    let rows = sqlx::query_as::<_, AccountDto>("select pillid as id, budget_pillid as budget_id, name, currency_code, closed_at from accounts where user_id = $1 and deleted_at is null order by created_at")
         .bind(user_id)
         .fetch_all(&state.db)
         .await
@@ -416,16 +601,66 @@ async fn create_account(
     Json(payload): Json<SaveAccount>,
 ) -> Result<Json<AccountDto>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
-    let row = sqlx::query_as::<_, AccountDto>("insert into accounts (user_id, user_pillid, budget_id, budget_pillid, name) select u.id, u.pillid, b.id, b.pillid, $3 from users u join budgets b on b.pillid = $2 and b.user_id = u.id and b.deleted_at is null where u.id = $1 returning pillid as id, budget_pillid as budget_id, name")
+    let currency = payload
+        .currency_code
+        .as_deref()
+        .map(parse_currency)
+        .transpose()?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let row = sqlx::query_as::<_, AccountDto>("insert into accounts (user_id, user_pillid, budget_id, budget_pillid, name, currency_code) select u.id, u.pillid, b.id, b.pillid, $3, $4 from users u join budgets b on b.pillid = $2 and b.user_id = u.id and b.deleted_at is null where u.id = $1 returning pillid as id, budget_pillid as budget_id, name, currency_code, closed_at")
         .bind(user_id)
         .bind(payload.budget_id)
         .bind(payload.name)
-        .fetch_one(&state.db)
+        .bind(currency.as_ref().map(Currency::code))
+        .fetch_one(&mut *tx)
         .await
         .map_err(|_| StatusCode::BAD_REQUEST)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &row.budget_id,
+        ChangeEntity::Account,
+        &row.id,
+        ChangeOp::Create,
+        None,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(row))
 }
 
+/// Rows stay in the budget they were created in: composite foreign keys tie
+/// them, and everything that refers to them, to it. An update naming another
+/// budget is refused with 409 instead of failing on those keys.
+async fn ensure_same_budget(
+    conn: &mut PgConnection,
+    entity: ChangeEntity,
+    id: &str,
+    user_id: Uuid,
+    budget_id: &str,
+) -> Result<(), StatusCode> {
+    let sql = format!(
+        "select budget_pillid from {} where pillid = $1 and user_id = $2",
+        entity.table()
+    );
+    let current: Option<(String,)> = sqlx::query_as(&sql)
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match current {
+        Some((current,)) if current != budget_id => Err(StatusCode::CONFLICT),
+        _ => Ok(()),
+    }
+}
+
 async fn update_account(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -433,27 +668,132 @@ async fn update_account(
     Json(payload): Json<SaveAccount>,
 ) -> Result<Json<AccountDto>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
-    let row = sqlx::query_as::<_, AccountDto>("update accounts a set budget_id = b.id, budget_pillid = b.pillid, name = $3, updated_at = now() from budgets b where a.pillid = $1 and a.user_id = $4 and b.pillid = $2 and b.user_id = $4 and b.deleted_at is null returning a.pillid as id, a.budget_pillid as budget_id, a.name")
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    ensure_same_budget(
+        &mut tx,
+        ChangeEntity::Account,
+        &id,
+        user_id,
+        &payload.budget_id,
+    )
+    .await?;
+    let before = snapshot(&mut tx, ChangeEntity::Account, &id).await?;
+    let row = sqlx::query_as::<_, AccountDto>("update accounts a set name = $3, updated_at = now() from budgets b where a.pillid = $1 and a.user_id = $4 and b.pillid = $2 and b.user_id = $4 and b.deleted_at is null and ($5::text is null or a.currency_code = $5) returning a.pillid as id, a.budget_pillid as budget_id, a.name, a.currency_code, a.closed_at")
         .bind(id)
         .bind(payload.budget_id)
         .bind(payload.name)
         .bind(user_id)
-        .fetch_one(&state.db)
+        .bind(payload.currency_code.map(|code| code.trim().to_ascii_uppercase()))
+        .fetch_one(&mut *tx)
         .await
         .map_err(|_| StatusCode::BAD_REQUEST)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &row.budget_id,
+        ChangeEntity::Account,
+        &row.id,
+        ChangeOp::Update,
+        before,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(row))
 }
 
-async fn delete_account(
+/// Closing keeps an account and its history but stops new transactions being
+/// added to it; reopening undoes that.
+async fn close_account(
     State(state): State<AppState>,
     headers: HeaderMap,
     Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
+) -> Result<Json<AccountDto>, StatusCode> {
+    set_account_closed(state, headers, id, true).await
+}
+
+async fn reopen_account(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<AccountDto>, StatusCode> {
+    set_account_closed(state, headers, id, false).await
+}
+
+async fn set_account_closed(
+    state: AppState,
+    headers: HeaderMap,
+    id: String,
+    closed: bool,
+) -> Result<Json<AccountDto>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
-    sqlx::query("update accounts set deleted_at = now() where pillid = $1 and user_id = $2")
-        .bind(id)
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let before = snapshot(&mut tx, ChangeEntity::Account, &id).await?;
+    let row = sqlx::query_as::<_, AccountDto>("update accounts set closed_at = case when $3 then coalesce(closed_at, now()) end, updated_at = now() where pillid = $1 and user_id = $2 and deleted_at is null returning pillid as id, budget_pillid as budget_id, name, currency_code, closed_at")
+        .bind(&id)
         .bind(user_id)
-        .execute(&state.db)
+        .bind(closed)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &row.budget_id,
+        ChangeEntity::Account,
+        &row.id,
+        ChangeOp::Update,
+        before,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(row))
+}
+
+async fn delete_account(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, DeleteError> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some(target) = live_target(&mut tx, ChangeEntity::Account, &id, user_id).await? else {
+        return Ok(StatusCode::NO_CONTENT);
+    };
+    release_account(&mut tx, &target).await?;
+    let before = snapshot(&mut tx, ChangeEntity::Account, &id).await?;
+    sqlx::query("update accounts set deleted_at = now() where id = $1")
+        .bind(target.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &target.budget_pillid,
+        ChangeEntity::Account,
+        &id,
+        ChangeOp::Delete,
+        before,
+    )
+    .await?;
+    tx.commit()
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(StatusCode::NO_CONTENT)
@@ -486,8 +826,26 @@ async fn create_supercategory(
     Json(payload): Json<SaveSupercategory>,
 ) -> Result<Json<SupercategoryDto>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     let row = sqlx::query_as::<_, SupercategoryDto>("insert into supercategories (user_id, user_pillid, budget_id, budget_pillid, name) select u.id, u.pillid, b.id, b.pillid, $3 from users u join budgets b on b.pillid = $2 and b.user_id = u.id and b.deleted_at is null where u.id = $1 returning pillid as id, budget_pillid as budget_id, name")
-        .bind(user_id).bind(payload.budget_id).bind(payload.name).fetch_one(&state.db).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+        .bind(user_id).bind(payload.budget_id).bind(payload.name).fetch_one(&mut *tx).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &row.budget_id,
+        ChangeEntity::Supercategory,
+        &row.id,
+        ChangeOp::Create,
+        None,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(row))
 }
 async fn update_supercategory(
@@ -497,20 +855,71 @@ async fn update_supercategory(
     Json(payload): Json<SaveSupercategory>,
 ) -> Result<Json<SupercategoryDto>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
-    let row = sqlx::query_as::<_, SupercategoryDto>("update supercategories s set budget_id=b.id,budget_pillid=b.pillid,name=$3,updated_at=now() from budgets b where s.pillid=$1 and s.user_id=$4 and b.pillid=$2 and b.user_id=$4 and b.deleted_at is null returning s.pillid as id,s.budget_pillid as budget_id,s.name")
-        .bind(id).bind(payload.budget_id).bind(payload.name).bind(user_id).fetch_one(&state.db).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    ensure_same_budget(
+        &mut tx,
+        ChangeEntity::Supercategory,
+        &id,
+        user_id,
+        &payload.budget_id,
+    )
+    .await?;
+    let before = snapshot(&mut tx, ChangeEntity::Supercategory, &id).await?;
+    let row = sqlx::query_as::<_, SupercategoryDto>("update supercategories s set name=$3,updated_at=now() from budgets b where s.pillid=$1 and s.user_id=$4 and b.pillid=$2 and b.user_id=$4 and b.deleted_at is null returning s.pillid as id,s.budget_pillid as budget_id,s.name")
+        .bind(id).bind(payload.budget_id).bind(payload.name).bind(user_id).fetch_one(&mut *tx).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &row.budget_id,
+        ChangeEntity::Supercategory,
+        &row.id,
+        ChangeOp::Update,
+        before,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(row))
 }
 async fn delete_supercategory(
     State(state): State<AppState>,
     headers: HeaderMap,
     Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
+    Query(query): Query<DeleteQuery>,
+) -> Result<StatusCode, DeleteError> {
     let user_id = user_from_headers(&state, &headers).await?;
-    sqlx::query("update supercategories set deleted_at=now() where pillid=$1 and user_id=$2")
-        .bind(id)
-        .bind(user_id)
-        .execute(&state.db)
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some(target) = live_target(&mut tx, ChangeEntity::Supercategory, &id, user_id).await?
+    else {
+        return Ok(StatusCode::NO_CONTENT);
+    };
+    release_supercategory(&mut tx, user_id, &target, query.reassign_to.as_deref()).await?;
+    let before = snapshot(&mut tx, ChangeEntity::Supercategory, &id).await?;
+    sqlx::query("update supercategories set deleted_at = now() where id = $1")
+        .bind(target.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &target.budget_pillid,
+        ChangeEntity::Supercategory,
+        &id,
+        ChangeOp::Delete,
+        before,
+    )
+    .await?;
+    tx.commit()
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(StatusCode::NO_CONTENT)
@@ -522,12 +931,20 @@ struct CategoryDto {
     budget_id: String,
     supercategory_id: String,
     name: String,
+    /// Holds transfers between accounts; reports leave it out.
+    is_transfer: bool,
+    /// Its inflows are income, what there is to assign.
+    is_income: bool,
 }
 #[derive(Deserialize)]
 struct SaveCategory {
     budget_id: String,
     supercategory_id: String,
     name: String,
+    /// Unchanged on update when missing.
+    is_transfer: Option<bool>,
+    /// Unchanged on update when missing.
+    is_income: Option<bool>,
 }
 
 async fn list_categories(
@@ -535,7 +952,7 @@ async fn list_categories(
     headers: HeaderMap,
 ) -> Result<Json<Vec<CategoryDto>>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
-    let rows = sqlx::query_as::<_, CategoryDto>("select pillid as id, budget_pillid as budget_id, supercategory_pillid as supercategory_id, name from categories where user_id = $1 and deleted_at is null order by created_at")
+    let rows = sqlx::query_as::<_, CategoryDto>("select pillid as id, budget_pillid as budget_id, supercategory_pillid as supercategory_id, name, is_transfer, is_income from categories where user_id = $1 and deleted_at is null order by created_at")
         .bind(user_id).fetch_all(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(rows))
 }
@@ -545,8 +962,26 @@ async fn create_category(
     Json(payload): Json<SaveCategory>,
 ) -> Result<Json<CategoryDto>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
-    let row = sqlx::query_as::<_, CategoryDto>("insert into categories (user_id, user_pillid, budget_id, budget_pillid, supercategory_id, supercategory_pillid, name) select u.id, u.pillid, b.id, b.pillid, s.id, s.pillid, $4 from users u join budgets b on b.pillid=$2 and b.user_id=u.id and b.deleted_at is null join supercategories s on s.pillid=$3 and s.user_id=u.id and s.deleted_at is null and s.budget_id=b.id where u.id=$1 returning pillid as id,budget_pillid as budget_id,supercategory_pillid as supercategory_id,name")
-        .bind(user_id).bind(payload.budget_id).bind(payload.supercategory_id).bind(payload.name).fetch_one(&state.db).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let row = sqlx::query_as::<_, CategoryDto>("insert into categories (user_id, user_pillid, budget_id, budget_pillid, supercategory_id, supercategory_pillid, name, is_transfer, is_income) select u.id, u.pillid, b.id, b.pillid, s.id, s.pillid, $4, coalesce($5, false), coalesce($6, false) from users u join budgets b on b.pillid=$2 and b.user_id=u.id and b.deleted_at is null join supercategories s on s.pillid=$3 and s.user_id=u.id and s.deleted_at is null and s.budget_id=b.id where u.id=$1 returning pillid as id,budget_pillid as budget_id,supercategory_pillid as supercategory_id,name,is_transfer,is_income")
+        .bind(user_id).bind(payload.budget_id).bind(payload.supercategory_id).bind(payload.name).bind(payload.is_transfer).bind(payload.is_income).fetch_one(&mut *tx).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &row.budget_id,
+        ChangeEntity::Category,
+        &row.id,
+        ChangeOp::Create,
+        None,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(row))
 }
 async fn update_category(
@@ -556,20 +991,70 @@ async fn update_category(
     Json(payload): Json<SaveCategory>,
 ) -> Result<Json<CategoryDto>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
-    let row = sqlx::query_as::<_, CategoryDto>("update categories c set budget_id=b.id,budget_pillid=b.pillid,supercategory_id=s.id,supercategory_pillid=s.pillid,name=$4,updated_at=now() from budgets b, supercategories s where c.pillid=$1 and c.user_id=$5 and b.pillid=$2 and b.user_id=$5 and b.deleted_at is null and s.pillid=$3 and s.user_id=$5 and s.deleted_at is null and s.budget_id=b.id returning c.pillid as id,c.budget_pillid as budget_id,c.supercategory_pillid as supercategory_id,c.name")
-        .bind(id).bind(payload.budget_id).bind(payload.supercategory_id).bind(payload.name).bind(user_id).fetch_one(&state.db).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    ensure_same_budget(
+        &mut tx,
+        ChangeEntity::Category,
+        &id,
+        user_id,
+        &payload.budget_id,
+    )
+    .await?;
+    let before = snapshot(&mut tx, ChangeEntity::Category, &id).await?;
+    let row = sqlx::query_as::<_, CategoryDto>("update categories c set supercategory_id=s.id,supercategory_pillid=s.pillid,name=$4,is_transfer=coalesce($6,c.is_transfer),is_income=coalesce($7,c.is_income),updated_at=now() from budgets b, supercategories s where c.pillid=$1 and c.user_id=$5 and b.pillid=$2 and b.user_id=$5 and b.deleted_at is null and s.pillid=$3 and s.user_id=$5 and s.deleted_at is null and s.budget_id=b.id returning c.pillid as id,c.budget_pillid as budget_id,c.supercategory_pillid as supercategory_id,c.name,c.is_transfer,c.is_income")
+        .bind(id).bind(payload.budget_id).bind(payload.supercategory_id).bind(payload.name).bind(user_id).bind(payload.is_transfer).bind(payload.is_income).fetch_one(&mut *tx).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &row.budget_id,
+        ChangeEntity::Category,
+        &row.id,
+        ChangeOp::Update,
+        before,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(row))
 }
 async fn delete_category(
     State(state): State<AppState>,
     headers: HeaderMap,
     Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
+    Query(query): Query<DeleteQuery>,
+) -> Result<StatusCode, DeleteError> {
     let user_id = user_from_headers(&state, &headers).await?;
-    sqlx::query("update categories set deleted_at=now() where pillid=$1 and user_id=$2")
-        .bind(id)
-        .bind(user_id)
-        .execute(&state.db)
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some(target) = live_target(&mut tx, ChangeEntity::Category, &id, user_id).await? else {
+        return Ok(StatusCode::NO_CONTENT);
+    };
+    release_category(&mut tx, user_id, &target, query.reassign_to.as_deref()).await?;
+    let before = snapshot(&mut tx, ChangeEntity::Category, &id).await?;
+    sqlx::query("update categories set deleted_at = now() where id = $1")
+        .bind(target.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &target.budget_pillid,
+        ChangeEntity::Category,
+        &id,
+        ChangeOp::Delete,
+        before,
+    )
+    .await?;
+    tx.commit()
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(StatusCode::NO_CONTENT)
@@ -602,6 +1087,59 @@ fn validate_splits(splits: &[SplitInput]) -> Result<(), StatusCode> {
 
     Ok(())
 }
+async fn insert_transaction_splits(
+    conn: &mut PgConnection,
+    transaction_pillid: &str,
+    splits: &[SplitInput],
+    user_id: Uuid,
+) -> Result<(), StatusCode> {
+    let (account_currency, budget_currency, date): (String, String, NaiveDate) = sqlx::query_as(
+        "select a.currency_code, b.currency_code, t.tx_date
+         from transactions t
+         join accounts a on a.id = t.account_id
+         join budgets b on b.id = t.budget_id
+         where t.pillid = $1",
+    )
+    .bind(transaction_pillid)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::BAD_REQUEST)?;
+    let account_currency = Currency::stored(&account_currency);
+    let budget_currency = Currency::stored(&budget_currency);
+    let foreign = account_currency != budget_currency;
+
+    for s in splits {
+        // Amounts come in the account's currency; the budget counts them in
+        // its own, at the day's rate. No rate, or too little to show, is an
+        // invalid split.
+        let (inflow, outflow) = if foreign {
+            let amount = s.inflow.max(s.outflow);
+            let converted = fx::convert(conn, &account_currency, &budget_currency, date, amount)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .filter(|converted| *converted > 0)
+                .ok_or(StatusCode::BAD_REQUEST)?;
+            if s.inflow > 0 {
+                (converted, 0)
+            } else {
+                (0, converted)
+            }
+        } else {
+            (s.inflow, s.outflow)
+        };
+        let inserted = sqlx::query("insert into transaction_splits (transaction_id,transaction_pillid,category_id,category_pillid,memo,inflow,outflow,account_inflow,account_outflow) select t.id,t.pillid,c.id,c.pillid,$3,$4,$5,$7,$8 from transactions t join categories c on c.pillid=$2 and c.user_id=$6 and c.deleted_at is null and c.budget_id=t.budget_id where t.pillid=$1")
+            .bind(transaction_pillid).bind(&s.category_id).bind(s.memo.clone()).bind(inflow).bind(outflow).bind(user_id)
+            .bind(foreign.then_some(s.inflow)).bind(foreign.then_some(s.outflow))
+            .execute(&mut *conn).await.map_err(|err| write_status(err, StatusCode::BAD_REQUEST))?;
+        // The join silently matches nothing for an unknown or foreign category.
+        if inserted.rows_affected() == 0 {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    Ok(())
+}
+
 #[derive(Deserialize)]
 struct SaveTransaction {
     budget_id: String,
@@ -611,6 +1149,8 @@ struct SaveTransaction {
     memo: Option<String>,
     splits: Vec<SplitInput>,
 }
+/// `inflow` and `outflow` are in the account's currency, the `budget_` ones
+/// in the budget's; they differ only in foreign-currency accounts.
 #[derive(Serialize, FromRow)]
 struct SplitDto {
     id: String,
@@ -618,6 +1158,8 @@ struct SplitDto {
     memo: Option<String>,
     inflow: i64,
     outflow: i64,
+    budget_inflow: i64,
+    budget_outflow: i64,
 }
 
 type TransactionRow = (
@@ -640,17 +1182,56 @@ struct TransactionDto {
     splits: Vec<SplitDto>,
 }
 
+/// Narrows `GET /api/transactions` and its exports; every field is optional.
+#[derive(Deserialize)]
+pub(crate) struct TransactionFilter {
+    pub budget_id: Option<String>,
+    pub account_id: Option<String>,
+    /// First day to include.
+    pub from: Option<NaiveDate>,
+    /// Last day to include.
+    pub to: Option<NaiveDate>,
+}
+
+impl TransactionFilter {
+    /// SQL matching the filter against `t`, the transactions table, with the
+    /// filter values bound from `$2` on by [`TransactionFilter::bind`].
+    pub const SQL: &'static str = "($2::text is null or t.budget_pillid = $2)
+        and ($3::text is null or t.account_pillid = $3)
+        and ($4::date is null or t.tx_date >= $4)
+        and ($5::date is null or t.tx_date <= $5)";
+
+    pub fn bind<'q, O>(
+        &'q self,
+        query: sqlx::query::QueryAs<'q, sqlx::Postgres, O, sqlx::postgres::PgArguments>,
+    ) -> sqlx::query::QueryAs<'q, sqlx::Postgres, O, sqlx::postgres::PgArguments> {
+        query
+            .bind(&self.budget_id)
+            .bind(&self.account_id)
+            .bind(self.from)
+            .bind(self.to)
+    }
+}
+
 async fn list_transactions(
     State(state): State<AppState>,
     headers: HeaderMap,
+    Query(filter): Query<TransactionFilter>,
 ) -> Result<Json<Vec<TransactionDto>>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
-    let tx_rows: Vec<TransactionRow> = sqlx::query_as("select pillid,budget_pillid,account_pillid,tx_date,payee,memo from transactions where user_id=$1 and deleted_at is null order by tx_date desc, created_at desc")
-        .bind(user_id).fetch_all(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let sql = format!(
+        "select pillid,budget_pillid,account_pillid,tx_date,payee,memo from transactions t where user_id=$1 and deleted_at is null and {} order by tx_date desc, created_at desc",
+        TransactionFilter::SQL
+    );
+    let tx_rows: Vec<TransactionRow> = filter
+        .bind(sqlx::query_as(&sql).bind(user_id))
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let mut out = Vec::with_capacity(tx_rows.len());
     for (id, budget_id, account_id, date, payee, memo) in tx_rows {
-        let splits = sqlx::query_as::<_, SplitDto>("select pillid as id, category_pillid as category_id, memo, inflow, outflow from transaction_splits where transaction_pillid=$1 and deleted_at is null order by created_at")
+        let splits = sqlx::query_as::<_, SplitDto>("select pillid as id, category_pillid as category_id, memo, coalesce(account_inflow, inflow) as inflow, coalesce(account_outflow, outflow) as outflow, inflow as budget_inflow, outflow as budget_outflow from transaction_splits where transaction_pillid=$1 and deleted_at is null order by created_at")
             .bind(&id).fetch_all(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
         out.push(TransactionDto {
             id,
@@ -678,14 +1259,20 @@ async fn create_transaction(
         .begin()
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let (id, budget_id, account_id): (String, String, String) = sqlx::query_as("insert into transactions (user_id,user_pillid,budget_id,budget_pillid,account_id,account_pillid,tx_date,payee,memo) select u.id,u.pillid,b.id,b.pillid,a.id,a.pillid,$4,$5,$6 from users u join budgets b on b.pillid=$2 and b.user_id=u.id and b.deleted_at is null join accounts a on a.pillid=$3 and a.user_id=u.id and a.budget_id=b.id and a.deleted_at is null where u.id=$1 returning pillid,budget_pillid,account_pillid")
+    let (id, budget_id, account_id): (String, String, String) = sqlx::query_as("insert into transactions (user_id,user_pillid,budget_id,budget_pillid,account_id,account_pillid,tx_date,payee,memo) select u.id,u.pillid,b.id,b.pillid,a.id,a.pillid,$4,$5,$6 from users u join budgets b on b.pillid=$2 and b.user_id=u.id and b.deleted_at is null join accounts a on a.pillid=$3 and a.user_id=u.id and a.budget_id=b.id and a.deleted_at is null and a.closed_at is null where u.id=$1 returning pillid,budget_pillid,account_pillid")
         .bind(user_id).bind(payload.budget_id.clone()).bind(payload.account_id.clone()).bind(payload.date).bind(payload.payee.clone()).bind(payload.memo.clone())
-        .fetch_one(&mut *tx).await.map_err(|_| StatusCode::BAD_REQUEST)?;
-    for s in &payload.splits {
-        sqlx::query("insert into transaction_splits (transaction_id,transaction_pillid,category_id,category_pillid,memo,inflow,outflow) select t.id,t.pillid,c.id,c.pillid,$3,$4,$5 from transactions t join categories c on c.pillid=$2 and c.user_id=$6 and c.deleted_at is null and c.budget_id=t.budget_id where t.pillid=$1")
-            .bind(&id).bind(&s.category_id).bind(s.memo.clone()).bind(s.inflow).bind(s.outflow).bind(user_id)
-            .execute(&mut *tx).await.map_err(|_| StatusCode::BAD_REQUEST)?;
-    }
+        .fetch_one(&mut *tx).await.map_err(|err| write_status(err, StatusCode::BAD_REQUEST))?;
+    insert_transaction_splits(&mut tx, &id, &payload.splits, user_id).await?;
+    record_change(
+        &mut tx,
+        user_id,
+        &budget_id,
+        ChangeEntity::Transaction,
+        &id,
+        ChangeOp::Create,
+        None,
+    )
+    .await?;
     tx.commit()
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
@@ -700,6 +1287,27 @@ async fn create_transaction(
     }))
 }
 
+/// Reconciled transactions are locked, online as in offline replay; see
+/// `docs/offline-sync.md`. Locks the row so it cannot be reconciled meanwhile.
+async fn ensure_unreconciled(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    id: &str,
+) -> Result<(), StatusCode> {
+    let reconciled: Option<(bool,)> = sqlx::query_as(
+        "select reconciled_at is not null from transactions where pillid = $1 and user_id = $2 for update",
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some((true,)) = reconciled {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(())
+}
+
 async fn update_transaction(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -714,19 +1322,35 @@ async fn update_transaction(
         .begin()
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let (budget_id, account_id): (String, String) = sqlx::query_as("update transactions t set budget_id=b.id,budget_pillid=b.pillid,account_id=a.id,account_pillid=a.pillid,tx_date=$4,payee=$5,memo=$6,updated_at=now() from budgets b, accounts a where t.pillid=$1 and t.user_id=$7 and b.pillid=$2 and b.user_id=$7 and b.deleted_at is null and a.pillid=$3 and a.user_id=$7 and a.budget_id=b.id and a.deleted_at is null returning t.budget_pillid,t.account_pillid")
+    ensure_unreconciled(&mut tx, user_id, &id).await?;
+    ensure_same_budget(
+        &mut tx,
+        ChangeEntity::Transaction,
+        &id,
+        user_id,
+        &payload.budget_id,
+    )
+    .await?;
+    let before = snapshot(&mut tx, ChangeEntity::Transaction, &id).await?;
+    let (budget_id, account_id): (String, String) = sqlx::query_as("update transactions t set account_id=a.id,account_pillid=a.pillid,tx_date=$4,payee=$5,memo=$6,updated_at=now() from budgets b, accounts a where t.pillid=$1 and t.user_id=$7 and b.pillid=$2 and b.user_id=$7 and b.deleted_at is null and a.pillid=$3 and a.user_id=$7 and a.budget_id=b.id and a.deleted_at is null and a.closed_at is null returning t.budget_pillid,t.account_pillid")
         .bind(&id).bind(payload.budget_id.clone()).bind(payload.account_id.clone()).bind(payload.date).bind(payload.payee.clone()).bind(payload.memo.clone()).bind(user_id)
-        .fetch_one(&mut *tx).await.map_err(|_| StatusCode::BAD_REQUEST)?;
+        .fetch_one(&mut *tx).await.map_err(|err| write_status(err, StatusCode::BAD_REQUEST))?;
     sqlx::query("update transaction_splits set deleted_at=now() where transaction_pillid=$1")
         .bind(&id)
         .execute(&mut *tx)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    for s in &payload.splits {
-        sqlx::query("insert into transaction_splits (transaction_id,transaction_pillid,category_id,category_pillid,memo,inflow,outflow) select t.id,t.pillid,c.id,c.pillid,$3,$4,$5 from transactions t join categories c on c.pillid=$2 and c.user_id=$6 and c.deleted_at is null and c.budget_id=t.budget_id where t.pillid=$1")
-            .bind(&id).bind(&s.category_id).bind(s.memo.clone()).bind(s.inflow).bind(s.outflow).bind(user_id)
-            .execute(&mut *tx).await.map_err(|_| StatusCode::BAD_REQUEST)?;
-    }
+        .map_err(|err| write_status(err, StatusCode::INTERNAL_SERVER_ERROR))?;
+    insert_transaction_splits(&mut tx, &id, &payload.splits, user_id).await?;
+    record_change(
+        &mut tx,
+        user_id,
+        &budget_id,
+        ChangeEntity::Transaction,
+        &id,
+        ChangeOp::Update,
+        before,
+    )
+    .await?;
     tx.commit()
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
@@ -747,57 +1371,245 @@ async fn delete_transaction(
     Path(id): Path<String>,
 ) -> Result<StatusCode, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
-    sqlx::query("update transactions set deleted_at=now() where pillid=$1 and user_id=$2")
-        .bind(id)
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    ensure_unreconciled(&mut tx, user_id, &id).await?;
+    let before = snapshot(&mut tx, ChangeEntity::Transaction, &id).await?;
+    let deleted: Option<(String,)> = sqlx::query_as("update transactions set deleted_at=now() where pillid=$1 and user_id=$2 and deleted_at is null returning budget_pillid")
+        .bind(&id)
         .bind(user_id)
-        .execute(&state.db)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|err| write_status(err, StatusCode::INTERNAL_SERVER_ERROR))?;
+    if let Some((budget_pillid,)) = deleted {
+        record_change(
+            &mut tx,
+            user_id,
+            &budget_pillid,
+            ChangeEntity::Transaction,
+            &id,
+            ChangeOp::Delete,
+            before,
+        )
+        .await?;
+    }
+    tx.commit()
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(StatusCode::NO_CONTENT)
 }
 
-#[derive(Serialize)]
-struct DashboardDto {
-    inflow: i64,
-    outflow: i64,
-    available: i64,
+#[derive(Deserialize)]
+struct DashboardQuery {
+    /// `YYYY-MM`; defaults to the current month.
+    month: Option<String>,
+    /// How many months up to `month` to break down, 1 to 120; defaults to 12.
+    months: Option<u32>,
 }
 
-fn project_available(inflow: i64, outflow: i64) -> i64 {
-    inflow - outflow
+/// One category's assignments and activity (outflow minus inflow) in a month.
+#[derive(FromRow)]
+struct CategoryMonth {
+    month: NaiveDate,
+    assigned: i64,
+    activity: i64,
+    /// The category is marked `is_income`; its inflows are what there is to
+    /// assign.
+    income: bool,
 }
 
-async fn compute_dashboard_projection(
-    db: &PgPool,
-    user_id: Uuid,
+#[derive(Debug, PartialEq, Serialize)]
+struct DashboardMonthDto {
+    month: String,
+    income: i64,
+    assigned: i64,
+    /// Spending in categories other than income.
+    activity: i64,
+    /// How far categories spent past what was assigned to them that month.
+    overspent: i64,
+    /// Income minus assignments up to the end of the month.
+    ready_to_assign: i64,
+}
+
+#[derive(Serialize, FromRow)]
+struct DashboardAccountDto {
+    id: String,
+    name: String,
+    /// At the end of the month.
+    balance: i64,
+    /// During the month.
+    inflow: i64,
+    outflow: i64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DashboardDto {
+    budget_id: String,
+    #[serde(flatten)]
+    currency: Currency,
+    month: String,
+    ready_to_assign: i64,
+    overspent: i64,
+    /// Oldest first, ending with `month`.
+    months: Vec<DashboardMonthDto>,
+    accounts: Vec<DashboardAccountDto>,
+}
+
+/// Per-month totals over `months` (first days, ascending) from category
+/// months up to the last of them. Transfers are not in `rows`.
+fn project_months(rows: &[CategoryMonth], months: &[NaiveDate]) -> Vec<DashboardMonthDto> {
+    let mut ready_to_assign: i64 = rows
+        .iter()
+        .filter(|row| row.month < months[0])
+        .map(|row| if row.income { -row.activity } else { 0 } - row.assigned)
+        .sum();
+    months
+        .iter()
+        .map(|month| {
+            let mut totals = DashboardMonthDto {
+                month: month.format("%Y-%m").to_string(),
+                income: 0,
+                assigned: 0,
+                activity: 0,
+                overspent: 0,
+                ready_to_assign: 0,
+            };
+            for row in rows.iter().filter(|row| row.month == *month) {
+                totals.assigned += row.assigned;
+                if row.income {
+                    totals.income -= row.activity;
+                } else {
+                    totals.activity += row.activity;
+                    totals.overspent += (row.activity - row.assigned).max(0);
+                }
+            }
+            ready_to_assign += totals.income - totals.assigned;
+            totals.ready_to_assign = ready_to_assign;
+            totals
+        })
+        .collect()
+}
+
+pub(crate) async fn compute_dashboard_projection(
+    conn: &mut PgConnection,
+    budget_id: Uuid,
+    month: NaiveDate,
+    months: u32,
 ) -> Result<DashboardDto, StatusCode> {
-    let (inflow, outflow): (i64, i64) = sqlx::query_as(
-        "select coalesce(sum(ts.inflow),0)::bigint as inflow, coalesce(sum(ts.outflow),0)::bigint as outflow from transactions t join transaction_splits ts on ts.transaction_id=t.id where t.user_id=$1 and t.deleted_at is null and ts.deleted_at is null",
+    let (budget_pillid, currency_code): (String, String) =
+        sqlx::query_as("select pillid, currency_code from budgets where id = $1")
+            .bind(budget_id)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let first = month
+        .checked_sub_months(Months::new(months - 1))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let end = month
+        .checked_add_months(Months::new(1))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let rows: Vec<CategoryMonth> = sqlx::query_as(
+        "with splits as (
+             select ts.category_id, t.tx_date, ts.outflow - ts.inflow as activity
+             from transactions t
+             join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+             join categories c on c.id = ts.category_id and not c.is_transfer
+             where t.budget_id = $1 and t.deleted_at is null
+         ), activity as (
+             select category_id, date_trunc('month', tx_date::timestamp)::date as month,
+                    sum(activity)::bigint as activity
+             from splits where tx_date < $2
+             group by 1, 2
+         ), assigned as (
+             select category_id, month, sum(amount)::bigint as assigned
+             from category_assignments
+             where budget_id = $1 and deleted_at is null and month < $2
+             group by 1, 2
+         )
+         select coalesce(a.month, s.month) as month,
+                coalesce(s.assigned, 0) as assigned,
+                coalesce(a.activity, 0) as activity,
+                c.is_income as income
+         from activity a
+         full join assigned s on s.category_id = a.category_id and s.month = a.month
+         join categories c on c.id = coalesce(a.category_id, s.category_id)
+         order by 1",
     )
-    .bind(user_id)
-    .fetch_one(db)
+    .bind(budget_id)
+    .bind(end)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let accounts: Vec<DashboardAccountDto> = sqlx::query_as(
+        "select a.pillid as id, a.name,
+                coalesce(sum(ts.inflow - ts.outflow), 0)::bigint as balance,
+                coalesce(sum(ts.inflow) filter (where t.tx_date >= $2), 0)::bigint as inflow,
+                coalesce(sum(ts.outflow) filter (where t.tx_date >= $2), 0)::bigint as outflow
+         from accounts a
+         left join transactions t on t.account_id = a.id and t.deleted_at is null and t.tx_date < $3
+         left join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+         where a.budget_id = $1 and a.deleted_at is null
+         group by a.id
+         order by a.created_at, a.pillid",
+    )
+    .bind(budget_id)
+    .bind(month)
+    .bind(end)
+    .fetch_all(&mut *conn)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let periods: Vec<NaiveDate> = (0..months)
+        .filter_map(|i| first.checked_add_months(Months::new(i)))
+        .collect();
+    let months = project_months(&rows, &periods);
+    let current = months.last().ok_or(StatusCode::BAD_REQUEST)?;
     Ok(DashboardDto {
-        inflow,
-        outflow,
-        available: project_available(inflow, outflow),
+        budget_id: budget_pillid,
+        currency: Currency::stored(&currency_code),
+        month: current.month.clone(),
+        ready_to_assign: current.ready_to_assign,
+        overspent: current.overspent,
+        months,
+        accounts,
     })
 }
 
+/// `GET /api/budgets/:id/dashboard?month=YYYY-MM&months=12`
 async fn dashboard(
     State(state): State<AppState>,
     headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+    Query(query): Query<DashboardQuery>,
 ) -> Result<Json<DashboardDto>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
+    let month = match query.month {
+        Some(month) => parse_projection_month(&month)?,
+        None => {
+            let today = Utc::now().date_naive();
+            NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap_or(today)
+        }
+    };
+    let months = query.months.unwrap_or(12);
+    if !(1..=120).contains(&months) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut conn, &budget_pillid, user_id).await?;
     Ok(Json(
-        compute_dashboard_projection(&state.db, user_id).await?,
+        compute_dashboard_projection(&mut conn, budget_id, month, months).await?,
     ))
 }
 
 #[derive(Serialize, FromRow)]
-struct CategoryProjectionDto {
+pub(crate) struct CategoryProjectionDto {
     category_id: String,
     assigned: i64,
     activity: i64,
@@ -821,62 +1633,70 @@ struct CategoryAssignmentDto {
     amount: i64,
 }
 
-fn parse_projection_month(month: &str) -> Result<NaiveDate, StatusCode> {
+pub(crate) fn parse_projection_month(month: &str) -> Result<NaiveDate, StatusCode> {
     let stamped = format!("{month}-01");
     NaiveDate::parse_from_str(&stamped, "%Y-%m-%d").map_err(|_| StatusCode::BAD_REQUEST)
 }
 
+/// `GET /api/budgets/:id/projections/month/:month`, from the snapshot if the
+/// month is closed.
 async fn month_projection(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Path(month): Path<String>,
+    Path((budget_pillid, month)): Path<(String, String)>,
 ) -> Result<Json<Vec<CategoryProjectionDto>>, StatusCode> {
     let user_id = user_from_headers(&state, &headers).await?;
     let period = parse_projection_month(&month)?;
-
-    let categories: Vec<(String, Uuid)> = sqlx::query_as(
-        "select pillid, id from categories where user_id = $1 and deleted_at is null order by created_at",
-    )
-    .bind(user_id)
-    .fetch_all(&state.db)
-    .await
-    .unwrap_or_default();
-
-    let mut rows = Vec::with_capacity(categories.len());
-    for (category_pillid, category_id) in categories {
-        let (assigned,): (i64,) = sqlx::query_as(
-            "select coalesce(sum(amount), 0)::bigint from category_assignments where category_id = $1 and month = $2 and deleted_at is null",
-        )
-        .bind(category_id)
-        .bind(period)
-        .fetch_one(&state.db)
-        .await
-        .unwrap_or((0,));
-
-        let (activity,): (i64,) = sqlx::query_as(
-            "select coalesce(sum(ts.outflow - ts.inflow), 0)::bigint
-             from transaction_splits ts
-             join transactions t on t.id = ts.transaction_id
-             where ts.category_id = $1
-               and ts.deleted_at is null
-               and t.deleted_at is null
-               and date_trunc('month', t.tx_date::timestamp)::date = $2",
-        )
-        .bind(category_id)
-        .bind(period)
-        .fetch_one(&state.db)
+    let mut conn = state
+        .db
+        .acquire()
         .await
-        .unwrap_or((0,));
-
-        rows.push(CategoryProjectionDto {
-            category_id: category_pillid,
-            assigned,
-            activity,
-            available: assigned - activity,
-        });
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut conn, &budget_pillid, user_id).await?;
+    if let Some(rows) = closing::snapshot_categories(&mut conn, budget_id, period).await? {
+        return Ok(Json(rows));
     }
-
-    Ok(Json(rows))
+    Ok(Json(category_month(&mut conn, budget_id, period).await?))
+}
+
+/// Each live category's assigned, activity and available in one month.
+pub(crate) async fn category_month(
+    conn: &mut PgConnection,
+    budget_id: Uuid,
+    period: NaiveDate,
+) -> Result<Vec<CategoryProjectionDto>, StatusCode> {
+    let end = period
+        .checked_add_months(Months::new(1))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    sqlx::query_as::<_, CategoryProjectionDto>(
+        "select c.pillid as category_id,
+                coalesce(a.assigned, 0) as assigned,
+                coalesce(x.activity, 0) as activity,
+                coalesce(a.assigned, 0) - coalesce(x.activity, 0) as available
+         from categories c
+         left join (
+             select category_id, sum(amount)::bigint as assigned
+             from category_assignments
+             where budget_id = $1 and month = $2 and deleted_at is null
+             group by category_id
+         ) a on a.category_id = c.id
+         left join (
+             select ts.category_id, sum(ts.outflow - ts.inflow)::bigint as activity
+             from transactions t
+             join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+             where t.budget_id = $1 and t.deleted_at is null
+               and t.tx_date >= $2 and t.tx_date < $3
+             group by ts.category_id
+         ) x on x.category_id = c.id
+         where c.budget_id = $1 and c.deleted_at is null
+         order by c.created_at, c.pillid",
+    )
+    .bind(budget_id)
+    .bind(period)
+    .bind(end)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
 
 async fn list_category_assignments(
@@ -914,6 +1734,11 @@ async fn create_category_assignment(
     let user_id = user_from_headers(&state, &headers).await?;
     let period = parse_projection_month(&payload.month)?;
 
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     let row = sqlx::query_as::<_, CategoryAssignmentDto>(
         "insert into category_assignments (user_id, user_pillid, budget_id, budget_pillid, category_id, category_pillid, month, amount)
          select u.id, u.pillid, b.id, b.pillid, c.id, c.pillid, $4, $5
@@ -928,9 +1753,22 @@ async fn create_category_assignment(
     .bind(payload.category_id)
     .bind(period)
     .bind(payload.amount)
-    .fetch_one(&state.db)
+    .fetch_one(&mut *tx)
     .await
-    .map_err(|_| StatusCode::BAD_REQUEST)?;
+    .map_err(|err| write_status(err, StatusCode::BAD_REQUEST))?;
+    record_change(
+        &mut tx,
+        user_id,
+        &row.budget_id,
+        ChangeEntity::CategoryAssignment,
+        &row.id,
+        ChangeOp::Create,
+        None,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     Ok(Json(row))
 }
@@ -1052,9 +1890,33 @@ mod unit_tests {
     }
 
     #[test]
-    fn dashboard_projection_is_deterministic() {
-        assert_eq!(project_available(4_500, 1_200), 3_300);
-        assert_eq!(project_available(4_500, 1_200), 3_300);
+    fn dashboard_projection_carries_earlier_months_into_ready_to_assign() {
+        let month = |m| NaiveDate::from_ymd_opt(2026, m, 1).unwrap();
+        let row = |m, assigned, activity, income| CategoryMonth {
+            month: month(m),
+            assigned,
+            activity,
+            income,
+        };
+        let rows = [
+            row(1, 0, -5_000, true),
+            row(1, 3_000, 1_000, false),
+            row(2, 500, 1_200, false),
+            row(2, 0, 300, false),
+        ];
+        let months = project_months(&rows, &[month(2), month(3)]);
+        assert_eq!(
+            months[0],
+            DashboardMonthDto {
+                month: "2026-02".into(),
+                income: 0,
+                assigned: 500,
+                activity: 1_500,
+                overspent: 1_000,
+                ready_to_assign: 1_500,
+            }
+        );
+        assert_eq!((months[1].activity, months[1].ready_to_assign), (0, 1_500));
     }
 
     #[test]
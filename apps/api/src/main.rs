@@ -1,15 +1,127 @@
 use std::env;
+use std::fs;
+use std::io;
+use std::io::Read;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
 
 use anyhow::Context;
+use clap::Parser;
+use clap::Subcommand;
+use envelopezero_api::admin;
+use envelopezero_api::archive::BudgetArchive;
+use envelopezero_api::fsck::run_fsck;
+use envelopezero_api::fx;
+use envelopezero_api::importers::actual;
+use envelopezero_api::importers::gnucash;
+use envelopezero_api::importers::ynab;
+use envelopezero_api::importers::Imported;
+use envelopezero_api::purge_change_events;
+use envelopezero_api::purge_idempotency_keys;
+use envelopezero_api::purge_trash;
 use envelopezero_api::router;
 use envelopezero_api::seed_dev_data;
 use envelopezero_api::AppState;
+use envelopezero_api::ChangeFeed;
 use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
 use tower_http::services::ServeDir;
 use tower_http::services::ServeFile;
 use tower_http::trace::TraceLayer;
 
+#[derive(Parser)]
+#[command(version, about = "EnvelopeZero API server and admin tools")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run migrations and start the API server (the default).
+    Serve,
+    /// Check the database for inconsistencies. Exits non-zero if any remain.
+    Fsck {
+        /// Repair what can be repaired, in one transaction.
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Apply pending database migrations and exit.
+    Migrate,
+    /// Load the development seed data.
+    Seed,
+    /// Create a user (with a default budget) for an email address.
+    CreateUser { email: String },
+    /// Print a sign-in link for an email address instead of mailing it.
+    IssueLoginLink { email: String },
+    /// Sign a user out everywhere.
+    RevokeSessions { email: String },
+    /// Write a budget as a JSON archive.
+    ExportBudget {
+        /// Budget id (pillid).
+        budget_id: String,
+        /// File to write; standard output if omitted.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Replace names, payees, memos and ids, e.g. to share as a test
+        /// fixture.
+        #[arg(long)]
+        anonymize: bool,
+    },
+    /// Recreate a budget from a JSON archive for the user with this email,
+    /// creating the user if needed.
+    ImportBudget {
+        email: String,
+        /// Archive to read; standard input if omitted.
+        #[arg(long, short)]
+        input: Option<PathBuf>,
+    },
+    /// Convert a YNAB export into a new budget for the user with this email,
+    /// creating the user if needed. Prints what could not be mapped.
+    ImportYnab {
+        email: String,
+        /// YNAB4 `Budget.yfull`, YNAB API budget JSON, or register CSV.
+        export: PathBuf,
+        /// Budget CSV with the monthly amounts, next to a register CSV.
+        #[arg(long)]
+        budget_csv: Option<PathBuf>,
+        /// Budget name; defaults to the export's, if it has one.
+        #[arg(long)]
+        name: Option<String>,
+        /// ISO 4217 code. Required for CSVs unless USD; YNAB4 files name a
+        /// locale that is used otherwise.
+        #[arg(long)]
+        currency: Option<String>,
+        /// chrono format of register CSV dates, e.g. `%d/%m/%Y`.
+        #[arg(long)]
+        date_format: Option<String>,
+    },
+    /// Convert an Actual Budget export (zip or `db.sqlite`) into a new budget
+    /// for the user with this email. Prints what could not be mapped.
+    ImportActual {
+        email: String,
+        export: PathBuf,
+        /// Budget name; defaults to the one in the export zip.
+        #[arg(long)]
+        name: Option<String>,
+        /// ISO 4217 code; Actual does not record one.
+        #[arg(long, default_value = "USD")]
+        currency: String,
+    },
+    /// Convert a GnuCash book (XML, compressed or not, or SQLite) into a new
+    /// budget for the user with this email. Prints what could not be mapped.
+    ImportGnucash {
+        email: String,
+        book: PathBuf,
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Load exchange rates from a CSV file with a `date,base,quote,rate`
+    /// header, replacing any already loaded for the same pair and day.
+    LoadRates { file: PathBuf },
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenvy::dotenv().ok();
@@ -17,7 +129,147 @@ async fn main() -> anyhow::Result<()> {
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
+    let cli = Cli::parse();
     let database_url = env::var("DATABASE_URL").context("DATABASE_URL missing")?;
+    let pool = connect(&database_url).await?;
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(pool).await?,
+        Command::Fsck { fix } => fsck(&pool, fix).await?,
+        Command::Migrate => migrate(&pool).await?,
+        Command::Seed => seed_dev_data(&pool).await.context("seed failed")?,
+        Command::CreateUser { email } => {
+            println!("{}", admin::create_user(&pool, &email).await?);
+        }
+        Command::IssueLoginLink { email } => {
+            let app_origin =
+                env::var("APP_ORIGIN").unwrap_or_else(|_| "http://localhost:8080".into());
+            println!(
+                "{}",
+                admin::issue_login_link(&pool, &app_origin, &email).await?
+            );
+        }
+        Command::RevokeSessions { email } => {
+            let revoked = admin::revoke_sessions(&pool, &email).await?;
+            println!("revoked {revoked} session(s)");
+        }
+        Command::ExportBudget {
+            budget_id,
+            output,
+            anonymize,
+        } => {
+            let mut archive = admin::export_budget_archive(&pool, &budget_id).await?;
+            if anonymize {
+                archive.anonymize();
+            }
+            let json = serde_json::to_string_pretty(&archive)?;
+            match output {
+                Some(path) => fs::write(&path, json)
+                    .with_context(|| format!("failed to write {}", path.display()))?,
+                None => println!("{json}"),
+            }
+        }
+        Command::ImportBudget { email, input } => {
+            let json = match input {
+                Some(path) => read(&path)?,
+                None => {
+                    let mut json = String::new();
+                    io::stdin().read_to_string(&mut json)?;
+                    json
+                }
+            };
+            let archive: BudgetArchive =
+                serde_json::from_str(&json).context("not a budget archive")?;
+            println!(
+                "{}",
+                admin::import_budget_archive(&pool, &email, &archive).await?
+            );
+        }
+        Command::ImportYnab {
+            email,
+            export,
+            budget_csv,
+            name,
+            currency,
+            date_format,
+        } => {
+            let text = read(&export)?;
+            let imported = if text.trim_start().starts_with('{') {
+                ynab::from_json(&text, name.as_deref(), currency.as_deref())?
+            } else {
+                let budget = budget_csv.as_deref().map(read).transpose()?;
+                ynab::from_csv(
+                    &text,
+                    budget.as_deref(),
+                    name.as_deref(),
+                    currency.as_deref().unwrap_or("USD"),
+                    date_format.as_deref(),
+                )?
+            };
+            import_converted(&pool, &email, imported).await?;
+        }
+        Command::ImportActual {
+            email,
+            export,
+            name,
+            currency,
+        } => {
+            let imported = actual::from_file(&export, name.as_deref(), &currency).await?;
+            import_converted(&pool, &email, imported).await?;
+        }
+        Command::ImportGnucash { email, book, name } => {
+            let imported = gnucash::from_file(&book, name.as_deref()).await?;
+            import_converted(&pool, &email, imported).await?;
+        }
+        Command::LoadRates { file } => {
+            let rates = fx::parse_rates(&read(&file)?)
+                .with_context(|| format!("{} is not a rates CSV", file.display()))?;
+            let stored = fx::store_rates(&pool, &rates).await?;
+            println!("loaded {stored} rate(s)");
+        }
+    }
+    Ok(())
+}
+
+/// Imports a converted budget, reporting what was lost on stderr and the new
+/// budget id on stdout.
+async fn import_converted(pool: &PgPool, email: &str, imported: Imported) -> anyhow::Result<()> {
+    eprint!("{imported}");
+    println!(
+        "{}",
+        admin::import_budget_archive(pool, email, &imported.archive).await?
+    );
+    Ok(())
+}
+
+fn read(path: &Path) -> anyhow::Result<String> {
+    fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+async fn connect(database_url: &str) -> anyhow::Result<PgPool> {
+    PgPoolOptions::new()
+        .max_connections(10)
+        .connect(database_url)
+        .await
+        .context("failed to connect to postgres")
+}
+
+async fn migrate(pool: &PgPool) -> anyhow::Result<()> {
+    sqlx::migrate!("./migrations")
+        .run(pool)
+        .await
+        .context("failed to run migrations")
+}
+
+async fn fsck(pool: &PgPool, fix: bool) -> anyhow::Result<()> {
+    let report = run_fsck(pool, fix).await.context("fsck failed")?;
+    print!("{report}");
+    match report.outstanding() {
+        0 => Ok(()),
+        n => anyhow::bail!("{n} problem(s) remaining"),
+    }
+}
+
+async fn serve(pool: PgPool) -> anyhow::Result<()> {
     let port: u16 = env::var("PORT").unwrap_or_else(|_| "8080".into()).parse()?;
     let app_origin = env::var("APP_ORIGIN").unwrap_or_else(|_| "http://localhost:8080".into());
     let feature_passkeys =
@@ -33,22 +285,45 @@ async fn main() -> anyhow::Result<()> {
         .parse()
         .unwrap_or(1025);
     let smtp_from = env::var("SMTP_FROM").unwrap_or_else(|_| "noreply@envelopezero.local".into());
+    let trash_retention_days: i64 = env::var("TRASH_RETENTION_DAYS")
+        .unwrap_or_else(|_| "30".into())
+        .parse()
+        .context("TRASH_RETENTION_DAYS must be a number of days")?;
+    // Anything less would purge trash as soon as it is made, or all of it.
+    if trash_retention_days < 1 {
+        anyhow::bail!("TRASH_RETENTION_DAYS must be at least 1, got {trash_retention_days}");
+    }
 
-    let pool = PgPoolOptions::new()
-        .max_connections(10)
-        .connect(&database_url)
-        .await
-        .context("failed to connect to postgres")?;
-
-    sqlx::migrate!("./migrations")
-        .run(&pool)
-        .await
-        .context("failed to run migrations")?;
+    migrate(&pool).await?;
 
     if dev_seed {
         seed_dev_data(&pool).await.context("seed failed")?;
     }
 
+    let purge_pool = pool.clone();
+    tokio::spawn(async move {
+        let retention = chrono::Duration::days(trash_retention_days);
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match purge_trash(&purge_pool, retention).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!(purged, "purged expired trash"),
+                Err(err) => tracing::warn!(%err, "trash purge failed"),
+            }
+            match purge_change_events(&purge_pool).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!(purged, "purged old change events"),
+                Err(err) => tracing::warn!(%err, "change event purge failed"),
+            }
+            match purge_idempotency_keys(&purge_pool).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!(purged, "purged expired idempotency keys"),
+                Err(err) => tracing::warn!(%err, "idempotency key purge failed"),
+            }
+        }
+    });
+
     let api_router = router(AppState {
         db: pool,
         feature_passkeys,
@@ -58,6 +333,7 @@ async fn main() -> anyhow::Result<()> {
         smtp_host,
         smtp_port,
         smtp_from,
+        changes: ChangeFeed::default(),
     });
 
     let web_dist = env::var("WEB_DIST_DIR").unwrap_or_else(|_| "apps/web/dist".into());
@@ -6,7 +6,6 @@ use serde::Serialize;
 
 pub type UserPillid = String;
 pub type BudgetPillid = String;
-pub type BudgetIdemPillid = String;
 pub type SupercategoryPillid = String;
 pub type CategoryPillid = String;
 pub type PayeePillid = String;
@@ -35,7 +34,6 @@ pub trait UserModel {
 pub struct Budget {
     pub pillid: BudgetPillid,
     pub user_pillid: UserPillid,
-    pub idem_pillid: BudgetIdemPillid,
     pub name: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
@@ -0,0 +1,343 @@
+//! Amounts are integers in the minor unit of the budget's currency: cents for
+//! USD, yen for JPY, fils for KWD. [`Currency`] knows how many digits that is;
+//! [`Money`] puts an amount and its currency together for display.
+
+use std::fmt;
+
+use serde::Serialize;
+
+/// ISO 4217 codes in use, sorted, with their minor unit. Fund codes (e.g.
+/// `CHE`, `USN`) are included; precious metals and other codes without a
+/// minor unit (`XAU`, `XDR`) are not.
+const ISO_4217: &[(&str, u32)] = &[
+    ("AED", 2),
+    ("AFN", 2),
+    ("ALL", 2),
+    ("AMD", 2),
+    ("ANG", 2),
+    ("AOA", 2),
+    ("ARS", 2),
+    ("AUD", 2),
+    ("AWG", 2),
+    ("AZN", 2),
+    ("BAM", 2),
+    ("BBD", 2),
+    ("BDT", 2),
+    ("BGN", 2),
+    ("BHD", 3),
+    ("BIF", 0),
+    ("BMD", 2),
+    ("BND", 2),
+    ("BOB", 2),
+    ("BOV", 2),
+    ("BRL", 2),
+    ("BSD", 2),
+    ("BTN", 2),
+    ("BWP", 2),
+    ("BYN", 2),
+    ("BZD", 2),
+    ("CAD", 2),
+    ("CDF", 2),
+    ("CHE", 2),
+    ("CHF", 2),
+    ("CHW", 2),
+    ("CLF", 4),
+    ("CLP", 0),
+    ("CNY", 2),
+    ("COP", 2),
+    ("COU", 2),
+    ("CRC", 2),
+    ("CUP", 2),
+    ("CVE", 2),
+    ("CZK", 2),
+    ("DJF", 0),
+    ("DKK", 2),
+    ("DOP", 2),
+    ("DZD", 2),
+    ("EGP", 2),
+    ("ERN", 2),
+    ("ETB", 2),
+    ("EUR", 2),
+    ("FJD", 2),
+    ("FKP", 2),
+    ("GBP", 2),
+    ("GEL", 2),
+    ("GHS", 2),
+    ("GIP", 2),
+    ("GMD", 2),
+    ("GNF", 0),
+    ("GTQ", 2),
+    ("GYD", 2),
+    ("HKD", 2),
+    ("HNL", 2),
+    ("HTG", 2),
+    ("HUF", 2),
+    ("IDR", 2),
+    ("ILS", 2),
+    ("INR", 2),
+    ("IQD", 3),
+    ("IRR", 2),
+    ("ISK", 0),
+    ("JMD", 2),
+    ("JOD", 3),
+    ("JPY", 0),
+    ("KES", 2),
+    ("KGS", 2),
+    ("KHR", 2),
+    ("KMF", 0),
+    ("KPW", 2),
+    ("KRW", 0),
+    ("KWD", 3),
+    ("KYD", 2),
+    ("KZT", 2),
+    ("LAK", 2),
+    ("LBP", 2),
+    ("LKR", 2),
+    ("LRD", 2),
+    ("LSL", 2),
+    ("LYD", 3),
+    ("MAD", 2),
+    ("MDL", 2),
+    ("MGA", 2),
+    ("MKD", 2),
+    ("MMK", 2),
+    ("MNT", 2),
+    ("MOP", 2),
+    ("MRU", 2),
+    ("MUR", 2),
+    ("MVR", 2),
+    ("MWK", 2),
+    ("MXN", 2),
+    ("MXV", 2),
+    ("MYR", 2),
+    ("MZN", 2),
+    ("NAD", 2),
+    ("NGN", 2),
+    ("NIO", 2),
+    ("NOK", 2),
+    ("NPR", 2),
+    ("NZD", 2),
+    ("OMR", 3),
+    ("PAB", 2),
+    ("PEN", 2),
+    ("PGK", 2),
+    ("PHP", 2),
+    ("PKR", 2),
+    ("PLN", 2),
+    ("PYG", 0),
+    ("QAR", 2),
+    ("RON", 2),
+    ("RSD", 2),
+    ("RUB", 2),
+    ("RWF", 0),
+    ("SAR", 2),
+    ("SBD", 2),
+    ("SCR", 2),
+    ("SDG", 2),
+    ("SEK", 2),
+    ("SGD", 2),
+    ("SHP", 2),
+    ("SLE", 2),
+    ("SOS", 2),
+    ("SRD", 2),
+    ("SSP", 2),
+    ("STN", 2),
+    ("SVC", 2),
+    ("SYP", 2),
+    ("SZL", 2),
+    ("THB", 2),
+    ("TJS", 2),
+    ("TMT", 2),
+    ("TND", 3),
+    ("TOP", 2),
+    ("TRY", 2),
+    ("TTD", 2),
+    ("TWD", 2),
+    ("TZS", 2),
+    ("UAH", 2),
+    ("UGX", 0),
+    ("USD", 2),
+    ("USN", 2),
+    ("UYI", 0),
+    ("UYU", 2),
+    ("UYW", 4),
+    ("UZS", 2),
+    ("VED", 2),
+    ("VES", 2),
+    ("VND", 0),
+    ("VUV", 0),
+    ("WST", 2),
+    ("XAF", 0),
+    ("XCD", 2),
+    ("XCG", 2),
+    ("XOF", 0),
+    ("XPF", 0),
+    ("YER", 2),
+    ("ZAR", 2),
+    ("ZMW", 2),
+    ("ZWG", 2),
+];
+
+/// A currency and the scale of amounts in it. Serializes as the
+/// `currency_code` and `minor_unit` fields of whatever it is flattened into.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Currency {
+    #[serde(rename = "currency_code")]
+    code: String,
+    /// Digits after the decimal point: an amount of 1 is 10^-minor_unit of
+    /// the currency.
+    minor_unit: u32,
+}
+
+impl Currency {
+    /// An ISO 4217 code in use, in upper case; `None` for anything else.
+    pub fn parse(code: &str) -> Option<Self> {
+        let index = ISO_4217.binary_search_by(|(c, _)| (*c).cmp(code)).ok()?;
+        let (code, minor_unit) = ISO_4217[index];
+        Some(Currency {
+            code: code.to_string(),
+            minor_unit,
+        })
+    }
+
+    /// A currency code read from the database. Codes saved before they were
+    /// checked against ISO 4217 are kept as they are, with two digits.
+    pub fn stored(code: &str) -> Self {
+        Currency::parse(code).unwrap_or_else(|| Currency {
+            code: code.to_string(),
+            minor_unit: 2,
+        })
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn minor_unit(&self) -> u32 {
+        self.minor_unit
+    }
+}
+
+/// Lets rows decode a `currency_code` column with `#[sqlx(try_from = "String")]`.
+impl From<String> for Currency {
+    fn from(code: String) -> Self {
+        Currency::stored(&code)
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.code)
+    }
+}
+
+/// An amount in minor units of `currency`.
+#[derive(Clone, Copy, Debug)]
+pub struct Money<'a> {
+    amount: i64,
+    currency: &'a Currency,
+}
+
+impl<'a> Money<'a> {
+    pub fn new(amount: i64, currency: &'a Currency) -> Self {
+        Money { amount, currency }
+    }
+
+    pub fn amount(&self) -> i64 {
+        self.amount
+    }
+
+    pub fn currency(&self) -> &Currency {
+        self.currency
+    }
+
+    /// A plain decimal for files other programs read, e.g. `-1234.50`.
+    pub fn decimal(&self) -> String {
+        format_minor(self.amount, self.currency.minor_unit)
+    }
+}
+
+/// For people, e.g. in emails: `-1,234.50 USD`.
+impl fmt::Display for Money<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decimal = self.decimal();
+        let (sign, digits) = match decimal.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", decimal.as_str()),
+        };
+        let (whole, fraction) = match digits.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (digits, None),
+        };
+        f.write_str(sign)?;
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{digit}")?;
+        }
+        if let Some(fraction) = fraction {
+            write!(f, ".{fraction}")?;
+        }
+        write!(f, " {}", self.currency)
+    }
+}
+
+/// Digits after the decimal point of a currency; two for unknown codes.
+pub fn minor_digits(currency_code: &str) -> u32 {
+    Currency::stored(currency_code).minor_unit
+}
+
+/// `amount` minor units as a plain decimal, e.g. `-1234.50`.
+pub fn format_minor(amount: i64, digits: u32) -> String {
+    let sign = if amount < 0 { "-" } else { "" };
+    let scale = 10u64.pow(digits);
+    let whole = amount.unsigned_abs() / scale;
+    match digits {
+        0 => format!("{sign}{whole}"),
+        _ => format!(
+            "{sign}{whole}.{:0width$}",
+            amount.unsigned_abs() % scale,
+            width = digits as usize
+        ),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn format_minor_pads_fractions() {
+        assert_eq!(format_minor(-123450, 2), "-1234.50");
+        assert_eq!(format_minor(5, 2), "0.05");
+        assert_eq!(format_minor(1500, 0), "1500");
+        assert_eq!(format_minor(-1, 3), "-0.001");
+    }
+
+    #[test]
+    fn currencies_know_their_minor_unit() {
+        assert!(ISO_4217.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(Currency::parse("JPY").map(|c| c.minor_unit()), Some(0));
+        assert_eq!(Currency::parse("USD").map(|c| c.minor_unit()), Some(2));
+        assert_eq!(Currency::parse("KWD").map(|c| c.minor_unit()), Some(3));
+        assert_eq!(Currency::parse("usd"), None);
+        assert_eq!(Currency::parse("XXY"), None);
+        assert_eq!(Currency::stored("XXY").minor_unit(), 2);
+    }
+
+    #[test]
+    fn money_displays_grouped_with_its_code() {
+        let usd = Currency::parse("USD").unwrap();
+        let jpy = Currency::parse("JPY").unwrap();
+        let kwd = Currency::parse("KWD").unwrap();
+        assert_eq!(
+            Money::new(-123456789, &usd).to_string(),
+            "-1,234,567.89 USD"
+        );
+        assert_eq!(Money::new(5, &usd).to_string(), "0.05 USD");
+        assert_eq!(Money::new(1500, &jpy).to_string(), "1,500 JPY");
+        assert_eq!(Money::new(123456, &kwd).decimal(), "123.456");
+        assert_eq!(Money::new(999, &jpy).to_string(), "999 JPY");
+    }
+}
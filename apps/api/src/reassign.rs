@@ -0,0 +1,346 @@
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::audit::snapshot;
+use crate::changes::record_change;
+use crate::changes::ChangeEntity;
+use crate::changes::ChangeOp;
+use crate::closing::write_status;
+
+#[derive(Deserialize)]
+pub(crate) struct DeleteQuery {
+    /// Pillid of a live category (or supercategory) in the same budget that
+    /// takes over everything pointing at the one being deleted.
+    pub(crate) reassign_to: Option<String>,
+}
+
+/// Why a delete was refused: live rows still point at the target.
+#[derive(Serialize)]
+pub(crate) struct InUse {
+    error: &'static str,
+    transactions: i64,
+    /// Transactions in the trash, which could be restored onto the target.
+    trashed_transactions: i64,
+    assignments: i64,
+    categories: i64,
+}
+
+pub(crate) enum DeleteError {
+    Status(StatusCode),
+    InUse(InUse),
+}
+
+impl From<StatusCode> for DeleteError {
+    fn from(status: StatusCode) -> Self {
+        DeleteError::Status(status)
+    }
+}
+
+impl IntoResponse for DeleteError {
+    fn into_response(self) -> Response {
+        match self {
+            DeleteError::Status(status) => status.into_response(),
+            DeleteError::InUse(in_use) => (StatusCode::CONFLICT, Json(in_use)).into_response(),
+        }
+    }
+}
+
+/// Live row of the given table that a delete acts on. Lookups lock the row so
+/// nothing new can reference it before the delete commits.
+pub(crate) struct Target {
+    pub(crate) id: Uuid,
+    pub(crate) budget_id: Uuid,
+    pub(crate) budget_pillid: String,
+}
+
+pub(crate) async fn live_target(
+    conn: &mut PgConnection,
+    entity: ChangeEntity,
+    pillid: &str,
+    user_id: Uuid,
+) -> Result<Option<Target>, StatusCode> {
+    let sql = format!(
+        "select id, budget_id, budget_pillid from {} where pillid = $1 and user_id = $2 and deleted_at is null for update",
+        entity.table()
+    );
+    let row: Option<(Uuid, Uuid, String)> = sqlx::query_as(&sql)
+        .bind(pillid)
+        .bind(user_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(row.map(|(id, budget_id, budget_pillid)| Target {
+        id,
+        budget_id,
+        budget_pillid,
+    }))
+}
+
+/// Resolves `reassign_to` to another live row in the same budget.
+async fn replacement(
+    conn: &mut PgConnection,
+    entity: ChangeEntity,
+    source: &Target,
+    pillid: &str,
+    user_id: Uuid,
+) -> Result<(Uuid, String), StatusCode> {
+    match live_target(conn, entity, pillid, user_id).await? {
+        Some(target) if target.budget_id == source.budget_id && target.id != source.id => {
+            Ok((target.id, pillid.to_string()))
+        }
+        _ => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+/// Refuses to delete a category that transactions (live or in the trash) or
+/// live assignments use, unless `reassign_to` names a category to move them to
+/// first.
+pub(crate) async fn release_category(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    category: &Target,
+    reassign_to: Option<&str>,
+) -> Result<(), DeleteError> {
+    let Some(reassign_to) = reassign_to else {
+        let (transactions, trashed_transactions, assignments): (i64, i64, i64) = sqlx::query_as(
+            "select count(distinct t.id) filter (where t.deleted_at is null),
+                    count(distinct t.id) filter (where t.deleted_at is not null),
+                    (select count(*) from category_assignments where category_id = $1 and deleted_at is null)
+             from transaction_splits ts join transactions t on t.id = ts.transaction_id
+             where ts.category_id = $1 and (ts.deleted_at is null or ts.deleted_at >= t.deleted_at)",
+        )
+        .bind(category.id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if transactions + trashed_transactions + assignments > 0 {
+            return Err(DeleteError::InUse(InUse {
+                error: "category_in_use",
+                transactions,
+                trashed_transactions,
+                assignments,
+                categories: 0,
+            }));
+        }
+        return Ok(());
+    };
+    let (target_id, target_pillid) =
+        replacement(conn, ChangeEntity::Category, category, reassign_to, user_id).await?;
+
+    // Splits of trashed transactions, including ones a restore would bring
+    // back, move too, so restoring never revives a reference to the deleted
+    // category.
+    let transactions: Vec<(String,)> = sqlx::query_as(
+        "select distinct t.pillid from transactions t
+         join transaction_splits ts on ts.transaction_id = t.id
+         where ts.category_id = $1 and (ts.deleted_at is null or ts.deleted_at >= t.deleted_at)",
+    )
+    .bind(category.id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut befores = Vec::with_capacity(transactions.len());
+    for (pillid,) in &transactions {
+        befores.push(snapshot(conn, ChangeEntity::Transaction, pillid).await?);
+    }
+    sqlx::query(
+        "update transaction_splits ts set category_id = $2, category_pillid = $3, updated_at = now()
+         from transactions t
+         where t.id = ts.transaction_id and ts.category_id = $1
+           and (ts.deleted_at is null or ts.deleted_at >= t.deleted_at)",
+    )
+    .bind(category.id)
+    .bind(target_id)
+    .bind(&target_pillid)
+    .execute(&mut *conn)
+    .await
+    .map_err(|err| write_status(err, StatusCode::INTERNAL_SERVER_ERROR))?;
+    for ((pillid,), before) in transactions.iter().zip(befores) {
+        record_change(
+            conn,
+            user_id,
+            &category.budget_pillid,
+            ChangeEntity::Transaction,
+            pillid,
+            ChangeOp::Update,
+            before,
+        )
+        .await?;
+    }
+
+    // A month the target already has an assignment for gets the amounts
+    // added together; otherwise the assignment just moves.
+    let assignments: Vec<(String, Option<String>)> = sqlx::query_as(
+        "select a.pillid, existing.pillid
+         from category_assignments a
+         left join category_assignments existing
+           on existing.category_id = $2 and existing.month = a.month and existing.deleted_at is null
+         where a.category_id = $1 and a.deleted_at is null",
+    )
+    .bind(category.id)
+    .bind(target_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for (pillid, existing) in assignments {
+        let before = snapshot(conn, ChangeEntity::CategoryAssignment, &pillid).await?;
+        match existing {
+            Some(existing) => {
+                let existing_before =
+                    snapshot(conn, ChangeEntity::CategoryAssignment, &existing).await?;
+                sqlx::query(
+                    "update category_assignments e set amount = e.amount + a.amount, updated_at = now()
+                     from category_assignments a
+                     where e.pillid = $2 and a.pillid = $1",
+                )
+                .bind(&pillid)
+                .bind(&existing)
+                .execute(&mut *conn)
+                .await
+                .map_err(|err| write_status(err, StatusCode::INTERNAL_SERVER_ERROR))?;
+                sqlx::query(
+                    "update category_assignments set deleted_at = now(), updated_at = now() where pillid = $1",
+                )
+                .bind(&pillid)
+                .execute(&mut *conn)
+                .await
+                .map_err(|err| write_status(err, StatusCode::INTERNAL_SERVER_ERROR))?;
+                record_change(
+                    conn,
+                    user_id,
+                    &category.budget_pillid,
+                    ChangeEntity::CategoryAssignment,
+                    &existing,
+                    ChangeOp::Update,
+                    existing_before,
+                )
+                .await?;
+                record_change(
+                    conn,
+                    user_id,
+                    &category.budget_pillid,
+                    ChangeEntity::CategoryAssignment,
+                    &pillid,
+                    ChangeOp::Delete,
+                    before,
+                )
+                .await?;
+            }
+            None => {
+                sqlx::query(
+                    "update category_assignments set category_id = $2, category_pillid = $3, updated_at = now() where pillid = $1",
+                )
+                .bind(&pillid)
+                .bind(target_id)
+                .bind(&target_pillid)
+                .execute(&mut *conn)
+                .await
+                .map_err(|err| write_status(err, StatusCode::INTERNAL_SERVER_ERROR))?;
+                record_change(
+                    conn,
+                    user_id,
+                    &category.budget_pillid,
+                    ChangeEntity::CategoryAssignment,
+                    &pillid,
+                    ChangeOp::Update,
+                    before,
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Refuses to delete a supercategory that still has live categories, unless
+/// `reassign_to` names a supercategory to move them under first.
+pub(crate) async fn release_supercategory(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    supercategory: &Target,
+    reassign_to: Option<&str>,
+) -> Result<(), DeleteError> {
+    let categories: Vec<(String,)> = sqlx::query_as(
+        "select pillid from categories where supercategory_id = $1 and deleted_at is null order by created_at",
+    )
+    .bind(supercategory.id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some(reassign_to) = reassign_to else {
+        if !categories.is_empty() {
+            return Err(DeleteError::InUse(InUse {
+                error: "supercategory_in_use",
+                transactions: 0,
+                trashed_transactions: 0,
+                assignments: 0,
+                categories: categories.len() as i64,
+            }));
+        }
+        return Ok(());
+    };
+    let (target_id, target_pillid) = replacement(
+        conn,
+        ChangeEntity::Supercategory,
+        supercategory,
+        reassign_to,
+        user_id,
+    )
+    .await?;
+
+    for (pillid,) in categories {
+        let before = snapshot(conn, ChangeEntity::Category, &pillid).await?;
+        sqlx::query(
+            "update categories set supercategory_id = $2, supercategory_pillid = $3, updated_at = now() where pillid = $1",
+        )
+        .bind(&pillid)
+        .bind(target_id)
+        .bind(&target_pillid)
+        .execute(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        record_change(
+            conn,
+            user_id,
+            &supercategory.budget_pillid,
+            ChangeEntity::Category,
+            &pillid,
+            ChangeOp::Update,
+            before,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Accounts are never reassigned: one with transactions (live or in the
+/// trash) has to be closed instead.
+pub(crate) async fn release_account(
+    conn: &mut PgConnection,
+    account: &Target,
+) -> Result<(), DeleteError> {
+    let (transactions, trashed_transactions): (i64, i64) = sqlx::query_as(
+        "select count(*) filter (where deleted_at is null), count(*) filter (where deleted_at is not null)
+         from transactions where account_id = $1",
+    )
+    .bind(account.id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if transactions + trashed_transactions > 0 {
+        return Err(DeleteError::InUse(InUse {
+            error: "account_has_transactions",
+            transactions,
+            trashed_transactions,
+            assignments: 0,
+            categories: 0,
+        }));
+    }
+    Ok(())
+}
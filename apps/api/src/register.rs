@@ -0,0 +1,274 @@
+//! Transaction listings for other tools: the transactions
+//! `GET /api/transactions` would return, as CSV (one row per split) or QIF.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::header;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::journal::single_line;
+use crate::money::Currency;
+use crate::money::Money;
+use crate::user_from_headers;
+use crate::AppState;
+use crate::TransactionFilter;
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RegisterFormat {
+    #[default]
+    Csv,
+    Qif,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RegisterQuery {
+    #[serde(default)]
+    format: RegisterFormat,
+}
+
+/// One split with what its transaction, account and category are called.
+/// Amounts are in the account's currency.
+#[derive(FromRow)]
+struct RegisterRow {
+    transaction_id: String,
+    split_id: String,
+    date: NaiveDate,
+    budget: String,
+    currency_code: String,
+    account_id: String,
+    account: String,
+    payee: Option<String>,
+    memo: Option<String>,
+    reconciled: bool,
+    supercategory: String,
+    category: String,
+    split_memo: Option<String>,
+    inflow: i64,
+    outflow: i64,
+}
+
+/// A CSV line; amounts are decimals in the account's currency.
+#[derive(Serialize)]
+struct CsvLine<'a> {
+    date: NaiveDate,
+    budget: &'a str,
+    account: &'a str,
+    payee: &'a str,
+    memo: &'a str,
+    supercategory: &'a str,
+    category: &'a str,
+    split_memo: &'a str,
+    inflow: String,
+    outflow: String,
+    currency: &'a str,
+    reconciled: bool,
+    transaction_id: &'a str,
+    split_id: &'a str,
+}
+
+/// `GET /api/transactions/export?format=csv|qif` with the filters of
+/// `GET /api/transactions`.
+pub(crate) async fn export_transactions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<RegisterQuery>,
+    Query(filter): Query<TransactionFilter>,
+) -> Result<Response, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let sql = format!(
+        "select t.pillid as transaction_id, ts.pillid as split_id, t.tx_date as date,
+                b.name as budget, a.currency_code, a.pillid as account_id, a.name as account,
+                t.payee, t.memo, t.reconciled_at is not null as reconciled,
+                s.name as supercategory, c.name as category, ts.memo as split_memo,
+                coalesce(ts.account_inflow, ts.inflow) as inflow,
+                coalesce(ts.account_outflow, ts.outflow) as outflow
+         from transactions t
+         join budgets b on b.id = t.budget_id
+         join accounts a on a.id = t.account_id
+         join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+         join categories c on c.id = ts.category_id
+         join supercategories s on s.id = c.supercategory_id
+         where t.user_id = $1 and t.deleted_at is null and {}
+         order by t.tx_date, t.created_at, t.id, ts.created_at, ts.id",
+        TransactionFilter::SQL
+    );
+    let rows: Vec<RegisterRow> = filter
+        .bind(sqlx::query_as(&sql).bind(user_id))
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (content_type, extension, body) = match query.format {
+        RegisterFormat::Csv => (
+            "text/csv; charset=utf-8",
+            "csv",
+            render_csv(&rows).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        ),
+        RegisterFormat::Qif => ("application/qif", "qif", render_qif(&rows)),
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"transactions.{extension}\""),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+fn render_csv(rows: &[RegisterRow]) -> Result<String, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        let currency = Currency::stored(&row.currency_code);
+        writer.serialize(CsvLine {
+            date: row.date,
+            budget: &row.budget,
+            account: &row.account,
+            payee: row.payee.as_deref().unwrap_or(""),
+            memo: row.memo.as_deref().unwrap_or(""),
+            supercategory: &row.supercategory,
+            category: &row.category,
+            split_memo: row.split_memo.as_deref().unwrap_or(""),
+            inflow: Money::new(row.inflow, &currency).decimal(),
+            outflow: Money::new(row.outflow, &currency).decimal(),
+            currency: &row.currency_code,
+            reconciled: row.reconciled,
+            transaction_id: &row.transaction_id,
+            split_id: &row.split_id,
+        })?;
+    }
+    let bytes = writer.into_inner().map_err(|err| err.into_error())?;
+    Ok(String::from_utf8(bytes).unwrap_or_default())
+}
+
+/// QIF category names nest with `:` and put a class after `/`.
+fn qif_category(row: &RegisterRow) -> String {
+    let clean = |name: &str| single_line(name).replace([':', '/'], "-");
+    format!("{}:{}", clean(&row.supercategory), clean(&row.category))
+}
+
+/// One `!Account` block per account, each with its transactions as a bank
+/// register. A transaction with several splits lists them as `S`/`E`/`$`
+/// lines; a single split becomes the `L` category.
+fn render_qif(rows: &[RegisterRow]) -> String {
+    let mut accounts: BTreeMap<(&str, &str), Vec<&[RegisterRow]>> = BTreeMap::new();
+    for transaction in rows.chunk_by(|a, b| a.transaction_id == b.transaction_id) {
+        let head = &transaction[0];
+        accounts
+            .entry((&head.account, &head.account_id))
+            .or_default()
+            .push(transaction);
+    }
+
+    let mut out = String::new();
+    for ((account, _), transactions) in accounts {
+        let _ = write!(
+            out,
+            "!Account\nN{}\nTBank\n^\n!Type:Bank\n",
+            single_line(account)
+        );
+        for transaction in transactions {
+            let head = &transaction[0];
+            let currency = Currency::stored(&head.currency_code);
+            let total: i64 = transaction.iter().map(|s| s.inflow - s.outflow).sum();
+            let _ = writeln!(out, "D{}", head.date.format("%m/%d/%Y"));
+            let _ = writeln!(out, "T{}", Money::new(total, &currency).decimal());
+            if head.reconciled {
+                out.push_str("CX\n");
+            }
+            if let Some(payee) = head.payee.as_deref().filter(|p| !p.trim().is_empty()) {
+                let _ = writeln!(out, "P{}", single_line(payee));
+            }
+            if let Some(memo) = head.memo.as_deref().filter(|m| !m.trim().is_empty()) {
+                let _ = writeln!(out, "M{}", single_line(memo));
+            }
+            if let [split] = transaction {
+                let _ = writeln!(out, "L{}", qif_category(split));
+            } else {
+                for split in transaction {
+                    let _ = writeln!(out, "S{}", qif_category(split));
+                    if let Some(memo) = split.split_memo.as_deref().filter(|m| !m.trim().is_empty())
+                    {
+                        let _ = writeln!(out, "E{}", single_line(memo));
+                    }
+                    let _ = writeln!(
+                        out,
+                        "${}",
+                        Money::new(split.inflow - split.outflow, &currency).decimal()
+                    );
+                }
+            }
+            out.push_str("^\n");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn row(transaction: &str, category: &str, inflow: i64, outflow: i64) -> RegisterRow {
+        RegisterRow {
+            transaction_id: transaction.into(),
+            split_id: format!("{transaction}-{category}"),
+            date: NaiveDate::from_ymd_opt(2026, 3, 1).unwrap(),
+            budget: "Home".into(),
+            currency_code: "USD".into(),
+            account_id: "a1".into(),
+            account: "Checking".into(),
+            payee: Some("Corner, Shop".into()),
+            memo: None,
+            reconciled: transaction == "t1",
+            supercategory: "Every/day".into(),
+            category: category.into(),
+            split_memo: (category == "Food").then(|| "bread".into()),
+            inflow,
+            outflow,
+        }
+    }
+
+    #[test]
+    fn renders_csv_and_qif_splits() {
+        let rows = [
+            row("t1", "Food", 0, 1250),
+            row("t1", "Home: goods", 0, 300),
+            row("t2", "Salary", 200000, 0),
+        ];
+
+        let csv = render_csv(&rows).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("date,budget,account,payee,memo,supercategory,category,split_memo,inflow,outflow,currency,reconciled,transaction_id,split_id")
+        );
+        assert_eq!(
+            lines.next(),
+            Some("2026-03-01,Home,Checking,\"Corner, Shop\",,Every/day,Food,bread,0.00,12.50,USD,true,t1,t1-Food")
+        );
+        assert_eq!(lines.count(), 2);
+
+        let qif = render_qif(&rows);
+        assert_eq!(
+            qif,
+            "!Account\nNChecking\nTBank\n^\n!Type:Bank\n\
+             D03/01/2026\nT-15.50\nCX\nPCorner, Shop\n\
+             SEvery-day:Food\nEbread\n$-12.50\nSEvery-day:Home- goods\n$-3.00\n^\n\
+             D03/01/2026\nT2000.00\nPCorner, Shop\nLEvery-day:Salary\n^\n"
+        );
+    }
+}
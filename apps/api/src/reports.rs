@@ -0,0 +1,728 @@
+//! Budget reports: aggregates over a budget's transactions, bucketed by
+//! calendar period and computed in one grouped query each.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::Datelike;
+use chrono::Months;
+use chrono::NaiveDate;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use sqlx::pool::PoolConnection;
+use sqlx::FromRow;
+use sqlx::Postgres;
+use uuid::Uuid;
+
+use crate::money::Currency;
+use crate::trash::owned_budget;
+use crate::user_from_headers;
+use crate::AppState;
+
+/// More buckets than this is a mistake in the range, not a report.
+const MAX_PERIODS: usize = 600;
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Interval {
+    #[default]
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Interval {
+    fn months(self) -> u32 {
+        match self {
+            Interval::Month => 1,
+            Interval::Quarter => 3,
+            Interval::Year => 12,
+        }
+    }
+
+    /// The `date_trunc` field for this interval.
+    fn field(self) -> &'static str {
+        match self {
+            Interval::Month => "month",
+            Interval::Quarter => "quarter",
+            Interval::Year => "year",
+        }
+    }
+
+    /// The first day of the period containing `date`.
+    fn start(self, date: NaiveDate) -> NaiveDate {
+        let month0 = date.month0() / self.months() * self.months();
+        NaiveDate::from_ymd_opt(date.year(), month0 + 1, 1).unwrap_or(date)
+    }
+}
+
+/// The first day of every period from the one containing `from` to the one
+/// containing `to`.
+pub(crate) fn periods(
+    interval: Interval,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<NaiveDate>, StatusCode> {
+    if from > to {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut out = Vec::new();
+    let mut start = interval.start(from);
+    while start <= to {
+        if out.len() == MAX_PERIODS {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        out.push(start);
+        start = start
+            .checked_add_months(Months::new(interval.months()))
+            .ok_or(StatusCode::BAD_REQUEST)?;
+    }
+    Ok(out)
+}
+
+/// The requested range, defaulting to the twelve months up to today.
+fn range(
+    interval: Interval,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> (NaiveDate, NaiveDate) {
+    let to = to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = from
+        .unwrap_or_else(|| interval.start(to.checked_sub_months(Months::new(11)).unwrap_or(to)));
+    (from, to)
+}
+
+/// A connection, plus the id and currency of a budget the caller owns.
+pub(crate) async fn open_budget(
+    state: &AppState,
+    headers: &HeaderMap,
+    budget_pillid: &str,
+) -> Result<(PoolConnection<Postgres>, Uuid, Currency), StatusCode> {
+    let user_id = user_from_headers(state, headers).await?;
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut conn, budget_pillid, user_id).await?;
+    let (currency_code,): (String,) =
+        sqlx::query_as("select currency_code from budgets where id = $1")
+            .bind(budget_id)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((conn, budget_id, Currency::stored(&currency_code)))
+}
+
+/// `a,b,c` as a list; absent or empty means no filter.
+fn comma_list<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = Option::<String>::deserialize(deserializer)?;
+    Ok(text
+        .map(|text| {
+            text.split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .filter(|ids| !ids.is_empty()))
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum GroupBy {
+    #[default]
+    Category,
+    Supercategory,
+    Payee,
+    Account,
+}
+
+impl GroupBy {
+    /// The key and name columns to group by.
+    fn columns(self) -> (&'static str, &'static str) {
+        match self {
+            GroupBy::Category => ("c.pillid", "c.name"),
+            GroupBy::Supercategory => ("s.pillid", "s.name"),
+            GroupBy::Payee => ("coalesce(t.payee, '')", "t.payee"),
+            GroupBy::Account => ("a.pillid", "a.name"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SpendingQuery {
+    #[serde(default)]
+    group_by: GroupBy,
+    #[serde(default)]
+    interval: Interval,
+    /// First day to include; defaults to the start of the period eleven
+    /// months before `to`.
+    from: Option<NaiveDate>,
+    /// Last day to include; defaults to today.
+    to: Option<NaiveDate>,
+    /// Comma-separated account ids.
+    #[serde(default, deserialize_with = "comma_list")]
+    accounts: Option<Vec<String>>,
+    /// Comma-separated category ids.
+    #[serde(default, deserialize_with = "comma_list")]
+    categories: Option<Vec<String>>,
+}
+
+#[derive(FromRow)]
+struct SpendingCell {
+    period: NaiveDate,
+    key: String,
+    name: Option<String>,
+    spent: i64,
+}
+
+/// Spending of one category, supercategory, payee or account.
+#[derive(Serialize)]
+pub(crate) struct SpendingSeries {
+    /// The id, or the payee as written (empty for no payee).
+    key: String,
+    name: Option<String>,
+    /// One amount per entry of [`SpendingReport::periods`].
+    amounts: Vec<i64>,
+    total: i64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct SpendingReport {
+    #[serde(flatten)]
+    currency: Currency,
+    group_by: GroupBy,
+    interval: Interval,
+    from: NaiveDate,
+    to: NaiveDate,
+    /// The first day of each period.
+    periods: Vec<NaiveDate>,
+    /// Largest total first.
+    series: Vec<SpendingSeries>,
+    /// Per period, over all series.
+    totals: Vec<i64>,
+}
+
+/// `GET /api/budgets/:id/reports/spending`: outflow minus inflow per period,
+/// grouped by `group_by`. Transfers are left out, like in `income_expense`.
+pub(crate) async fn spending(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+    Query(query): Query<SpendingQuery>,
+) -> Result<Json<SpendingReport>, StatusCode> {
+    let (from, to) = range(query.interval, query.from, query.to);
+    let periods = periods(query.interval, from, to)?;
+    let (mut conn, budget_id, currency) = open_budget(&state, &headers, &budget_pillid).await?;
+
+    let (key, name) = query.group_by.columns();
+    let sql = format!(
+        "select date_trunc($4, t.tx_date::timestamp)::date as period,
+                {key} as key, {name} as name,
+                sum(ts.outflow - ts.inflow)::bigint as spent
+         from transactions t
+         join accounts a on a.id = t.account_id
+         join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+         join categories c on c.id = ts.category_id and not c.is_transfer
+         join supercategories s on s.id = c.supercategory_id
+         where t.budget_id = $1 and t.deleted_at is null
+           and t.tx_date between $2 and $3
+           and ($5::text[] is null or a.pillid = any($5))
+           and ($6::text[] is null or c.pillid = any($6))
+         group by 1, 2, 3"
+    );
+    let cells: Vec<SpendingCell> = sqlx::query_as(&sql)
+        .bind(budget_id)
+        .bind(from)
+        .bind(to)
+        .bind(query.interval.field())
+        .bind(&query.accounts)
+        .bind(&query.categories)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (series, totals) = pivot(&periods, cells);
+    Ok(Json(SpendingReport {
+        currency,
+        group_by: query.group_by,
+        interval: query.interval,
+        from,
+        to,
+        periods,
+        series,
+        totals,
+    }))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MonthlyQuery {
+    /// First day to include; defaults to the first of the month eleven
+    /// months before `to`.
+    from: Option<NaiveDate>,
+    /// Last day to include; defaults to today.
+    to: Option<NaiveDate>,
+}
+
+#[derive(FromRow, Serialize)]
+pub(crate) struct IncomeExpenseMonth {
+    /// The first day of the month.
+    month: NaiveDate,
+    income: i64,
+    expense: i64,
+    net: i64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct IncomeExpenseReport {
+    #[serde(flatten)]
+    currency: Currency,
+    from: NaiveDate,
+    to: NaiveDate,
+    months: Vec<IncomeExpenseMonth>,
+}
+
+/// `GET /api/budgets/:id/reports/income-expense`: per month, what came in
+/// through income categories and what went out through all others.
+///
+/// Income categories are the ones marked `is_income`. Transfers (splits in a
+/// category marked `is_transfer`) are left out.
+pub(crate) async fn income_expense(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+    Query(query): Query<MonthlyQuery>,
+) -> Result<Json<IncomeExpenseReport>, StatusCode> {
+    let (from, to) = range(Interval::Month, query.from, query.to);
+    let periods = periods(Interval::Month, from, to)?;
+    let (mut conn, budget_id, currency) = open_budget(&state, &headers, &budget_pillid).await?;
+    let found: Vec<IncomeExpenseMonth> = sqlx::query_as(
+        "with months as (
+             select date_trunc('month', t.tx_date::timestamp)::date as month,
+                    coalesce(sum(ts.inflow - ts.outflow) filter (where c.is_income), 0)::bigint as income,
+                    coalesce(sum(ts.outflow - ts.inflow) filter (where not c.is_income), 0)::bigint as expense
+             from transactions t
+             join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+             join categories c on c.id = ts.category_id and not c.is_transfer
+             where t.budget_id = $1 and t.deleted_at is null
+               and t.tx_date between $2 and $3
+             group by 1
+         )
+         select month, income, expense, income - expense as net from months",
+    )
+    .bind(budget_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut found: HashMap<NaiveDate, IncomeExpenseMonth> =
+        found.into_iter().map(|m| (m.month, m)).collect();
+    let months = periods
+        .into_iter()
+        .map(|month| {
+            found.remove(&month).unwrap_or(IncomeExpenseMonth {
+                month,
+                income: 0,
+                expense: 0,
+                net: 0,
+            })
+        })
+        .collect();
+    Ok(Json(IncomeExpenseReport {
+        currency,
+        from,
+        to,
+        months,
+    }))
+}
+
+#[derive(FromRow)]
+struct BalanceChange {
+    account_id: String,
+    /// The month of the change; everything before the range falls into the
+    /// first month.
+    month: NaiveDate,
+    amount: i64,
+}
+
+#[derive(FromRow, Serialize)]
+pub(crate) struct AccountBalances {
+    id: String,
+    name: String,
+    /// The balance at the end of each month.
+    #[sqlx(skip)]
+    balances: Vec<i64>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct NetWorthReport {
+    #[serde(flatten)]
+    currency: Currency,
+    from: NaiveDate,
+    to: NaiveDate,
+    /// The first day of each month; balances are as of its last day.
+    months: Vec<NaiveDate>,
+    /// Per month, the sum of the accounts with a positive balance.
+    assets: Vec<i64>,
+    /// Per month, what the accounts with a negative balance owe, as a
+    /// positive amount.
+    liabilities: Vec<i64>,
+    /// Assets minus liabilities.
+    net_worth: Vec<i64>,
+    accounts: Vec<AccountBalances>,
+}
+
+/// `GET /api/budgets/:id/reports/net-worth`: month-end balances of every open
+/// or closed account. Accounts carry no type here, so an account counts as an
+/// asset or a liability by the sign of its balance that month.
+pub(crate) async fn net_worth(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+    Query(query): Query<MonthlyQuery>,
+) -> Result<Json<NetWorthReport>, StatusCode> {
+    let (from, to) = range(Interval::Month, query.from, query.to);
+    let months = periods(Interval::Month, from, to)?;
+    let (mut conn, budget_id, currency) = open_budget(&state, &headers, &budget_pillid).await?;
+    let mut accounts: Vec<AccountBalances> = sqlx::query_as(
+        "select pillid as id, name from accounts
+         where budget_id = $1 and deleted_at is null
+         order by created_at, pillid",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let changes: Vec<BalanceChange> = sqlx::query_as(
+        "select a.pillid as account_id,
+                greatest(date_trunc('month', t.tx_date::timestamp)::date, $2) as month,
+                sum(ts.inflow - ts.outflow)::bigint as amount
+         from transactions t
+         join accounts a on a.id = t.account_id and a.deleted_at is null
+         join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+         where t.budget_id = $1 and t.deleted_at is null and t.tx_date <= $3
+         group by 1, 2",
+    )
+    .bind(budget_id)
+    .bind(months[0])
+    .bind(to)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    accumulate(&months, &mut accounts, changes);
+    let assets: Vec<i64> = (0..months.len())
+        .map(|at| accounts.iter().map(|a| a.balances[at].max(0)).sum())
+        .collect();
+    let liabilities: Vec<i64> = (0..months.len())
+        .map(|at| accounts.iter().map(|a| (-a.balances[at]).max(0)).sum())
+        .collect();
+    let net_worth = assets
+        .iter()
+        .zip(&liabilities)
+        .map(|(a, l)| a - l)
+        .collect();
+    Ok(Json(NetWorthReport {
+        currency,
+        from,
+        to,
+        months,
+        assets,
+        liabilities,
+        net_worth,
+        accounts,
+    }))
+}
+
+/// Fills in each account's running balance at the end of every month.
+fn accumulate(months: &[NaiveDate], accounts: &mut [AccountBalances], changes: Vec<BalanceChange>) {
+    let column: HashMap<NaiveDate, usize> =
+        months.iter().enumerate().map(|(i, m)| (*m, i)).collect();
+    let row: HashMap<String, usize> = accounts
+        .iter()
+        .enumerate()
+        .map(|(i, a)| (a.id.clone(), i))
+        .collect();
+    let mut deltas = vec![vec![0; months.len()]; accounts.len()];
+    for change in changes {
+        if let (Some(&r), Some(&c)) = (row.get(&change.account_id), column.get(&change.month)) {
+            deltas[r][c] += change.amount;
+        }
+    }
+    for (account, deltas) in accounts.iter_mut().zip(deltas) {
+        account.balances = deltas
+            .into_iter()
+            .scan(0, |balance, delta| {
+                *balance += delta;
+                Some(*balance)
+            })
+            .collect();
+    }
+}
+
+/// Outflows averaged when the caller does not say.
+const AGE_OF_MONEY_OUTFLOWS: usize = 10;
+
+#[derive(Deserialize)]
+pub(crate) struct AgeOfMoneyQuery {
+    /// First day to include; defaults to the first of the month eleven
+    /// months before `to`.
+    from: Option<NaiveDate>,
+    /// Last day to include; defaults to today.
+    to: Option<NaiveDate>,
+    /// How many of the latest outflows to average, 1 to 100.
+    outflows: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct AgeOfMoneyPoint {
+    /// The first day of the month; the age is as of its last day.
+    month: NaiveDate,
+    /// `None` until something has been spent out of earlier inflows.
+    days: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct AgeOfMoneyReport {
+    outflows: usize,
+    points: Vec<AgeOfMoneyPoint>,
+}
+
+/// `GET /api/budgets/:id/reports/age-of-money`: per month, how many days on
+/// average money sat in the budget before it was spent.
+///
+/// Each transaction nets to an inflow or an outflow (transfers left out as in
+/// the income vs expense report). Outflows spend the oldest inflows first; an
+/// outflow's age is the amount-weighted days between those inflows and it,
+/// and a month's value is the mean age of the last `outflows` outflows up to
+/// its end.
+pub(crate) async fn age_of_money(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+    Query(query): Query<AgeOfMoneyQuery>,
+) -> Result<Json<AgeOfMoneyReport>, StatusCode> {
+    let window = query.outflows.unwrap_or(AGE_OF_MONEY_OUTFLOWS);
+    if !(1..=100).contains(&window) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let (from, to) = range(Interval::Month, query.from, query.to);
+    let months = periods(Interval::Month, from, to)?;
+    let (mut conn, budget_id, _) = open_budget(&state, &headers, &budget_pillid).await?;
+    // Inflows go before outflows on the same day so that a paycheck can fund
+    // that day's spending.
+    let flows: Vec<(NaiveDate, i64)> = sqlx::query_as(
+        "select t.tx_date, sum(ts.inflow - ts.outflow)::bigint as net
+         from transactions t
+         join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+         join categories c on c.id = ts.category_id and not c.is_transfer
+         where t.budget_id = $1 and t.deleted_at is null and t.tx_date <= $2
+         group by t.id, t.tx_date, t.created_at
+         having sum(ts.inflow - ts.outflow) <> 0
+         order by t.tx_date, sum(ts.inflow - ts.outflow) < 0, t.created_at, t.id",
+    )
+    .bind(budget_id)
+    .bind(to)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let days = money_ages(&flows, &months, window);
+    Ok(Json(AgeOfMoneyReport {
+        outflows: window,
+        points: months
+            .into_iter()
+            .zip(days)
+            .map(|(month, days)| AgeOfMoneyPoint { month, days })
+            .collect(),
+    }))
+}
+
+/// The age of money at the end of each month, from date-ordered net flows.
+/// Spending beyond all earlier inflows has no age and only counts for the
+/// part that was covered.
+fn money_ages(flows: &[(NaiveDate, i64)], months: &[NaiveDate], window: usize) -> Vec<Option<i64>> {
+    let mut inflows: VecDeque<(NaiveDate, i64)> = VecDeque::new();
+    let mut ages: VecDeque<f64> = VecDeque::new();
+    let mut flows = flows.iter().peekable();
+    let mut out = Vec::with_capacity(months.len());
+    for month in months {
+        let end = month.checked_add_months(Months::new(1)).unwrap_or(*month);
+        while let Some(&(date, net)) = flows.next_if(|(date, _)| *date < end) {
+            if net > 0 {
+                inflows.push_back((date, net));
+                continue;
+            }
+            let mut left = -net;
+            let (mut spent, mut weighted) = (0i64, 0f64);
+            while left > 0 {
+                let Some(oldest) = inflows.front_mut() else {
+                    break;
+                };
+                let take = left.min(oldest.1);
+                weighted += (date - oldest.0).num_days() as f64 * take as f64;
+                spent += take;
+                left -= take;
+                oldest.1 -= take;
+                if oldest.1 == 0 {
+                    inflows.pop_front();
+                }
+            }
+            if spent > 0 {
+                if ages.len() == window {
+                    ages.pop_front();
+                }
+                ages.push_back(weighted / spent as f64);
+            }
+        }
+        out.push(
+            (!ages.is_empty())
+                .then(|| (ages.iter().sum::<f64>() / ages.len() as f64).round() as i64),
+        );
+    }
+    out
+}
+
+/// Lays grouped cells out as one series per key over `periods`.
+fn pivot(periods: &[NaiveDate], cells: Vec<SpendingCell>) -> (Vec<SpendingSeries>, Vec<i64>) {
+    let column: HashMap<NaiveDate, usize> =
+        periods.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+    let mut totals = vec![0; periods.len()];
+    let mut series: Vec<SpendingSeries> = Vec::new();
+    let mut row: HashMap<String, usize> = HashMap::new();
+    for cell in cells {
+        let Some(&at) = column.get(&cell.period) else {
+            continue;
+        };
+        let index = *row.entry(cell.key.clone()).or_insert_with(|| {
+            series.push(SpendingSeries {
+                key: cell.key,
+                name: cell.name,
+                amounts: vec![0; periods.len()],
+                total: 0,
+            });
+            series.len() - 1
+        });
+        series[index].amounts[at] += cell.spent;
+        series[index].total += cell.spent;
+        totals[at] += cell.spent;
+    }
+    series.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.key.cmp(&b.key)));
+    (series, totals)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn periods_cover_the_range() {
+        assert_eq!(
+            periods(Interval::Quarter, date(2025, 11, 20), date(2026, 4, 1)).unwrap(),
+            [date(2025, 10, 1), date(2026, 1, 1), date(2026, 4, 1)]
+        );
+        assert_eq!(
+            periods(Interval::Year, date(2026, 6, 1), date(2026, 6, 30)).unwrap(),
+            [date(2026, 1, 1)]
+        );
+        assert_eq!(
+            periods(Interval::Month, date(2026, 2, 1), date(2026, 1, 1)),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            periods(Interval::Month, date(1900, 1, 1), date(2100, 1, 1)),
+            Err(StatusCode::BAD_REQUEST)
+        );
+    }
+
+    #[test]
+    fn pivot_sums_cells_into_series() {
+        let periods = [date(2026, 1, 1), date(2026, 2, 1)];
+        let cell = |period, key: &str, spent| SpendingCell {
+            period,
+            key: key.into(),
+            name: Some(key.to_uppercase()),
+            spent,
+        };
+        let (series, totals) = pivot(
+            &periods,
+            vec![
+                cell(date(2026, 1, 1), "rent", 1000),
+                cell(date(2026, 2, 1), "food", 300),
+                cell(date(2026, 2, 1), "rent", 1000),
+            ],
+        );
+        assert_eq!(totals, [1000, 1300]);
+        assert_eq!(series[0].key, "rent");
+        assert_eq!(series[0].amounts, [1000, 1000]);
+        assert_eq!(series[1].amounts, [0, 300]);
+        assert_eq!(series[1].name.as_deref(), Some("FOOD"));
+    }
+
+    #[test]
+    fn accumulate_carries_balances_forward() {
+        let months = [date(2026, 1, 1), date(2026, 2, 1), date(2026, 3, 1)];
+        let mut accounts = ["checking", "card"].map(|id| AccountBalances {
+            id: id.into(),
+            name: id.into(),
+            balances: Vec::new(),
+        });
+        let change = |account_id: &str, month, amount| BalanceChange {
+            account_id: account_id.into(),
+            month,
+            amount,
+        };
+        accumulate(
+            &months,
+            &mut accounts,
+            vec![
+                change("checking", date(2026, 1, 1), 5000),
+                change("card", date(2026, 2, 1), -700),
+                change("checking", date(2026, 3, 1), -1000),
+            ],
+        );
+        assert_eq!(accounts[0].balances, [5000, 5000, 4000]);
+        assert_eq!(accounts[1].balances, [0, -700, -700]);
+    }
+
+    #[test]
+    fn money_ages_spend_oldest_inflows_first() {
+        let months = [date(2026, 1, 1), date(2026, 2, 1), date(2026, 3, 1)];
+        let flows = [
+            (date(2026, 1, 1), 1000),
+            (date(2026, 1, 11), -500),
+            (date(2026, 1, 31), 1000),
+            // 500 from January 1st (40 days) and 500 from January 31st (10).
+            (date(2026, 2, 10), -1000),
+            // Half of it is not covered by anything and has no age.
+            (date(2026, 3, 2), -1000),
+        ];
+        assert_eq!(
+            money_ages(&flows, &months, 10),
+            [Some(10), Some(18), Some(22)]
+        );
+        assert_eq!(
+            money_ages(&flows, &months, 1),
+            [Some(10), Some(25), Some(30)]
+        );
+        assert_eq!(money_ages(&flows[..1], &months, 10), [None, None, None]);
+    }
+}
@@ -0,0 +1,547 @@
+//! What the forecast projects from: scheduled transactions and category
+//! targets.
+//!
+//! A scheduled transaction moves money in or out of one account on
+//! `next_date` and then every `frequency` until `end_date`. A category target
+//! is the amount the category is expected to spend each month.
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::Days;
+use chrono::Months;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::FromRow;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::audit::snapshot;
+use crate::changes::record_change;
+use crate::changes::ChangeEntity;
+use crate::changes::ChangeOp;
+use crate::closing::write_status;
+use crate::trash::owned_budget;
+use crate::user_from_headers;
+use crate::AppState;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Frequency {
+    Once,
+    Weekly,
+    EveryOtherWeek,
+    Monthly,
+    Yearly,
+}
+
+impl Frequency {
+    fn as_str(self) -> &'static str {
+        match self {
+            Frequency::Once => "once",
+            Frequency::Weekly => "weekly",
+            Frequency::EveryOtherWeek => "every_other_week",
+            Frequency::Monthly => "monthly",
+            Frequency::Yearly => "yearly",
+        }
+    }
+
+    /// The `n`th occurrence after `first`. Months and years count from
+    /// `first`, so a schedule on the 31st lands on the last day of shorter
+    /// months and returns to the 31st after them.
+    fn nth(self, first: NaiveDate, n: u32) -> Option<NaiveDate> {
+        match self {
+            Frequency::Once => (n == 0).then_some(first),
+            Frequency::Weekly => first.checked_add_days(Days::new(7 * u64::from(n))),
+            Frequency::EveryOtherWeek => first.checked_add_days(Days::new(14 * u64::from(n))),
+            Frequency::Monthly => first.checked_add_months(Months::new(n)),
+            Frequency::Yearly => first.checked_add_months(Months::new(n.checked_mul(12)?)),
+        }
+    }
+}
+
+impl TryFrom<String> for Frequency {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "once" => Ok(Frequency::Once),
+            "weekly" => Ok(Frequency::Weekly),
+            "every_other_week" => Ok(Frequency::EveryOtherWeek),
+            "monthly" => Ok(Frequency::Monthly),
+            "yearly" => Ok(Frequency::Yearly),
+            _ => Err(format!("unknown frequency {value}")),
+        }
+    }
+}
+
+#[derive(Serialize, FromRow)]
+pub(crate) struct ScheduledTransactionDto {
+    pub(crate) id: String,
+    pub(crate) account_id: String,
+    payee: Option<String>,
+    memo: Option<String>,
+    pub(crate) inflow: i64,
+    pub(crate) outflow: i64,
+    #[sqlx(try_from = "String")]
+    pub(crate) frequency: Frequency,
+    pub(crate) next_date: NaiveDate,
+    pub(crate) end_date: Option<NaiveDate>,
+}
+
+impl ScheduledTransactionDto {
+    /// The days it lands on in `after < date <= until`.
+    pub(crate) fn occurrences(&self, after: NaiveDate, until: NaiveDate) -> Vec<NaiveDate> {
+        let until = self.end_date.map_or(until, |end| end.min(until));
+        (0..)
+            .map_while(|n| self.frequency.nth(self.next_date, n))
+            .take_while(|date| *date <= until)
+            .filter(|date| *date > after)
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SaveScheduledTransaction {
+    account_id: String,
+    payee: Option<String>,
+    memo: Option<String>,
+    #[serde(default)]
+    inflow: i64,
+    #[serde(default)]
+    outflow: i64,
+    frequency: Frequency,
+    next_date: NaiveDate,
+    end_date: Option<NaiveDate>,
+}
+
+impl SaveScheduledTransaction {
+    fn validate(&self) -> Result<(), StatusCode> {
+        let one_direction =
+            (self.inflow == 0 && self.outflow > 0) || (self.outflow == 0 && self.inflow > 0);
+        if !one_direction || self.end_date.is_some_and(|end| end < self.next_date) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Ok(())
+    }
+}
+
+const SCHEDULED_COLUMNS: &str =
+    "pillid as id, account_pillid as account_id, payee, memo, inflow, outflow, frequency, next_date, end_date";
+
+/// `GET /api/budgets/:id/scheduled`, soonest first.
+pub(crate) async fn list_scheduled(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+) -> Result<Json<Vec<ScheduledTransactionDto>>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut conn, &budget_pillid, user_id).await?;
+    let rows = sqlx::query_as(&format!(
+        "select {SCHEDULED_COLUMNS} from scheduled_transactions
+         where budget_id = $1 and deleted_at is null
+         order by next_date, pillid"
+    ))
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(rows))
+}
+
+/// `POST /api/budgets/:id/scheduled`: the account must be an open account of
+/// the budget.
+pub(crate) async fn create_scheduled(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+    Json(payload): Json<SaveScheduledTransaction>,
+) -> Result<Json<ScheduledTransactionDto>, StatusCode> {
+    payload.validate()?;
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut tx, &budget_pillid, user_id).await?;
+    let row: ScheduledTransactionDto = sqlx::query_as(&format!(
+        "insert into scheduled_transactions (user_id, user_pillid, budget_id, budget_pillid, account_id, account_pillid, payee, memo, inflow, outflow, frequency, next_date, end_date)
+         select a.user_id, a.user_pillid, a.budget_id, a.budget_pillid, a.id, a.pillid, $3, $4, $5, $6, $7, $8, $9
+         from accounts a
+         where a.budget_id = $1 and a.pillid = $2 and a.deleted_at is null and a.closed_at is null
+         returning {SCHEDULED_COLUMNS}"
+    ))
+    .bind(budget_id)
+    .bind(&payload.account_id)
+    .bind(&payload.payee)
+    .bind(&payload.memo)
+    .bind(payload.inflow)
+    .bind(payload.outflow)
+    .bind(payload.frequency.as_str())
+    .bind(payload.next_date)
+    .bind(payload.end_date)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|err| write_status(err, StatusCode::BAD_REQUEST))?;
+    record_change(
+        &mut tx,
+        user_id,
+        &budget_pillid,
+        ChangeEntity::ScheduledTransaction,
+        &row.id,
+        ChangeOp::Create,
+        None,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(row))
+}
+
+/// `PUT /api/budgets/:id/scheduled/:scheduled_id` replaces every field.
+pub(crate) async fn update_scheduled(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((budget_pillid, id)): Path<(String, String)>,
+    Json(payload): Json<SaveScheduledTransaction>,
+) -> Result<Json<ScheduledTransactionDto>, StatusCode> {
+    payload.validate()?;
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut tx, &budget_pillid, user_id).await?;
+    if !live_scheduled(&mut tx, budget_id, &id).await? {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let before = snapshot(&mut tx, ChangeEntity::ScheduledTransaction, &id).await?;
+    let row = sqlx::query_as(
+        "update scheduled_transactions st
+         set account_id = a.id, account_pillid = a.pillid, payee = $4, memo = $5, inflow = $6, outflow = $7,
+             frequency = $8, next_date = $9, end_date = $10, updated_at = now()
+         from accounts a
+         where st.budget_id = $1 and st.pillid = $2
+           and a.budget_id = $1 and a.pillid = $3 and a.deleted_at is null and a.closed_at is null
+         returning st.pillid as id, st.account_pillid as account_id, st.payee, st.memo, st.inflow, st.outflow,
+                   st.frequency, st.next_date, st.end_date"
+    )
+    .bind(budget_id)
+    .bind(&id)
+    .bind(&payload.account_id)
+    .bind(&payload.payee)
+    .bind(&payload.memo)
+    .bind(payload.inflow)
+    .bind(payload.outflow)
+    .bind(payload.frequency.as_str())
+    .bind(payload.next_date)
+    .bind(payload.end_date)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|err| write_status(err, StatusCode::BAD_REQUEST))?;
+    record_change(
+        &mut tx,
+        user_id,
+        &budget_pillid,
+        ChangeEntity::ScheduledTransaction,
+        &id,
+        ChangeOp::Update,
+        before,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(row))
+}
+
+/// `DELETE /api/budgets/:id/scheduled/:scheduled_id` moves the schedule to the
+/// trash.
+pub(crate) async fn delete_scheduled(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((budget_pillid, id)): Path<(String, String)>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut tx, &budget_pillid, user_id).await?;
+    if !live_scheduled(&mut tx, budget_id, &id).await? {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let before = snapshot(&mut tx, ChangeEntity::ScheduledTransaction, &id).await?;
+    sqlx::query(
+        "update scheduled_transactions set deleted_at = now() where budget_id = $1 and pillid = $2",
+    )
+    .bind(budget_id)
+    .bind(&id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &budget_pillid,
+        ChangeEntity::ScheduledTransaction,
+        &id,
+        ChangeOp::Delete,
+        before,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Whether the budget has this schedule outside the trash; locks it for the
+/// write that follows.
+async fn live_scheduled(
+    conn: &mut PgConnection,
+    budget_id: Uuid,
+    pillid: &str,
+) -> Result<bool, StatusCode> {
+    let row: Option<(String,)> = sqlx::query_as(
+        "select pillid from scheduled_transactions
+         where budget_id = $1 and pillid = $2 and deleted_at is null
+         for update",
+    )
+    .bind(budget_id)
+    .bind(pillid)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(row.is_some())
+}
+
+#[derive(Serialize, FromRow)]
+pub(crate) struct CategoryTargetDto {
+    id: String,
+    category_id: String,
+    monthly_amount: i64,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SaveCategoryTarget {
+    monthly_amount: i64,
+}
+
+/// `GET /api/budgets/:id/targets`: the targets of the budget's live
+/// categories.
+pub(crate) async fn list_targets(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_pillid): Path<String>,
+) -> Result<Json<Vec<CategoryTargetDto>>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut conn, &budget_pillid, user_id).await?;
+    let rows = sqlx::query_as(
+        "select ct.pillid as id, ct.category_pillid as category_id, ct.monthly_amount
+         from category_targets ct
+         join categories c on c.id = ct.category_id and c.deleted_at is null
+         where ct.budget_id = $1 and ct.deleted_at is null
+         order by c.created_at, c.pillid",
+    )
+    .bind(budget_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(rows))
+}
+
+/// `PUT /api/budgets/:id/targets/:category_id` sets or replaces the target.
+/// A category keeps one target row, so setting a cleared target again brings
+/// that row back.
+pub(crate) async fn set_target(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((budget_pillid, category_pillid)): Path<(String, String)>,
+    Json(payload): Json<SaveCategoryTarget>,
+) -> Result<Json<CategoryTargetDto>, StatusCode> {
+    if payload.monthly_amount <= 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut tx, &budget_pillid, user_id).await?;
+    let before = match target_of(&mut tx, budget_id, &category_pillid).await? {
+        Some((pillid, _)) => snapshot(&mut tx, ChangeEntity::CategoryTarget, &pillid).await?,
+        None => None,
+    };
+    let row: Option<CategoryTargetDto> = sqlx::query_as(
+        "insert into category_targets (user_id, user_pillid, budget_id, budget_pillid, category_id, category_pillid, monthly_amount)
+         select c.user_id, c.user_pillid, c.budget_id, c.budget_pillid, c.id, c.pillid, $3
+         from categories c
+         where c.budget_id = $1 and c.pillid = $2 and c.deleted_at is null
+         on conflict (category_id) do update set
+           monthly_amount = excluded.monthly_amount, deleted_at = null, updated_at = now()
+         returning pillid as id, category_pillid as category_id, monthly_amount",
+    )
+    .bind(budget_id)
+    .bind(&category_pillid)
+    .bind(payload.monthly_amount)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|err| write_status(err, StatusCode::BAD_REQUEST))?;
+    let row = row.ok_or(StatusCode::NOT_FOUND)?;
+    let was_set = before
+        .as_ref()
+        .is_some_and(|image| image["deleted_at"].is_null());
+    record_change(
+        &mut tx,
+        user_id,
+        &budget_pillid,
+        ChangeEntity::CategoryTarget,
+        &row.id,
+        if was_set {
+            ChangeOp::Update
+        } else {
+            ChangeOp::Create
+        },
+        before,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(row))
+}
+
+/// `DELETE /api/budgets/:id/targets/:category_id` clears the target; the
+/// forecast goes back to the category's average spending.
+pub(crate) async fn delete_target(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((budget_pillid, category_pillid)): Path<(String, String)>,
+) -> Result<StatusCode, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_id = owned_budget(&mut tx, &budget_pillid, user_id).await?;
+    let Some((pillid, true)) = target_of(&mut tx, budget_id, &category_pillid).await? else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let before = snapshot(&mut tx, ChangeEntity::CategoryTarget, &pillid).await?;
+    sqlx::query("update category_targets set deleted_at = now() where pillid = $1")
+        .bind(&pillid)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_change(
+        &mut tx,
+        user_id,
+        &budget_pillid,
+        ChangeEntity::CategoryTarget,
+        &pillid,
+        ChangeOp::Delete,
+        before,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The pillid of a category's target row, live or cleared, and whether it is
+/// live; locks it for the write that follows.
+async fn target_of(
+    conn: &mut PgConnection,
+    budget_id: Uuid,
+    category_pillid: &str,
+) -> Result<Option<(String, bool)>, StatusCode> {
+    sqlx::query_as(
+        "select pillid, deleted_at is null from category_targets
+         where budget_id = $1 and category_pillid = $2
+         for update",
+    )
+    .bind(budget_id)
+    .bind(category_pillid)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn scheduled(
+        frequency: Frequency,
+        next_date: NaiveDate,
+        end_date: Option<NaiveDate>,
+    ) -> ScheduledTransactionDto {
+        ScheduledTransactionDto {
+            id: "rent".into(),
+            account_id: "checking".into(),
+            payee: None,
+            memo: None,
+            inflow: 0,
+            outflow: 100,
+            frequency,
+            next_date,
+            end_date,
+        }
+    }
+
+    #[test]
+    fn occurrences_follow_frequency_within_the_window() {
+        let after = date(2026, 1, 15);
+        let until = date(2026, 4, 30);
+
+        let monthly = scheduled(Frequency::Monthly, date(2026, 1, 31), None);
+        assert_eq!(
+            monthly.occurrences(after, until),
+            [
+                date(2026, 1, 31),
+                date(2026, 2, 28),
+                date(2026, 3, 31),
+                date(2026, 4, 30)
+            ]
+        );
+        // Past occurrences are skipped, the end date is included.
+        let weekly = scheduled(
+            Frequency::EveryOtherWeek,
+            date(2026, 1, 1),
+            Some(date(2026, 2, 12)),
+        );
+        assert_eq!(
+            weekly.occurrences(after, until),
+            [date(2026, 1, 29), date(2026, 2, 12)]
+        );
+        let once = scheduled(Frequency::Once, date(2026, 1, 10), None);
+        assert!(once.occurrences(after, until).is_empty());
+        let yearly = scheduled(Frequency::Yearly, date(2025, 3, 1), None);
+        assert_eq!(yearly.occurrences(after, until), [date(2026, 3, 1)]);
+    }
+}
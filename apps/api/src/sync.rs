@@ -0,0 +1,703 @@
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::DateTime;
+use chrono::NaiveDate;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use sqlx::Acquire;
+use sqlx::FromRow;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::audit::snapshot;
+use crate::changes::committed_knowledge;
+use crate::changes::record_change;
+use crate::changes::ChangeEntity;
+use crate::changes::ChangeOp;
+use crate::closing::write_status;
+use crate::insert_transaction_splits;
+use crate::money::Currency;
+use crate::user_from_headers;
+use crate::validate_splits;
+use crate::AppState;
+use crate::SaveTransaction;
+
+#[derive(Deserialize)]
+pub(crate) struct SyncQuery {
+    /// Cursor returned by a previous sync; omit or pass 0 for a full snapshot.
+    since: Option<i64>,
+    budget_id: Option<String>,
+}
+
+#[derive(Serialize, FromRow)]
+struct SyncBudgetDto {
+    id: String,
+    name: String,
+    /// `currency_code` and the `minor_unit` every amount in the budget is in.
+    #[serde(flatten)]
+    #[sqlx(rename = "currency_code", try_from = "String")]
+    currency: Currency,
+    is_default: bool,
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    knowledge: i64,
+}
+
+#[derive(Serialize, FromRow)]
+struct SyncAccountDto {
+    id: String,
+    budget_id: String,
+    name: String,
+    /// `currency_code` and `minor_unit` of the account's own amounts.
+    #[serde(flatten)]
+    #[sqlx(rename = "currency_code", try_from = "String")]
+    currency: Currency,
+    closed_at: Option<DateTime<Utc>>,
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    knowledge: i64,
+}
+
+#[derive(Serialize, FromRow)]
+struct SyncSupercategoryDto {
+    id: String,
+    budget_id: String,
+    name: String,
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    knowledge: i64,
+}
+
+#[derive(Serialize, FromRow)]
+struct SyncCategoryDto {
+    id: String,
+    budget_id: String,
+    supercategory_id: String,
+    name: String,
+    is_transfer: bool,
+    is_income: bool,
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    knowledge: i64,
+}
+
+#[derive(Serialize, FromRow)]
+struct SyncTransactionDto {
+    id: String,
+    budget_id: String,
+    account_id: String,
+    date: NaiveDate,
+    payee: Option<String>,
+    memo: Option<String>,
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    knowledge: i64,
+}
+
+#[derive(Serialize, FromRow)]
+struct SyncSplitDto {
+    id: String,
+    transaction_id: String,
+    category_id: String,
+    memo: Option<String>,
+    /// In the account's currency, as in mutations.
+    inflow: i64,
+    outflow: i64,
+    /// In the budget's currency.
+    budget_inflow: i64,
+    budget_outflow: i64,
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    knowledge: i64,
+}
+
+#[derive(Serialize, FromRow)]
+struct SyncCategoryAssignmentDto {
+    id: String,
+    budget_id: String,
+    category_id: String,
+    month: String,
+    amount: i64,
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    knowledge: i64,
+}
+
+#[derive(Serialize, FromRow)]
+struct SyncScheduledTransactionDto {
+    id: String,
+    budget_id: String,
+    account_id: String,
+    payee: Option<String>,
+    memo: Option<String>,
+    inflow: i64,
+    outflow: i64,
+    frequency: String,
+    next_date: NaiveDate,
+    end_date: Option<NaiveDate>,
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    knowledge: i64,
+}
+
+#[derive(Serialize, FromRow)]
+struct SyncCategoryTargetDto {
+    id: String,
+    budget_id: String,
+    category_id: String,
+    monthly_amount: i64,
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    knowledge: i64,
+}
+
+/// Everything that changed after `since`. Soft-deleted rows are included with
+/// `deleted_at` set so clients can drop them locally. Payees are free text on
+/// transactions, so they travel with the transaction rows.
+#[derive(Serialize)]
+pub(crate) struct SyncDto {
+    server_knowledge: i64,
+    budgets: Vec<SyncBudgetDto>,
+    accounts: Vec<SyncAccountDto>,
+    supercategories: Vec<SyncSupercategoryDto>,
+    categories: Vec<SyncCategoryDto>,
+    transactions: Vec<SyncTransactionDto>,
+    splits: Vec<SyncSplitDto>,
+    category_assignments: Vec<SyncCategoryAssignmentDto>,
+    scheduled_transactions: Vec<SyncScheduledTransactionDto>,
+    category_targets: Vec<SyncCategoryTargetDto>,
+}
+
+pub(crate) async fn sync(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<SyncQuery>,
+) -> Result<Json<SyncDto>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let since = query.since.unwrap_or(0);
+    if since < 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let budget_id = query.budget_id;
+
+    // Before the snapshot below; see the migration that defines it.
+    let committed = committed_knowledge(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // One snapshot for every entity list so the cursor is consistent.
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("set transaction isolation level repeatable read read only")
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let budgets = sqlx::query_as::<_, SyncBudgetDto>(
+        "select pillid as id, name, currency_code, is_default, updated_at, deleted_at, knowledge
+         from budgets
+         where user_id = $1 and knowledge > $2 and ($3::text is null or pillid = $3)
+         order by knowledge",
+    )
+    .bind(user_id)
+    .bind(since)
+    .bind(&budget_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let accounts = sqlx::query_as::<_, SyncAccountDto>(
+        "select pillid as id, budget_pillid as budget_id, name, currency_code, closed_at, updated_at, deleted_at, knowledge
+         from accounts
+         where user_id = $1 and knowledge > $2 and ($3::text is null or budget_pillid = $3)
+         order by knowledge",
+    )
+    .bind(user_id)
+    .bind(since)
+    .bind(&budget_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let supercategories = sqlx::query_as::<_, SyncSupercategoryDto>(
+        "select pillid as id, budget_pillid as budget_id, name, updated_at, deleted_at, knowledge
+         from supercategories
+         where user_id = $1 and knowledge > $2 and ($3::text is null or budget_pillid = $3)
+         order by knowledge",
+    )
+    .bind(user_id)
+    .bind(since)
+    .bind(&budget_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let categories = sqlx::query_as::<_, SyncCategoryDto>(
+        "select pillid as id, budget_pillid as budget_id, supercategory_pillid as supercategory_id, name, is_transfer, is_income, updated_at, deleted_at, knowledge
+         from categories
+         where user_id = $1 and knowledge > $2 and ($3::text is null or budget_pillid = $3)
+         order by knowledge",
+    )
+    .bind(user_id)
+    .bind(since)
+    .bind(&budget_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let transactions = sqlx::query_as::<_, SyncTransactionDto>(
+        "select pillid as id, budget_pillid as budget_id, account_pillid as account_id, tx_date as date, payee, memo, updated_at, deleted_at, knowledge
+         from transactions
+         where user_id = $1 and knowledge > $2 and ($3::text is null or budget_pillid = $3)
+         order by knowledge",
+    )
+    .bind(user_id)
+    .bind(since)
+    .bind(&budget_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let splits = sqlx::query_as::<_, SyncSplitDto>(
+        "select ts.pillid as id, ts.transaction_pillid as transaction_id, ts.category_pillid as category_id, ts.memo,
+                coalesce(ts.account_inflow, ts.inflow) as inflow, coalesce(ts.account_outflow, ts.outflow) as outflow,
+                ts.inflow as budget_inflow, ts.outflow as budget_outflow, ts.updated_at, ts.deleted_at, ts.knowledge
+         from transaction_splits ts
+         join transactions t on t.id = ts.transaction_id
+         where t.user_id = $1 and ts.knowledge > $2 and ($3::text is null or t.budget_pillid = $3)
+         order by ts.knowledge",
+    )
+    .bind(user_id)
+    .bind(since)
+    .bind(&budget_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let category_assignments = sqlx::query_as::<_, SyncCategoryAssignmentDto>(
+        "select pillid as id, budget_pillid as budget_id, category_pillid as category_id, to_char(month, 'YYYY-MM') as month, amount, updated_at, deleted_at, knowledge
+         from category_assignments
+         where user_id = $1 and knowledge > $2 and ($3::text is null or budget_pillid = $3)
+         order by knowledge",
+    )
+    .bind(user_id)
+    .bind(since)
+    .bind(&budget_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let scheduled_transactions = sqlx::query_as::<_, SyncScheduledTransactionDto>(
+        "select pillid as id, budget_pillid as budget_id, account_pillid as account_id, payee, memo, inflow, outflow, frequency, next_date, end_date, updated_at, deleted_at, knowledge
+         from scheduled_transactions
+         where user_id = $1 and knowledge > $2 and ($3::text is null or budget_pillid = $3)
+         order by knowledge",
+    )
+    .bind(user_id)
+    .bind(since)
+    .bind(&budget_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let category_targets = sqlx::query_as::<_, SyncCategoryTargetDto>(
+        "select pillid as id, budget_pillid as budget_id, category_pillid as category_id, monthly_amount, updated_at, deleted_at, knowledge
+         from category_targets
+         where user_id = $1 and knowledge > $2 and ($3::text is null or budget_pillid = $3)
+         order by knowledge",
+    )
+    .bind(user_id)
+    .bind(since)
+    .bind(&budget_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Rows above a value still held by an uncommitted writer come back again
+    // next time, so the writer's rows are not skipped once it commits.
+    let seen = [
+        budgets.iter().map(|r| r.knowledge).max(),
+        accounts.iter().map(|r| r.knowledge).max(),
+        supercategories.iter().map(|r| r.knowledge).max(),
+        categories.iter().map(|r| r.knowledge).max(),
+        transactions.iter().map(|r| r.knowledge).max(),
+        splits.iter().map(|r| r.knowledge).max(),
+        category_assignments.iter().map(|r| r.knowledge).max(),
+        scheduled_transactions.iter().map(|r| r.knowledge).max(),
+        category_targets.iter().map(|r| r.knowledge).max(),
+    ]
+    .into_iter()
+    .flatten()
+    .fold(since, i64::max);
+    let server_knowledge = seen.min(committed).max(since);
+
+    Ok(Json(SyncDto {
+        server_knowledge,
+        budgets,
+        accounts,
+        supercategories,
+        categories,
+        transactions,
+        splits,
+        category_assignments,
+        scheduled_transactions,
+        category_targets,
+    }))
+}
+
+const MAX_MUTATIONS_PER_BATCH: usize = 500;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MutationEntity {
+    Transaction,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MutationOp {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Deserialize)]
+struct OfflineMutation {
+    entity: MutationEntity,
+    op: MutationOp,
+    /// Client-generated pillid for creates, the existing pillid otherwise.
+    id: String,
+    /// `knowledge` of the row when the client last synced it.
+    base_knowledge: Option<i64>,
+    transaction: Option<SaveTransaction>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ApplyMutations {
+    mutations: Vec<OfflineMutation>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum MutationStatus {
+    Applied,
+    Rejected,
+    Conflict,
+}
+
+#[derive(Serialize)]
+struct MutationOutcome {
+    id: String,
+    status: MutationStatus,
+    reason: Option<&'static str>,
+    knowledge: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ApplyMutationsDto {
+    results: Vec<MutationOutcome>,
+}
+
+/// Replays a queue of offline mutations in one database transaction.
+///
+/// Each mutation runs in its own savepoint so a rejected or conflicting entry
+/// does not undo the ones around it. A server error fails the whole batch.
+/// The conflict rules are documented in `docs/offline-sync.md`.
+pub(crate) async fn apply_mutations(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<ApplyMutations>,
+) -> Result<Json<ApplyMutationsDto>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    if payload.mutations.len() > MAX_MUTATIONS_PER_BATCH {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut results = Vec::with_capacity(payload.mutations.len());
+    for mutation in &payload.mutations {
+        let mut savepoint = tx
+            .begin()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let applied = match mutation.entity {
+            MutationEntity::Transaction => {
+                apply_transaction_mutation(&mut savepoint, user_id, mutation).await
+            }
+        };
+        let rejected = |reason| MutationOutcome {
+            id: mutation.id.clone(),
+            status: MutationStatus::Rejected,
+            reason: Some(reason),
+            knowledge: None,
+        };
+        let outcome = match applied {
+            Ok(outcome) => outcome,
+            Err(StatusCode::CONFLICT) => rejected("month_closed"),
+            Err(status) if status.is_client_error() => rejected("invalid"),
+            // Not the mutation's fault: fail the whole batch, which rolls back,
+            // so the client keeps its queue and retries.
+            Err(status) => return Err(status),
+        };
+        if outcome.status == MutationStatus::Applied {
+            savepoint
+                .commit()
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        } else {
+            savepoint
+                .rollback()
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        results.push(outcome);
+    }
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(ApplyMutationsDto { results }))
+}
+
+type CurrentTransactionRow = (i64, bool, bool, String, String, NaiveDate, Option<String>);
+
+async fn apply_transaction_mutation(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    mutation: &OfflineMutation,
+) -> Result<MutationOutcome, StatusCode> {
+    let id = mutation.id.clone();
+    let outcome = |status, reason, knowledge| MutationOutcome {
+        id: id.clone(),
+        status,
+        reason,
+        knowledge,
+    };
+
+    if let MutationOp::Create = mutation.op {
+        let payload = mutation
+            .transaction
+            .as_ref()
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        validate_client_pillid(&mutation.id)?;
+        validate_splits(&payload.splits)?;
+
+        let existing: Option<(Uuid, i64)> =
+            sqlx::query_as("select user_id, knowledge from transactions where pillid = $1")
+                .bind(&mutation.id)
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if let Some((owner, knowledge)) = existing {
+            // A create whose acknowledgement was lost is replayed verbatim.
+            return Ok(if owner == user_id {
+                outcome(
+                    MutationStatus::Applied,
+                    Some("already_applied"),
+                    Some(knowledge),
+                )
+            } else {
+                outcome(MutationStatus::Rejected, Some("id_taken"), None)
+            });
+        }
+
+        let (knowledge,): (i64,) = sqlx::query_as("insert into transactions (pillid,user_id,user_pillid,budget_id,budget_pillid,account_id,account_pillid,tx_date,payee,memo) select $7,u.id,u.pillid,b.id,b.pillid,a.id,a.pillid,$4,$5,$6 from users u join budgets b on b.pillid=$2 and b.user_id=u.id and b.deleted_at is null join accounts a on a.pillid=$3 and a.user_id=u.id and a.budget_id=b.id and a.deleted_at is null and a.closed_at is null where u.id=$1 returning knowledge")
+            .bind(user_id).bind(&payload.budget_id).bind(&payload.account_id).bind(payload.date).bind(&payload.payee).bind(&payload.memo).bind(&mutation.id)
+            .fetch_one(&mut *conn).await.map_err(|err| write_status(err, StatusCode::BAD_REQUEST))?;
+        insert_transaction_splits(conn, &mutation.id, &payload.splits, user_id).await?;
+        record_change(
+            conn,
+            user_id,
+            &payload.budget_id,
+            ChangeEntity::Transaction,
+            &mutation.id,
+            ChangeOp::Create,
+            None,
+        )
+        .await?;
+        return Ok(outcome(MutationStatus::Applied, None, Some(knowledge)));
+    }
+
+    let base_knowledge = mutation.base_knowledge.ok_or(StatusCode::BAD_REQUEST)?;
+    let current: Option<CurrentTransactionRow> = sqlx::query_as(
+        "select knowledge, reconciled_at is not null, deleted_at is not null, budget_pillid, account_pillid, tx_date, payee from transactions where pillid = $1 and user_id = $2 for update",
+    )
+    .bind(&mutation.id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some((knowledge, reconciled, deleted, budget_id, account_id, date, payee)) = current else {
+        return Ok(outcome(MutationStatus::Rejected, Some("not_found"), None));
+    };
+    if reconciled {
+        return Ok(outcome(
+            MutationStatus::Rejected,
+            Some("reconciled"),
+            Some(knowledge),
+        ));
+    }
+    let stale = knowledge > base_knowledge;
+
+    if let MutationOp::Delete = mutation.op {
+        if deleted {
+            return Ok(outcome(
+                MutationStatus::Applied,
+                Some("already_applied"),
+                Some(knowledge),
+            ));
+        }
+        if stale {
+            return Ok(outcome(
+                MutationStatus::Conflict,
+                Some("stale"),
+                Some(knowledge),
+            ));
+        }
+        let before = snapshot(conn, ChangeEntity::Transaction, &mutation.id).await?;
+        let (knowledge,): (i64,) = sqlx::query_as(
+            "update transactions set deleted_at = now() where pillid = $1 returning knowledge",
+        )
+        .bind(&mutation.id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|err| write_status(err, StatusCode::INTERNAL_SERVER_ERROR))?;
+        record_change(
+            conn,
+            user_id,
+            &budget_id,
+            ChangeEntity::Transaction,
+            &mutation.id,
+            ChangeOp::Delete,
+            before,
+        )
+        .await?;
+        return Ok(outcome(MutationStatus::Applied, None, Some(knowledge)));
+    }
+
+    let payload = mutation
+        .transaction
+        .as_ref()
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    validate_splits(&payload.splits)?;
+    if deleted {
+        return Ok(outcome(
+            MutationStatus::Conflict,
+            Some("deleted"),
+            Some(knowledge),
+        ));
+    }
+    if budget_id != payload.budget_id {
+        return Ok(outcome(
+            MutationStatus::Rejected,
+            Some("budget_changed"),
+            Some(knowledge),
+        ));
+    }
+    if stale {
+        // Memos are last-writer-wins; any other concurrent edit is a conflict.
+        let server_splits: Vec<(String, i64, i64)> = sqlx::query_as(
+            "select category_pillid, coalesce(account_inflow, inflow), coalesce(account_outflow, outflow) from transaction_splits where transaction_pillid = $1 and deleted_at is null",
+        )
+        .bind(&mutation.id)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let client_splits = payload
+            .splits
+            .iter()
+            .map(|s| (s.category_id.clone(), s.inflow, s.outflow))
+            .collect();
+        let memo_only = account_id == payload.account_id
+            && date == payload.date
+            && payee == payload.payee
+            && same_split_amounts(server_splits, client_splits);
+        if !memo_only {
+            return Ok(outcome(
+                MutationStatus::Conflict,
+                Some("stale"),
+                Some(knowledge),
+            ));
+        }
+    }
+
+    let before = snapshot(conn, ChangeEntity::Transaction, &mutation.id).await?;
+    let (knowledge,): (i64,) = sqlx::query_as("update transactions t set account_id=a.id,account_pillid=a.pillid,tx_date=$4,payee=$5,memo=$6,updated_at=now() from budgets b, accounts a where t.pillid=$1 and t.user_id=$7 and b.pillid=$2 and b.user_id=$7 and b.deleted_at is null and a.pillid=$3 and a.user_id=$7 and a.budget_id=b.id and a.deleted_at is null and a.closed_at is null returning t.knowledge")
+        .bind(&mutation.id).bind(&payload.budget_id).bind(&payload.account_id).bind(payload.date).bind(&payload.payee).bind(&payload.memo).bind(user_id)
+        .fetch_one(&mut *conn).await.map_err(|err| write_status(err, StatusCode::BAD_REQUEST))?;
+    sqlx::query("update transaction_splits set deleted_at=now() where transaction_pillid=$1 and deleted_at is null")
+        .bind(&mutation.id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    insert_transaction_splits(conn, &mutation.id, &payload.splits, user_id).await?;
+    record_change(
+        conn,
+        user_id,
+        &payload.budget_id,
+        ChangeEntity::Transaction,
+        &mutation.id,
+        ChangeOp::Update,
+        before,
+    )
+    .await?;
+    Ok(outcome(MutationStatus::Applied, None, Some(knowledge)))
+}
+
+fn validate_client_pillid(id: &str) -> Result<(), StatusCode> {
+    let well_formed = !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if well_formed {
+        Ok(())
+    } else {
+        Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+fn same_split_amounts(
+    mut server: Vec<(String, i64, i64)>,
+    mut client: Vec<(String, i64, i64)>,
+) -> bool {
+    server.sort();
+    client.sort();
+    server == client
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn split_amount_comparison_ignores_order() {
+        let a = vec![("food".to_string(), 0, 100), ("fuel".to_string(), 0, 50)];
+        let b = vec![("fuel".to_string(), 0, 50), ("food".to_string(), 0, 100)];
+        assert!(same_split_amounts(a.clone(), b));
+        assert!(!same_split_amounts(a, vec![("food".to_string(), 0, 150)]));
+    }
+
+    #[test]
+    fn client_pillids_are_restricted() {
+        assert!(validate_client_pillid("0f3a-client_1").is_ok());
+        assert!(validate_client_pillid("").is_err());
+        assert!(validate_client_pillid("has space").is_err());
+        assert!(validate_client_pillid(&"a".repeat(65)).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::DateTime;
+use chrono::NaiveDate;
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::FromRow;
+use sqlx::PgConnection;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit::snapshot;
+use crate::changes::record_change;
+use crate::changes::ChangeEntity;
+use crate::changes::ChangeOp;
+use crate::closing::write_status;
+use crate::user_from_headers;
+use crate::AppState;
+
+#[derive(Serialize, FromRow)]
+pub(crate) struct TrashItemDto {
+    entity: String,
+    id: String,
+    /// Name, or payee for transactions.
+    label: Option<String>,
+    date: Option<NaiveDate>,
+    deleted_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct RestoreResponse {
+    entity: String,
+    id: String,
+    /// Restored rows that still point at something in the trash, e.g.
+    /// `supercategory_deleted` for a category.
+    warnings: Vec<String>,
+}
+
+pub(crate) async fn owned_budget(
+    conn: &mut PgConnection,
+    budget_pillid: &str,
+    user_id: Uuid,
+) -> Result<Uuid, StatusCode> {
+    let row: Option<(Uuid,)> = sqlx::query_as(
+        "select id from budgets where pillid = $1 and user_id = $2 and deleted_at is null",
+    )
+    .bind(budget_pillid)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    row.map(|r| r.0).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Soft-deleted accounts, supercategories, categories, transactions and
+/// scheduled transactions of one budget, most recently deleted first.
+pub(crate) async fn list_trash(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_id): Path<String>,
+) -> Result<Json<Vec<TrashItemDto>>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_uuid = owned_budget(&mut conn, &budget_id, user_id).await?;
+
+    let rows = sqlx::query_as::<_, TrashItemDto>(
+        "select 'account' as entity, pillid as id, name as label, null::date as date, deleted_at
+         from accounts where budget_id = $1 and deleted_at is not null
+         union all
+         select 'supercategory', pillid, name, null, deleted_at
+         from supercategories where budget_id = $1 and deleted_at is not null
+         union all
+         select 'category', pillid, name, null, deleted_at
+         from categories where budget_id = $1 and deleted_at is not null
+         union all
+         select 'transaction', pillid, payee, tx_date, deleted_at
+         from transactions where budget_id = $1 and deleted_at is not null
+         union all
+         select 'scheduled_transaction', pillid, payee, next_date, deleted_at
+         from scheduled_transactions where budget_id = $1 and deleted_at is not null
+         order by deleted_at desc, id",
+    )
+    .bind(budget_uuid)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(rows))
+}
+
+/// Undeletes one row from the trash. A transaction gets back the splits it had
+/// when it was deleted.
+pub(crate) async fn restore_from_trash(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((budget_id, entity, id)): Path<(String, String, String)>,
+) -> Result<Json<RestoreResponse>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let entity = match ChangeEntity::parse(&entity) {
+        Some(
+            entity @ (ChangeEntity::Account
+            | ChangeEntity::Supercategory
+            | ChangeEntity::Category
+            | ChangeEntity::Transaction
+            | ChangeEntity::ScheduledTransaction),
+        ) => entity,
+        _ => return Err(StatusCode::NOT_FOUND),
+    };
+
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let budget_uuid = owned_budget(&mut tx, &budget_id, user_id).await?;
+    let before = snapshot(&mut tx, entity, &id).await?;
+
+    if let ChangeEntity::Transaction = entity {
+        // Splits removed by the delete (or later) come back; ones replaced by
+        // earlier edits stay deleted.
+        sqlx::query(
+            "update transaction_splits ts set deleted_at = null, updated_at = now()
+             from transactions t
+             where t.pillid = $1 and t.budget_id = $2 and t.deleted_at is not null
+               and ts.transaction_id = t.id and ts.deleted_at >= t.deleted_at",
+        )
+        .bind(&id)
+        .bind(budget_uuid)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| write_status(err, StatusCode::INTERNAL_SERVER_ERROR))?;
+    }
+
+    let sql = format!(
+        "update {} set deleted_at = null, updated_at = now() where pillid = $1 and budget_id = $2 and deleted_at is not null",
+        entity.table()
+    );
+    let restored = sqlx::query(&sql)
+        .bind(&id)
+        .bind(budget_uuid)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| write_status(err, StatusCode::INTERNAL_SERVER_ERROR))?;
+    if restored.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let warnings = restore_warnings(&mut tx, entity, &id).await?;
+    record_change(
+        &mut tx,
+        user_id,
+        &budget_id,
+        entity,
+        &id,
+        ChangeOp::Create,
+        before,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(RestoreResponse {
+        entity: entity.as_str().to_string(),
+        id,
+        warnings,
+    }))
+}
+
+async fn restore_warnings(
+    conn: &mut PgConnection,
+    entity: ChangeEntity,
+    pillid: &str,
+) -> Result<Vec<String>, StatusCode> {
+    let sql = match entity {
+        ChangeEntity::Category => {
+            "select 'supercategory_deleted' from categories c
+             join supercategories s on s.id = c.supercategory_id
+             where c.pillid = $1 and s.deleted_at is not null"
+        }
+        ChangeEntity::Transaction => {
+            "select 'account_deleted' from transactions t
+             join accounts a on a.id = t.account_id
+             where t.pillid = $1 and a.deleted_at is not null
+             union all
+             select distinct 'category_deleted' from transactions t
+             join transaction_splits ts on ts.transaction_id = t.id and ts.deleted_at is null
+             join categories c on c.id = ts.category_id
+             where t.pillid = $1 and c.deleted_at is not null"
+        }
+        ChangeEntity::ScheduledTransaction => {
+            "select 'account_deleted' from scheduled_transactions st
+             join accounts a on a.id = st.account_id
+             where st.pillid = $1 and a.deleted_at is not null"
+        }
+        _ => return Ok(Vec::new()),
+    };
+    let rows: Vec<(String,)> = sqlx::query_as(sql)
+        .bind(pillid)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(rows.into_iter().map(|r| r.0).collect())
+}
+
+/// Hard-deletes trash that has been soft-deleted for longer than `retention`
+/// and returns how many rows went.
+///
+/// Superseded transaction splits, category assignments and cleared category
+/// targets are purged too.
+/// Accounts, supercategories and categories are kept while anything still
+/// references them, because deleting them would cascade into live rows.
+pub async fn purge_trash(db: &PgPool, retention: chrono::Duration) -> Result<u64, sqlx::Error> {
+    let cutoff = Utc::now() - retention;
+    let statements = [
+        "delete from transaction_splits where deleted_at < $1",
+        "delete from transactions where deleted_at < $1",
+        "delete from category_assignments where deleted_at < $1",
+        "delete from scheduled_transactions where deleted_at < $1",
+        "delete from category_targets where deleted_at < $1",
+        "delete from categories c where c.deleted_at < $1
+           and not exists (select 1 from transaction_splits ts where ts.category_id = c.id)
+           and not exists (select 1 from category_assignments ca where ca.category_id = c.id)
+           and not exists (select 1 from category_targets ct where ct.category_id = c.id)",
+        "delete from supercategories s where s.deleted_at < $1
+           and not exists (select 1 from categories c where c.supercategory_id = s.id)",
+        "delete from accounts a where a.deleted_at < $1
+           and not exists (select 1 from transactions t where t.account_id = a.id)
+           and not exists (select 1 from scheduled_transactions st where st.account_id = a.id)",
+    ];
+
+    let mut tx = db.begin().await?;
+    let mut purged = 0;
+    for sql in statements {
+        purged += sqlx::query(sql)
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+    }
+    tx.commit().await?;
+    Ok(purged)
+}
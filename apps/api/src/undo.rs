@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::FromRow;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::audit::snapshot;
+use crate::changes::record_change;
+use crate::changes::ChangeEntity;
+use crate::changes::ChangeOp;
+use crate::closing::MONTH_CLOSED;
+use crate::user_from_headers;
+use crate::AppState;
+
+const MAX_STEPS: i64 = 50;
+
+#[derive(Deserialize)]
+pub(crate) struct UndoQuery {
+    count: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct UndoResponse {
+    steps: Vec<UndoStep>,
+}
+
+/// One reverted (or re-applied) mutation group: everything a single request
+/// wrote, e.g. one delete or a whole bulk edit.
+#[derive(Serialize)]
+struct UndoStep {
+    changes: Vec<StepChange>,
+}
+
+#[derive(Serialize)]
+struct StepChange {
+    entity: String,
+    entity_id: String,
+    op: String,
+}
+
+#[derive(FromRow)]
+struct AuditRow {
+    entity: String,
+    entity_pillid: String,
+    before: Option<Value>,
+    after: Option<Value>,
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Undo,
+    Redo,
+}
+
+impl Direction {
+    fn kind(self) -> &'static str {
+        match self {
+            Direction::Undo => "undo",
+            Direction::Redo => "redo",
+        }
+    }
+}
+
+/// Reverts the caller's last `count` (default 1) mutation groups in a budget,
+/// newest first.
+///
+/// A group is every audit entry written by one database transaction. If an
+/// entity in a group has changed since, the whole request is a `409` and
+/// nothing is reverted.
+pub(crate) async fn undo(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_id): Path<String>,
+    Query(query): Query<UndoQuery>,
+) -> Result<Json<UndoResponse>, StatusCode> {
+    run(state, headers, budget_id, query, Direction::Undo).await
+}
+
+/// Re-applies the last `count` undone groups. New mutations in the budget
+/// after an undo discard what could be redone.
+pub(crate) async fn redo(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(budget_id): Path<String>,
+    Query(query): Query<UndoQuery>,
+) -> Result<Json<UndoResponse>, StatusCode> {
+    run(state, headers, budget_id, query, Direction::Redo).await
+}
+
+async fn run(
+    state: AppState,
+    headers: HeaderMap,
+    budget_pillid: String,
+    query: UndoQuery,
+    direction: Direction,
+) -> Result<Json<UndoResponse>, StatusCode> {
+    let user_id = user_from_headers(&state, &headers).await?;
+    let count = query.count.unwrap_or(1);
+    if !(1..=MAX_STEPS).contains(&count) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Deleted budgets are included so that creating one can be undone and redone.
+    let (budget_uuid,): (Uuid,) =
+        sqlx::query_as("select id from budgets where pillid = $1 and user_id = $2")
+            .bind(&budget_pillid)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut steps = Vec::new();
+    for _ in 0..count {
+        let target = match direction {
+            Direction::Undo => next_undo_target(&mut tx, budget_uuid, user_id).await?,
+            Direction::Redo => next_redo_target(&mut tx, budget_uuid, user_id).await?,
+        };
+        let Some(txid) = target else {
+            break;
+        };
+        let step = revert_group(
+            &mut tx,
+            user_id,
+            budget_uuid,
+            &budget_pillid,
+            txid,
+            direction,
+        )
+        .await?;
+        steps.push(step);
+    }
+
+    // Every step is validated against the current rows before it is written,
+    // and the split cardinality trigger is deferred, so the invariant is
+    // checked here against the final state.
+    tx.commit().await.map_err(revert_status)?;
+    Ok(Json(UndoResponse { steps }))
+}
+
+/// Newest group of the user's own (or redone) writes that has not been undone.
+async fn next_undo_target(
+    conn: &mut PgConnection,
+    budget_uuid: Uuid,
+    user_id: Uuid,
+) -> Result<Option<i64>, StatusCode> {
+    let row: Option<(i64,)> = sqlx::query_as(
+        "select a.txid
+         from audit_log a
+         where a.budget_id = $1 and a.user_id = $2 and a.kind in ('do', 'redo')
+           and not exists (select 1 from audit_log u where u.kind = 'undo' and u.reverts_txid = a.txid)
+         group by a.txid
+         order by max(a.id) desc
+         limit 1",
+    )
+    .bind(budget_uuid)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(row.map(|r| r.0))
+}
+
+/// Most recently undone group that has not been redone, provided the user has
+/// made no new changes in the budget since undoing it.
+async fn next_redo_target(
+    conn: &mut PgConnection,
+    budget_uuid: Uuid,
+    user_id: Uuid,
+) -> Result<Option<i64>, StatusCode> {
+    let row: Option<(i64,)> = sqlx::query_as(
+        "select u.reverts_txid
+         from audit_log u
+         where u.budget_id = $1 and u.user_id = $2 and u.kind = 'undo'
+           and not exists (
+             select 1 from audit_log r
+             where r.kind = 'redo' and r.reverts_txid = u.reverts_txid and r.id > u.id
+           )
+           and not exists (
+             select 1 from audit_log d
+             where d.budget_id = u.budget_id and d.user_id = u.user_id and d.kind = 'do' and d.id > u.id
+           )
+         group by u.reverts_txid
+         order by max(u.id) desc
+         limit 1",
+    )
+    .bind(budget_uuid)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(row.map(|r| r.0))
+}
+
+async fn revert_group(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    budget_uuid: Uuid,
+    budget_pillid: &str,
+    txid: i64,
+    direction: Direction,
+) -> Result<UndoStep, StatusCode> {
+    let entries = sqlx::query_as::<_, AuditRow>(
+        "select entity, entity_pillid, before, after
+         from audit_log
+         where budget_id = $1 and txid = $2 and kind in ('do', 'redo')
+         order by id",
+    )
+    .bind(budget_uuid)
+    .bind(txid)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // What each entity must look like right now for the step to be safe: the
+    // group's own result for undo, the result of undoing it for redo.
+    let mut expected: HashMap<(String, String), Option<Value>> = HashMap::new();
+    let plan: Vec<(ChangeEntity, String, Option<Value>)> = match direction {
+        Direction::Undo => {
+            for entry in &entries {
+                expected.insert(
+                    (entry.entity.clone(), entry.entity_pillid.clone()),
+                    entry.after.clone(),
+                );
+            }
+            entries
+                .into_iter()
+                .rev()
+                .map(|e| Ok((parse_entity(&e.entity)?, e.entity_pillid, e.before)))
+                .collect::<Result<_, StatusCode>>()?
+        }
+        Direction::Redo => {
+            let undone = sqlx::query_as::<_, AuditRow>(
+                "select entity, entity_pillid, before, after
+                 from audit_log
+                 where budget_id = $1 and kind = 'undo' and reverts_txid = $2
+                 order by id",
+            )
+            .bind(budget_uuid)
+            .bind(txid)
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            for entry in undone {
+                expected.insert((entry.entity, entry.entity_pillid), entry.after);
+            }
+            entries
+                .into_iter()
+                .map(|e| Ok((parse_entity(&e.entity)?, e.entity_pillid, e.after)))
+                .collect::<Result<_, StatusCode>>()?
+        }
+    };
+
+    for ((entity, pillid), image) in &expected {
+        let entity = parse_entity(entity)?;
+        if comparable(snapshot(conn, entity, pillid).await?) != comparable(image.clone()) {
+            return Err(StatusCode::CONFLICT);
+        }
+    }
+
+    sqlx::query(
+        "select set_config('envelopezero.audit_kind', $1, true), set_config('envelopezero.reverts_txid', $2, true)",
+    )
+    .bind(direction.kind())
+    .bind(txid.to_string())
+    .execute(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut changes = Vec::with_capacity(plan.len());
+    for (entity, pillid, image) in plan {
+        let before = snapshot(conn, entity, &pillid).await?;
+        restore(conn, entity, &pillid, image.as_ref()).await?;
+        let op = restore_op(before.as_ref(), image.as_ref());
+        record_change(conn, user_id, budget_pillid, entity, &pillid, op, before).await?;
+        changes.push(StepChange {
+            entity: entity.as_str().to_string(),
+            entity_id: pillid,
+            op: op.as_str().to_string(),
+        });
+    }
+
+    Ok(UndoStep { changes })
+}
+
+fn parse_entity(value: &str) -> Result<ChangeEntity, StatusCode> {
+    ChangeEntity::parse(value).ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Drops `updated_at`, which every write (including a restore) bumps, so an
+/// entity that was undone and redone still matches its journaled image.
+fn comparable(image: Option<Value>) -> Option<Value> {
+    let mut image = image?;
+    if let Some(row) = image.as_object_mut() {
+        row.remove("updated_at");
+        if let Some(Value::Array(splits)) = row.get_mut("splits") {
+            for split in splits.iter_mut().filter_map(Value::as_object_mut) {
+                split.remove("updated_at");
+            }
+        }
+    }
+    Some(image)
+}
+
+fn is_live(image: Option<&Value>) -> bool {
+    image.is_some_and(|row| row.get("deleted_at").is_none_or(Value::is_null))
+}
+
+/// How a restore looks to sync clients and SSE listeners.
+fn restore_op(current: Option<&Value>, image: Option<&Value>) -> ChangeOp {
+    match (is_live(current), is_live(image)) {
+        (true, false) => ChangeOp::Delete,
+        (false, true) => ChangeOp::Create,
+        _ => ChangeOp::Update,
+    }
+}
+
+/// Puts a row back to `image`. `None` (the row did not exist before it was
+/// created) soft-deletes it, since other rows may already reference it.
+async fn restore(
+    conn: &mut PgConnection,
+    entity: ChangeEntity,
+    pillid: &str,
+    image: Option<&Value>,
+) -> Result<(), StatusCode> {
+    let Some(image) = image else {
+        let sql = format!(
+            "update {} set deleted_at = coalesce(deleted_at, now()), updated_at = now() where pillid = $1",
+            entity.table()
+        );
+        sqlx::query(&sql)
+            .bind(pillid)
+            .execute(&mut *conn)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        return Ok(());
+    };
+
+    let sql = match entity {
+        ChangeEntity::Budget => {
+            "update budgets set name = $2->>'name', currency_code = $2->>'currency_code', deleted_at = ($2->>'deleted_at')::timestamptz, updated_at = now()
+             where pillid = $1"
+        }
+        ChangeEntity::Account => {
+            "update accounts a set name = $2->>'name', budget_id = b.id, budget_pillid = b.pillid, closed_at = ($2->>'closed_at')::timestamptz, deleted_at = ($2->>'deleted_at')::timestamptz, updated_at = now()
+             from budgets b
+             where a.pillid = $1 and b.pillid = $2->>'budget_pillid' and b.user_id = a.user_id"
+        }
+        ChangeEntity::Supercategory => {
+            "update supercategories s set name = $2->>'name', budget_id = b.id, budget_pillid = b.pillid, deleted_at = ($2->>'deleted_at')::timestamptz, updated_at = now()
+             from budgets b
+             where s.pillid = $1 and b.pillid = $2->>'budget_pillid' and b.user_id = s.user_id"
+        }
+        ChangeEntity::Category => {
+            "update categories c set name = $2->>'name', budget_id = s.budget_id, budget_pillid = s.budget_pillid, supercategory_id = s.id, supercategory_pillid = s.pillid,
+                 is_transfer = coalesce(($2->>'is_transfer')::boolean, c.is_transfer),
+                 is_income = coalesce(($2->>'is_income')::boolean, c.is_income), deleted_at = ($2->>'deleted_at')::timestamptz, updated_at = now()
+             from supercategories s
+             where c.pillid = $1 and s.pillid = $2->>'supercategory_pillid' and s.user_id = c.user_id"
+        }
+        ChangeEntity::Transaction => {
+            "update transactions t set budget_id = a.budget_id, budget_pillid = a.budget_pillid, account_id = a.id, account_pillid = a.pillid,
+                 tx_date = ($2->>'tx_date')::date, payee = $2->>'payee', memo = $2->>'memo', deleted_at = ($2->>'deleted_at')::timestamptz, updated_at = now()
+             from accounts a
+             where t.pillid = $1 and a.pillid = $2->>'account_pillid' and a.user_id = t.user_id"
+        }
+        ChangeEntity::CategoryAssignment => {
+            "update category_assignments set amount = ($2->>'amount')::bigint, deleted_at = ($2->>'deleted_at')::timestamptz, updated_at = now()
+             where pillid = $1"
+        }
+        ChangeEntity::ScheduledTransaction => {
+            "update scheduled_transactions st set account_id = a.id, account_pillid = a.pillid, payee = $2->>'payee', memo = $2->>'memo',
+                 inflow = ($2->>'inflow')::bigint, outflow = ($2->>'outflow')::bigint, frequency = $2->>'frequency',
+                 next_date = ($2->>'next_date')::date, end_date = ($2->>'end_date')::date, deleted_at = ($2->>'deleted_at')::timestamptz, updated_at = now()
+             from accounts a
+             where st.pillid = $1 and a.pillid = $2->>'account_pillid' and a.budget_id = st.budget_id"
+        }
+        ChangeEntity::CategoryTarget => {
+            "update category_targets set monthly_amount = ($2->>'monthly_amount')::bigint, deleted_at = ($2->>'deleted_at')::timestamptz, updated_at = now()
+             where pillid = $1"
+        }
+    };
+    let updated = sqlx::query(sql)
+        .bind(pillid)
+        .bind(image)
+        .execute(&mut *conn)
+        .await
+        .map_err(revert_status)?;
+    if updated.rows_affected() == 0 {
+        // The row (or the parent it pointed at) has since been purged.
+        return Err(StatusCode::CONFLICT);
+    }
+
+    if let ChangeEntity::Transaction = entity {
+        restore_splits(conn, pillid, image).await?;
+    }
+    Ok(())
+}
+
+/// A revert that no longer fits the current rows trips a constraint or a
+/// trigger check (a closed month among them) and is a `409 Conflict`; any other
+/// failure is a server error.
+fn revert_status(err: sqlx::Error) -> StatusCode {
+    let code = err.as_database_error().and_then(|err| err.code());
+    match code.as_deref() {
+        Some(code) if code == MONTH_CLOSED || code.starts_with("23") || code.starts_with("P0") => {
+            StatusCode::CONFLICT
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Makes the active splits of a transaction exactly those in `image`, reviving
+/// soft-deleted split rows by pillid and re-inserting purged ones.
+async fn restore_splits(
+    conn: &mut PgConnection,
+    pillid: &str,
+    image: &Value,
+) -> Result<(), StatusCode> {
+    let splits = image
+        .get("splits")
+        .cloned()
+        .unwrap_or(Value::Array(Vec::new()));
+    sqlx::query(
+        "update transaction_splits ts set deleted_at = now(), updated_at = now()
+         from transactions t
+         where t.pillid = $1 and ts.transaction_id = t.id and ts.deleted_at is null
+           and ts.pillid not in (select s->>'pillid' from jsonb_array_elements($2) s)",
+    )
+    .bind(pillid)
+    .bind(&splits)
+    .execute(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let restored = sqlx::query(
+        "insert into transaction_splits (pillid, transaction_id, transaction_pillid, category_id, category_pillid, memo, inflow, outflow, account_inflow, account_outflow)
+         select s->>'pillid', t.id, t.pillid, c.id, c.pillid, s->>'memo', (s->>'inflow')::bigint, (s->>'outflow')::bigint,
+                (s->>'account_inflow')::bigint, (s->>'account_outflow')::bigint
+         from transactions t
+         cross join jsonb_array_elements($2) s
+         join categories c on c.pillid = s->>'category_pillid' and c.user_id = t.user_id
+         where t.pillid = $1
+         on conflict (pillid) do update set
+           category_id = excluded.category_id, category_pillid = excluded.category_pillid, memo = excluded.memo,
+           inflow = excluded.inflow, outflow = excluded.outflow, account_inflow = excluded.account_inflow,
+           account_outflow = excluded.account_outflow, deleted_at = null, updated_at = now()",
+    )
+    .bind(pillid)
+    .bind(&splits)
+    .execute(&mut *conn)
+    .await
+    .map_err(revert_status)?;
+    if restored.rows_affected() != splits.as_array().map_or(0, Vec::len) as u64 {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn restore_op_follows_deleted_at() {
+        let live = json!({ "name": "Rent", "deleted_at": null });
+        let deleted = json!({ "name": "Rent", "deleted_at": "2026-02-28T00:00:00Z" });
+
+        assert!(matches!(restore_op(Some(&live), None), ChangeOp::Delete));
+        assert!(matches!(
+            restore_op(Some(&live), Some(&deleted)),
+            ChangeOp::Delete
+        ));
+        assert!(matches!(
+            restore_op(Some(&deleted), Some(&live)),
+            ChangeOp::Create
+        ));
+        assert!(matches!(
+            restore_op(Some(&live), Some(&live)),
+            ChangeOp::Update
+        ));
+    }
+}
@@ -1,24 +1,42 @@
+use std::time::Duration;
+
 use axum::body::Body;
 use axum::http::Request;
 use axum::http::StatusCode;
+use chrono::Datelike;
+use chrono::NaiveDate;
+use envelopezero_api::admin;
+use envelopezero_api::fsck::run_fsck;
+use envelopezero_api::fx;
+use envelopezero_api::importers::ynab;
+use envelopezero_api::purge_change_events;
+use envelopezero_api::purge_idempotency_keys;
+use envelopezero_api::purge_trash;
 use envelopezero_api::router;
 use envelopezero_api::seed_dev_data;
 use envelopezero_api::AppState;
+use envelopezero_api::ChangeFeed;
+use http_body_util::BodyExt;
 use serde_json::json;
 use serde_json::Value;
 use sqlx::PgPool;
 use tower::ServiceExt;
 
 fn app_for(pool: PgPool) -> axum::Router {
+    app_with_multi_budget(pool, false)
+}
+
+fn app_with_multi_budget(pool: PgPool, feature_multi_budget: bool) -> axum::Router {
     router(AppState {
         db: pool,
         feature_passkeys: false,
-        feature_multi_budget: false,
+        feature_multi_budget,
         feature_assignments: true,
         app_origin: "http://localhost:8080".to_string(),
         smtp_host: "127.0.0.1".to_string(),
         smtp_port: 1025,
         smtp_from: "noreply@envelopezero.local".to_string(),
+        changes: ChangeFeed::default(),
     })
 }
 
@@ -217,3 +235,3282 @@ async fn transaction_rejects_invalid_split_shapes(pool: PgPool) {
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 }
+
+#[sqlx::test(migrations = "./migrations")]
+async fn idempotency_key_replays_transaction_create(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "idem@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+
+    let payload = json!({
+        "budget_id": budget_id,
+        "account_id": account_id,
+        "date": "2026-02-19",
+        "payee": "Grocer",
+        "memo": null,
+        "splits": [{"category_id": category_id, "inflow": 0, "outflow": 1250, "memo": null}]
+    });
+    let post = |body: Value, key: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/api/transactions")
+            .header("authorization", auth_header.clone())
+            .header("content-type", "application/json")
+            .header("idempotency-key", key)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let response = app
+            .clone()
+            .oneshot(post(payload.clone(), "retry-1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        ids.push(serde_json::from_slice::<Value>(&body).unwrap()["id"].clone());
+    }
+    assert_eq!(ids[0], ids[1]);
+
+    let (count,): (i64,) = sqlx::query_as("select count(*) from transactions")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+
+    let mut changed = payload.clone();
+    changed["payee"] = json!("Someone else");
+    let response = app.clone().oneshot(post(changed, "retry-1")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // A reservation of the same request blocks retries for as long as its
+    // heartbeat is fresh, however long ago it started.
+    let reserve = |key: &'static str,
+                   age: &'static str,
+                   beat: &'static str,
+                   expires: &'static str| {
+        let pool = pool.clone();
+        async move {
+            sqlx::query(
+                "insert into idempotency_keys (user_id, idempotency_key, request_method, request_path, request_hash, created_at, heartbeat_at, expires_at)
+                 select user_id, $1, request_method, request_path, request_hash, now() - $2::interval, now() - $3::interval, now() + $4::interval
+                 from idempotency_keys where idempotency_key = 'retry-1'",
+            )
+            .bind(key)
+            .bind(age)
+            .bind(beat)
+            .bind(expires)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+    };
+    reserve("in-flight", "1 second", "1 second", "1 day").await;
+    reserve("long-running", "10 minutes", "5 seconds", "1 day").await;
+    reserve("abandoned", "10 minutes", "10 minutes", "1 day").await;
+    for key in ["in-flight", "long-running"] {
+        let response = app
+            .clone()
+            .oneshot(post(payload.clone(), key))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+    let response = app
+        .clone()
+        .oneshot(post(payload.clone(), "abandoned"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Session tokens are never stored for replay.
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/auth/magic-link/request")
+                .header("content-type", "application/json")
+                .header("idempotency-key", "sign-in")
+                .body(Body::from(json!({"email": "idem@example.com"}).to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let (auth_keys,): (i64,) = sqlx::query_as(
+        "select count(*) from idempotency_keys where request_path like '/api/auth/%'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(auth_keys, 0);
+
+    reserve("expired", "2 days", "2 days", "-1 day").await;
+    assert_eq!(purge_idempotency_keys(&pool).await.unwrap(), 1);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn sync_returns_changes_since_cursor(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "sync@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, _category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+
+    let fetch_sync = |since: i64| {
+        Request::builder()
+            .uri(format!("/api/sync?since={since}"))
+            .header("authorization", auth_header.clone())
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(fetch_sync(0)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let full = serde_json::from_slice::<Value>(&body).unwrap();
+    assert_eq!(full["budgets"].as_array().unwrap().len(), 1);
+    assert_eq!(full["budgets"][0]["currency_code"], "USD");
+    assert_eq!(full["budgets"][0]["minor_unit"], 2);
+    assert_eq!(full["accounts"].as_array().unwrap().len(), 1);
+    assert_eq!(full["categories"].as_array().unwrap().len(), 1);
+    let cursor = full["server_knowledge"].as_i64().unwrap();
+
+    let req = Request::builder()
+        .method("DELETE")
+        .uri(format!("/api/accounts/{account_id}"))
+        .header("authorization", auth_header.clone())
+        .body(Body::empty())
+        .unwrap();
+    app.clone().oneshot(req).await.unwrap();
+
+    let response = app.oneshot(fetch_sync(cursor)).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let delta = serde_json::from_slice::<Value>(&body).unwrap();
+    assert!(delta["budgets"].as_array().unwrap().is_empty());
+    assert!(delta["categories"].as_array().unwrap().is_empty());
+    let accounts = delta["accounts"].as_array().unwrap();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts[0]["id"], json!(account_id));
+    assert!(!accounts[0]["deleted_at"].is_null());
+    assert!(delta["server_knowledge"].as_i64().unwrap() > cursor);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn sync_cursor_stays_below_uncommitted_writers(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "slow-writer@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, _) = bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let (_, full) = send_json(&app, "GET", "/api/sync", &auth_header, None).await;
+    let cursor = full["server_knowledge"].as_i64().unwrap();
+
+    // A slow writer takes its knowledge first and commits last.
+    let mut slow = pool.begin().await.unwrap();
+    let (slow_knowledge,): (i64,) = sqlx::query_as(
+        "update accounts set name = 'Renamed', updated_at = now() where pillid = $1 returning knowledge",
+    )
+    .bind(&account_id)
+    .fetch_one(&mut *slow)
+    .await
+    .unwrap();
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/supercategories",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Later"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, delta) = send_json(
+        &app,
+        "GET",
+        &format!("/api/sync?since={cursor}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(delta["supercategories"].as_array().unwrap().len(), 1);
+    assert!(delta["supercategories"][0]["knowledge"].as_i64().unwrap() > slow_knowledge);
+    assert!(delta["accounts"].as_array().unwrap().is_empty());
+    let next = delta["server_knowledge"].as_i64().unwrap();
+    assert!(next < slow_knowledge);
+
+    slow.commit().await.unwrap();
+    let (_, delta) = send_json(
+        &app,
+        "GET",
+        &format!("/api/sync?since={next}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    let accounts = delta["accounts"].as_array().unwrap();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts[0]["name"], json!("Renamed"));
+    // The supercategory comes back too; nothing is in flight any more.
+    assert_eq!(delta["supercategories"].as_array().unwrap().len(), 1);
+    let settled = delta["server_knowledge"].as_i64().unwrap();
+    assert!(settled > slow_knowledge);
+
+    // Advisory locks outside the reserved range, like sqlx's migration lock,
+    // leave the cursor alone.
+    let mut other = pool.acquire().await.unwrap();
+    sqlx::query("select pg_advisory_lock(-1), pg_advisory_lock(1, 1), pg_advisory_lock(-1, 0)")
+        .execute(&mut *other)
+        .await
+        .unwrap();
+    let (_, delta) = send_json(
+        &app,
+        "GET",
+        &format!("/api/sync?since={settled}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(delta["server_knowledge"].as_i64().unwrap(), settled);
+    sqlx::query("select pg_advisory_unlock_all()")
+        .execute(&mut *other)
+        .await
+        .unwrap();
+}
+
+async fn send_json(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    auth_header: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let builder = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("authorization", auth_header);
+    let req = match body {
+        Some(body) => builder
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+        None => builder.body(Body::empty()).unwrap(),
+    };
+    let response = app.clone().oneshot(req).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let value = serde_json::from_slice::<Value>(&body).unwrap_or(Value::Null);
+    (status, value)
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn offline_mutations_report_applied_conflict_and_rejected(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "offline@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let transaction = |payee: &str, memo: &str| {
+        json!({
+            "budget_id": budget_id,
+            "account_id": account_id,
+            "date": "2026-02-19",
+            "payee": payee,
+            "memo": memo,
+            "splits": [{"category_id": category_id, "inflow": 0, "outflow": 900, "memo": memo}]
+        })
+    };
+
+    let create = json!({"entity": "transaction", "op": "create", "id": "client-tx-1", "transaction": transaction("Cafe", "first")});
+    let (status, body) = send_json(
+        &app,
+        "POST",
+        "/api/sync/mutations",
+        &auth_header,
+        Some(json!({ "mutations": [create.clone(), create] })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["results"][0]["status"], json!("applied"));
+    assert_eq!(body["results"][1]["reason"], json!("already_applied"));
+    let base = body["results"][0]["knowledge"].as_i64().unwrap();
+
+    // Someone else edits the payee online after the client's base version.
+    let (status, _) = send_json(
+        &app,
+        "PUT",
+        "/api/transactions/client-tx-1",
+        &auth_header,
+        Some(transaction("Coffee Shop", "first")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = send_json(
+        &app,
+        "POST",
+        "/api/sync/mutations",
+        &auth_header,
+        Some(json!({ "mutations": [
+            {"entity": "transaction", "op": "update", "id": "client-tx-1", "base_knowledge": base, "transaction": transaction("Cafe", "second")},
+            {"entity": "transaction", "op": "update", "id": "client-tx-1", "base_knowledge": base, "transaction": transaction("Coffee Shop", "memo wins")},
+        ] })),
+    )
+    .await;
+    assert_eq!(body["results"][0]["status"], json!("conflict"));
+    assert_eq!(body["results"][1]["status"], json!("applied"));
+
+    let (memo,): (Option<String>,) =
+        sqlx::query_as("select memo from transactions where pillid = 'client-tx-1'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(memo.as_deref(), Some("memo wins"));
+
+    // Transactions stay in their budget.
+    let mut moved = transaction("Coffee Shop", "memo wins");
+    moved["budget_id"] = json!("another-budget");
+    let (_, body) = send_json(
+        &app,
+        "POST",
+        "/api/sync/mutations",
+        &auth_header,
+        Some(json!({ "mutations": [
+            {"entity": "transaction", "op": "update", "id": "client-tx-1", "base_knowledge": i64::MAX, "transaction": moved},
+        ] })),
+    )
+    .await;
+    assert_eq!(body["results"][0]["status"], json!("rejected"));
+    assert_eq!(body["results"][0]["reason"], json!("budget_changed"));
+
+    sqlx::query("update transactions set reconciled_at = now() where pillid = 'client-tx-1'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let (_, body) = send_json(
+        &app,
+        "POST",
+        "/api/sync/mutations",
+        &auth_header,
+        Some(json!({ "mutations": [
+            {"entity": "transaction", "op": "delete", "id": "client-tx-1", "base_knowledge": i64::MAX},
+        ] })),
+    )
+    .await;
+    assert_eq!(body["results"][0]["status"], json!("rejected"));
+    assert_eq!(body["results"][0]["reason"], json!("reconciled"));
+    // Online edits are held to the same rule.
+    let (status, _) = send_json(
+        &app,
+        "PUT",
+        "/api/transactions/client-tx-1",
+        &auth_header,
+        Some(transaction("Coffee Shop", "online")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send_json(
+        &app,
+        "DELETE",
+        "/api/transactions/client-tx-1",
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // A server failure is not the mutation's fault: the batch fails as a
+    // whole, so the client keeps its queue.
+    sqlx::query(
+        "create function fail_serialization() returns trigger as $$
+         begin
+           if new.payee = 'Busy' then
+             raise exception 'could not serialize access' using errcode = 'serialization_failure';
+           end if;
+           return new;
+         end;
+         $$ language plpgsql",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "create trigger fail_serialization before insert on transactions
+         for each row execute function fail_serialization()",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/sync/mutations",
+        &auth_header,
+        Some(json!({ "mutations": [
+            {"entity": "transaction", "op": "create", "id": "client-tx-2", "transaction": transaction("Bakery", "fine")},
+            {"entity": "transaction", "op": "create", "id": "client-tx-3", "transaction": transaction("Busy", "retry")},
+        ] })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    let (created,): (i64,) =
+        sqlx::query_as("select count(*) from transactions where pillid = 'client-tx-2'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(created, 0);
+}
+
+async fn next_sse_event(events: &mut Body) -> String {
+    let frame = tokio::time::timeout(Duration::from_secs(5), events.frame())
+        .await
+        .expect("event within timeout")
+        .unwrap()
+        .unwrap();
+    String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap()
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn budget_events_replay_then_stream_live_changes(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "events@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, _category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+
+    let req = Request::builder()
+        .uri(format!("/api/budgets/{budget_id}/events"))
+        .header("authorization", auth_header.clone())
+        .header("last-event-id", "0")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut events = response.into_body();
+
+    // Replayed backlog: account, supercategory, category.
+    let first = next_sse_event(&mut events).await;
+    assert!(first.contains("event: change"));
+    assert!(first.contains(&format!("\"entity_id\":\"{account_id}\"")));
+    next_sse_event(&mut events).await;
+    next_sse_event(&mut events).await;
+
+    let (status, created) = send_json(
+        &app,
+        "POST",
+        "/api/accounts",
+        &auth_header,
+        Some(json!({ "name": "Savings", "budget_id": budget_id })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let live = next_sse_event(&mut events).await;
+    assert!(live.contains(&format!(
+        "\"entity_id\":\"{}\"",
+        created["id"].as_str().unwrap()
+    )));
+    assert!(live.contains("\"op\":\"create\""));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn budget_events_open_with_a_short_lived_ticket(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "ticket@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, _) = bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+
+    let (status, minted) =
+        send_json(&app, "POST", "/api/auth/event-ticket", &auth_header, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let ticket = minted["ticket"].as_str().unwrap().to_string();
+    assert!(minted["expires_at"].is_string());
+    let open = |query: String| {
+        app.clone().oneshot(
+            Request::builder()
+                .uri(format!("/api/budgets/{budget_id}/events?{query}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+
+    // No header at all: the ticket authenticates, the query resumes.
+    let response = open(format!("ticket={ticket}&last_event_id=0"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut events = response.into_body();
+    let first = next_sse_event(&mut events).await;
+    assert!(first.contains(&format!("\"entity_id\":\"{account_id}\"")));
+    // Reusable until it expires, so the browser can reconnect with it.
+    let response = open(format!("ticket={ticket}")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = open("ticket=forged".to_string()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    sqlx::query("update event_tickets set expires_at = now() - interval '1 second'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let response = open(format!("ticket={ticket}")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Minting clears the caller's expired tickets, and a ticket goes with the
+    // session that minted it.
+    let (_, minted) = send_json(&app, "POST", "/api/auth/event-ticket", &auth_header, None).await;
+    let (tickets,): (i64,) = sqlx::query_as("select count(*) from event_tickets")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tickets, 1);
+    sqlx::query("update sessions set revoked_at = now()")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let response = open(format!("ticket={}", minted["ticket"].as_str().unwrap()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn budget_events_wait_for_slower_writers_and_reset_after_purge(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "slow-events@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let open_stream = |last_event_id: Option<&str>| {
+        let mut req = Request::builder()
+            .uri(format!("/api/budgets/{budget_id}/events"))
+            .header("authorization", auth_header.clone());
+        if let Some(id) = last_event_id {
+            req = req.header("last-event-id", id);
+        }
+        app.clone().oneshot(req.body(Body::empty()).unwrap())
+    };
+    let mut events = open_stream(None).await.unwrap().into_body();
+
+    // A slow writer takes its change id first and commits last, without
+    // notifying; the faster change behind it is held back until it commits.
+    let mut slow = pool.begin().await.unwrap();
+    sqlx::query(
+        "insert into change_events (budget_id, budget_pillid, entity, entity_pillid, op)
+         select id, pillid, 'budget', pillid, 'update' from budgets where pillid = $1",
+    )
+    .bind(&budget_id)
+    .execute(&mut *slow)
+    .await
+    .unwrap();
+    let (status, created) = send_json(
+        &app,
+        "POST",
+        "/api/accounts",
+        &auth_header,
+        Some(json!({ "name": "Savings", "budget_id": budget_id })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), events.frame())
+            .await
+            .is_err()
+    );
+    slow.commit().await.unwrap();
+    let first = next_sse_event(&mut events).await;
+    assert!(first.contains(&format!("\"entity_id\":\"{budget_id}\"")));
+    let second = next_sse_event(&mut events).await;
+    assert!(second.contains(&format!(
+        "\"entity_id\":\"{}\"",
+        created["id"].as_str().unwrap()
+    )));
+
+    sqlx::query("update change_events set created_at = now() - interval '60 days'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(purge_change_events(&pool).await.unwrap(), 2);
+    let mut resumed = open_stream(Some("0")).await.unwrap().into_body();
+    let reset = next_sse_event(&mut resumed).await;
+    assert!(reset.contains("event: reset"));
+
+    // The reset's id is the purge horizon, so the browser's own reconnect
+    // resumes from there.
+    let horizon = reset
+        .lines()
+        .find_map(|line| line.strip_prefix("id: "))
+        .expect("reset carries an id")
+        .to_string();
+    assert!(second.contains(&format!("id: {horizon}\n")));
+    let mut reconnected = open_stream(Some(&horizon)).await.unwrap().into_body();
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/accounts",
+        &auth_header,
+        Some(json!({ "name": "Checking", "budget_id": budget_id })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(next_sse_event(&mut reconnected)
+        .await
+        .contains("event: change"));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn audit_log_records_before_and_after_images(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "audit@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let transaction = |payee: &str| {
+        json!({
+            "budget_id": budget_id,
+            "account_id": account_id,
+            "date": "2026-02-19",
+            "payee": payee,
+            "memo": null,
+            "splits": [{"category_id": category_id, "inflow": 0, "outflow": 700, "memo": null}]
+        })
+    };
+
+    let (_, created) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(transaction("Before")),
+    )
+    .await;
+    let tx_id = created["id"].as_str().unwrap().to_string();
+    send_json(
+        &app,
+        "PUT",
+        &format!("/api/transactions/{tx_id}"),
+        &auth_header,
+        Some(transaction("After")),
+    )
+    .await;
+    send_json(
+        &app,
+        "DELETE",
+        &format!("/api/transactions/{tx_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+
+    let (status, entries) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/audit?entity_id={tx_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let entries = entries.as_array().unwrap();
+    let ops: Vec<&str> = entries.iter().map(|e| e["op"].as_str().unwrap()).collect();
+    assert_eq!(ops, ["delete", "update", "create"]);
+    assert!(entries[2]["before"].is_null());
+    assert_eq!(entries[1]["before"]["payee"], json!("Before"));
+    assert_eq!(entries[1]["after"]["payee"], json!("After"));
+    assert_eq!(entries[1]["after"]["splits"][0]["outflow"], json!(700));
+    assert!(entries[1]["after"].get("user_id").is_none());
+    assert!(!entries[0]["after"]["deleted_at"].is_null());
+
+    let (_, accounts) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/audit?entity=account"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(accounts.as_array().unwrap().len(), 1);
+
+    for sql in [
+        "delete from audit_log",
+        "truncate audit_log",
+        "update audit_log set before = null",
+        "update audit_log set budget_id = null",
+    ] {
+        assert!(sqlx::query(sql).execute(&pool).await.is_err(), "{sql}");
+    }
+    // Purging the user detaches its history rather than erasing it.
+    let (count,): (i64,) = sqlx::query_as("select count(*) from audit_log")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    sqlx::query("delete from users")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let (kept, detached): (i64, i64) = sqlx::query_as(
+        "select count(*), count(*) filter (where user_id is null and budget_id is null and budget_pillid = $1)
+         from audit_log",
+    )
+    .bind(&budget_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((kept, detached), (count, count));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn undo_and_redo_revert_recent_mutations(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "undo@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let transaction = |payee: &str, outflow: i64| {
+        json!({
+            "budget_id": budget_id,
+            "account_id": account_id,
+            "date": "2026-02-19",
+            "payee": payee,
+            "memo": null,
+            "splits": [{"category_id": category_id, "inflow": 0, "outflow": outflow, "memo": null}]
+        })
+    };
+    let payee_of = |tx_id: String| {
+        let app = app.clone();
+        let auth_header = auth_header.clone();
+        async move {
+            let (_, list) = send_json(&app, "GET", "/api/transactions", &auth_header, None).await;
+            list.as_array()
+                .unwrap()
+                .iter()
+                .find(|t| t["id"] == json!(tx_id))
+                .map(|t| (t["payee"].clone(), t["splits"][0]["outflow"].clone()))
+        }
+    };
+
+    let (_, created) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(transaction("Grocer", 700)),
+    )
+    .await;
+    let tx_id = created["id"].as_str().unwrap().to_string();
+    send_json(
+        &app,
+        "PUT",
+        &format!("/api/transactions/{tx_id}"),
+        &auth_header,
+        Some(transaction("Typo", 9000)),
+    )
+    .await;
+    send_json(
+        &app,
+        "DELETE",
+        &format!("/api/transactions/{tx_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(payee_of(tx_id.clone()).await, None);
+
+    let undo = format!("/api/budgets/{budget_id}/undo");
+    let redo = format!("/api/budgets/{budget_id}/redo");
+    let (status, body) =
+        send_json(&app, "POST", &format!("{undo}?count=2"), &auth_header, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["steps"].as_array().unwrap().len(), 2);
+    assert_eq!(body["steps"][0]["changes"][0]["op"], json!("create"));
+    assert_eq!(
+        payee_of(tx_id.clone()).await,
+        Some((json!("Grocer"), json!(700)))
+    );
+
+    let (status, _) = send_json(&app, "POST", &redo, &auth_header, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        payee_of(tx_id.clone()).await,
+        Some((json!("Typo"), json!(9000)))
+    );
+
+    // A new edit discards the rest of the redo stack.
+    send_json(
+        &app,
+        "PUT",
+        &format!("/api/transactions/{tx_id}"),
+        &auth_header,
+        Some(transaction("Fixed", 650)),
+    )
+    .await;
+    let (status, body) = send_json(&app, "POST", &redo, &auth_header, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["steps"].as_array().unwrap().is_empty());
+
+    // Undoing the new edit, then the redone one, walks back to the original.
+    let (status, _) = send_json(&app, "POST", &format!("{undo}?count=2"), &auth_header, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        payee_of(tx_id.clone()).await,
+        Some((json!("Grocer"), json!(700)))
+    );
+
+    // An entity changed outside the history blocks undoing over it.
+    sqlx::query("update transactions set payee = 'Elsewhere' where pillid = $1")
+        .bind(&tx_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let (status, _) = send_json(&app, "POST", &undo, &auth_header, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // A write that fails for any other reason is a server error, not a 409.
+    sqlx::query("update transactions set payee = 'Grocer' where pillid = $1")
+        .bind(&tx_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query(
+        "create function fail_cast() returns trigger as $$
+         begin
+           raise exception 'invalid input syntax' using errcode = 'invalid_text_representation';
+         end;
+         $$ language plpgsql",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "create trigger fail_cast before update on transactions
+         for each row execute function fail_cast()",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let (status, _) = send_json(&app, "POST", &undo, &auth_header, None).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    sqlx::query("drop trigger fail_cast on transactions")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let (status, _) = send_json(&app, "POST", &undo, &auth_header, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(payee_of(tx_id).await, None);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn trash_lists_restores_and_purges_deleted_rows(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "trash@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let (_, categories) = send_json(&app, "GET", "/api/categories", &auth_header, None).await;
+    let supercategory_id = categories[0]["supercategory_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (_, created) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(json!({
+            "budget_id": budget_id,
+            "account_id": account_id,
+            "date": "2026-02-19",
+            "payee": "Grocer",
+            "memo": null,
+            "splits": [{"category_id": category_id, "inflow": 0, "outflow": 700, "memo": null}]
+        })),
+    )
+    .await;
+    let tx_id = created["id"].as_str().unwrap().to_string();
+    // The trashed transaction still uses the category, so it moves elsewhere.
+    let (_, other) = send_json(
+        &app,
+        "POST",
+        "/api/supercategories",
+        &auth_header,
+        Some(json!({ "name": "Other", "budget_id": budget_id })),
+    )
+    .await;
+    let (_, spare) = send_json(
+        &app,
+        "POST",
+        "/api/categories",
+        &auth_header,
+        Some(json!({ "name": "Spare", "budget_id": budget_id, "supercategory_id": other["id"] })),
+    )
+    .await;
+    let spare_id = spare["id"].as_str().unwrap();
+    for uri in [
+        format!("/api/transactions/{tx_id}"),
+        format!("/api/categories/{category_id}?reassign_to={spare_id}"),
+        format!("/api/supercategories/{supercategory_id}"),
+    ] {
+        let (status, _) = send_json(&app, "DELETE", &uri, &auth_header, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+    // Splits soft-deleted along with their transaction come back on restore.
+    sqlx::query("update transaction_splits set deleted_at = (select deleted_at from transactions where pillid = $1) where transaction_pillid = $1")
+        .bind(&tx_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let (status, trash) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/trash"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let mut entities: Vec<&str> = trash
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["entity"].as_str().unwrap())
+        .collect();
+    entities.sort_unstable();
+    assert_eq!(entities, ["category", "supercategory", "transaction"]);
+
+    let restore =
+        |entity: &str, id: &str| format!("/api/budgets/{budget_id}/trash/{entity}/{id}/restore");
+    let (status, restored) = send_json(
+        &app,
+        "POST",
+        &restore("category", &category_id),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(restored["warnings"], json!(["supercategory_deleted"]));
+
+    let (status, restored) = send_json(
+        &app,
+        "POST",
+        &restore("transaction", &tx_id),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(restored["warnings"], json!([]));
+    let (_, transactions) = send_json(&app, "GET", "/api/transactions", &auth_header, None).await;
+    assert_eq!(transactions[0]["id"], json!(tx_id));
+    assert_eq!(transactions[0]["splits"][0]["outflow"], json!(700));
+
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        &restore("transaction", &tx_id),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // The supercategory still has a live category, so purging keeps it.
+    assert_eq!(
+        purge_trash(&pool, chrono::Duration::zero()).await.unwrap(),
+        0
+    );
+    send_json(
+        &app,
+        "DELETE",
+        &format!("/api/transactions/{tx_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(
+        purge_trash(&pool, chrono::Duration::days(1)).await.unwrap(),
+        0
+    );
+    assert_eq!(
+        purge_trash(&pool, chrono::Duration::zero()).await.unwrap(),
+        1
+    );
+    let (_, trash) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/trash"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(trash.as_array().unwrap().len(), 1);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn deletes_refuse_live_references_unless_reassigned(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "reassign@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, groceries_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let (_, categories) = send_json(&app, "GET", "/api/categories", &auth_header, None).await;
+    let needs_id = categories[0]["supercategory_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let (_, dining) = send_json(
+        &app,
+        "POST",
+        "/api/categories",
+        &auth_header,
+        Some(json!({ "name": "Dining", "budget_id": budget_id, "supercategory_id": needs_id })),
+    )
+    .await;
+    let dining_id = dining["id"].as_str().unwrap().to_string();
+    let transaction = json!({
+        "budget_id": budget_id,
+        "account_id": account_id,
+        "date": "2026-02-19",
+        "payee": "Grocer",
+        "memo": null,
+        "splits": [{"category_id": groceries_id, "inflow": 0, "outflow": 700, "memo": null}]
+    });
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(transaction.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    for (category_id, amount) in [(&groceries_id, 300), (&dining_id, 200)] {
+        let (status, _) = send_json(
+            &app,
+            "POST",
+            "/api/category-assignments",
+            &auth_header,
+            Some(json!({ "budget_id": budget_id, "category_id": category_id, "month": "2026-02", "amount": amount })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, body) = send_json(
+        &app,
+        "DELETE",
+        &format!("/api/categories/{groceries_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], json!("category_in_use"));
+    assert_eq!(body["transactions"], json!(1));
+    assert_eq!(body["assignments"], json!(1));
+
+    let (status, _) = send_json(
+        &app,
+        "DELETE",
+        &format!("/api/categories/{groceries_id}?reassign_to={groceries_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send_json(
+        &app,
+        "DELETE",
+        &format!("/api/categories/{groceries_id}?reassign_to={dining_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, transactions) = send_json(&app, "GET", "/api/transactions", &auth_header, None).await;
+    assert_eq!(
+        transactions[0]["splits"][0]["category_id"],
+        json!(dining_id)
+    );
+    let (_, assignments) =
+        send_json(&app, "GET", "/api/category-assignments", &auth_header, None).await;
+    assert_eq!(assignments.as_array().unwrap().len(), 1);
+    assert_eq!(assignments[0]["category_id"], json!(dining_id));
+    assert_eq!(assignments[0]["amount"], json!(500));
+
+    // The reassignment and the delete are one undo step.
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        &format!("/api/budgets/{budget_id}/undo"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, transactions) = send_json(&app, "GET", "/api/transactions", &auth_header, None).await;
+    assert_eq!(
+        transactions[0]["splits"][0]["category_id"],
+        json!(groceries_id)
+    );
+    let (_, assignments) =
+        send_json(&app, "GET", "/api/category-assignments", &auth_header, None).await;
+    assert_eq!(assignments.as_array().unwrap().len(), 2);
+
+    let (status, body) = send_json(
+        &app,
+        "DELETE",
+        &format!("/api/supercategories/{needs_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["categories"], json!(2));
+    let (_, wants) = send_json(
+        &app,
+        "POST",
+        "/api/supercategories",
+        &auth_header,
+        Some(json!({ "name": "Wants", "budget_id": budget_id })),
+    )
+    .await;
+    let wants_id = wants["id"].as_str().unwrap();
+    let (status, _) = send_json(
+        &app,
+        "DELETE",
+        &format!("/api/supercategories/{needs_id}?reassign_to={wants_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, categories) = send_json(&app, "GET", "/api/categories", &auth_header, None).await;
+    assert!(categories
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|c| c["supercategory_id"] == json!(wants_id)));
+
+    let (status, body) = send_json(
+        &app,
+        "DELETE",
+        &format!("/api/accounts/{account_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], json!("account_has_transactions"));
+    let (status, closed) = send_json(
+        &app,
+        "POST",
+        &format!("/api/accounts/{account_id}/close"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!closed["closed_at"].is_null());
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(transaction.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Nothing moves into, or is edited in, a closed account either.
+    let (_, savings) = send_json(
+        &app,
+        "POST",
+        "/api/accounts",
+        &auth_header,
+        Some(json!({ "name": "Savings", "budget_id": budget_id })),
+    )
+    .await;
+    let mut in_savings = transaction.clone();
+    in_savings["account_id"] = savings["id"].clone();
+    in_savings["splits"][0]["category_id"] = json!(dining_id);
+    let (_, moved) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(in_savings.clone()),
+    )
+    .await;
+    let moved_id = moved["id"].as_str().unwrap();
+    let (status, _) = send_json(
+        &app,
+        "PUT",
+        &format!("/api/transactions/{moved_id}"),
+        &auth_header,
+        Some(transaction.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, knowledge): (String, i64) =
+        sqlx::query_as("select pillid, knowledge from transactions where pillid = $1")
+            .bind(moved_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let (_, body) = send_json(
+        &app,
+        "POST",
+        "/api/sync/mutations",
+        &auth_header,
+        Some(json!({ "mutations": [
+            {"entity": "transaction", "op": "update", "id": moved_id, "base_knowledge": knowledge, "transaction": transaction},
+        ] })),
+    )
+    .await;
+    assert_eq!(body["results"][0]["status"], json!("rejected"));
+    assert_eq!(body["results"][0]["reason"], json!("invalid"));
+
+    // A transaction in the trash still uses its category and account.
+    let (status, _) = send_json(
+        &app,
+        "DELETE",
+        &format!("/api/transactions/{moved_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, body) = send_json(
+        &app,
+        "DELETE",
+        &format!("/api/categories/{dining_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["transactions"], json!(0));
+    assert_eq!(body["trashed_transactions"], json!(1));
+    let (status, body) = send_json(
+        &app,
+        "DELETE",
+        &format!("/api/accounts/{}", savings["id"].as_str().unwrap()),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["trashed_transactions"], json!(1));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn schema_rejects_rows_that_cross_budgets(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "consistency@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let (_, created) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(json!({
+            "budget_id": budget_id,
+            "account_id": account_id,
+            "date": "2026-02-19",
+            "payee": "Grocer",
+            "memo": null,
+            "splits": [{"category_id": category_id, "inflow": 0, "outflow": 700, "memo": null}]
+        })),
+    )
+    .await;
+    let tx_id = created["id"].as_str().unwrap().to_string();
+
+    let other_budget: (uuid::Uuid,) = sqlx::query_as(
+        "insert into budgets (user_id, user_pillid, name)
+         select user_id, user_pillid, 'Other' from budgets where pillid = $1
+         returning id",
+    )
+    .bind(&budget_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    let other_category: (uuid::Uuid,) = sqlx::query_as(
+        "with s as (
+           insert into supercategories (user_id, user_pillid, budget_id, budget_pillid, name)
+           select b.user_id, b.user_pillid, b.id, b.pillid, 'Elsewhere' from budgets b where b.id = $1
+           returning *
+         )
+         insert into categories (user_id, user_pillid, budget_id, budget_pillid, supercategory_id, supercategory_pillid, name)
+         select user_id, user_pillid, budget_id, budget_pillid, id, pillid, 'Elsewhere' from s
+         returning id",
+    )
+    .bind(other_budget.0)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+
+    // A split may not use a category from another budget.
+    let err =
+        sqlx::query("update transaction_splits set category_id = $2 where transaction_pillid = $1")
+            .bind(&tx_id)
+            .bind(other_category.0)
+            .execute(&pool)
+            .await
+            .unwrap_err();
+    assert!(err.to_string().contains("same budget"));
+
+    // A category may not point at a supercategory in another budget.
+    let err = sqlx::query("update categories set budget_id = $2 where pillid = $1")
+        .bind(&category_id)
+        .bind(other_budget.0)
+        .execute(&pool)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("foreign key"));
+
+    // A transaction may not sit in a different budget than its account.
+    let err = sqlx::query("update transactions set budget_id = $2 where pillid = $1")
+        .bind(&tx_id)
+        .bind(other_budget.0)
+        .execute(&pool)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("foreign key"));
+
+    // Through the API, moving a row to another budget is refused outright.
+    let (other_budget_id,): (String,) = sqlx::query_as("select pillid from budgets where id = $1")
+        .bind(other_budget.0)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let (_, supercategories) =
+        send_json(&app, "GET", "/api/supercategories", &auth_header, None).await;
+    let supercategory_id = supercategories[0]["id"].as_str().unwrap();
+    for (uri, body) in [
+        (
+            format!("/api/accounts/{account_id}"),
+            json!({"budget_id": other_budget_id, "name": "Moved"}),
+        ),
+        (
+            format!("/api/supercategories/{supercategory_id}"),
+            json!({"budget_id": other_budget_id, "name": "Moved"}),
+        ),
+        (
+            format!("/api/categories/{category_id}"),
+            json!({"budget_id": other_budget_id, "supercategory_id": supercategory_id, "name": "Moved"}),
+        ),
+        (
+            format!("/api/transactions/{tx_id}"),
+            json!({
+                "budget_id": other_budget_id,
+                "account_id": account_id,
+                "date": "2026-02-19",
+                "payee": "Grocer",
+                "memo": null,
+                "splits": [{"category_id": category_id, "inflow": 0, "outflow": 700, "memo": null}]
+            }),
+        ),
+    ] {
+        let (status, _) = send_json(&app, "PUT", &uri, &auth_header, Some(body)).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{uri}");
+    }
+    let (status, _) = send_json(
+        &app,
+        "PUT",
+        &format!("/api/accounts/{account_id}"),
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Renamed"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn fsck_reports_and_fixes_inconsistencies(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "fsck@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(json!({
+            "budget_id": budget_id,
+            "account_id": account_id,
+            "date": "2026-02-19",
+            "payee": "Grocer",
+            "memo": null,
+            "splits": [{"category_id": category_id, "inflow": 0, "outflow": 700, "memo": null}]
+        })),
+    )
+    .await;
+    let clean = run_fsck(&pool, false).await.unwrap();
+    assert_eq!(clean.outstanding(), 0);
+
+    // What the handlers no longer allow, but older data may contain.
+    sqlx::query("update categories set deleted_at = now() where pillid = $1")
+        .bind(&category_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("update transactions set account_pillid = 'stale'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let report = run_fsck(&pool, false).await.unwrap();
+    let found: Vec<&str> = report
+        .findings
+        .iter()
+        .filter(|f| f.count > 0)
+        .map(|f| f.name.as_str())
+        .collect();
+    assert_eq!(
+        found,
+        [
+            "stale_pillid:transactions.account_pillid",
+            "splits_on_deleted_categories"
+        ]
+    );
+    assert_eq!(report.outstanding(), 2);
+
+    let fixed = run_fsck(&pool, true).await.unwrap();
+    assert_eq!(fixed.outstanding(), 0);
+    assert_eq!(run_fsck(&pool, false).await.unwrap().outstanding(), 0);
+    let (_, categories) = send_json(&app, "GET", "/api/categories", &auth_header, None).await;
+    assert_eq!(categories[0]["id"], json!(category_id));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_commands_manage_users_and_move_budgets(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "admin@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(json!({
+            "budget_id": budget_id,
+            "account_id": account_id,
+            "date": "2026-02-19",
+            "payee": "Grocer",
+            "memo": "weekly",
+            "splits": [
+                {"category_id": category_id, "inflow": 0, "outflow": 700, "memo": null},
+                {"category_id": category_id, "inflow": 0, "outflow": 300, "memo": "snacks"}
+            ]
+        })),
+    )
+    .await;
+
+    let link = admin::issue_login_link(&pool, "http://localhost:8080", "Admin@Example.com")
+        .await
+        .unwrap();
+    assert!(link.starts_with("http://localhost:8080/?token="));
+    assert_eq!(
+        admin::revoke_sessions(&pool, "admin@example.com")
+            .await
+            .unwrap(),
+        1
+    );
+    let (status, _) = send_json(&app, "GET", "/api/budgets", &auth_header, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let archive = admin::export_budget_archive(&pool, &budget_id)
+        .await
+        .unwrap();
+    let json = serde_json::to_value(&archive).unwrap();
+    assert_eq!(json["format"], json!("envelopezero-budget"));
+    assert_eq!(
+        json["transactions"][0]["splits"].as_array().unwrap().len(),
+        2
+    );
+
+    let user = admin::create_user(&pool, "copy@example.com").await.unwrap();
+    assert_eq!(
+        admin::create_user(&pool, "copy@example.com").await.unwrap(),
+        user
+    );
+    let copy_id = admin::import_budget_archive(&pool, "copy@example.com", &archive)
+        .await
+        .unwrap();
+    // The original still exists here, so the copy gets fresh ids.
+    assert_ne!(copy_id, budget_id);
+    let copy = admin::export_budget_archive(&pool, &copy_id).await.unwrap();
+    let copy = serde_json::to_value(&copy).unwrap();
+    assert_ne!(copy["transactions"][0]["id"], json["transactions"][0]["id"]);
+    for key in ["accounts", "supercategories", "categories", "transactions"] {
+        assert_eq!(
+            copy[key].as_array().unwrap().len(),
+            json[key].as_array().unwrap().len()
+        );
+    }
+    assert_eq!(copy["transactions"][0]["memo"], json!("weekly"));
+    assert_eq!(
+        copy["transactions"][0]["splits"][1]["memo"],
+        json!("snacks")
+    );
+    assert_eq!(run_fsck(&pool, false).await.unwrap().outstanding(), 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn budgets_export_and_import_over_http(pool: PgPool) {
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "export@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    for payee in ["Landlord", "Grocer", "Landlord"] {
+        send_json(
+            &app,
+            "POST",
+            "/api/transactions",
+            &auth_header,
+            Some(json!({
+                "budget_id": budget_id,
+                "account_id": account_id,
+                "date": "2026-02-19",
+                "payee": payee,
+                "memo": "private",
+                "splits": [{"category_id": category_id, "inflow": 0, "outflow": 1200, "memo": null}]
+            })),
+        )
+        .await;
+    }
+    send_json(
+        &app,
+        "POST",
+        &format!("/api/budgets/{budget_id}/scheduled"),
+        &auth_header,
+        Some(json!({
+            "account_id": account_id,
+            "payee": "Landlord",
+            "memo": "private",
+            "outflow": 90000,
+            "frequency": "monthly",
+            "next_date": "2026-03-01",
+        })),
+    )
+    .await;
+    send_json(
+        &app,
+        "PUT",
+        &format!("/api/budgets/{budget_id}/targets/{category_id}"),
+        &auth_header,
+        Some(json!({"monthly_amount": 5000})),
+    )
+    .await;
+
+    let export_uri = format!("/api/budgets/{budget_id}/export");
+    let (status, archive) = send_json(&app, "GET", &export_uri, &auth_header, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(archive["version"], json!(2));
+    assert_eq!(archive["transactions"].as_array().unwrap().len(), 3);
+    assert_eq!(
+        archive["scheduled_transactions"][0]["account_id"],
+        json!(account_id)
+    );
+    assert_eq!(
+        archive["category_targets"][0]["category_id"],
+        json!(category_id)
+    );
+
+    let (status, fixture) = send_json(
+        &app,
+        "GET",
+        &format!("{export_uri}?anonymize=true"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(fixture["budget"]["id"], archive["budget"]["id"]);
+    let payees: Vec<_> = fixture["transactions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| (t["payee"].clone(), t["memo"].clone()))
+        .collect();
+    assert_eq!(
+        payees,
+        [
+            (json!("Payee 1"), Value::Null),
+            (json!("Payee 2"), Value::Null),
+            (json!("Payee 1"), Value::Null)
+        ]
+    );
+    assert_eq!(
+        fixture["scheduled_transactions"][0]["payee"],
+        json!("Payee 1")
+    );
+    assert_eq!(fixture["scheduled_transactions"][0]["memo"], Value::Null);
+
+    let (app, other_token, _) = bootstrap_auth(app, "other@example.com").await;
+    let other_header = format!("Bearer {other_token}");
+    let (status, _) = send_json(&app, "GET", &export_uri, &other_header, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Without multi-budget an import takes the one slot next to the default
+    // budget, shared with created budgets.
+    let (status, imported) = send_json(
+        &app,
+        "POST",
+        "/api/budgets/import",
+        &other_header,
+        Some(fixture.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/budgets/import",
+        &other_header,
+        Some(fixture.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/budgets",
+        &other_header,
+        Some(json!({"name": "Second"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    // Deleted budgets do not count against the limit.
+    sqlx::query("update budgets set deleted_at = now() where pillid = $1")
+        .bind(imported["budget_id"].as_str().unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+    let (status, created) = send_json(
+        &app,
+        "POST",
+        "/api/budgets",
+        &other_header,
+        Some(json!({"name": "Second"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    sqlx::query("update budgets set deleted_at = now() where pillid = $1")
+        .bind(created["id"].as_str().unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let app = app_with_multi_budget(pool, true);
+    let mut broken = fixture.clone();
+    broken["transactions"][0]["account_id"] = json!("missing");
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/budgets/import",
+        &other_header,
+        Some(broken),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, imported) = send_json(
+        &app,
+        "POST",
+        "/api/budgets/import",
+        &other_header,
+        Some(fixture.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    // HTTP imports never reuse archived ids, even unused ones.
+    assert_ne!(imported["budget_id"], fixture["budget"]["id"]);
+    let (_, transactions) = send_json(&app, "GET", "/api/transactions", &other_header, None).await;
+    let imported_transactions = transactions
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|t| t["budget_id"] == imported["budget_id"])
+        .count();
+    assert_eq!(imported_transactions, 3);
+    let imported_id = imported["budget_id"].as_str().unwrap();
+    let (_, scheduled) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{imported_id}/scheduled"),
+        &other_header,
+        None,
+    )
+    .await;
+    assert_eq!(scheduled[0]["outflow"], json!(90000));
+    assert_eq!(scheduled[0]["frequency"], json!("monthly"));
+    let (_, targets) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{imported_id}/targets"),
+        &other_header,
+        None,
+    )
+    .await;
+    assert_eq!(targets[0]["monthly_amount"], json!(5000));
+
+    // Version 1 archives had neither section and still import.
+    let mut old = fixture;
+    old["version"] = json!(1);
+    let old_object = old.as_object_mut().unwrap();
+    old_object.remove("scheduled_transactions");
+    old_object.remove("category_targets");
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/budgets/import",
+        &other_header,
+        Some(old),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn ynab_exports_import_as_budgets(pool: PgPool) {
+    let yfull = r#"{
+        "budgetMetaData": {"currencyLocale": "en_US"},
+        "accounts": [
+            {"entityId": "A1", "accountName": "Checking", "onBudget": true},
+            {"entityId": "A2", "accountName": "Savings", "onBudget": true, "hidden": true}
+        ],
+        "masterCategories": [
+            {"entityId": "M1", "name": "Bills", "subCategories": [{"entityId": "C1", "name": "Rent"}]}
+        ],
+        "payees": [{"entityId": "P1", "name": "Landlord"}],
+        "transactions": [
+            {"entityId": "T1", "accountId": "A1", "date": "2016-01-01", "amount": 2000,
+             "categoryId": "Category/__ImmediateIncome__", "cleared": "Reconciled"},
+            {"entityId": "T2", "accountId": "A1", "date": "2016-01-02", "amount": -900,
+             "payeeId": "P1", "categoryId": "Category/__Split__",
+             "subTransactions": [
+               {"entityId": "S1", "amount": -800, "categoryId": "C1"},
+               {"entityId": "S2", "amount": -100, "transferTransactionId": "T3"}
+             ]},
+            {"entityId": "T3", "accountId": "A2", "date": "2016-01-02", "amount": 100,
+             "transferTransactionId": "S2"}
+        ],
+        "monthlyBudgets": [{"month": "2016-01-01", "monthlySubCategoryBudgets": [
+            {"categoryId": "C1", "budgeted": 800},
+            {"categoryId": "C1", "budgeted": 0}
+        ]}]
+    }"#;
+    let imported = ynab::from_json(yfull, Some("From YNAB"), None).unwrap();
+    assert_eq!(
+        imported.unmapped["transfer between accounts booked to Imported/Transfers"],
+        2
+    );
+    let budget_id = admin::import_budget_archive(&pool, "ynab@example.com", &imported.archive)
+        .await
+        .unwrap();
+
+    let archive = admin::export_budget_archive(&pool, &budget_id)
+        .await
+        .unwrap();
+    assert_eq!(archive.budget.name, "From YNAB");
+    assert_eq!(archive.transactions.len(), 3);
+    assert!(archive.accounts[1].closed_at.is_some());
+    let (transfers,): (i64,) = sqlx::query_as(
+        "select sum(ts.inflow - ts.outflow)::bigint from transaction_splits ts
+         join categories c on c.id = ts.category_id where c.is_transfer",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(transfers, 0);
+    assert_eq!(archive.category_assignments.len(), 1);
+    assert_eq!(run_fsck(&pool, false).await.unwrap().outstanding(), 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn budgets_export_as_plain_text_journals(pool: PgPool) {
+    let app = app_for(pool);
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "journal@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(json!({
+            "budget_id": budget_id,
+            "account_id": account_id,
+            "date": "2026-02-19",
+            "payee": "Grocer",
+            "memo": null,
+            "splits": [{"category_id": category_id, "inflow": 0, "outflow": 1250, "memo": null}]
+        })),
+    )
+    .await;
+
+    let fetch = |query: &str, auth: &str| {
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/api/budgets/{budget_id}/export/journal?{query}"))
+            .header("authorization", auth)
+            .body(Body::empty())
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(req).await.unwrap();
+            let status = response.status();
+            let content_type = response
+                .headers()
+                .get("content-type")
+                .map(|value| value.to_str().unwrap().to_string());
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (
+                status,
+                content_type,
+                String::from_utf8(body.to_vec()).unwrap(),
+            )
+        }
+    };
+
+    let (status, content_type, journal) = fetch("format=beancount", &auth_header).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(content_type.unwrap().starts_with("text/plain"));
+    assert!(journal.contains("2026-02-19 open Assets:Checking USD"));
+    assert!(journal.contains("    Expenses:Needs:Groceries  12.50 USD\n"));
+    assert!(journal.contains("    Assets:Checking  -12.50 USD\n"));
+
+    let (status, _, ledger) = fetch("format=ledger&from=2026-03-01", &auth_header).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!ledger.contains("Grocer"));
+
+    let (status, _, _) = fetch("format=qif", &auth_header).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, other_token, _) = bootstrap_auth(app.clone(), "journal-other@example.com").await;
+    let (status, _, _) = fetch("format=ledger", &format!("Bearer {other_token}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn transactions_filter_and_export_as_csv_and_qif(pool: PgPool) {
+    let app = app_for(pool);
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "register@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    for (date, payee) in [("2026-01-10", "Baker"), ("2026-02-19", "Grocer")] {
+        send_json(
+            &app,
+            "POST",
+            "/api/transactions",
+            &auth_header,
+            Some(json!({
+                "budget_id": budget_id,
+                "account_id": account_id,
+                "date": date,
+                "payee": payee,
+                "memo": null,
+                "splits": [{"category_id": category_id, "inflow": 0, "outflow": 1250, "memo": null}]
+            })),
+        )
+        .await;
+    }
+
+    let (status, listed) = send_json(
+        &app,
+        "GET",
+        &format!("/api/transactions?account_id={account_id}&from=2026-02-01"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    assert_eq!(listed[0]["payee"], json!("Grocer"));
+
+    let fetch = |query: &str| {
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/api/transactions/export?{query}"))
+            .header("authorization", &auth_header)
+            .body(Body::empty())
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(req).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+
+    let (status, csv) = fetch("format=csv&from=2026-02-01").await;
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].starts_with("2026-02-19,"));
+    assert!(lines[1].contains(",Checking,Grocer,,Needs,Groceries,,0.00,12.50,USD,false,"));
+
+    let (status, qif) = fetch("format=qif").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(qif.starts_with("!Account\nNChecking\nTBank\n^\n!Type:Bank\nD01/10/2026\nT-12.50\nPBaker\nLNeeds:Groceries\n^\n"));
+    assert_eq!(qif.matches("^\n").count(), 3);
+
+    let (status, _) = fetch("format=ofx").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn spending_report_groups_by_period_and_key(pool: PgPool) {
+    let app = app_for(pool);
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "reports@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    for (date, payee, inflow, outflow) in [
+        ("2026-01-10", "Baker", 0, 1250),
+        ("2026-01-20", "Grocer", 0, 4000),
+        ("2026-03-02", "Grocer", 500, 0),
+        ("2026-03-05", "Grocer", 0, 2000),
+    ] {
+        send_json(
+            &app,
+            "POST",
+            "/api/transactions",
+            &auth_header,
+            Some(json!({
+                "budget_id": budget_id,
+                "account_id": account_id,
+                "date": date,
+                "payee": payee,
+                "memo": null,
+                "splits": [{"category_id": category_id, "inflow": inflow, "outflow": outflow, "memo": null}]
+            })),
+        )
+        .await;
+    }
+
+    let report_uri = format!("/api/budgets/{budget_id}/reports/spending");
+    let (status, report) = send_json(
+        &app,
+        "GET",
+        &format!("{report_uri}?group_by=payee&from=2026-01-01&to=2026-03-31"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        report["periods"],
+        json!(["2026-01-01", "2026-02-01", "2026-03-01"])
+    );
+    assert_eq!(report["totals"], json!([5250, 0, 1500]));
+    assert_eq!(report["series"][0]["key"], json!("Grocer"));
+    assert_eq!(report["series"][0]["amounts"], json!([4000, 0, 1500]));
+    assert_eq!(report["series"][1]["total"], json!(1250));
+
+    let (status, report) = send_json(
+        &app,
+        "GET",
+        &format!(
+            "{report_uri}?interval=quarter&from=2026-01-01&to=2026-03-31&categories={category_id}&accounts=other,{account_id}"
+        ),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["series"][0]["name"], json!("Groceries"));
+    assert_eq!(report["series"][0]["amounts"], json!([6750]));
+
+    let (_, report) = send_json(
+        &app,
+        "GET",
+        &format!("{report_uri}?from=2026-01-01&to=2026-03-31&accounts=other"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(report["series"], json!([]));
+
+    // A transfer's two legs are neither spending on one account nor negative
+    // spending on the other.
+    let (_, savings) = send_json(
+        &app,
+        "POST",
+        "/api/accounts",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Savings"})),
+    )
+    .await;
+    let (_, moves) = send_json(
+        &app,
+        "POST",
+        "/api/supercategories",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Moves"})),
+    )
+    .await;
+    let (_, transfers) = send_json(
+        &app,
+        "POST",
+        "/api/categories",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "supercategory_id": moves["id"], "name": "Transfers", "is_transfer": true})),
+    )
+    .await;
+    for (account, inflow, outflow) in [
+        (account_id.as_str(), 0, 3000),
+        (savings["id"].as_str().unwrap(), 3000, 0),
+    ] {
+        let (status, _) = send_json(
+            &app,
+            "POST",
+            "/api/transactions",
+            &auth_header,
+            Some(json!({
+                "budget_id": budget_id,
+                "account_id": account,
+                "date": "2026-03-10",
+                "payee": "Savings",
+                "memo": null,
+                "splits": [{"category_id": transfers["id"], "inflow": inflow, "outflow": outflow, "memo": null}]
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, report) = send_json(
+        &app,
+        "GET",
+        &format!("{report_uri}?group_by=account&from=2026-01-01&to=2026-03-31"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["totals"], json!([5250, 0, 1500]));
+    let series = report["series"].as_array().unwrap();
+    assert_eq!(series.len(), 1);
+    assert_eq!(series[0]["key"], json!(account_id));
+    assert_eq!(series[0]["amounts"], json!([5250, 0, 1500]));
+
+    let (status, _) = send_json(
+        &app,
+        "GET",
+        &format!("{report_uri}?from=2026-04-01&to=2026-03-31"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn income_expense_net_worth_and_age_of_money_reports(pool: PgPool) {
+    let app = app_for(pool);
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "networth@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (checking_id, groceries_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let (_, card) = send_json(
+        &app,
+        "POST",
+        "/api/accounts",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Card"})),
+    )
+    .await;
+    let card_id = card["id"].as_str().unwrap().to_string();
+    let category = |group: &'static str, name: &'static str, is_transfer: bool, is_income: bool| {
+        let app = app.clone();
+        let auth_header = auth_header.clone();
+        let budget_id = budget_id.clone();
+        async move {
+            let (_, supercategory) = send_json(
+                &app,
+                "POST",
+                "/api/supercategories",
+                &auth_header,
+                Some(json!({"budget_id": budget_id, "name": group})),
+            )
+            .await;
+            let (_, category) = send_json(
+                &app,
+                "POST",
+                "/api/categories",
+                &auth_header,
+                Some(json!({"budget_id": budget_id, "supercategory_id": supercategory["id"], "name": name, "is_transfer": is_transfer, "is_income": is_income})),
+            )
+            .await;
+            assert_eq!(category["is_transfer"], is_transfer);
+            assert_eq!(category["is_income"], is_income);
+            category["id"].as_str().unwrap().to_string()
+        }
+    };
+    let salary_id = category("Income", "Salary", false, true).await;
+    // Transfers are recognised by the flag, whatever the category is called.
+    let transfers_id = category("Savings", "Moves", true, false).await;
+
+    for (account_id, date, category_id, inflow, outflow) in [
+        (&checking_id, "2025-12-31", &salary_id, 100000, 0),
+        (&checking_id, "2026-01-05", &salary_id, 300000, 0),
+        (&checking_id, "2026-01-06", &groceries_id, 0, 5000),
+        (&card_id, "2026-02-10", &groceries_id, 0, 20000),
+        (&card_id, "2026-03-01", &transfers_id, 15000, 0),
+        (&checking_id, "2026-03-01", &transfers_id, 0, 15000),
+    ] {
+        let (status, _) = send_json(
+            &app,
+            "POST",
+            "/api/transactions",
+            &auth_header,
+            Some(json!({
+                "budget_id": budget_id,
+                "account_id": account_id,
+                "date": date,
+                "payee": null,
+                "memo": null,
+                "splits": [{"category_id": category_id, "inflow": inflow, "outflow": outflow, "memo": null}]
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, report) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/reports/income-expense?from=2026-01-01&to=2026-03-31"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        report["months"],
+        json!([
+            {"month": "2026-01-01", "income": 300000, "expense": 5000, "net": 295000},
+            {"month": "2026-02-01", "income": 0, "expense": 20000, "net": -20000},
+            {"month": "2026-03-01", "income": 0, "expense": 0, "net": 0}
+        ])
+    );
+
+    let (status, report) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/reports/net-worth?from=2026-01-01&to=2026-03-31"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        report["accounts"][0]["balances"],
+        json!([395000, 395000, 380000])
+    );
+    assert_eq!(report["accounts"][1]["balances"], json!([0, -20000, -5000]));
+    assert_eq!(report["assets"], json!([395000, 395000, 380000]));
+    assert_eq!(report["liabilities"], json!([0, 20000, 5000]));
+    assert_eq!(report["net_worth"], json!([395000, 375000, 375000]));
+
+    let (status, report) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/reports/age-of-money?from=2026-01-01&to=2026-03-31"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let days: Vec<_> = report["points"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|point| point["days"].clone())
+        .collect();
+    // Both outflows spend the December 31st inflow: 6 and 41 days later.
+    assert_eq!(days, [json!(6), json!(24), json!(24)]);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn forecast_projects_balances_and_flags_overdrafts(pool: PgPool) {
+    let app = app_for(pool);
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "forecast@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let today = chrono::Utc::now().date_naive();
+    let last_month = today.with_day(1).unwrap() - chrono::Months::new(1);
+    for (date, inflow, outflow) in [
+        // Outside the one month averaged below.
+        (last_month - chrono::Months::new(1), 10000, 0),
+        (last_month, 0, 3000),
+        (today + chrono::Days::new(5), 0, 15000),
+    ] {
+        let (status, _) = send_json(
+            &app,
+            "POST",
+            "/api/transactions",
+            &auth_header,
+            Some(json!({
+                "budget_id": budget_id,
+                "account_id": account_id,
+                "date": date,
+                "payee": null,
+                "memo": null,
+                "splits": [{"category_id": category_id, "inflow": inflow, "outflow": outflow, "memo": null}]
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let forecast_uri = format!("/api/budgets/{budget_id}/forecast");
+    let (status, forecast) = send_json(
+        &app,
+        "GET",
+        &format!("{forecast_uri}?months=1"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(forecast["dates"][0], json!(today + chrono::Days::new(1)));
+    let account = &forecast["accounts"][0];
+    assert_eq!(account["balance"], json!(7000));
+    assert_eq!(account["balances"][3], json!(7000));
+    assert_eq!(account["balances"][4], json!(-8000));
+    assert_eq!(
+        forecast["alerts"],
+        json!([{"account_id": account_id, "date": today + chrono::Days::new(5), "balance": -8000}])
+    );
+
+    let (_, forecast) = send_json(
+        &app,
+        "GET",
+        &format!("{forecast_uri}?months=1&average_months=1"),
+        &auth_header,
+        None,
+    )
+    .await;
+    let account = &forecast["accounts"][0];
+    assert_eq!(account["monthly_spending"], json!(3000));
+    assert_eq!(account["balances"][0], json!(6900));
+
+    // A monthly bill from ten days out, and a target that replaces the
+    // category's average spending.
+    let scheduled_uri = format!("/api/budgets/{budget_id}/scheduled");
+    let bill = |outflow: i64| {
+        json!({
+            "account_id": account_id,
+            "payee": "Power",
+            "outflow": outflow,
+            "frequency": "monthly",
+            "next_date": today + chrono::Days::new(10),
+        })
+    };
+    let (status, created) =
+        send_json(&app, "POST", &scheduled_uri, &auth_header, Some(bill(1500))).await;
+    assert_eq!(status, StatusCode::OK);
+    let bill_uri = format!("{scheduled_uri}/{}", created["id"].as_str().unwrap());
+    let (status, updated) = send_json(&app, "PUT", &bill_uri, &auth_header, Some(bill(2000))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["outflow"], json!(2000));
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        &scheduled_uri,
+        &auth_header,
+        Some(json!({"account_id": account_id, "inflow": 5, "outflow": 5, "frequency": "once", "next_date": today})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, listed) = send_json(&app, "GET", &scheduled_uri, &auth_header, None).await;
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+
+    let target_uri = format!("/api/budgets/{budget_id}/targets/{category_id}");
+    let (status, target) = send_json(
+        &app,
+        "PUT",
+        &target_uri,
+        &auth_header,
+        Some(json!({"monthly_amount": 6000})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(target["category_id"], json!(category_id));
+    let (status, _) = send_json(
+        &app,
+        "PUT",
+        &format!("/api/budgets/{budget_id}/targets/unknown"),
+        &auth_header,
+        Some(json!({"monthly_amount": 6000})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, forecast) = send_json(
+        &app,
+        "GET",
+        &format!("{forecast_uri}?months=1&average_months=1"),
+        &auth_header,
+        None,
+    )
+    .await;
+    let account = &forecast["accounts"][0];
+    assert_eq!(account["monthly_spending"], json!(0));
+    assert_eq!(account["monthly_targets"], json!(6000));
+    // 200 a day for the target, the entered transaction on day 5 and the
+    // bill on day 10.
+    assert_eq!(account["balances"][0], json!(6800));
+    assert_eq!(account["balances"][4], json!(-9000));
+    assert_eq!(account["balances"][9], json!(-12000));
+
+    for uri in [&bill_uri, &target_uri] {
+        let (status, _) = send_json(&app, "DELETE", uri, &auth_header, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+    let (_, forecast) = send_json(
+        &app,
+        "GET",
+        &format!("{forecast_uri}?months=1"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(forecast["accounts"][0]["balances"][9], json!(-8000));
+
+    let (status, _) = send_json(
+        &app,
+        "GET",
+        &format!("{forecast_uri}?months=0"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn schedules_and_targets_are_journaled_synced_and_undoable(pool: PgPool) {
+    let app = app_for(pool);
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "schedules@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let scheduled_uri = format!("/api/budgets/{budget_id}/scheduled");
+    let (status, bill) = send_json(
+        &app,
+        "POST",
+        &scheduled_uri,
+        &auth_header,
+        Some(json!({
+            "account_id": account_id,
+            "payee": "Rent",
+            "outflow": 90000,
+            "frequency": "monthly",
+            "next_date": "2030-01-01",
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let bill_id = bill["id"].as_str().unwrap().to_string();
+    let bill_uri = format!("{scheduled_uri}/{bill_id}");
+    let target_uri = format!("/api/budgets/{budget_id}/targets/{category_id}");
+    let (status, target) = send_json(
+        &app,
+        "PUT",
+        &target_uri,
+        &auth_header,
+        Some(json!({"monthly_amount": 6000})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let target_id = target["id"].as_str().unwrap().to_string();
+
+    let sync_uri = format!("/api/sync?since=0&budget_id={budget_id}");
+    let (status, synced) = send_json(&app, "GET", &sync_uri, &auth_header, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(synced["scheduled_transactions"][0]["id"], json!(bill_id));
+    assert_eq!(
+        synced["scheduled_transactions"][0]["frequency"],
+        json!("monthly")
+    );
+    assert_eq!(synced["category_targets"][0]["id"], json!(target_id));
+    assert_eq!(
+        synced["category_targets"][0]["category_id"],
+        json!(category_id)
+    );
+    let cursor = synced["server_knowledge"].as_i64().unwrap();
+
+    for uri in [&bill_uri, &target_uri] {
+        let (status, _) = send_json(&app, "DELETE", uri, &auth_header, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send_json(&app, "DELETE", uri, &auth_header, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+    let (_, listed) = send_json(&app, "GET", &scheduled_uri, &auth_header, None).await;
+    assert!(listed.as_array().unwrap().is_empty());
+    let (status, _) = send_json(
+        &app,
+        "PUT",
+        &bill_uri,
+        &auth_header,
+        Some(json!({"account_id": account_id, "outflow": 1, "frequency": "once", "next_date": "2030-01-01"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // The deletes reach sync clients as tombstones.
+    let (_, synced) = send_json(
+        &app,
+        "GET",
+        &format!("/api/sync?since={cursor}&budget_id={budget_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert!(!synced["scheduled_transactions"][0]["deleted_at"].is_null());
+    assert!(!synced["category_targets"][0]["deleted_at"].is_null());
+
+    let (_, entries) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/audit?entity=scheduled_transaction"),
+        &auth_header,
+        None,
+    )
+    .await;
+    let ops: Vec<&str> = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["op"].as_str().unwrap())
+        .collect();
+    assert_eq!(ops, ["delete", "create"]);
+    let (_, trash) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/trash"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(trash[0]["entity"], json!("scheduled_transaction"));
+    assert_eq!(trash[0]["id"], json!(bill_id));
+
+    // Undo brings the target back; the schedule comes back from the trash.
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        &format!("/api/budgets/{budget_id}/undo"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, targets) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/targets"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(
+        targets,
+        json!([{"id": target_id, "category_id": category_id, "monthly_amount": 6000}])
+    );
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        &format!("/api/budgets/{budget_id}/trash/scheduled_transaction/{bill_id}/restore"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, listed) = send_json(&app, "GET", &scheduled_uri, &auth_header, None).await;
+    assert_eq!(listed[0]["id"], json!(bill_id));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn dashboard_and_month_projection_are_scoped_to_a_budget(pool: PgPool) {
+    let app = app_with_multi_budget(pool.clone(), true);
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "dashboard@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, groceries_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let (_, income) = send_json(
+        &app,
+        "POST",
+        "/api/supercategories",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Income"})),
+    )
+    .await;
+    let (_, salary) = send_json(
+        &app,
+        "POST",
+        "/api/categories",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "supercategory_id": income["id"], "name": "Salary", "is_income": true})),
+    )
+    .await;
+    let (_, travel) = send_json(
+        &app,
+        "POST",
+        "/api/budgets",
+        &auth_header,
+        Some(json!({"name": "Travel", "currency_code": "EUR"})),
+    )
+    .await;
+    let travel_id = travel["id"].as_str().unwrap().to_string();
+    let (travel_account_id, travel_category_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &travel_id).await;
+
+    for (budget, account, category, date, inflow, outflow) in [
+        (
+            &budget_id,
+            &account_id,
+            salary["id"].as_str().unwrap(),
+            "2026-01-02",
+            500000,
+            0,
+        ),
+        (
+            &budget_id,
+            &account_id,
+            groceries_id.as_str(),
+            "2026-02-03",
+            0,
+            40000,
+        ),
+        (
+            &travel_id,
+            &travel_account_id,
+            travel_category_id.as_str(),
+            "2026-02-04",
+            0,
+            99900,
+        ),
+    ] {
+        let (status, _) = send_json(
+            &app,
+            "POST",
+            "/api/transactions",
+            &auth_header,
+            Some(json!({
+                "budget_id": budget,
+                "account_id": account,
+                "date": date,
+                "payee": null,
+                "memo": null,
+                "splits": [{"category_id": category, "inflow": inflow, "outflow": outflow, "memo": null}]
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/category-assignments",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "category_id": groceries_id, "month": "2026-02", "amount": 30000})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, dashboard) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/dashboard?month=2026-02&months=2"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        dashboard["months"],
+        json!([
+            {"month": "2026-01", "income": 500000, "assigned": 0, "activity": 0, "overspent": 0, "ready_to_assign": 500000},
+            {"month": "2026-02", "income": 0, "assigned": 30000, "activity": 40000, "overspent": 10000, "ready_to_assign": 470000}
+        ])
+    );
+    assert_eq!(dashboard["ready_to_assign"], json!(470000));
+    assert_eq!(dashboard["overspent"], json!(10000));
+    assert_eq!(
+        dashboard["accounts"],
+        json!([{"id": account_id, "name": "Checking", "balance": 460000, "inflow": 0, "outflow": 40000}])
+    );
+
+    let (status, projection) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/projections/month/2026-02"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let salary_id = salary["id"].as_str().unwrap();
+    assert_eq!(
+        projection,
+        json!([
+            {"category_id": groceries_id, "assigned": 30000, "activity": 40000, "available": -10000},
+            {"category_id": salary_id, "assigned": 0, "activity": 0, "available": 0}
+        ])
+    );
+    let (_, projection) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/projections/month/2026-01"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(
+        projection,
+        json!([
+            {"category_id": groceries_id, "assigned": 0, "activity": 0, "available": 0},
+            {"category_id": salary_id, "assigned": 0, "activity": -500000, "available": 500000}
+        ])
+    );
+
+    // One transaction split over several categories, two of them assigned.
+    let (_, home) = send_json(
+        &app,
+        "POST",
+        "/api/supercategories",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Home"})),
+    )
+    .await;
+    let (_, household) = send_json(
+        &app,
+        "POST",
+        "/api/categories",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "supercategory_id": home["id"], "name": "Household"})),
+    )
+    .await;
+    let household_id = household["id"].as_str().unwrap();
+    for (category, amount) in [(groceries_id.as_str(), 20000), (household_id, 5000)] {
+        let (status, _) = send_json(
+            &app,
+            "POST",
+            "/api/category-assignments",
+            &auth_header,
+            Some(json!({"budget_id": budget_id, "category_id": category, "month": "2026-03", "amount": amount})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(json!({
+            "budget_id": budget_id,
+            "account_id": account_id,
+            "date": "2026-03-05",
+            "payee": "Market",
+            "memo": null,
+            "splits": [
+                {"category_id": groceries_id, "inflow": 0, "outflow": 12000, "memo": null},
+                {"category_id": household_id, "inflow": 0, "outflow": 7000, "memo": null},
+                {"category_id": salary_id, "inflow": 100000, "outflow": 0, "memo": null}
+            ]
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let march_uri = format!("/api/budgets/{budget_id}/projections/month/2026-03");
+    let (_, projection) = send_json(&app, "GET", &march_uri, &auth_header, None).await;
+    assert_eq!(
+        projection,
+        json!([
+            {"category_id": groceries_id, "assigned": 20000, "activity": 12000, "available": 8000},
+            {"category_id": salary_id, "assigned": 0, "activity": -100000, "available": 100000},
+            {"category_id": household_id, "assigned": 5000, "activity": 7000, "available": -2000}
+        ])
+    );
+
+    // A failing query is an error, not a month of zeros.
+    sqlx::query("alter table category_assignments rename to category_assignments_away")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let (status, _) = send_json(&app, "GET", &march_uri, &auth_header, None).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    sqlx::query("alter table category_assignments_away rename to category_assignments")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let (_, travel_dashboard) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{travel_id}/dashboard?month=2026-02&months=1"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(travel_dashboard["currency_code"], json!("EUR"));
+    assert_eq!(travel_dashboard["months"][0]["activity"], json!(99900));
+
+    let (_, other_token, _) = bootstrap_auth(app.clone(), "dashboard-other@example.com").await;
+    let (status, _) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/dashboard"),
+        &format!("Bearer {other_token}"),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn closed_months_keep_their_snapshot_until_reopened(pool: PgPool) {
+    let app = app_for(pool);
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "closing@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (account_id, groceries_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+    let (_, income) = send_json(
+        &app,
+        "POST",
+        "/api/supercategories",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Income"})),
+    )
+    .await;
+    let (_, salary) = send_json(
+        &app,
+        "POST",
+        "/api/categories",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "supercategory_id": income["id"], "name": "Salary", "is_income": true})),
+    )
+    .await;
+    let transaction = |date: &str, category: &str, inflow: i64, outflow: i64| {
+        json!({
+            "budget_id": budget_id,
+            "account_id": account_id,
+            "date": date,
+            "payee": null,
+            "memo": null,
+            "splits": [{"category_id": category, "inflow": inflow, "outflow": outflow, "memo": null}]
+        })
+    };
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(transaction(
+            "2026-01-02",
+            salary["id"].as_str().unwrap(),
+            500000,
+            0,
+        )),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, groceries) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(transaction("2026-02-03", &groceries_id, 0, 40000)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let groceries_tx = format!("/api/transactions/{}", groceries["id"].as_str().unwrap());
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/category-assignments",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "category_id": groceries_id, "month": "2026-02", "amount": 30000})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let months = format!("/api/budgets/{budget_id}/months");
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        &format!("{months}/2099-01/close"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, snapshot) = send_json(
+        &app,
+        "POST",
+        &format!("{months}/2026-02/close"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(snapshot["month"], json!("2026-02"));
+    assert_eq!(snapshot["ready_to_assign"], json!(470000));
+    let grocery_row = snapshot["categories"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["category_id"] == json!(groceries_id))
+        .unwrap()
+        .clone();
+    assert_eq!(
+        grocery_row,
+        json!({"category_id": groceries_id, "assigned": 30000, "activity": 40000, "available": -10000})
+    );
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        &format!("{months}/2026-02/close"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // The closed month and everything before it are frozen; later months are not.
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(transaction("2026-01-15", &groceries_id, 0, 100)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send_json(
+        &app,
+        "PUT",
+        &groceries_tx,
+        &auth_header,
+        Some(transaction("2026-03-03", &groceries_id, 0, 40000)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send_json(&app, "DELETE", &groceries_tx, &auth_header, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/category-assignments",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "category_id": groceries_id, "month": "2026-01", "amount": 100})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (_, body) = send_json(
+        &app,
+        "POST",
+        "/api/sync/mutations",
+        &auth_header,
+        Some(json!({ "mutations": [
+            {"entity": "transaction", "op": "create", "id": "offline-feb", "transaction": transaction("2026-02-20", &groceries_id, 0, 500)},
+            {"entity": "transaction", "op": "create", "id": "offline-mar", "transaction": transaction("2026-03-20", &groceries_id, 0, 500)},
+        ] })),
+    )
+    .await;
+    assert_eq!(body["results"][0]["status"], json!("rejected"));
+    assert_eq!(body["results"][0]["reason"], json!("month_closed"));
+    assert_eq!(body["results"][1]["status"], json!("applied"));
+
+    let (status, projection) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/projections/month/2026-02"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(projection, snapshot["categories"]);
+    let (_, closed) = send_json(&app, "GET", &format!("{months}/closed"), &auth_header, None).await;
+    assert_eq!(closed.as_array().unwrap().len(), 1);
+    assert_eq!(closed[0]["month"], json!("2026-02"));
+
+    // Only the latest closed month can be reopened.
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        &format!("{months}/2026-01/close"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        &format!("{months}/2026-01/reopen"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        &format!("{months}/2026-02/reopen"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send_json(
+        &app,
+        "GET",
+        &format!("{months}/2026-02/snapshot"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send_json(&app, "DELETE", &groceries_tx, &auth_header, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(transaction("2026-01-15", &groceries_id, 0, 100)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn budget_currencies_are_iso_4217_with_their_minor_unit(pool: PgPool) {
+    let app = app_with_multi_budget(pool, true);
+    let (app, auth_token, _) = bootstrap_auth(app, "currency@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+
+    for code in ["ABC", "US", "usd$"] {
+        let (status, _) = send_json(
+            &app,
+            "POST",
+            "/api/budgets",
+            &auth_header,
+            Some(json!({"name": "Bad", "currency_code": code})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{code}");
+    }
+    let (status, yen) = send_json(
+        &app,
+        "POST",
+        "/api/budgets",
+        &auth_header,
+        Some(json!({"name": "Tokyo", "currency_code": "jpy"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(yen["currency_code"], json!("JPY"));
+    assert_eq!(yen["minor_unit"], json!(0));
+    let (_, dinar) = send_json(
+        &app,
+        "POST",
+        "/api/budgets",
+        &auth_header,
+        Some(json!({"name": "Kuwait", "currency_code": "KWD"})),
+    )
+    .await;
+    assert_eq!(dinar["minor_unit"], json!(3));
+
+    let (_, budgets) = send_json(&app, "GET", "/api/budgets", &auth_header, None).await;
+    let units: Vec<(String, i64)> = budgets
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| {
+            (
+                b["currency_code"].as_str().unwrap().to_string(),
+                b["minor_unit"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        units,
+        [("USD".into(), 2), ("JPY".into(), 0), ("KWD".into(), 3)]
+    );
+
+    let yen_id = yen["id"].as_str().unwrap();
+    let (_, dashboard) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{yen_id}/dashboard"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(dashboard["currency_code"], json!("JPY"));
+    assert_eq!(dashboard["minor_unit"], json!(0));
+    let (_, forecast) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{yen_id}/forecast"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(forecast["minor_unit"], json!(0));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn foreign_currency_accounts_convert_at_the_day_rate(pool: PgPool) {
+    let rates = fx::parse_rates(
+        "date,base,quote,rate\n2026-03-01,EUR,USD,1.10\n2026-03-15,EUR,USD,1.20\n2026-03-01,USD,JPY,150\n",
+    )
+    .unwrap();
+    assert_eq!(fx::store_rates(&pool, &rates).await.unwrap(), 3);
+    let app = app_for(pool.clone());
+    let (app, auth_token, budget_id) = bootstrap_auth(app, "fx@example.com").await;
+    let auth_header = format!("Bearer {auth_token}");
+    let (checking_id, groceries_id) =
+        bootstrap_budget_graph(app.clone(), &auth_header, &budget_id).await;
+
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/accounts",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Bad", "currency_code": "XYZ"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, euros) = send_json(
+        &app,
+        "POST",
+        "/api/accounts",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Girokonto", "currency_code": "eur"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(euros["currency_code"], json!("EUR"));
+    let euros_id = euros["id"].as_str().unwrap().to_string();
+    let (_, accounts) = send_json(
+        &app,
+        "GET",
+        &format!("/api/accounts?budget_id={budget_id}"),
+        &auth_header,
+        None,
+    )
+    .await;
+    let checking = accounts
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|a| a["id"] == json!(checking_id))
+        .unwrap();
+    assert_eq!(checking["currency_code"], json!("USD"));
+    let (status, _) = send_json(
+        &app,
+        "PUT",
+        &format!("/api/accounts/{euros_id}"),
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Girokonto", "currency_code": "USD"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let post = |date: &'static str, inflow: i64, outflow: i64| {
+        let app = app.clone();
+        let auth_header = auth_header.clone();
+        let body = json!({
+            "budget_id": budget_id,
+            "account_id": euros_id,
+            "date": date,
+            "payee": null,
+            "memo": null,
+            "splits": [{"category_id": groceries_id, "inflow": inflow, "outflow": outflow, "memo": null}]
+        });
+        async move { send_json(&app, "POST", "/api/transactions", &auth_header, Some(body)).await }
+    };
+    // No rate before March.
+    let (status, _) = post("2026-02-20", 0, 1000).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post("2026-03-01", 50000, 0).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post("2026-03-10", 0, 10001).await;
+    assert_eq!(status, StatusCode::OK);
+    let splits = |account_id: String| {
+        let app = app.clone();
+        let auth_header = auth_header.clone();
+        async move {
+            let (_, transactions) = send_json(
+                &app,
+                "GET",
+                &format!("/api/transactions?account_id={account_id}"),
+                &auth_header,
+                None,
+            )
+            .await;
+            let mut splits: Vec<(NaiveDate, [i64; 4])> = transactions
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|t| {
+                    let split = &t["splits"][0];
+                    let amount = |field: &str| split[field].as_i64().unwrap();
+                    (
+                        t["date"].as_str().unwrap().parse().unwrap(),
+                        [
+                            amount("inflow"),
+                            amount("outflow"),
+                            amount("budget_inflow"),
+                            amount("budget_outflow"),
+                        ],
+                    )
+                })
+                .collect();
+            splits.sort();
+            splits
+                .into_iter()
+                .map(|(_, amounts)| amounts)
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(
+        splits(euros_id.clone()).await,
+        [[50000, 0, 55000, 0], [0, 10001, 0, 11001]]
+    );
+    assert_eq!(splits(checking_id.clone()).await, Vec::<[i64; 4]>::new());
+
+    let (status, gains) = send_json(
+        &app,
+        "GET",
+        &format!("/api/budgets/{budget_id}/reports/fx-gains?date=2026-03-20"),
+        &auth_header,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(gains["currency_code"], json!("USD"));
+    assert_eq!(gains["accounts"].as_array().unwrap().len(), 1);
+    let account = &gains["accounts"][0];
+    assert_eq!(account["currency_code"], json!("EUR"));
+    assert_eq!(account["balance"], json!(39999));
+    assert_eq!(account["book_value"], json!(43999));
+    assert_eq!(account["market_value"], json!(47999));
+    assert_eq!(account["unrealized_gain"], json!(4000));
+    assert_eq!(gains["unrealized_gain"], json!(4000));
+
+    // Account amounts come in pairs, and neither is negative.
+    for sql in [
+        "update transaction_splits set account_outflow = null where account_inflow is not null",
+        "update transaction_splits set account_inflow = -1 where account_inflow is not null",
+    ] {
+        let err = sqlx::query(sql).execute(&pool).await.unwrap_err();
+        assert_eq!(
+            err.as_database_error().and_then(|e| e.constraint()),
+            Some("transaction_splits_account_amounts")
+        );
+    }
+
+    // Exports and sync show account postings in the account's currency.
+    let text = |uri: String| {
+        let req = Request::builder()
+            .method("GET")
+            .uri(uri)
+            .header("authorization", &auth_header)
+            .body(Body::empty())
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(req).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        }
+    };
+    let csv = text(format!(
+        "/api/transactions/export?format=csv&account_id={euros_id}"
+    ))
+    .await;
+    assert!(csv.contains(",0.00,100.01,EUR,"));
+    let ledger = text(format!(
+        "/api/budgets/{budget_id}/export/journal?format=ledger"
+    ))
+    .await;
+    assert!(ledger.contains(
+        "    Expenses:Needs:Groceries  110.01 USD\n    Assets:Girokonto  -100.01 EUR @@ 110.01 USD\n"
+    ));
+    let (_, synced) = send_json(&app, "GET", "/api/sync", &auth_header, None).await;
+    let synced_euros = synced["accounts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|a| a["id"] == json!(euros_id))
+        .unwrap();
+    assert_eq!(synced_euros["currency_code"], json!("EUR"));
+    assert_eq!(synced_euros["minor_unit"], json!(2));
+
+    // The inverse of a loaded rate works too, across minor units.
+    let (_, yen) = send_json(
+        &app,
+        "POST",
+        "/api/accounts",
+        &auth_header,
+        Some(json!({"budget_id": budget_id, "name": "Suica", "currency_code": "JPY"})),
+    )
+    .await;
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/transactions",
+        &auth_header,
+        Some(json!({
+            "budget_id": budget_id,
+            "account_id": yen["id"],
+            "date": "2026-03-05",
+            "payee": null,
+            "memo": null,
+            "splits": [{"category_id": groceries_id, "inflow": 0, "outflow": 3000, "memo": null}]
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        splits(yen["id"].as_str().unwrap().to_string()).await,
+        [[0, 3000, 0, 2000]]
+    );
+}
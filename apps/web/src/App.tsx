@@ -3,11 +3,13 @@ import { ArrowLeft, ArrowRight, CircleUserRound, Landmark, PiggyBank, Plus, Rece
 import { Badge } from './components/ui/badge'
 import { Button } from './components/ui/button'
 import { Input } from './components/ui/input'
+import { subscribeToBudget } from './lib/events'
 import { cn } from './lib/utils'
 
-type Budget = { id: string; name: string; currency_code: string; is_default: boolean }
+type MoneyUnit = { currency_code: string; minor_unit: number }
+type Budget = MoneyUnit & { id: string; name: string; is_default: boolean }
 type Named = { id: string; name: string; budget_id?: string }
-type Category = { id: string; name: string; budget_id: string; supercategory_id: string }
+type Category = { id: string; name: string; budget_id: string; supercategory_id: string; is_transfer: boolean; is_income: boolean }
 type Split = { id?: string; category_id: string; inflow: number; outflow: number; memo?: string }
 type Transaction = { id: string; budget_id: string; account_id: string; date: string; payee?: string; memo?: string; splits: Split[] }
 type Session = { token: string; user_id: string }
@@ -15,6 +17,7 @@ type AppTab = 'budget' | 'transactions' | 'accounts' | 'settings'
 type ToastTone = 'info' | 'success' | 'error'
 type Toast = { id: number; message: string; tone: ToastTone }
 type CategoryProjection = { category_id: string; assigned: number; activity: number; available: number }
+type Dashboard = { ready_to_assign: number; overspent: number; accounts: { id: string; inflow: number; outflow: number }[] }
 type BudgetRow = { categoryId: string; categoryName: string; supercategoryId: string; supercategoryName: string; assigned: number; activity: number; available: number }
 
 class ApiError extends Error { constructor(public status: number, message: string) { super(message) }}
@@ -42,7 +45,8 @@ async function api<T>(path: string, token: string, init?: RequestInit): Promise<
   return (await res.json()) as T
 }
 
-function currency(amount: number) { return new Intl.NumberFormat('en-US', { style: 'currency', currency: 'USD', maximumFractionDigits: 0 }).format(amount / 100) }
+const USD: MoneyUnit = { currency_code: 'USD', minor_unit: 2 }
+function currency(amount: number, unit: MoneyUnit = USD) { return new Intl.NumberFormat('en-US', { style: 'currency', currency: unit.currency_code, maximumFractionDigits: 0 }).format(amount / 10 ** unit.minor_unit) }
 function monthShift(month: string, direction: -1 | 1) { const [y, m] = month.split('-').map(Number); const d = new Date(y, m - 1 + direction, 1); return `${d.getFullYear()}-${String(d.getMonth() + 1).padStart(2, '0')}` }
 
 export function App() {
@@ -58,7 +62,7 @@ export function App() {
   const [supercategories, setSupercategories] = useState<Named[]>([])
   const [categories, setCategories] = useState<Category[]>([])
   const [transactions, setTransactions] = useState<Transaction[]>([])
-  const [dashboard, setDashboard] = useState({ inflow: 0, outflow: 0, available: 0 })
+  const [dashboard, setDashboard] = useState<Dashboard>({ ready_to_assign: 0, overspent: 0, accounts: [] })
   const [projections, setProjections] = useState<CategoryProjection[]>([])
   const [month, setMonth] = useState(new Date().toISOString().slice(0, 7))
   const [selectedCategoryId, setSelectedCategoryId] = useState<string | null>(null)
@@ -72,24 +76,39 @@ export function App() {
   const [txOutflow, setTxOutflow] = useState('0')
 
   const activeBudget = useMemo(() => budgets.find((b) => b.is_default) || budgets[0], [budgets])
+  const money = (amount: number) => currency(amount, activeBudget)
   const pushToast = (message: string, tone: ToastTone = 'info') => { const id = Date.now(); setToasts((p) => [...p, { id, message, tone }]); setTimeout(() => setToasts((p) => p.filter((t) => t.id !== id)), 3200) }
 
   useEffect(() => { const raw = localStorage.getItem('ez_session'); if (raw) setSession(JSON.parse(raw)) }, [])
   useEffect(() => { if (!session) return; localStorage.setItem('ez_session', JSON.stringify(session)); refresh(session.token).catch(() => pushToast('Could not load your data.', 'error')) }, [session])
-  useEffect(() => { if (!session) return; refreshMonthProjection(session.token).catch(() => pushToast('Could not refresh budget projection.', 'error')) }, [month, session, categories.length])
+  useEffect(() => { if (!session) return; refreshMonthProjection(session.token).catch(() => pushToast('Could not refresh budget projection.', 'error')) }, [month, session, categories.length, activeBudget?.id])
+  useEffect(() => {
+    if (!session || !activeBudget) return
+    // Changes from other devices (and this one) arrive in bursts; reload once per burst.
+    let pending: ReturnType<typeof setTimeout> | undefined
+    const refreshSoon = () => { clearTimeout(pending); pending = setTimeout(() => refresh(session.token).catch(() => pushToast('Could not load your data.', 'error')), 250) }
+    const unsubscribe = subscribeToBudget(API, activeBudget.id, session.token, { onChange: refreshSoon, onReset: refreshSoon })
+    return () => { clearTimeout(pending); unsubscribe() }
+  }, [session, activeBudget?.id])
 
   async function refresh(token = session?.token) {
     if (!token) return
     setLoading(true)
     try {
-      const [b, a, s, c, t, d] = await Promise.all([
-        api<Budget[]>('/budgets', token), api<Named[]>('/accounts', token), api<Named[]>('/supercategories', token), api<Category[]>('/categories', token), api<Transaction[]>('/transactions', token), api<{ inflow: number; outflow: number; available: number }>('/dashboard', token),
+      const [b, a, s, c, t] = await Promise.all([
+        api<Budget[]>('/budgets', token), api<Named[]>('/accounts', token), api<Named[]>('/supercategories', token), api<Category[]>('/categories', token), api<Transaction[]>('/transactions', token),
       ])
-      setBudgets(b); setAccounts(a); setSupercategories(s); setCategories(c); setTransactions(t); setDashboard(d)
+      setBudgets(b); setAccounts(a); setSupercategories(s); setCategories(c); setTransactions(t)
+      const budget = b.find((x) => x.is_default) || b[0]
+      if (budget) await refreshMonthProjection(token, budget.id)
     } finally { setLoading(false) }
   }
 
-  async function refreshMonthProjection(token = session?.token) { if (!token) return; setProjections(await api<CategoryProjection[]>(`/projections/month/${month}`, token)) }
+  async function refreshMonthProjection(token = session?.token, budgetId = activeBudget?.id) {
+    if (!token || !budgetId) return
+    const [p, d] = await Promise.all([api<CategoryProjection[]>(`/budgets/${budgetId}/projections/month/${month}`, token), api<Dashboard>(`/budgets/${budgetId}/dashboard?month=${month}`, token)])
+    setProjections(p); setDashboard(d)
+  }
   async function requestMagicLink(e: FormEvent) { e.preventDefault(); const res = await fetch(`${API}/auth/magic-link/request`, { method: 'POST', headers: { 'Content-Type': 'application/json' }, body: JSON.stringify({ email }) }); const data = await res.json(); setNotice(`Mail sent. Dev token: ${data.debug_token ?? 'check Mailpit'}`); setTokenInput(data.debug_token || '') }
   async function verifyToken(e: FormEvent) { e.preventDefault(); const res = await fetch(`${API}/auth/magic-link/verify`, { method: 'POST', headers: { 'Content-Type': 'application/json' }, body: JSON.stringify({ token: tokenInput }) }); if (!res.ok) return setNotice('Token invalid or expired'); setSession(await res.json()) }
 
@@ -98,7 +117,8 @@ export function App() {
     return categories.filter((c) => !activeBudget || c.budget_id === activeBudget.id).map((c) => ({ categoryId: c.id, categoryName: c.name, supercategoryId: c.supercategory_id, supercategoryName: smap.get(c.supercategory_id) || 'Uncategorized', assigned: pmap.get(c.id)?.assigned ?? 0, activity: pmap.get(c.id)?.activity ?? 0, available: pmap.get(c.id)?.available ?? 0 }))
   }, [activeBudget, categories, projections, supercategories])
   const groupedRows = useMemo(() => { const grouped = new Map<string, { name: string; rows: BudgetRow[] }>(); budgetRows.forEach((row) => { if (!grouped.has(row.supercategoryId)) grouped.set(row.supercategoryId, { name: row.supercategoryName, rows: [] }); grouped.get(row.supercategoryId)?.rows.push(row) }); return [...grouped.entries()].map(([id, group]) => ({ id, ...group })) }, [budgetRows])
-  const readyToAssign = useMemo(() => dashboard.available - budgetRows.reduce((sum, row) => sum + row.assigned, 0), [dashboard.available, budgetRows])
+  const readyToAssign = dashboard.ready_to_assign
+  const monthFlow = useMemo(() => dashboard.accounts.reduce((sum, a) => ({ inflow: sum.inflow + a.inflow, outflow: sum.outflow + a.outflow }), { inflow: 0, outflow: 0 }), [dashboard.accounts])
   const overspentRows = useMemo(() => budgetRows.filter((row) => row.available < 0), [budgetRows])
   const selectedRow = useMemo(() => budgetRows.find((r) => r.categoryId === selectedCategoryId) || budgetRows[0], [selectedCategoryId, budgetRows])
 
@@ -149,7 +169,7 @@ export function App() {
 
       {activeTab === 'budget' && <section className="workspace-strip space-y-2" data-testid="budget-workspace">
         <div data-testid="dashboard-totals" className="grid gap-2 border-b border-white/10 pb-2 md:grid-cols-[1fr_auto]">
-          <div><p className="text-xs uppercase tracking-[0.18em] text-muted-foreground">Ready to Assign</p><p data-testid="ready-to-assign" className={cn('text-3xl font-extrabold', readyToAssign < 0 ? 'text-danger' : 'text-success')}>{currency(readyToAssign)}</p></div>
+          <div><p className="text-xs uppercase tracking-[0.18em] text-muted-foreground">Ready to Assign</p><p data-testid="ready-to-assign" className={cn('text-3xl font-extrabold', readyToAssign < 0 ? 'text-danger' : 'text-success')}>{money(readyToAssign)}</p></div>
           <div className="hidden items-center gap-2 md:flex"><Button variant="outline" size="sm" onClick={() => setMonth((m) => monthShift(m, -1))}><ArrowLeft className="h-4 w-4" />Previous</Button><span className="w-20 text-center text-sm font-bold">{month}</span><Button variant="outline" size="sm" onClick={() => setMonth((m) => monthShift(m, 1))}>Next<ArrowRight className="h-4 w-4" /></Button></div>
           <div className="flex gap-2 md:col-span-2"><Badge variant="outline">{budgetRows.length} categories</Badge><Badge variant="outline">{overspentRows.length} overspent</Badge></div>
         </div>
@@ -159,7 +179,7 @@ export function App() {
         <div className="hidden overflow-x-auto md:block"><table className="ez-table min-w-full text-[13px]"><thead className="border-b border-white/10 text-muted-foreground"><tr><th className="px-3 py-1.5 text-left text-[10px] font-bold uppercase tracking-[0.14em]">Category</th><th className="px-3 py-1.5 text-right text-[10px] font-bold uppercase tracking-[0.14em]">Assigned</th><th className="px-3 py-1.5 text-right text-[10px] font-bold uppercase tracking-[0.14em]">Activity</th><th className="px-3 py-1.5 text-right text-[10px] font-bold uppercase tracking-[0.14em]">Available</th></tr></thead><tbody>
           {groupedRows.map((group) => <Fragment key={group.id}>
             <tr><td colSpan={4} className="px-3 py-1 text-[10px] font-bold uppercase tracking-[0.14em] text-muted-foreground">{group.name}</td></tr>
-            {group.rows.map((row) => { const isSelected = selectedCategoryId === row.categoryId; const isEditing = editingCategoryId === row.categoryId; return <tr key={row.categoryId} data-testid={`budget-row-${row.categoryId}`} onClick={() => setSelectedCategoryId(row.categoryId)} className={cn('ez-row border-t border-white/5 cursor-pointer', isSelected && 'bg-muted/50')}><td className="px-3 py-1.5"><div className="flex items-center gap-1.5"><span className="font-semibold">{row.categoryName}</span>{row.available < 0 && <Badge variant="danger">Overspent</Badge>}</div></td><td className="px-3 py-1.5 text-right">{isEditing ? <div className="ml-auto flex max-w-[240px] items-center gap-2"><Input aria-label={`Assigned amount for ${row.categoryName}`} type="number" value={editingAmount} onChange={(e) => setEditingAmount(e.target.value)} /><Button size="sm" onClick={(e) => { e.stopPropagation(); saveAssignment(row.categoryId, Number(editingAmount)) }} disabled={!assignmentsEnabled}>Save</Button></div> : <button className="px-2 py-1 font-bold text-primary transition hover:bg-primary/10 disabled:text-muted-foreground" disabled={!assignmentsEnabled} onClick={(e) => { e.stopPropagation(); setEditingCategoryId(row.categoryId); setEditingAmount(String(row.assigned)) }}>{currency(row.assigned)}</button>}</td><td className="px-3 py-1.5 text-right text-muted-foreground">{currency(row.activity)}</td><td className="px-3 py-1.5 text-right"><AvailabilityChip amount={row.available} /></td></tr> })}
+            {group.rows.map((row) => { const isSelected = selectedCategoryId === row.categoryId; const isEditing = editingCategoryId === row.categoryId; return <tr key={row.categoryId} data-testid={`budget-row-${row.categoryId}`} onClick={() => setSelectedCategoryId(row.categoryId)} className={cn('ez-row border-t border-white/5 cursor-pointer', isSelected && 'bg-muted/50')}><td className="px-3 py-1.5"><div className="flex items-center gap-1.5"><span className="font-semibold">{row.categoryName}</span>{row.available < 0 && <Badge variant="danger">Overspent</Badge>}</div></td><td className="px-3 py-1.5 text-right">{isEditing ? <div className="ml-auto flex max-w-[240px] items-center gap-2"><Input aria-label={`Assigned amount for ${row.categoryName}`} type="number" value={editingAmount} onChange={(e) => setEditingAmount(e.target.value)} /><Button size="sm" onClick={(e) => { e.stopPropagation(); saveAssignment(row.categoryId, Number(editingAmount)) }} disabled={!assignmentsEnabled}>Save</Button></div> : <button className="px-2 py-1 font-bold text-primary transition hover:bg-primary/10 disabled:text-muted-foreground" disabled={!assignmentsEnabled} onClick={(e) => { e.stopPropagation(); setEditingCategoryId(row.categoryId); setEditingAmount(String(row.assigned)) }}>{money(row.assigned)}</button>}</td><td className="px-3 py-1.5 text-right text-muted-foreground">{money(row.activity)}</td><td className="px-3 py-1.5 text-right"><AvailabilityChip amount={row.available} unit={activeBudget} /></td></tr> })}
           </Fragment>)}
           {!groupedRows.length && <tr><td colSpan={4} className="px-4 py-8 text-center text-muted-foreground">No categories yet. Add categories to start assigning money.</td></tr>}
         </tbody></table></div>
@@ -167,12 +187,12 @@ export function App() {
         <div className="space-y-1.5 md:hidden">
           {groupedRows.map((group) => <section key={group.id} className="border-b border-white/10 pb-1">
             <p className="px-1 pb-0.5 text-[10px] font-bold uppercase tracking-[0.14em] text-muted-foreground">{group.name}</p>
-            {group.rows.map((row) => <button key={row.categoryId} onClick={() => setSelectedCategoryId(row.categoryId)} className="flex w-full items-center gap-2 border-t border-white/10 px-1 py-1.5 text-left first:border-t-0"><span className="text-sm">{row.available < 0 ? '⚠️' : '💼'}</span><span className="min-w-0 flex-1"><span className="block truncate text-sm font-semibold">{row.categoryName}</span><span className="block text-[10px] text-muted-foreground">Assigned {currency(row.assigned)} • Activity {currency(row.activity)}</span></span><AvailabilityChip amount={row.available} /></button>)}
+            {group.rows.map((row) => <button key={row.categoryId} onClick={() => setSelectedCategoryId(row.categoryId)} className="flex w-full items-center gap-2 border-t border-white/10 px-1 py-1.5 text-left first:border-t-0"><span className="text-sm">{row.available < 0 ? '⚠️' : '💼'}</span><span className="min-w-0 flex-1"><span className="block truncate text-sm font-semibold">{row.categoryName}</span><span className="block text-[10px] text-muted-foreground">Assigned {money(row.assigned)} • Activity {money(row.activity)}</span></span><AvailabilityChip amount={row.available} unit={activeBudget} /></button>)}
           </section>)}
         </div>
       </section>}
 
-      {activeTab === 'transactions' && <section className="space-y-4 border-t border-white/10 pt-3"><header><h2 className="font-brand text-2xl">Transactions</h2></header><form className="grid gap-2 md:grid-cols-2" onSubmit={async (e) => { e.preventDefault(); if (!activeBudget || !accounts[0] || !categories[0] || !session) return setNotice('Need budget/account/category'); await api('/transactions', session.token, { method: 'POST', body: JSON.stringify({ budget_id: activeBudget.id, account_id: accounts[0].id, date: txDate, payee: txPayee || null, memo: txMemo || null, splits: [{ category_id: categories[0].id, inflow: Number(txInflow), outflow: Number(txOutflow), memo: null }] }) }); setNotice('Transaction created'); setTxPayee(''); setTxMemo(''); setTxInflow('0'); setTxOutflow('0'); await refresh(); await refreshMonthProjection() }}><Input aria-label="Transaction date" type="date" value={txDate} onChange={(e) => setTxDate(e.target.value)} /><Input aria-label="Payee" value={txPayee} onChange={(e) => setTxPayee(e.target.value)} placeholder="Payee" /><Input aria-label="Memo" value={txMemo} onChange={(e) => setTxMemo(e.target.value)} placeholder="Memo" /><Input aria-label="Inflow" type="number" value={txInflow} onChange={(e) => setTxInflow(e.target.value)} /><Input aria-label="Outflow" type="number" value={txOutflow} onChange={(e) => setTxOutflow(e.target.value)} /><Button className="md:col-span-2">Create transaction</Button></form>{!transactions.length ? <p className="text-sm text-muted-foreground">No transactions yet</p> : <ul className="divide-y divide-white/10 border-y border-white/10">{transactions.slice(0, 8).map((tx) => <li key={tx.id} className="flex items-center justify-between px-1 py-2 text-sm"><span className="truncate">🧾 {tx.payee || 'Transaction'}</span><span className="font-semibold text-warning">{currency(tx.splits.reduce((acc, s) => acc + s.outflow - s.inflow, 0))}</span></li>)}</ul>}</section>}
+      {activeTab === 'transactions' && <section className="space-y-4 border-t border-white/10 pt-3"><header><h2 className="font-brand text-2xl">Transactions</h2></header><form className="grid gap-2 md:grid-cols-2" onSubmit={async (e) => { e.preventDefault(); if (!activeBudget || !accounts[0] || !categories[0] || !session) return setNotice('Need budget/account/category'); await api('/transactions', session.token, { method: 'POST', body: JSON.stringify({ budget_id: activeBudget.id, account_id: accounts[0].id, date: txDate, payee: txPayee || null, memo: txMemo || null, splits: [{ category_id: categories[0].id, inflow: Number(txInflow), outflow: Number(txOutflow), memo: null }] }) }); setNotice('Transaction created'); setTxPayee(''); setTxMemo(''); setTxInflow('0'); setTxOutflow('0'); await refresh(); await refreshMonthProjection() }}><Input aria-label="Transaction date" type="date" value={txDate} onChange={(e) => setTxDate(e.target.value)} /><Input aria-label="Payee" value={txPayee} onChange={(e) => setTxPayee(e.target.value)} placeholder="Payee" /><Input aria-label="Memo" value={txMemo} onChange={(e) => setTxMemo(e.target.value)} placeholder="Memo" /><Input aria-label="Inflow" type="number" value={txInflow} onChange={(e) => setTxInflow(e.target.value)} /><Input aria-label="Outflow" type="number" value={txOutflow} onChange={(e) => setTxOutflow(e.target.value)} /><Button className="md:col-span-2">Create transaction</Button></form>{!transactions.length ? <p className="text-sm text-muted-foreground">No transactions yet</p> : <ul className="divide-y divide-white/10 border-y border-white/10">{transactions.slice(0, 8).map((tx) => <li key={tx.id} className="flex items-center justify-between px-1 py-2 text-sm"><span className="truncate">🧾 {tx.payee || 'Transaction'}</span><span className="font-semibold text-warning">{money(tx.splits.reduce((acc, s) => acc + s.outflow - s.inflow, 0))}</span></li>)}</ul>}</section>}
       {activeTab === 'accounts' && <CrudPanel title="Accounts" items={accounts} parentRequired={!activeBudget} onCreate={async (name) => { if (!session || !activeBudget) return; await api('/accounts', session.token, { method: 'POST', body: JSON.stringify({ name, budget_id: activeBudget.id }) }); await refresh() }} />}
       {activeTab === 'settings' && <section className="space-y-4 border-t border-white/10 pt-3"><header><h2 className="font-brand text-2xl">Settings</h2></header><p className="text-sm text-muted-foreground">Manage authentication and session controls.</p><Button variant="secondary" onClick={() => { localStorage.removeItem('ez_session'); setSession(null) }}>Logout</Button></section>}
       {notice && <p className="mt-3 text-xs text-muted-foreground">{notice}</p>}
@@ -182,13 +202,13 @@ export function App() {
       <p className="text-xs uppercase tracking-[0.18em] text-muted-foreground">Inspector</p>
       <h3 className="mt-1 text-lg font-semibold">{selectedRow?.categoryName || 'Select a category'}</h3>
       <div className="mt-4 space-y-3 text-sm">
-        <SummaryRow label="Assigned" value={currency(selectedRow?.assigned || 0)} />
-        <SummaryRow label="Activity" value={currency(selectedRow?.activity || 0)} />
-        <SummaryRow label="Available" value={currency(selectedRow?.available || 0)} highlighted />
+        <SummaryRow label="Assigned" value={money(selectedRow?.assigned || 0)} />
+        <SummaryRow label="Activity" value={money(selectedRow?.activity || 0)} />
+        <SummaryRow label="Available" value={money(selectedRow?.available || 0)} highlighted />
       </div>
       <div className="mt-6 space-y-2">
-        <Badge variant="outline">Inflow {currency(dashboard.inflow)}</Badge>
-        <Badge variant="outline">Outflow {currency(dashboard.outflow)}</Badge>
+        <Badge variant="outline">Inflow {money(monthFlow.inflow)}</Badge>
+        <Badge variant="outline">Outflow {money(monthFlow.outflow)}</Badge>
       </div>
     </aside>
 
@@ -200,8 +220,8 @@ export function App() {
   </div>
 }
 
-function AvailabilityChip({ amount }: { amount: number }) {
-  return <span className={cn('inline-flex min-w-[74px] justify-center rounded-full px-2 py-0.5 text-[11px] font-semibold', amount < 0 ? 'bg-rose-500/20 text-rose-200' : amount === 0 ? 'bg-slate-500/25 text-slate-300' : 'bg-emerald-500/20 text-emerald-200')}>{currency(amount)}</span>
+function AvailabilityChip({ amount, unit }: { amount: number; unit?: MoneyUnit }) {
+  return <span className={cn('inline-flex min-w-[74px] justify-center rounded-full px-2 py-0.5 text-[11px] font-semibold', amount < 0 ? 'bg-rose-500/20 text-rose-200' : amount === 0 ? 'bg-slate-500/25 text-slate-300' : 'bg-emerald-500/20 text-emerald-200')}>{currency(amount, unit)}</span>
 }
 
 function SummaryRow({ label, value, highlighted }: { label: string; value: string; highlighted?: boolean }) {
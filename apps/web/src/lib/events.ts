@@ -0,0 +1,40 @@
+export type ChangeEvent = { id: number; budget_id: string; entity: string; entity_id: string; op: 'create' | 'update' | 'delete' }
+type Handlers = { onChange: (event: ChangeEvent) => void; onReset: () => void }
+
+const RETRY_MS = 5000
+
+/**
+ * Follows one budget's change feed. `EventSource` cannot send the bearer token, so the stream is
+ * opened with a short-lived ticket: the browser reconnects on its own while the ticket lasts, and
+ * once a reconnect is refused a fresh ticket is minted and the stream resumes after the last event
+ * seen. `onReset` means events were missed and everything should be reloaded. Returns a function
+ * that stops following.
+ */
+export function subscribeToBudget(api: string, budgetId: string, token: string, { onChange, onReset }: Handlers): () => void {
+  let source: EventSource | null = null
+  let lastEventId = ''
+  let retry: ReturnType<typeof setTimeout> | undefined
+  let stopped = false
+
+  const reopenLater = () => { if (!stopped) retry = setTimeout(() => void open(), RETRY_MS) }
+  async function open() {
+    let ticket: string
+    try {
+      const res = await fetch(`${api}/auth/event-ticket`, { method: 'POST', headers: { Authorization: `Bearer ${token}` } })
+      if (res.status === 401) return
+      if (!res.ok) return reopenLater()
+      ticket = ((await res.json()) as { ticket: string }).ticket
+    } catch { return reopenLater() }
+    if (stopped) return
+    const query = new URLSearchParams({ ticket })
+    if (lastEventId) query.set('last_event_id', lastEventId)
+    const current = new EventSource(`${api}/budgets/${budgetId}/events?${query}`)
+    source = current
+    current.addEventListener('change', (e) => { const message = e as MessageEvent<string>; lastEventId = message.lastEventId; onChange(JSON.parse(message.data) as ChangeEvent) })
+    current.addEventListener('reset', (e) => { lastEventId = (e as MessageEvent<string>).lastEventId; onReset() })
+    current.onerror = () => { if (current.readyState === EventSource.CLOSED) { source = null; reopenLater() } }
+  }
+
+  void open()
+  return () => { stopped = true; clearTimeout(retry); source?.close() }
+}